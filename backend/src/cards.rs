@@ -0,0 +1,244 @@
+//! Importing Tavern character cards, as JSON files or PNG images with the card in a `chara`
+//! text chunk. V2 cards (`chara_card_v2`) keep their fields under `data`; V1 cards, with
+//! the fields at the top level, are read too.
+
+use crate::dbs::Database;
+use base64::Engine;
+use serde::Deserialize;
+use shared::models::{CardMetadata, Character, DiscoveryFailure, GreetingMode};
+use std::collections::HashSet;
+use std::path::Path;
+use uuid::Uuid;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The card fields Renoma has a place for, the rest are ignored
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CardData {
+    name: String,
+    description: String,
+    personality: String,
+    scenario: String,
+    first_mes: String,
+    mes_example: String,
+    alternate_greetings: Vec<String>,
+    creator: String,
+    creator_notes: String,
+    tags: Vec<String>,
+    character_version: String,
+}
+
+/// Read a card from the bytes of a JSON or PNG file
+pub fn parse_card(bytes: &[u8]) -> Result<Character, String> {
+    let json = if bytes.starts_with(PNG_SIGNATURE) {
+        png_card_json(bytes)?
+    } else {
+        String::from_utf8(bytes.to_vec()).map_err(|_| "Not a JSON or PNG file".to_string())?
+    };
+    let mut value: serde_json::Value =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid card JSON: {}", e))?;
+    if value.get("spec").and_then(|s| s.as_str()) == Some("chara_card_v2") {
+        value = value["data"].take();
+    }
+    let card: CardData =
+        serde_json::from_value(value).map_err(|e| format!("Invalid card: {}", e))?;
+    if card.name.trim().is_empty() {
+        return Err("Card has no name".to_string());
+    }
+
+    let non_empty = |s: String| Some(s).filter(|s| !s.trim().is_empty());
+    Ok(Character {
+        id: Uuid::new_v4(),
+        name: card.name.trim().to_string(),
+        description: card.description,
+        personality: card.personality,
+        scenario: card.scenario,
+        first_message: card.first_mes,
+        example_messages: card.mes_example,
+        alternate_greetings: card.alternate_greetings,
+        greeting_mode: GreetingMode::default(),
+        is_favorite: false,
+        response_length: None,
+        reasoning_effort: None,
+        metadata: CardMetadata {
+            creator: non_empty(card.creator),
+            creator_notes: non_empty(card.creator_notes),
+            tags: card.tags,
+            character_version: non_empty(card.character_version),
+        },
+    })
+}
+
+/// The base64 card JSON from a PNG's `chara` text chunk
+fn png_card_json(bytes: &[u8]) -> Result<String, String> {
+    let mut rest = &bytes[PNG_SIGNATURE.len()..];
+    // Each chunk is a 4 byte length, a 4 byte type, the data and a 4 byte checksum
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let data = rest
+            .get(8..8 + len)
+            .ok_or_else(|| "Truncated PNG".to_string())?;
+        if &rest[4..8] == b"tEXt"
+            && let Some(separator) = data.iter().position(|&b| b == 0)
+            && &data[..separator] == b"chara"
+        {
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(data[separator + 1..].trim_ascii())
+                .map_err(|e| format!("Invalid card data in PNG: {}", e))?;
+            return String::from_utf8(decoded)
+                .map_err(|_| "Invalid card data in PNG: not UTF-8".to_string());
+        }
+        rest = rest.get(12 + len..).unwrap_or_default();
+    }
+    Err("PNG has no character card".to_string())
+}
+
+/// `name`, or `name (2)`, `name (3)`... if it's taken
+fn unique_name(name: &str, taken: &HashSet<String>) -> String {
+    if !taken.contains(name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| !taken.contains(candidate))
+        .expect("some suffix is free")
+}
+
+/// Cards imported from a directory, and the files that couldn't be
+#[derive(Debug, Default)]
+pub struct CardImportReport {
+    /// Names the characters were saved under
+    pub imported: Vec<String>,
+    pub failed: Vec<DiscoveryFailure>,
+}
+
+/// Import every `.json` and `.png` card in `dir`. Files that aren't valid cards are skipped
+/// and reported. A character whose name is already taken gets a number appended.
+pub async fn import_card_dir(
+    db: &dyn Database,
+    dir: &Path,
+) -> Result<CardImportReport, Box<dyn std::error::Error + Send + Sync>> {
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_card = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json") || ext.eq_ignore_ascii_case("png"));
+        if is_card && path.is_file() {
+            paths.push(path);
+        }
+    }
+    // Sorted, so which duplicate gets which number doesn't depend on the filesystem
+    paths.sort();
+
+    let mut taken: HashSet<String> = db
+        .get_characters()
+        .await?
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    let mut report = CardImportReport::default();
+    for path in paths {
+        let file = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let card = match tokio::fs::read(&path).await {
+            Ok(bytes) => parse_card(&bytes),
+            Err(e) => Err(e.to_string()),
+        };
+        match card {
+            Ok(mut character) => {
+                character.name = unique_name(&character.name, &taken);
+                match db.create_character(character.clone()).await {
+                    Ok(()) => {
+                        taken.insert(character.name.clone());
+                        report.imported.push(character.name);
+                    }
+                    Err(e) => report.failed.push(DiscoveryFailure {
+                        file,
+                        error: e.to_string(),
+                    }),
+                }
+            }
+            Err(error) => report.failed.push(DiscoveryFailure { file, error }),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn v2_card() -> serde_json::Value {
+        json!({
+            "spec": "chara_card_v2",
+            "spec_version": "2.0",
+            "data": {
+                "name": " Ada ",
+                "description": "A mathematician",
+                "first_mes": "Hello.",
+                "alternate_greetings": ["Good day."],
+                "creator": "someone",
+                "creator_notes": "Best with low temperature",
+                "tags": ["history", "science"],
+                "character_version": "1.2",
+                "extensions": {},
+            },
+        })
+    }
+
+    /// A PNG with nothing but the card in a `chara` chunk
+    fn png(card: &serde_json::Value) -> Vec<u8> {
+        let mut data = b"chara\0".to_vec();
+        data.extend(
+            base64::engine::general_purpose::STANDARD
+                .encode(card.to_string())
+                .bytes(),
+        );
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend((data.len() as u32).to_be_bytes());
+        bytes.extend(b"tEXt");
+        bytes.extend(data);
+        bytes.extend([0; 4]);
+        bytes
+    }
+
+    #[test]
+    fn v2_cards_keep_all_their_metadata() {
+        let character = parse_card(v2_card().to_string().as_bytes()).unwrap();
+        assert_eq!(character.name, "Ada");
+        assert_eq!(character.first_message, "Hello.");
+        assert_eq!(character.alternate_greetings, ["Good day."]);
+        assert_eq!(
+            character.metadata,
+            CardMetadata {
+                creator: Some("someone".to_string()),
+                creator_notes: Some("Best with low temperature".to_string()),
+                tags: vec!["history".to_string(), "science".to_string()],
+                character_version: Some("1.2".to_string()),
+            }
+        );
+
+        assert_eq!(
+            parse_card(&png(&v2_card())).unwrap().metadata,
+            character.metadata
+        );
+    }
+
+    #[test]
+    fn v1_cards_and_blank_metadata_read_as_none() {
+        let card = json!({ "name": "Bea", "first_mes": "Hi", "creator": " " });
+        let character = parse_card(card.to_string().as_bytes()).unwrap();
+        assert_eq!(character.first_message, "Hi");
+        assert!(character.metadata.is_empty());
+
+        assert!(parse_card(json!({ "name": "" }).to_string().as_bytes()).is_err());
+        assert!(parse_card(PNG_SIGNATURE).is_err());
+    }
+}
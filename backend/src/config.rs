@@ -0,0 +1,104 @@
+use crate::clock::{Clock, SystemClock};
+use shared::models::ModelPricing;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Longest message content the server stores, in characters
+pub const DEFAULT_MAX_MESSAGE_LENGTH: usize = 200_000;
+
+/// Largest character bundle the server imports, in bytes
+pub const DEFAULT_MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Runtime options for the backend that are independent of the database
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Model name -> price, used to estimate per-chat spend
+    pub pricing: HashMap<String, ModelPricing>,
+    /// When set, every completion's raw provider chunks are written to a file in this directory
+    pub debug_provider_dir: Option<PathBuf>,
+    /// Reject plain-http API bases instead of only warning about them
+    pub require_https: bool,
+    /// Don't create the sample character on an empty database
+    pub skip_seed: bool,
+    /// Time source for message ids, swap in a `FixedClock` for reproducible timestamps
+    pub clock: Arc<dyn Clock>,
+    /// Which plugins may load, by the name they report on startup
+    pub plugin_filter: PluginFilter,
+    /// Accept plugin uploads. Turn off when the plugins directory is read-only;
+    /// plugins already placed there are still discovered.
+    pub allow_plugin_install: bool,
+    /// Appends and edits with longer content are rejected, longer tool results are cut short
+    pub max_message_length: usize,
+    /// Character bundles bigger than this many bytes are rejected
+    pub max_import_size: usize,
+    /// Import every character card in this directory on startup
+    pub import_cards_dir: Option<PathBuf>,
+    /// How loaded plugins are checked for having stalled
+    pub plugin_health: PluginHealthCheck,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pricing: HashMap::new(),
+            debug_provider_dir: None,
+            require_https: false,
+            skip_seed: false,
+            clock: Arc::new(SystemClock),
+            plugin_filter: PluginFilter::default(),
+            allow_plugin_install: true,
+            max_message_length: DEFAULT_MAX_MESSAGE_LENGTH,
+            max_import_size: DEFAULT_MAX_IMPORT_SIZE,
+            import_cards_dir: None,
+            plugin_health: PluginHealthCheck::default(),
+        }
+    }
+}
+
+/// Plugin names that may or may not be loaded. The denylist wins over the allowlist.
+#[derive(Clone, Debug, Default)]
+pub struct PluginFilter {
+    /// When set, only these plugins load
+    pub allow: Option<HashSet<String>>,
+    pub deny: HashSet<String>,
+}
+
+impl PluginFilter {
+    pub fn allows(&self, name: &str) -> bool {
+        !self.deny.contains(name) && self.allow.as_ref().is_none_or(|allow| allow.contains(name))
+    }
+}
+
+/// Periodic `ping` of every loaded plugin. A plugin that misses `max_missed` pings in a row
+/// is considered hung: its process is killed and its tools go away.
+#[derive(Clone, Debug)]
+pub struct PluginHealthCheck {
+    /// Time between pings, `None` to never ping
+    pub interval: Option<Duration>,
+    /// How long a plugin has to answer a ping
+    pub timeout: Duration,
+    pub max_missed: u32,
+    /// Start a plugin that was unloaded this way again from the same file
+    pub restart: bool,
+}
+
+impl Default for PluginHealthCheck {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(30)),
+            timeout: Duration::from_secs(10),
+            max_missed: 3,
+            restart: false,
+        }
+    }
+}
+
+/// Load a price table from a JSON file of the form `{ "model": { "prompt": 0.5, "completion": 1.5 } }`
+pub fn load_pricing(
+    path: impl AsRef<Path>,
+) -> Result<HashMap<String, ModelPricing>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
@@ -1,23 +1,238 @@
-use crate::dbs::{Database, DbError, DbResult};
+use crate::dbs::{Database, DbError, DbResult, MessageLocks};
 use async_trait::async_trait;
 use serde_json::Value;
-use shared::models::{Character, Chat, ChatMessage, ChatParticipant};
-use sqlx::{Pool, Row, Sqlite, sqlite::SqlitePoolOptions};
+use shared::models::{
+    CHAT_PREVIEW_LENGTH, CardMetadata, Character, Chat, ChatMessage, ChatParticipant, GreetingMode,
+    LoreEntry, ModelUsage, ResponseLength,
+};
+use shared::text::to_snippet;
+use sqlx::{
+    Pool, Row, Sqlite,
+    sqlite::{
+        SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow, SqliteSynchronous,
+    },
+};
+use std::str::FromStr;
+use std::time::Duration;
 use uuid::Uuid;
 
+/// Connections in the pool. WAL allows any number of readers next to the one writer.
+const MAX_CONNECTIONS: u32 = 8;
+/// How long a write waits for the database lock before failing with "database is locked"
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Chats with their message counts in one query; callers add the filter and `GROUP BY c.id`
+const CHAT_LIST_QUERY: &str = "SELECT c.id, c.character_id, c.participants, c.scenario_override, c.tags, COUNT(m.id) AS message_count, (SELECT lm.content FROM messages lm WHERE lm.chat_id = c.id ORDER BY lm.created_at DESC, lm.id DESC LIMIT 1) AS last_message FROM chats c LEFT JOIN messages m ON m.chat_id = c.id";
+
+const CHARACTER_COLUMNS: &str = "id, name, description, personality, scenario, first_message, example_messages, is_favorite, response_length, reasoning_effort, creator, creator_notes, tags, character_version, alternate_greetings, greeting_mode";
+
+fn character_from_row(row: &SqliteRow) -> Character {
+    Character {
+        id: Uuid::parse_str(row.get("id")).unwrap_or_default(),
+        name: row.get("name"),
+        description: row.get("description"),
+        personality: row.get("personality"),
+        scenario: row.get("scenario"),
+        first_message: row.get("first_message"),
+        example_messages: row.get("example_messages"),
+        alternate_greetings: serde_json::from_value(row.get::<Value, _>("alternate_greetings"))
+            .unwrap_or_default(),
+        greeting_mode: row
+            .get::<Option<String>, _>("greeting_mode")
+            .as_deref()
+            .and_then(GreetingMode::parse)
+            .unwrap_or_default(),
+        is_favorite: row.get("is_favorite"),
+        response_length: row
+            .get::<Option<String>, _>("response_length")
+            .as_deref()
+            .and_then(ResponseLength::parse),
+        reasoning_effort: row.get("reasoning_effort"),
+        metadata: CardMetadata {
+            creator: row.get("creator"),
+            creator_notes: row.get("creator_notes"),
+            tags: serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default(),
+            character_version: row.get("character_version"),
+        },
+    }
+}
+
+const MESSAGE_COLUMNS: &str = "id, role, content, sender_id, alternatives, alternative_senders, active_index, keep_in_context, hidden_from_context, tool_calls, tool_call_id, created_at, reasoning, alternative_reasoning";
+
+fn message_from_row(row: &SqliteRow) -> ChatMessage {
+    let alts_val: Value = row.get("alternatives");
+    let senders_val: Value = row.get("alternative_senders");
+    let id_str: String = row.get("id");
+    let sender_id_str: Option<String> = row.get("sender_id");
+
+    ChatMessage {
+        id: Uuid::parse_str(&id_str).unwrap_or_default(),
+        role: row.get("role"),
+        content: row.get("content"),
+        sender_id: sender_id_str.and_then(|s| Uuid::parse_str(&s).ok()),
+        alternatives: serde_json::from_value(alts_val).unwrap_or_default(),
+        alternative_senders: serde_json::from_value(senders_val).unwrap_or_default(),
+        active_index: row.get::<i64, _>("active_index") as usize,
+        tool_calls: row
+            .get::<Option<Value>, _>("tool_calls")
+            .and_then(|v| serde_json::from_value(v).ok()),
+        tool_call_id: row.get("tool_call_id"),
+        keep_in_context: row.get("keep_in_context"),
+        hidden_from_context: row.get("hidden_from_context"),
+        created_at: row.get("created_at"),
+        reasoning: row.get("reasoning"),
+        alternative_reasoning: serde_json::from_value(row.get::<Value, _>("alternative_reasoning"))
+            .unwrap_or_default(),
+    }
+}
+
+const LORE_COLUMNS: &str = "id, name, keys, content, enabled";
+
+fn lore_entry_from_row(row: &SqliteRow) -> LoreEntry {
+    LoreEntry {
+        id: Uuid::parse_str(row.get("id")).unwrap_or_default(),
+        name: row.get("name"),
+        keys: serde_json::from_value(row.get::<Value, _>("keys")).unwrap_or_default(),
+        content: row.get("content"),
+        enabled: row.get("enabled"),
+    }
+}
+
+/// Insert a message row, either straight on the pool or inside a transaction.
+/// Returns false without touching anything if a message with that id already exists.
+async fn insert_message<'e>(
+    executor: impl sqlx::Executor<'e, Database = Sqlite>,
+    chat_id: Uuid,
+    message: ChatMessage,
+) -> DbResult<bool> {
+    // The chat_id foreign key rejects messages for chats that don't exist
+    let alts_json = serde_json::to_value(&message.alternatives)?;
+    let senders_json = serde_json::to_value(&message.alternative_senders)?;
+    let reasoning_json = serde_json::to_value(&message.alternative_reasoning)?;
+    let tool_calls_json = message
+        .tool_calls
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+    let sender_id = message.sender_id.map(|u| u.to_string());
+
+    let result = sqlx::query(
+        "INSERT INTO messages (id, chat_id, role, content, sender_id, alternatives, alternative_senders, active_index, keep_in_context, hidden_from_context, tool_calls, tool_call_id, created_at, reasoning, alternative_reasoning) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(message.id.to_string())
+    .bind(chat_id.to_string())
+    .bind(message.role)
+    .bind(message.content)
+    .bind(sender_id)
+    .bind(alts_json)
+    .bind(senders_json)
+    .bind(message.active_index as i64)
+    .bind(message.keep_in_context)
+    .bind(message.hidden_from_context)
+    .bind(tool_calls_json)
+    .bind(message.tool_call_id)
+    .bind(message.created_at)
+    .bind(message.reasoning)
+    .bind(reasoning_json)
+    .execute(executor)
+    .await
+    .map_err(|e| DbError::missing_parent(e, || format!("Chat {} not found", chat_id)))?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn insert_character<'e>(
+    executor: impl sqlx::Executor<'e, Database = Sqlite>,
+    character: Character,
+) -> DbResult<()> {
+    sqlx::query(
+        "INSERT INTO characters (id, name, description, personality, scenario, first_message, example_messages, is_favorite, response_length, reasoning_effort, creator, creator_notes, tags, character_version, alternate_greetings, greeting_mode) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(character.id.to_string())
+    .bind(character.name)
+    .bind(character.description)
+    .bind(character.personality)
+    .bind(character.scenario)
+    .bind(character.first_message)
+    .bind(character.example_messages)
+    .bind(character.is_favorite)
+    .bind(character.response_length.map(|l| l.as_str()))
+    .bind(character.reasoning_effort)
+    .bind(character.metadata.creator)
+    .bind(character.metadata.creator_notes)
+    .bind(serde_json::to_value(&character.metadata.tags)?)
+    .bind(character.metadata.character_version)
+    .bind(serde_json::to_value(&character.alternate_greetings)?)
+    .bind(character.greeting_mode.as_str())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Insert a chat and its messages. Several statements, so run it inside a transaction.
+async fn insert_chat(conn: &mut sqlx::SqliteConnection, chat: Chat) -> DbResult<()> {
+    let participants_json = serde_json::to_value(&chat.participants)?;
+    sqlx::query(
+        "INSERT INTO chats (id, character_id, participants, scenario_override, tags) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(chat.id.to_string())
+    .bind(chat.character_id.to_string())
+    .bind(participants_json)
+    .bind(chat.scenario_override)
+    .bind(serde_json::to_value(&chat.tags)?)
+    .execute(&mut *conn)
+    .await?;
+
+    for msg in chat.messages {
+        insert_message(&mut *conn, chat.id, msg).await?;
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct LocalDatabase {
     pool: Pool<Sqlite>,
+    message_locks: MessageLocks,
+    ephemeral: bool,
 }
 
 impl LocalDatabase {
     pub async fn new(database_url: &str) -> Self {
+        // Applied to every connection the pool opens
+        let options = SqliteConnectOptions::from_str(database_url)
+            .expect("Invalid database url")
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT);
         let pool = SqlitePoolOptions::new()
-            .connect(database_url)
+            .max_connections(MAX_CONNECTIONS)
+            .connect_with(options)
             .await
             .expect("Failed to connect to database");
+        Self::open(pool, false).await
+    }
+
+    /// A database held only in memory, gone when the server stops
+    pub async fn in_memory() -> Self {
+        let options =
+            SqliteConnectOptions::from_str("sqlite::memory:").expect("Invalid database url");
+        // The data lives as long as a connection does, so keep exactly one open for good
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+            .expect("Failed to open in-memory database");
+        Self::open(pool, true).await
+    }
 
-        let db = Self { pool };
+    async fn open(pool: Pool<Sqlite>, ephemeral: bool) -> Self {
+        let db = Self {
+            pool,
+            message_locks: MessageLocks::default(),
+            ephemeral,
+        };
         db.init().await;
         db
     }
@@ -65,50 +280,139 @@ impl LocalDatabase {
         .execute(&self.pool)
         .await
         .expect("Failed to create messages table");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS chat_stats (
+                id TEXT PRIMARY KEY,
+                chat_id TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                FOREIGN KEY(chat_id) REFERENCES chats(id)
+            )",
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to create chat_stats table");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS lore_entries (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                keys JSON NOT NULL,
+                content TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 1
+            )",
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to create lore_entries table");
+
+        self.add_column("characters", "is_favorite INTEGER NOT NULL DEFAULT 0")
+            .await;
+        self.add_column("messages", "alternative_senders JSON NOT NULL DEFAULT '[]'")
+            .await;
+        self.add_column("characters", "response_length TEXT").await;
+        self.add_column("characters", "reasoning_effort TEXT").await;
+        self.add_column("chats", "scenario_override TEXT").await;
+        self.add_column("characters", "creator TEXT").await;
+        self.add_column("characters", "creator_notes TEXT").await;
+        self.add_column("characters", "tags JSON NOT NULL DEFAULT '[]'")
+            .await;
+        self.add_column("characters", "character_version TEXT")
+            .await;
+        self.add_column(
+            "characters",
+            "alternate_greetings JSON NOT NULL DEFAULT '[]'",
+        )
+        .await;
+        self.add_column("characters", "greeting_mode TEXT").await;
+        self.add_column("messages", "keep_in_context INTEGER NOT NULL DEFAULT 0")
+            .await;
+        self.add_column("messages", "hidden_from_context INTEGER NOT NULL DEFAULT 0")
+            .await;
+        self.add_column("messages", "tool_calls JSON").await;
+        self.add_column("messages", "tool_call_id TEXT").await;
+        self.add_column("chats", "tags JSON NOT NULL DEFAULT '[]'")
+            .await;
+        self.add_column(
+            "messages",
+            "created_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z'",
+        )
+        .await;
+        self.add_column("messages", "reasoning TEXT").await;
+        self.add_column(
+            "messages",
+            "alternative_reasoning JSON NOT NULL DEFAULT '[]'",
+        )
+        .await;
+    }
+
+    /// Change a chat's tags in one transaction, so concurrent edits don't undo each other
+    async fn update_chat_tags(
+        &self,
+        chat_id: Uuid,
+        update: impl FnOnce(&mut Vec<String>),
+    ) -> DbResult<Vec<String>> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query("SELECT tags FROM chats WHERE id = ?")
+            .bind(chat_id.to_string())
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| DbError::NotFound(format!("Chat {} not found", chat_id)))?;
+        let mut tags: Vec<String> =
+            serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default();
+        update(&mut tags);
+        sqlx::query("UPDATE chats SET tags = ? WHERE id = ?")
+            .bind(serde_json::to_value(&tags)?)
+            .bind(chat_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(tags)
+    }
+
+    /// Add a column introduced after the table was first created
+    async fn add_column(&self, table: &str, definition: &str) {
+        let result = sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {}", table, definition))
+            .execute(&self.pool)
+            .await;
+        match result {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.message().contains("duplicate column") => {}
+            Err(e) => panic!("Failed to add column to {}: {}", table, e),
+        }
     }
 }
 
 #[async_trait]
 impl Database for LocalDatabase {
+    fn is_ephemeral(&self) -> bool {
+        self.ephemeral
+    }
+
     async fn get_characters(&self) -> DbResult<Vec<Character>> {
-        let rows = sqlx::query(
-            "SELECT id, name, description, personality, scenario, first_message, example_messages FROM characters"
-        )
-        .fetch_all(&self.pool)
-        .await?;
+        let rows = sqlx::query(&format!("SELECT {} FROM characters", CHARACTER_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
 
         Ok(rows
             .into_iter()
-            .map(|row| Character {
-                id: Uuid::parse_str(row.get("id")).unwrap_or_default(),
-                name: row.get("name"),
-                description: row.get("description"),
-                personality: row.get("personality"),
-                scenario: row.get("scenario"),
-                first_message: row.get("first_message"),
-                example_messages: row.get("example_messages"),
-            })
+            .map(|row| character_from_row(&row))
             .collect())
     }
 
     async fn get_character(&self, character_id: Uuid) -> DbResult<Character> {
-        let row = sqlx::query(
-            "SELECT id, name, description, personality, scenario, first_message, example_messages FROM characters WHERE id = ?",
-        )
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM characters WHERE id = ?",
+            CHARACTER_COLUMNS
+        ))
         .bind(character_id.to_string())
         .fetch_optional(&self.pool)
         .await?;
 
         match row {
-            Some(row) => Ok(Character {
-                id: Uuid::parse_str(row.get("id")).unwrap_or_default(),
-                name: row.get("name"),
-                description: row.get("description"),
-                personality: row.get("personality"),
-                scenario: row.get("scenario"),
-                first_message: row.get("first_message"),
-                example_messages: row.get("example_messages"),
-            }),
+            Some(row) => Ok(character_from_row(&row)),
             None => Err(DbError::NotFound(format!(
                 "Character {} not found",
                 character_id
@@ -116,17 +420,50 @@ impl Database for LocalDatabase {
         }
     }
 
-    async fn get_chats(&self, character_id: Option<Uuid>) -> DbResult<Vec<Chat>> {
-        let rows = if let Some(cid) = character_id {
-            sqlx::query("SELECT id, character_id, participants FROM chats WHERE character_id = ?")
-                .bind(cid.to_string())
-                .fetch_all(&self.pool)
-                .await?
+    async fn get_characters_by_ids(&self, character_ids: &[Uuid]) -> DbResult<Vec<Character>> {
+        if character_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; character_ids.len()].join(", ");
+        let sql = format!(
+            "SELECT {} FROM characters WHERE id IN ({})",
+            CHARACTER_COLUMNS, placeholders
+        );
+        let mut query = sqlx::query(&sql);
+        for id in character_ids {
+            query = query.bind(id.to_string());
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+
+        Ok(rows.iter().map(character_from_row).collect())
+    }
+
+    async fn get_chats(
+        &self,
+        character_id: Option<Uuid>,
+        tag: Option<&str>,
+    ) -> DbResult<Vec<Chat>> {
+        let mut conditions = Vec::new();
+        if character_id.is_some() {
+            conditions.push("c.character_id = ?");
+        }
+        if tag.is_some() {
+            conditions.push("EXISTS (SELECT 1 FROM json_each(c.tags) WHERE json_each.value = ?)");
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
         } else {
-            sqlx::query("SELECT id, character_id, participants FROM chats")
-                .fetch_all(&self.pool)
-                .await?
+            format!(" WHERE {}", conditions.join(" AND "))
         };
+        let sql = format!("{}{} GROUP BY c.id", CHAT_LIST_QUERY, filter);
+        let mut query = sqlx::query(&sql);
+        if let Some(cid) = character_id {
+            query = query.bind(cid.to_string());
+        }
+        if let Some(tag) = tag {
+            query = query.bind(tag);
+        }
+        let rows = query.fetch_all(&self.pool).await?;
 
         let mut chats = Vec::new();
         for row in rows {
@@ -141,16 +478,24 @@ impl Database for LocalDatabase {
                 character_id: Uuid::parse_str(&char_id_str).unwrap_or_default(),
                 messages: Vec::new(),
                 participants,
+                message_count: row.get::<i64, _>("message_count") as usize,
+                scenario_override: row.get("scenario_override"),
+                tags: serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default(),
+                preview: row
+                    .get::<Option<String>, _>("last_message")
+                    .map(|m| to_snippet(&m, CHAT_PREVIEW_LENGTH)),
             });
         }
         Ok(chats)
     }
 
     async fn get_chat(&self, chat_id: Uuid) -> DbResult<Chat> {
-        let row = sqlx::query("SELECT id, character_id, participants FROM chats WHERE id = ?")
-            .bind(chat_id.to_string())
-            .fetch_optional(&self.pool)
-            .await?;
+        let row = sqlx::query(
+            "SELECT id, character_id, participants, scenario_override, tags FROM chats WHERE id = ?",
+        )
+        .bind(chat_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
 
         match row {
             Some(row) => {
@@ -164,8 +509,12 @@ impl Database for LocalDatabase {
                 Ok(Chat {
                     id: chat_id,
                     character_id: Uuid::parse_str(&char_id_str).unwrap_or_default(),
+                    message_count: messages.len(),
                     messages,
                     participants,
+                    scenario_override: row.get("scenario_override"),
+                    tags: serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default(),
+                    preview: None,
                 })
             }
             None => Err(DbError::NotFound(format!("Chat {} not found", chat_id))),
@@ -179,70 +528,178 @@ impl Database for LocalDatabase {
     }
 
     async fn create_character(&self, character: Character) -> DbResult<()> {
-        sqlx::query(
-            "INSERT INTO characters (id, name, description, personality, scenario, first_message, example_messages) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        insert_character(&self.pool, character).await
+    }
+
+    async fn update_character(&self, character: Character) -> DbResult<()> {
+        let result = sqlx::query(
+            "UPDATE characters SET name = ?, description = ?, personality = ?, scenario = ?, first_message = ?, example_messages = ?, response_length = ?, reasoning_effort = ?, creator = ?, creator_notes = ?, tags = ?, character_version = ?, alternate_greetings = ?, greeting_mode = ? WHERE id = ?",
         )
-        .bind(character.id.to_string())
         .bind(character.name)
         .bind(character.description)
         .bind(character.personality)
         .bind(character.scenario)
         .bind(character.first_message)
         .bind(character.example_messages)
+        .bind(character.response_length.map(|l| l.as_str()))
+        .bind(character.reasoning_effort)
+        .bind(character.metadata.creator)
+        .bind(character.metadata.creator_notes)
+        .bind(serde_json::to_value(&character.metadata.tags)?)
+        .bind(character.metadata.character_version)
+        .bind(serde_json::to_value(&character.alternate_greetings)?)
+        .bind(character.greeting_mode.as_str())
+        .bind(character.id.to_string())
         .execute(&self.pool)
         .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Character {} not found",
+                character.id
+            )));
+        }
         Ok(())
     }
 
-    async fn create_chat(&self, chat: Chat) -> DbResult<()> {
-        let participants_json = serde_json::to_value(&chat.participants)?;
-        sqlx::query("INSERT INTO chats (id, character_id, participants) VALUES (?, ?, ?)")
-            .bind(chat.id.to_string())
-            .bind(chat.character_id.to_string())
-            .bind(participants_json)
+    async fn set_favorite(&self, character_id: Uuid, is_favorite: bool) -> DbResult<()> {
+        let result = sqlx::query("UPDATE characters SET is_favorite = ? WHERE id = ?")
+            .bind(is_favorite)
+            .bind(character_id.to_string())
             .execute(&self.pool)
             .await?;
-
-        // Also insert initial messages if any
-        for msg in chat.messages {
-            self.append_message(chat.id, msg).await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Character {} not found",
+                character_id
+            )));
         }
         Ok(())
     }
 
-    async fn delete_character(&self, character_id: Uuid) -> DbResult<()> {
-        // Cascading delete would be nice, but for now manual
-        // First get all chats
-        let chats = self.get_chats(Some(character_id)).await?;
+    async fn create_chat(&self, chat: Chat) -> DbResult<()> {
+        // The chat and its opening messages are created together or not at all
+        let mut tx = self.pool.begin().await?;
+        insert_chat(&mut tx, chat).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn import_character(&self, character: Character, chats: Vec<Chat>) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_character(&mut *tx, character).await?;
         for chat in chats {
-            // Delete messages for chat
-            sqlx::query("DELETE FROM messages WHERE chat_id = ?")
-                .bind(chat.id.to_string())
-                .execute(&self.pool)
-                .await?;
-            // Delete chat
-            sqlx::query("DELETE FROM chats WHERE id = ?")
-                .bind(chat.id.to_string())
+            insert_chat(&mut tx, chat).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn move_chat(
+        &self,
+        chat_id: Uuid,
+        character_id: Uuid,
+        participants: &[ChatParticipant],
+    ) -> DbResult<()> {
+        let participants_json = serde_json::to_value(participants)?;
+        let result =
+            sqlx::query("UPDATE chats SET character_id = ?, participants = ? WHERE id = ?")
+                .bind(character_id.to_string())
+                .bind(participants_json)
+                .bind(chat_id.to_string())
                 .execute(&self.pool)
                 .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("Chat {} not found", chat_id)));
         }
+        Ok(())
+    }
+
+    async fn set_participants(
+        &self,
+        chat_id: Uuid,
+        participants: &[ChatParticipant],
+    ) -> DbResult<()> {
+        let participants_json = serde_json::to_value(participants)?;
+        let result = sqlx::query("UPDATE chats SET participants = ? WHERE id = ?")
+            .bind(participants_json)
+            .bind(chat_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("Chat {} not found", chat_id)));
+        }
+        Ok(())
+    }
+
+    async fn set_scenario_override(&self, chat_id: Uuid, scenario: Option<String>) -> DbResult<()> {
+        let result = sqlx::query("UPDATE chats SET scenario_override = ? WHERE id = ?")
+            .bind(scenario)
+            .bind(chat_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("Chat {} not found", chat_id)));
+        }
+        Ok(())
+    }
+
+    async fn add_chat_tag(&self, chat_id: Uuid, tag: &str) -> DbResult<Vec<String>> {
+        self.update_chat_tags(chat_id, |tags| {
+            if !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_string());
+            }
+        })
+        .await
+    }
 
+    async fn remove_chat_tag(&self, chat_id: Uuid, tag: &str) -> DbResult<Vec<String>> {
+        self.update_chat_tags(chat_id, |tags| tags.retain(|t| t != tag))
+            .await
+    }
+
+    async fn delete_character(&self, character_id: Uuid) -> DbResult<()> {
+        // The character goes with all its chats, or nothing goes at all
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM messages WHERE chat_id IN (SELECT id FROM chats WHERE character_id = ?)",
+        )
+        .bind(character_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "DELETE FROM chat_stats WHERE chat_id IN (SELECT id FROM chats WHERE character_id = ?)",
+        )
+        .bind(character_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM chats WHERE character_id = ?")
+            .bind(character_id.to_string())
+            .execute(&mut *tx)
+            .await?;
         sqlx::query("DELETE FROM characters WHERE id = ?")
             .bind(character_id.to_string())
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await?;
         Ok(())
     }
 
     async fn delete_chat(&self, chat_id: Uuid) -> DbResult<()> {
+        // Messages go first, all in one transaction so a failure can't orphan them
+        let mut tx = self.pool.begin().await?;
         sqlx::query("DELETE FROM messages WHERE chat_id = ?")
             .bind(chat_id.to_string())
-            .execute(&self.pool)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM chat_stats WHERE chat_id = ?")
+            .bind(chat_id.to_string())
+            .execute(&mut *tx)
             .await?;
         sqlx::query("DELETE FROM chats WHERE id = ?")
             .bind(chat_id.to_string())
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -255,23 +712,22 @@ impl Database for LocalDatabase {
     }
 
     async fn append_message(&self, chat_id: Uuid, message: ChatMessage) -> DbResult<()> {
-        // Ensure chat exists? Optional but good practice.
-        // For now, raw insert.
-        let alts_json = serde_json::to_value(&message.alternatives)?;
-        let sender_id = message.sender_id.map(|u| u.to_string());
-
-        sqlx::query(
-            "INSERT INTO messages (id, chat_id, role, content, sender_id, alternatives, active_index) VALUES (?, ?, ?, ?, ?, ?, ?)",
-        )
-        .bind(message.id.to_string())
-        .bind(chat_id.to_string())
-        .bind(message.role)
-        .bind(message.content)
-        .bind(sender_id)
-        .bind(alts_json)
-        .bind(message.active_index as i64)
-        .execute(&self.pool)
-        .await?;
+        let message_id = message.id;
+        if insert_message(&self.pool, chat_id, message).await? {
+            return Ok(());
+        }
+        // Already there from an earlier attempt, as long as it's in the same chat
+        let existing = sqlx::query("SELECT 1 FROM messages WHERE id = ? AND chat_id = ?")
+            .bind(message_id.to_string())
+            .bind(chat_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        if existing.is_none() {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found in chat {}",
+                message_id, chat_id
+            )));
+        }
         Ok(())
     }
 
@@ -280,10 +736,14 @@ impl Database for LocalDatabase {
         _chat_id: Uuid,
         message_id: Uuid,
         content: String,
+        sender_id: Option<Uuid>,
+        reasoning: Option<String>,
     ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
         if let Some(mut msg) = self.get_message_by_id(message_id).await? {
-            msg.alternatives.push(content);
+            msg.push_alternative(content, sender_id);
             msg.active_index = msg.alternatives.len();
+            msg.set_variant_reasoning(msg.active_index, reasoning);
             self.save_message(message_id, msg).await?;
             Ok(())
         } else {
@@ -294,12 +754,41 @@ impl Database for LocalDatabase {
         }
     }
 
+    async fn update_alternative(
+        &self,
+        _chat_id: Uuid,
+        message_id: Uuid,
+        index: usize,
+        content: String,
+        sender_id: Option<Uuid>,
+        reasoning: Option<String>,
+    ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
+        let Some(mut msg) = self.get_message_by_id(message_id).await? else {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        };
+        if !msg.replace_variant(index, content, sender_id) {
+            return Err(DbError::NotFound(format!(
+                "Alternative {} of message {} not found",
+                index, message_id
+            )));
+        }
+        msg.set_variant_reasoning(index, reasoning);
+        msg.active_index = index;
+        self.save_message(message_id, msg).await?;
+        Ok(())
+    }
+
     async fn update_message(
         &self,
         _chat_id: Uuid,
         message_id: Uuid,
         content: String,
     ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
         if let Some(mut msg) = self.get_message_by_id(message_id).await? {
             if msg.active_index == 0 {
                 msg.content = content;
@@ -321,6 +810,7 @@ impl Database for LocalDatabase {
         message_id: Uuid,
         index: usize,
     ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
         if let Some(mut msg) = self.get_message_by_id(message_id).await? {
             if index < msg.variant_count() {
                 msg.active_index = index;
@@ -334,12 +824,113 @@ impl Database for LocalDatabase {
             )))
         }
     }
-}
 
-impl LocalDatabase {
-    async fn get_messages_for_chat(&self, chat_id: Uuid) -> DbResult<Vec<ChatMessage>> {
+    async fn set_keep_in_context(
+        &self,
+        chat_id: Uuid,
+        message_id: Uuid,
+        keep: bool,
+    ) -> DbResult<()> {
+        let result =
+            sqlx::query("UPDATE messages SET keep_in_context = ? WHERE id = ? AND chat_id = ?")
+                .bind(keep)
+                .bind(message_id.to_string())
+                .bind(chat_id.to_string())
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn set_hidden_from_context(
+        &self,
+        chat_id: Uuid,
+        message_id: Uuid,
+        hidden: bool,
+    ) -> DbResult<()> {
+        let result =
+            sqlx::query("UPDATE messages SET hidden_from_context = ? WHERE id = ? AND chat_id = ?")
+                .bind(hidden)
+                .bind(message_id.to_string())
+                .bind(chat_id.to_string())
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn replace_message(&self, chat_id: Uuid, message: ChatMessage) -> DbResult<()> {
+        let message_id = message.id;
+        let _guard = self.message_locks.lock(message_id).await;
+        let alts_json = serde_json::to_value(&message.alternatives)?;
+        let senders_json = serde_json::to_value(&message.alternative_senders)?;
+        let reasoning_json = serde_json::to_value(&message.alternative_reasoning)?;
+        let tool_calls_json = message
+            .tool_calls
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
+        let result = sqlx::query(
+            "UPDATE messages SET role = ?, content = ?, sender_id = ?, alternatives = ?, alternative_senders = ?, active_index = ?, keep_in_context = ?, hidden_from_context = ?, tool_calls = ?, tool_call_id = ?, reasoning = ?, alternative_reasoning = ? WHERE id = ? AND chat_id = ?",
+        )
+        .bind(message.role)
+        .bind(message.content)
+        .bind(message.sender_id.map(|u| u.to_string()))
+        .bind(alts_json)
+        .bind(senders_json)
+        .bind(message.active_index as i64)
+        .bind(message.keep_in_context)
+        .bind(message.hidden_from_context)
+        .bind(tool_calls_json)
+        .bind(message.tool_call_id)
+        .bind(message.reasoning)
+        .bind(reasoning_json)
+        .bind(message_id.to_string())
+        .bind(chat_id.to_string())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn record_usage(
+        &self,
+        chat_id: Uuid,
+        model: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+    ) -> DbResult<()> {
+        sqlx::query(
+            "INSERT INTO chat_stats (id, chat_id, model, prompt_tokens, completion_tokens) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(Uuid::now_v7().to_string())
+        .bind(chat_id.to_string())
+        .bind(model)
+        .bind(prompt_tokens as i64)
+        .bind(completion_tokens as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_usage(&self, chat_id: Uuid) -> DbResult<Vec<ModelUsage>> {
         let rows = sqlx::query(
-            "SELECT id, role, content, sender_id, alternatives, active_index FROM messages WHERE chat_id = ? ORDER BY id",
+            "SELECT model, SUM(prompt_tokens) AS prompt_tokens, SUM(completion_tokens) AS completion_tokens FROM chat_stats WHERE chat_id = ? GROUP BY model ORDER BY model",
         )
         .bind(chat_id.to_string())
         .fetch_all(&self.pool)
@@ -347,68 +938,542 @@ impl LocalDatabase {
 
         Ok(rows
             .into_iter()
-            .map(|row| {
-                let alts_val: Value = row.get("alternatives");
-                let alternatives: Vec<String> =
-                    serde_json::from_value(alts_val).unwrap_or_default();
-                let id_str: String = row.get("id");
-                let sender_id_str: Option<String> = row.get("sender_id");
-
-                ChatMessage {
-                    id: Uuid::parse_str(&id_str).unwrap_or_default(),
-                    role: row.get("role"),
-                    content: row.get("content"),
-                    sender_id: sender_id_str.and_then(|s| Uuid::parse_str(&s).ok()),
-                    alternatives,
-                    active_index: row.get::<i64, _>("active_index") as usize,
-                    tool_calls: None,
-                    tool_call_id: None,
-                }
+            .map(|row| ModelUsage {
+                model: row.get("model"),
+                prompt_tokens: row.get::<i64, _>("prompt_tokens") as u64,
+                completion_tokens: row.get::<i64, _>("completion_tokens") as u64,
+                estimated_cost: None,
             })
             .collect())
     }
 
-    async fn get_message_by_id(&self, message_id: Uuid) -> DbResult<Option<ChatMessage>> {
-        let row = sqlx::query(
-            "SELECT id, role, content, sender_id, alternatives, active_index FROM messages WHERE id = ?",
+    async fn get_lore_entries(&self) -> DbResult<Vec<LoreEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM lore_entries ORDER BY name",
+            LORE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(lore_entry_from_row).collect())
+    }
+
+    async fn create_lore_entry(&self, entry: LoreEntry) -> DbResult<()> {
+        sqlx::query(
+            "INSERT INTO lore_entries (id, name, keys, content, enabled) VALUES (?, ?, ?, ?, ?)",
         )
-        .bind(message_id.to_string())
-        .fetch_optional(&self.pool)
+        .bind(entry.id.to_string())
+        .bind(entry.name)
+        .bind(serde_json::to_value(&entry.keys)?)
+        .bind(entry.content)
+        .bind(entry.enabled)
+        .execute(&self.pool)
         .await?;
+        Ok(())
+    }
 
-        let row = match row {
-            Some(r) => r,
-            None => return Ok(None),
-        };
+    async fn update_lore_entry(&self, entry: LoreEntry) -> DbResult<()> {
+        let result = sqlx::query(
+            "UPDATE lore_entries SET name = ?, keys = ?, content = ?, enabled = ? WHERE id = ?",
+        )
+        .bind(entry.name)
+        .bind(serde_json::to_value(&entry.keys)?)
+        .bind(entry.content)
+        .bind(entry.enabled)
+        .bind(entry.id.to_string())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Lore entry {} not found",
+                entry.id
+            )));
+        }
+        Ok(())
+    }
 
-        let alts_val: Value = row.get("alternatives");
-        let alternatives: Vec<String> = serde_json::from_value(alts_val).unwrap_or_default();
-        let id_str: String = row.get("id");
-        let sender_id_str: Option<String> = row.get("sender_id");
+    async fn delete_lore_entry(&self, entry_id: Uuid) -> DbResult<()> {
+        let result = sqlx::query("DELETE FROM lore_entries WHERE id = ?")
+            .bind(entry_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Lore entry {} not found",
+                entry_id
+            )));
+        }
+        Ok(())
+    }
+}
 
-        Ok(Some(ChatMessage {
-            id: Uuid::parse_str(&id_str).unwrap_or_default(),
-            role: row.get("role"),
-            content: row.get("content"),
-            sender_id: sender_id_str.and_then(|s| Uuid::parse_str(&s).ok()),
-            alternatives,
-            active_index: row.get::<i64, _>("active_index") as usize,
-            tool_calls: None,
-            tool_call_id: None,
-        }))
+impl LocalDatabase {
+    async fn get_messages_for_chat(&self, chat_id: Uuid) -> DbResult<Vec<ChatMessage>> {
+        let rows = sqlx::query(&format!(
+            // Older messages all read as the epoch and fall back to their time-ordered ids
+            "SELECT {} FROM messages WHERE chat_id = ? ORDER BY created_at, id",
+            MESSAGE_COLUMNS
+        ))
+        .bind(chat_id.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    async fn get_message_by_id(&self, message_id: Uuid) -> DbResult<Option<ChatMessage>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM messages WHERE id = ?",
+            MESSAGE_COLUMNS
+        ))
+        .bind(message_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(message_from_row))
     }
 
     async fn save_message(&self, message_id: Uuid, msg: ChatMessage) -> DbResult<()> {
         let alts_json = serde_json::to_value(&msg.alternatives)?;
+        let senders_json = serde_json::to_value(&msg.alternative_senders)?;
+        let reasoning_json = serde_json::to_value(&msg.alternative_reasoning)?;
         sqlx::query(
-            "UPDATE messages SET content = ?, alternatives = ?, active_index = ? WHERE id = ?",
+            "UPDATE messages SET content = ?, sender_id = ?, alternatives = ?, alternative_senders = ?, active_index = ?, reasoning = ?, alternative_reasoning = ? WHERE id = ?",
         )
         .bind(msg.content)
+        .bind(msg.sender_id.map(|u| u.to_string()))
         .bind(alts_json)
+        .bind(senders_json)
         .bind(msg.active_index as i64)
+        .bind(msg.reasoning)
+        .bind(reasoning_json)
         .bind(message_id.to_string())
         .execute(&self.pool)
         .await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seed::seed_sample_character;
+    use shared::models::{ChatStats, ModelPricing};
+    use std::collections::HashMap;
+
+    fn chat_with(character_id: Uuid, contents: &[&str]) -> Chat {
+        Chat {
+            id: Uuid::now_v7(),
+            character_id,
+            messages: contents
+                .iter()
+                .map(|c| ChatMessage::new("user", *c))
+                .collect(),
+            participants: Vec::new(),
+            message_count: 0,
+            scenario_override: None,
+            tags: Vec::new(),
+            preview: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn the_sample_character_is_seeded_only_into_an_empty_database() {
+        let db = LocalDatabase::in_memory().await;
+        assert!(seed_sample_character(&db).await.unwrap());
+        assert!(!seed_sample_character(&db).await.unwrap());
+        let characters = db.get_characters().await.unwrap();
+        assert_eq!(characters.len(), 1);
+        assert_eq!(characters[0].name, "Assistant");
+
+        let db = LocalDatabase::in_memory().await;
+        let mut own = characters[0].clone();
+        own.id = Uuid::now_v7();
+        own.name = "Mine".to_string();
+        db.create_character(own).await.unwrap();
+        assert!(!seed_sample_character(&db).await.unwrap());
+        assert_eq!(db.get_characters().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_regenerations_keep_every_alternative() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let chat = chat_with(db.get_characters().await.unwrap()[0].id, &["Hi"]);
+        db.create_chat(chat.clone()).await.unwrap();
+        let message_id = chat.messages[0].id;
+
+        let regenerations = (0..8).map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.append_alternative(chat.id, message_id, format!("Take {}", i), None, None)
+                    .await
+            })
+        });
+        for done in futures::future::join_all(regenerations).await {
+            done.unwrap().unwrap();
+        }
+
+        let message = db.get_message_by_id(message_id).await.unwrap().unwrap();
+        let mut alternatives = message.alternatives.clone();
+        alternatives.sort();
+        let expected: Vec<_> = (0..8).map(|i| format!("Take {}", i)).collect();
+        assert_eq!(alternatives, expected);
+        assert_eq!(message.alternative_senders.len(), 8);
+        assert_eq!(message.active_index, 8);
+    }
+
+    #[tokio::test]
+    async fn messages_for_a_missing_chat_are_turned_away() {
+        let db = LocalDatabase::in_memory().await;
+        let missing = Uuid::now_v7();
+        let message = ChatMessage::new("user", "Anyone there?");
+
+        let error = db
+            .append_message(missing, message.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, DbError::NotFound(_)));
+        assert!(db.get_message_by_id(message.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_database_file_takes_concurrent_writes_and_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite:{}?mode=rwc", dir.path().join("renoma.db").display());
+        let db = LocalDatabase::new(&url).await;
+        let mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        seed_sample_character(&db).await.unwrap();
+        let character_id = db.get_characters().await.unwrap()[0].id;
+        let writes = (0..16).map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                let chat = chat_with(character_id, &["one", "two", "three"]);
+                db.create_chat(chat.clone()).await?;
+                db.append_message(chat.id, ChatMessage::new("user", "four"))
+                    .await
+            })
+        });
+        let reads = (0..16).map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move { db.get_chats(None, None).await.map(|_| ()) })
+        });
+        let (writes, reads) = futures::future::join(
+            futures::future::join_all(writes),
+            futures::future::join_all(reads),
+        )
+        .await;
+        for done in writes.into_iter().chain(reads) {
+            done.unwrap().unwrap();
+        }
+
+        let chats = db.get_chats(None, None).await.unwrap();
+        assert_eq!(chats.len(), 16);
+        assert!(chats.iter().all(|c| c.message_count == 4));
+    }
+
+    #[tokio::test]
+    async fn messages_from_before_timestamps_read_as_the_epoch_in_id_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite:{}?mode=rwc", dir.path().join("renoma.db").display());
+        // The tables as the first release created them
+        let old = sqlx::SqlitePool::connect(&url).await.unwrap();
+        for statement in [
+            "CREATE TABLE characters (id TEXT PRIMARY KEY, name TEXT NOT NULL, description TEXT NOT NULL, personality TEXT NOT NULL, scenario TEXT NOT NULL, first_message TEXT NOT NULL, example_messages TEXT NOT NULL)",
+            "CREATE TABLE chats (id TEXT PRIMARY KEY, character_id TEXT NOT NULL, participants JSON NOT NULL)",
+            "CREATE TABLE messages (id TEXT PRIMARY KEY, chat_id TEXT NOT NULL, role TEXT NOT NULL, content TEXT NOT NULL, sender_id TEXT, alternatives JSON NOT NULL, active_index INTEGER NOT NULL)",
+        ] {
+            sqlx::query(statement).execute(&old).await.unwrap();
+        }
+        let (character_id, chat_id) = (Uuid::now_v7(), Uuid::now_v7());
+        let (first, second) = (Uuid::now_v7(), Uuid::now_v7());
+        sqlx::query("INSERT INTO characters VALUES (?, 'Ada', '', '', '', '', '')")
+            .bind(character_id.to_string())
+            .execute(&old)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO chats VALUES (?, ?, '[]')")
+            .bind(chat_id.to_string())
+            .bind(character_id.to_string())
+            .execute(&old)
+            .await
+            .unwrap();
+        for (id, content) in [(second, "Two"), (first, "One")] {
+            sqlx::query("INSERT INTO messages VALUES (?, ?, 'user', ?, NULL, '[]', 0)")
+                .bind(id.to_string())
+                .bind(chat_id.to_string())
+                .bind(content)
+                .execute(&old)
+                .await
+                .unwrap();
+        }
+        old.close().await;
+
+        let db = LocalDatabase::new(&url).await;
+        db.append_message(chat_id, ChatMessage::new("user", "Three"))
+            .await
+            .unwrap();
+        let messages = db.get_messages_for_chat(chat_id).await.unwrap();
+        let contents: Vec<_> = messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["One", "Two", "Three"]);
+        assert!(!messages[0].has_created_at());
+        assert!(!messages[1].has_created_at());
+        assert!(messages[2].has_created_at());
+    }
+
+    #[tokio::test]
+    async fn deleting_a_chat_takes_its_messages_and_usage_or_nothing() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let character_id = db.get_characters().await.unwrap()[0].id;
+        let chat = chat_with(character_id, &["one", "two"]);
+        db.create_chat(chat.clone()).await.unwrap();
+        db.record_usage(chat.id, "m", 10, 5).await.unwrap();
+        let rows = |table: &'static str| {
+            let pool = db.pool.clone();
+            let chat_id = chat.id.to_string();
+            async move {
+                sqlx::query_scalar::<_, i64>(&format!(
+                    "SELECT COUNT(*) FROM {} WHERE chat_id = ?",
+                    table
+                ))
+                .bind(chat_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+            }
+        };
+
+        sqlx::query(
+            "CREATE TRIGGER keep_chats BEFORE DELETE ON chats BEGIN SELECT RAISE(ABORT, 'kept'); END",
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        assert!(db.delete_chat(chat.id).await.is_err());
+        assert_eq!(rows("messages").await, 2);
+        assert_eq!(rows("chat_stats").await, 1);
+
+        sqlx::query("DROP TRIGGER keep_chats")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.delete_chat(chat.id).await.unwrap();
+        assert_eq!(rows("messages").await, 0);
+        assert_eq!(rows("chat_stats").await, 0);
+        assert!(matches!(
+            db.get_chat(chat.id).await,
+            Err(DbError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn deleting_a_character_takes_its_chats_or_nothing() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let character_id = db.get_characters().await.unwrap()[0].id;
+        let chats = [
+            chat_with(character_id, &["one", "two"]),
+            chat_with(character_id, &["three"]),
+        ];
+        for chat in &chats {
+            db.create_chat(chat.clone()).await.unwrap();
+        }
+        let messages = || async {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM messages")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap()
+        };
+
+        // The last statement fails, so the chats deleted before it come back
+        sqlx::query(
+            "CREATE TRIGGER keep_characters BEFORE DELETE ON characters BEGIN SELECT RAISE(ABORT, 'kept'); END",
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        assert!(db.delete_character(character_id).await.is_err());
+        assert_eq!(db.get_characters().await.unwrap().len(), 1);
+        assert_eq!(db.get_chats(None, None).await.unwrap().len(), 2);
+        assert_eq!(messages().await, 3);
+
+        sqlx::query("DROP TRIGGER keep_characters")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.delete_character(character_id).await.unwrap();
+        assert!(db.get_characters().await.unwrap().is_empty());
+        assert_eq!(messages().await, 0);
+        for chat in &chats {
+            assert!(matches!(
+                db.get_chat(chat.id).await,
+                Err(DbError::NotFound(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn create_chat_inserts_all_messages_or_none() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let character_id = db.get_characters().await.unwrap()[0].id;
+        sqlx::query(
+            "CREATE TRIGGER reject_poison BEFORE INSERT ON messages WHEN NEW.content = 'poison'
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let chat = chat_with(character_id, &["one", "two", "three"]);
+        db.create_chat(chat.clone()).await.unwrap();
+        assert_eq!(db.get_chat(chat.id).await.unwrap().messages.len(), 3);
+
+        let failing = chat_with(character_id, &["one", "poison", "three"]);
+        assert!(db.create_chat(failing.clone()).await.is_err());
+        assert!(matches!(
+            db.get_chat(failing.id).await,
+            Err(DbError::NotFound(_))
+        ));
+        let stray: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE chat_id = ?")
+            .bind(failing.id.to_string())
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(stray, 0);
+    }
+
+    #[tokio::test]
+    async fn update_alternative_writes_the_variant_and_its_sender() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let character_id = db.get_characters().await.unwrap()[0].id;
+        let mut chat = chat_with(character_id, &["first"]);
+        chat.messages[0].push_alternative("second", None);
+        let message_id = chat.messages[0].id;
+        db.create_chat(chat.clone()).await.unwrap();
+
+        let sender = Uuid::now_v7();
+        db.update_alternative(
+            chat.id,
+            message_id,
+            1,
+            "rewritten".into(),
+            Some(sender),
+            Some("why".into()),
+        )
+        .await
+        .unwrap();
+        let message = db.get_message_by_id(message_id).await.unwrap().unwrap();
+        assert_eq!(message.content, "first");
+        assert_eq!(message.alternatives, ["rewritten"]);
+        assert_eq!(message.alternative_senders, [Some(sender)]);
+        assert_eq!(message.variant_reasoning(1), Some("why"));
+        assert_eq!(message.variant_reasoning(0), None);
+        assert_eq!(message.active_index, 1);
+
+        db.update_alternative(chat.id, message_id, 0, "again".into(), Some(sender), None)
+            .await
+            .unwrap();
+        let message = db.get_message_by_id(message_id).await.unwrap().unwrap();
+        assert_eq!(message.content, "again");
+        assert_eq!(message.sender_id, Some(sender));
+
+        assert!(matches!(
+            db.update_alternative(chat.id, message_id, 2, "missing".into(), None, None)
+                .await,
+            Err(DbError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn importing_a_character_keeps_all_of_it_or_none() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let mut character = db.get_characters().await.unwrap()[0].clone();
+        character.id = Uuid::now_v7();
+        sqlx::query(
+            "CREATE TRIGGER reject_poison BEFORE INSERT ON messages WHEN NEW.content = 'poison'
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let chats = vec![
+            chat_with(character.id, &["one"]),
+            chat_with(character.id, &["poison"]),
+        ];
+        assert!(db.import_character(character.clone(), chats).await.is_err());
+        assert_eq!(db.get_characters().await.unwrap().len(), 1);
+        assert!(db.get_chats(None, None).await.unwrap().is_empty());
+
+        let chats = vec![chat_with(character.id, &["one", "two"])];
+        db.import_character(character, chats).await.unwrap();
+        assert_eq!(db.get_characters().await.unwrap().len(), 2);
+        assert_eq!(db.get_chats(None, None).await.unwrap()[0].message_count, 2);
+    }
+
+    #[tokio::test]
+    async fn favoriting_a_character_keeps_the_list_in_order() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let first = db.get_characters().await.unwrap()[0].clone();
+        let second = Character {
+            id: Uuid::now_v7(),
+            name: "Aaron".to_string(),
+            ..first.clone()
+        };
+        db.create_character(second.clone()).await.unwrap();
+
+        db.set_favorite(second.id, true).await.unwrap();
+        let listed: Vec<_> = db
+            .get_characters()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.id, c.is_favorite))
+            .collect();
+        assert_eq!(listed, [(first.id, false), (second.id, true)]);
+    }
+
+    #[tokio::test]
+    async fn usage_adds_up_per_model_and_prices_what_it_can() {
+        let db = LocalDatabase::in_memory().await;
+        seed_sample_character(&db).await.unwrap();
+        let character_id = db.get_characters().await.unwrap()[0].id;
+        let chat = chat_with(character_id, &[]);
+        db.create_chat(chat.clone()).await.unwrap();
+        db.record_usage(chat.id, "priced", 1_000, 100)
+            .await
+            .unwrap();
+        db.record_usage(chat.id, "priced", 2_000, 200)
+            .await
+            .unwrap();
+        db.record_usage(chat.id, "unpriced", 500, 50).await.unwrap();
+
+        let pricing = HashMap::from([(
+            "priced".to_string(),
+            ModelPricing {
+                prompt: 1.0,
+                completion: 10.0,
+            },
+        )]);
+        let stats = ChatStats::from_usage(chat.id, db.get_usage(chat.id).await.unwrap(), &pricing);
+        assert_eq!((stats.prompt_tokens, stats.completion_tokens), (3_500, 350));
+        assert_eq!(stats.models.len(), 2);
+        assert_eq!(stats.models[0].prompt_tokens, 3_000);
+        assert_eq!(stats.models[0].estimated_cost, Some(0.006));
+        assert_eq!(stats.models[1].estimated_cost, None);
+        // The unpriced model doesn't wipe out the cost of the priced one
+        assert_eq!(stats.estimated_cost, Some(0.006));
+
+        let unpriced = ChatStats::from_usage(chat.id, stats.models, &HashMap::new());
+        assert_eq!(unpriced.estimated_cost, None);
+    }
+}
@@ -1,6 +1,9 @@
 use async_trait::async_trait;
-use shared::models::{Character, Chat, ChatMessage};
+use shared::models::{Character, Chat, ChatMessage, ChatParticipant, LoreEntry, ModelUsage};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
 use thiserror::Error;
+use tokio::sync::OwnedMutexGuard;
 use uuid::Uuid;
 
 pub mod local;
@@ -10,8 +13,39 @@ pub type DbResult<T> = Result<T, DbError>;
 
 #[derive(Clone, Debug)]
 pub enum DatabaseConfig {
-    Local { url: String },
-    Postgres { url: String },
+    Local {
+        url: String,
+    },
+    Postgres {
+        url: String,
+    },
+    /// SQLite in memory: nothing touches the disk, and everything is lost on restart
+    Memory,
+}
+
+/// Per-message locks that serialize read-modify-write updates,
+/// so concurrent regenerations can't drop each other's alternatives
+#[derive(Clone, Default)]
+pub struct MessageLocks {
+    locks: Arc<Mutex<HashMap<Uuid, Weak<tokio::sync::Mutex<()>>>>>,
+}
+
+impl MessageLocks {
+    pub async fn lock(&self, message_id: Uuid) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().expect("message locks poisoned");
+            match locks.get(&message_id).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    locks.retain(|_, l| l.strong_count() > 0);
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    locks.insert(message_id, Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
 }
 
 #[derive(Error, Debug)]
@@ -26,24 +60,84 @@ pub enum DbError {
     Internal(String),
 }
 
+impl DbError {
+    /// Report an insert that hit a foreign key constraint as the referenced row missing
+    pub fn missing_parent(e: sqlx::Error, parent: impl FnOnce() -> String) -> Self {
+        match e {
+            sqlx::Error::Database(db) if db.is_foreign_key_violation() => {
+                DbError::NotFound(parent())
+            }
+            e => e.into(),
+        }
+    }
+}
+
 #[async_trait]
 pub trait Database: Send + Sync {
+    /// Whether stored data is lost when the server stops
+    fn is_ephemeral(&self) -> bool {
+        false
+    }
     async fn get_characters(&self) -> DbResult<Vec<Character>>;
     async fn get_character(&self, character_id: Uuid) -> DbResult<Character>;
-    async fn get_chats(&self, character_id: Option<Uuid>) -> DbResult<Vec<Chat>>;
+    /// Fetch several characters at once; unknown ids are skipped
+    async fn get_characters_by_ids(&self, character_ids: &[Uuid]) -> DbResult<Vec<Character>>;
+    /// Chats without their messages, optionally only a character's or only those with a tag
+    async fn get_chats(&self, character_id: Option<Uuid>, tag: Option<&str>)
+    -> DbResult<Vec<Chat>>;
     async fn get_chat(&self, chat_id: Uuid) -> DbResult<Chat>;
     async fn get_message(&self, chat_id: Uuid, message_id: Uuid) -> DbResult<ChatMessage>;
     async fn create_character(&self, character: Character) -> DbResult<()>;
+    /// Overwrite a character's card, leaving its favorite flag alone
+    async fn update_character(&self, character: Character) -> DbResult<()>;
+    async fn set_favorite(&self, character_id: Uuid, is_favorite: bool) -> DbResult<()>;
     async fn create_chat(&self, chat: Chat) -> DbResult<()>;
+    /// Create a character along with its chats, all of them or none
+    async fn import_character(&self, character: Character, chats: Vec<Chat>) -> DbResult<()>;
+    /// Hand a chat over to another character, along with its updated participants
+    async fn move_chat(
+        &self,
+        chat_id: Uuid,
+        character_id: Uuid,
+        participants: &[ChatParticipant],
+    ) -> DbResult<()>;
+    async fn set_participants(
+        &self,
+        chat_id: Uuid,
+        participants: &[ChatParticipant],
+    ) -> DbResult<()>;
+    /// Set or clear the scenario that replaces the character's in this chat
+    async fn set_scenario_override(&self, chat_id: Uuid, scenario: Option<String>) -> DbResult<()>;
+    /// Label a chat, returning its tags. A tag it already has is not added twice.
+    async fn add_chat_tag(&self, chat_id: Uuid, tag: &str) -> DbResult<Vec<String>>;
+    /// Take a label off a chat, returning its remaining tags
+    async fn remove_chat_tag(&self, chat_id: Uuid, tag: &str) -> DbResult<Vec<String>>;
     async fn delete_character(&self, character_id: Uuid) -> DbResult<()>;
+    /// Delete a chat with its messages and usage, all or nothing
     async fn delete_chat(&self, chat_id: Uuid) -> DbResult<()>;
     async fn delete_message(&self, chat_id: Uuid, message_id: Uuid) -> DbResult<()>;
+    /// Appending a message whose id is already in the chat does nothing, so a retried
+    /// request can't insert it twice
     async fn append_message(&self, chat_id: Uuid, message: ChatMessage) -> DbResult<()>;
+    /// Add a variant with its reasoning trace and make it the active one
     async fn append_alternative(
         &self,
         chat_id: Uuid,
         message_id: Uuid,
         content: String,
+        sender_id: Option<Uuid>,
+        reasoning: Option<String>,
+    ) -> DbResult<()>;
+    /// Replace one variant of a message with its sender and reasoning trace, 0 being the
+    /// primary content, and make it the active one
+    async fn update_alternative(
+        &self,
+        chat_id: Uuid,
+        message_id: Uuid,
+        index: usize,
+        content: String,
+        sender_id: Option<Uuid>,
+        reasoning: Option<String>,
     ) -> DbResult<()>;
     async fn update_message(
         &self,
@@ -57,4 +151,32 @@ pub trait Database: Send + Sync {
         message_id: Uuid,
         index: usize,
     ) -> DbResult<()>;
+    async fn set_keep_in_context(
+        &self,
+        chat_id: Uuid,
+        message_id: Uuid,
+        keep: bool,
+    ) -> DbResult<()>;
+    async fn set_hidden_from_context(
+        &self,
+        chat_id: Uuid,
+        message_id: Uuid,
+        hidden: bool,
+    ) -> DbResult<()>;
+    /// Overwrite every stored field of a message, found by its id within the chat
+    async fn replace_message(&self, chat_id: Uuid, message: ChatMessage) -> DbResult<()>;
+    async fn record_usage(
+        &self,
+        chat_id: Uuid,
+        model: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+    ) -> DbResult<()>;
+    /// Token totals for a chat, grouped by model (costs are left unset)
+    async fn get_usage(&self, chat_id: Uuid) -> DbResult<Vec<ModelUsage>>;
+    /// Global lorebook entries, by name
+    async fn get_lore_entries(&self) -> DbResult<Vec<LoreEntry>>;
+    async fn create_lore_entry(&self, entry: LoreEntry) -> DbResult<()>;
+    async fn update_lore_entry(&self, entry: LoreEntry) -> DbResult<()>;
+    async fn delete_lore_entry(&self, entry_id: Uuid) -> DbResult<()>;
 }
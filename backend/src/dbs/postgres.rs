@@ -1,13 +1,186 @@
-use crate::dbs::{Database, DbError, DbResult};
+use crate::dbs::{Database, DbError, DbResult, MessageLocks};
 use async_trait::async_trait;
 use serde_json::Value;
-use shared::models::{Character, Chat, ChatMessage, ChatParticipant};
-use sqlx::{Pool, Postgres, Row, postgres::PgPoolOptions};
+use shared::models::{
+    CHAT_PREVIEW_LENGTH, CardMetadata, Character, Chat, ChatMessage, ChatParticipant, GreetingMode,
+    LoreEntry, ModelUsage, ResponseLength,
+};
+use shared::text::to_snippet;
+use sqlx::{
+    Pool, Postgres, Row,
+    postgres::{PgPoolOptions, PgRow},
+};
 use uuid::Uuid;
 
+/// Chats with their message counts in one query; callers add the filter and `GROUP BY c.id`
+const CHAT_LIST_QUERY: &str = "SELECT c.id, c.character_id, c.participants, c.scenario_override, c.tags, COUNT(m.id) AS message_count, (SELECT lm.content FROM messages lm WHERE lm.chat_id = c.id ORDER BY lm.created_at DESC, lm.id DESC LIMIT 1) AS last_message FROM chats c LEFT JOIN messages m ON m.chat_id = c.id";
+
+const CHARACTER_COLUMNS: &str = "id, name, description, personality, scenario, first_message, example_messages, is_favorite, response_length, reasoning_effort, creator, creator_notes, tags, character_version, alternate_greetings, greeting_mode";
+
+fn character_from_row(row: &PgRow) -> Character {
+    Character {
+        id: row.get("id"),
+        name: row.get("name"),
+        description: row.get("description"),
+        personality: row.get("personality"),
+        scenario: row.get("scenario"),
+        first_message: row.get("first_message"),
+        example_messages: row.get("example_messages"),
+        alternate_greetings: serde_json::from_value(row.get::<Value, _>("alternate_greetings"))
+            .unwrap_or_default(),
+        greeting_mode: row
+            .get::<Option<String>, _>("greeting_mode")
+            .as_deref()
+            .and_then(GreetingMode::parse)
+            .unwrap_or_default(),
+        is_favorite: row.get("is_favorite"),
+        response_length: row
+            .get::<Option<String>, _>("response_length")
+            .as_deref()
+            .and_then(ResponseLength::parse),
+        reasoning_effort: row.get("reasoning_effort"),
+        metadata: CardMetadata {
+            creator: row.get("creator"),
+            creator_notes: row.get("creator_notes"),
+            tags: serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default(),
+            character_version: row.get("character_version"),
+        },
+    }
+}
+
+const MESSAGE_COLUMNS: &str = "id, role, content, sender_id, alternatives, alternative_senders, active_index, keep_in_context, hidden_from_context, tool_calls, tool_call_id, created_at, reasoning, alternative_reasoning";
+
+fn message_from_row(row: &PgRow) -> ChatMessage {
+    let alts_val: Value = row.get("alternatives");
+    let senders_val: Value = row.get("alternative_senders");
+
+    ChatMessage {
+        id: row.get("id"),
+        role: row.get("role"),
+        content: row.get("content"),
+        sender_id: row.get("sender_id"),
+        alternatives: serde_json::from_value(alts_val).unwrap_or_default(),
+        alternative_senders: serde_json::from_value(senders_val).unwrap_or_default(),
+        active_index: row.get::<i64, _>("active_index") as usize,
+        tool_calls: row
+            .get::<Option<Value>, _>("tool_calls")
+            .and_then(|v| serde_json::from_value(v).ok()),
+        tool_call_id: row.get("tool_call_id"),
+        keep_in_context: row.get("keep_in_context"),
+        hidden_from_context: row.get("hidden_from_context"),
+        created_at: row.get("created_at"),
+        reasoning: row.get("reasoning"),
+        alternative_reasoning: serde_json::from_value(row.get::<Value, _>("alternative_reasoning"))
+            .unwrap_or_default(),
+    }
+}
+
+const LORE_COLUMNS: &str = "id, name, keys, content, enabled";
+
+fn lore_entry_from_row(row: &PgRow) -> LoreEntry {
+    LoreEntry {
+        id: row.get("id"),
+        name: row.get("name"),
+        keys: serde_json::from_value(row.get::<Value, _>("keys")).unwrap_or_default(),
+        content: row.get("content"),
+        enabled: row.get("enabled"),
+    }
+}
+
+/// Insert a message row, either straight on the pool or inside a transaction.
+/// Returns false without touching anything if a message with that id already exists.
+async fn insert_message<'e>(
+    executor: impl sqlx::Executor<'e, Database = Postgres>,
+    chat_id: Uuid,
+    message: ChatMessage,
+) -> DbResult<bool> {
+    // The chat_id foreign key rejects messages for chats that don't exist
+    let alts_json = serde_json::to_value(&message.alternatives)?;
+    let senders_json = serde_json::to_value(&message.alternative_senders)?;
+    let reasoning_json = serde_json::to_value(&message.alternative_reasoning)?;
+    let tool_calls_json = message
+        .tool_calls
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+    let sender_id = message.sender_id;
+
+    let result = sqlx::query(
+        "INSERT INTO messages (id, chat_id, role, content, sender_id, alternatives, alternative_senders, active_index, keep_in_context, hidden_from_context, tool_calls, tool_call_id, created_at, reasoning, alternative_reasoning) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(message.id)
+    .bind(chat_id)
+    .bind(message.role)
+    .bind(message.content)
+    .bind(sender_id)
+    .bind(alts_json)
+    .bind(senders_json)
+    .bind(message.active_index as i64)
+    .bind(message.keep_in_context)
+    .bind(message.hidden_from_context)
+    .bind(tool_calls_json)
+    .bind(message.tool_call_id)
+    .bind(message.created_at)
+    .bind(message.reasoning)
+    .bind(reasoning_json)
+    .execute(executor)
+    .await
+    .map_err(|e| DbError::missing_parent(e, || format!("Chat {} not found", chat_id)))?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn insert_character<'e>(
+    executor: impl sqlx::Executor<'e, Database = Postgres>,
+    character: Character,
+) -> DbResult<()> {
+    sqlx::query(
+        "INSERT INTO characters (id, name, description, personality, scenario, first_message, example_messages, is_favorite, response_length, reasoning_effort, creator, creator_notes, tags, character_version, alternate_greetings, greeting_mode) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)",
+    )
+    .bind(character.id)
+    .bind(character.name)
+    .bind(character.description)
+    .bind(character.personality)
+    .bind(character.scenario)
+    .bind(character.first_message)
+    .bind(character.example_messages)
+    .bind(character.is_favorite)
+    .bind(character.response_length.map(|l| l.as_str()))
+    .bind(character.reasoning_effort)
+    .bind(character.metadata.creator)
+    .bind(character.metadata.creator_notes)
+    .bind(serde_json::to_value(&character.metadata.tags)?)
+    .bind(character.metadata.character_version)
+    .bind(serde_json::to_value(&character.alternate_greetings)?)
+    .bind(character.greeting_mode.as_str())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Insert a chat and its messages. Several statements, so run it inside a transaction.
+async fn insert_chat(conn: &mut sqlx::PgConnection, chat: Chat) -> DbResult<()> {
+    let participants_json = serde_json::to_value(&chat.participants)?;
+    sqlx::query(
+        "INSERT INTO chats (id, character_id, participants, scenario_override, tags) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(chat.id)
+    .bind(chat.character_id)
+    .bind(participants_json)
+    .bind(chat.scenario_override)
+    .bind(serde_json::to_value(&chat.tags)?)
+    .execute(&mut *conn)
+    .await?;
+
+    for msg in chat.messages {
+        insert_message(&mut *conn, chat.id, msg).await?;
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct PostgresDatabase {
     pool: Pool<Postgres>,
+    message_locks: MessageLocks,
 }
 
 impl PostgresDatabase {
@@ -17,7 +190,10 @@ impl PostgresDatabase {
             .await
             .expect("Failed to connect to database");
 
-        let db = Self { pool };
+        let db = Self {
+            pool,
+            message_locks: MessageLocks::default(),
+        };
         db.init().await;
         db
     }
@@ -67,73 +243,130 @@ impl PostgresDatabase {
         .execute(&self.pool)
         .await
         .expect("Failed to create messages table");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS chat_stats (
+                id UUID PRIMARY KEY,
+                chat_id UUID NOT NULL,
+                model TEXT NOT NULL,
+                prompt_tokens BIGINT NOT NULL,
+                completion_tokens BIGINT NOT NULL,
+                FOREIGN KEY(chat_id) REFERENCES chats(id)
+            )",
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to create chat_stats table");
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS lore_entries (
+                id UUID PRIMARY KEY,
+                name TEXT NOT NULL,
+                keys JSONB NOT NULL,
+                content TEXT NOT NULL,
+                enabled BOOLEAN NOT NULL DEFAULT TRUE
+            )",
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to create lore_entries table");
+
+        self.add_column("characters", "is_favorite BOOLEAN NOT NULL DEFAULT FALSE")
+            .await;
+        self.add_column(
+            "messages",
+            "alternative_senders JSONB NOT NULL DEFAULT '[]'",
+        )
+        .await;
+        self.add_column("characters", "response_length TEXT").await;
+        self.add_column("characters", "reasoning_effort TEXT").await;
+        self.add_column("chats", "scenario_override TEXT").await;
+        self.add_column("characters", "creator TEXT").await;
+        self.add_column("characters", "creator_notes TEXT").await;
+        self.add_column("characters", "tags JSONB NOT NULL DEFAULT '[]'")
+            .await;
+        self.add_column("characters", "character_version TEXT")
+            .await;
+        self.add_column(
+            "characters",
+            "alternate_greetings JSONB NOT NULL DEFAULT '[]'",
+        )
+        .await;
+        self.add_column("characters", "greeting_mode TEXT").await;
+        self.add_column("messages", "keep_in_context BOOLEAN NOT NULL DEFAULT FALSE")
+            .await;
+        self.add_column(
+            "messages",
+            "hidden_from_context BOOLEAN NOT NULL DEFAULT FALSE",
+        )
+        .await;
+        self.add_column("messages", "tool_calls JSONB").await;
+        self.add_column("messages", "tool_call_id TEXT").await;
+        self.add_column("chats", "tags JSONB NOT NULL DEFAULT '[]'")
+            .await;
+        self.add_column(
+            "messages",
+            "created_at TIMESTAMPTZ NOT NULL DEFAULT 'epoch'",
+        )
+        .await;
+        self.add_column("messages", "reasoning TEXT").await;
+        self.add_column(
+            "messages",
+            "alternative_reasoning JSONB NOT NULL DEFAULT '[]'",
+        )
+        .await;
+    }
+
+    /// Add a column introduced after the table was first created
+    async fn add_column(&self, table: &str, definition: &str) {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {}",
+            table, definition
+        ))
+        .execute(&self.pool)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to add column to {}: {}", table, e));
     }
 
     async fn get_messages_for_chat(&self, chat_id: Uuid) -> DbResult<Vec<ChatMessage>> {
-        let rows = sqlx::query(
-            "SELECT id, role, content, sender_id, alternatives, active_index FROM messages WHERE chat_id = $1 ORDER BY id", // Assuming insertion order or ID sort. CockroachDB UUIDs aren't sequential by default, so we might need a timestamp. But local.rs doesn't sort explicitly either.
-        )
+        let rows = sqlx::query(&format!(
+            // Older messages all read as the epoch and fall back to their time-ordered ids
+            "SELECT {} FROM messages WHERE chat_id = $1 ORDER BY created_at, id",
+            MESSAGE_COLUMNS
+        ))
         .bind(chat_id)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(rows
-            .into_iter()
-            .map(|row| {
-                let alts_val: Value = row.get("alternatives");
-                let alternatives: Vec<String> =
-                    serde_json::from_value(alts_val).unwrap_or_default();
-
-                ChatMessage {
-                    id: row.get("id"),
-                    role: row.get("role"),
-                    content: row.get("content"),
-                    sender_id: row.get("sender_id"),
-                    alternatives,
-                    active_index: row.get::<i64, _>("active_index") as usize,
-                    tool_calls: None,
-                    tool_call_id: None,
-                }
-            })
-            .collect())
+        Ok(rows.iter().map(message_from_row).collect())
     }
 
     async fn get_message_by_id(&self, message_id: Uuid) -> DbResult<Option<ChatMessage>> {
-        let row = sqlx::query(
-            "SELECT id, role, content, sender_id, alternatives, active_index FROM messages WHERE id = $1",
-        )
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM messages WHERE id = $1",
+            MESSAGE_COLUMNS
+        ))
         .bind(message_id)
         .fetch_optional(&self.pool)
         .await?;
 
-        let row = match row {
-            Some(r) => r,
-            None => return Ok(None),
-        };
-
-        let alts_val: Value = row.get("alternatives");
-        let alternatives: Vec<String> = serde_json::from_value(alts_val).unwrap_or_default();
-
-        Ok(Some(ChatMessage {
-            id: row.get("id"),
-            role: row.get("role"),
-            content: row.get("content"),
-            sender_id: row.get("sender_id"),
-            alternatives,
-            active_index: row.get::<i64, _>("active_index") as usize,
-            tool_calls: None,
-            tool_call_id: None,
-        }))
+        Ok(row.as_ref().map(message_from_row))
     }
 
     async fn save_message(&self, message_id: Uuid, msg: ChatMessage) -> DbResult<()> {
         let alts_json = serde_json::to_value(&msg.alternatives)?;
+        let senders_json = serde_json::to_value(&msg.alternative_senders)?;
+        let reasoning_json = serde_json::to_value(&msg.alternative_reasoning)?;
         sqlx::query(
-            "UPDATE messages SET content = $1, alternatives = $2, active_index = $3 WHERE id = $4",
+            "UPDATE messages SET content = $1, sender_id = $2, alternatives = $3, alternative_senders = $4, active_index = $5, reasoning = $6, alternative_reasoning = $7 WHERE id = $8",
         )
         .bind(msg.content)
+        .bind(msg.sender_id)
         .bind(alts_json)
+        .bind(senders_json)
         .bind(msg.active_index as i64)
+        .bind(msg.reasoning)
+        .bind(reasoning_json)
         .bind(message_id)
         .execute(&self.pool)
         .await?;
@@ -144,44 +377,27 @@ impl PostgresDatabase {
 #[async_trait]
 impl Database for PostgresDatabase {
     async fn get_characters(&self) -> DbResult<Vec<Character>> {
-        let rows = sqlx::query(
-            "SELECT id, name, description, personality, scenario, first_message, example_messages FROM characters"
-        )
-        .fetch_all(&self.pool)
-        .await?;
+        let rows = sqlx::query(&format!("SELECT {} FROM characters", CHARACTER_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
 
         Ok(rows
             .into_iter()
-            .map(|row| Character {
-                id: row.get("id"),
-                name: row.get("name"),
-                description: row.get("description"),
-                personality: row.get("personality"),
-                scenario: row.get("scenario"),
-                first_message: row.get("first_message"),
-                example_messages: row.get("example_messages"),
-            })
+            .map(|row| character_from_row(&row))
             .collect())
     }
 
     async fn get_character(&self, character_id: Uuid) -> DbResult<Character> {
-        let row = sqlx::query(
-            "SELECT id, name, description, personality, scenario, first_message, example_messages FROM characters WHERE id = $1",
-        )
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM characters WHERE id = $1",
+            CHARACTER_COLUMNS
+        ))
         .bind(character_id)
         .fetch_optional(&self.pool)
         .await?;
 
         match row {
-            Some(row) => Ok(Character {
-                id: row.get("id"),
-                name: row.get("name"),
-                description: row.get("description"),
-                personality: row.get("personality"),
-                scenario: row.get("scenario"),
-                first_message: row.get("first_message"),
-                example_messages: row.get("example_messages"),
-            }),
+            Some(row) => Ok(character_from_row(&row)),
             None => Err(DbError::NotFound(format!(
                 "Character {} not found",
                 character_id
@@ -189,17 +405,47 @@ impl Database for PostgresDatabase {
         }
     }
 
-    async fn get_chats(&self, character_id: Option<Uuid>) -> DbResult<Vec<Chat>> {
-        let rows = if let Some(cid) = character_id {
-            sqlx::query("SELECT id, character_id, participants FROM chats WHERE character_id = $1")
-                .bind(cid)
-                .fetch_all(&self.pool)
-                .await?
+    async fn get_characters_by_ids(&self, character_ids: &[Uuid]) -> DbResult<Vec<Character>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM characters WHERE id = ANY($1)",
+            CHARACTER_COLUMNS
+        ))
+        .bind(character_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(character_from_row).collect())
+    }
+
+    async fn get_chats(
+        &self,
+        character_id: Option<Uuid>,
+        tag: Option<&str>,
+    ) -> DbResult<Vec<Chat>> {
+        let mut conditions = Vec::new();
+        if character_id.is_some() {
+            conditions.push(format!("c.character_id = ${}", conditions.len() + 1));
+        }
+        if tag.is_some() {
+            conditions.push(format!(
+                "c.tags @> jsonb_build_array(${}::text)",
+                conditions.len() + 1
+            ));
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
         } else {
-            sqlx::query("SELECT id, character_id, participants FROM chats")
-                .fetch_all(&self.pool)
-                .await?
+            format!(" WHERE {}", conditions.join(" AND "))
         };
+        let sql = format!("{}{} GROUP BY c.id", CHAT_LIST_QUERY, filter);
+        let mut query = sqlx::query(&sql);
+        if let Some(cid) = character_id {
+            query = query.bind(cid);
+        }
+        if let Some(tag) = tag {
+            query = query.bind(tag);
+        }
+        let rows = query.fetch_all(&self.pool).await?;
 
         let mut chats = Vec::new();
         for row in rows {
@@ -212,16 +458,24 @@ impl Database for PostgresDatabase {
                 character_id: row.get("character_id"),
                 messages: Vec::new(),
                 participants,
+                message_count: row.get::<i64, _>("message_count") as usize,
+                scenario_override: row.get("scenario_override"),
+                tags: serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default(),
+                preview: row
+                    .get::<Option<String>, _>("last_message")
+                    .map(|m| to_snippet(&m, CHAT_PREVIEW_LENGTH)),
             });
         }
         Ok(chats)
     }
 
     async fn get_chat(&self, chat_id: Uuid) -> DbResult<Chat> {
-        let row = sqlx::query("SELECT id, character_id, participants FROM chats WHERE id = $1")
-            .bind(chat_id)
-            .fetch_optional(&self.pool)
-            .await?;
+        let row = sqlx::query(
+            "SELECT id, character_id, participants, scenario_override, tags FROM chats WHERE id = $1",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
 
         match row {
             Some(row) => {
@@ -233,8 +487,12 @@ impl Database for PostgresDatabase {
                 Ok(Chat {
                     id: chat_id,
                     character_id: row.get("character_id"),
+                    message_count: messages.len(),
                     messages,
                     participants,
+                    scenario_override: row.get("scenario_override"),
+                    tags: serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default(),
+                    preview: None,
                 })
             }
             None => Err(DbError::NotFound(format!("Chat {} not found", chat_id))),
@@ -248,71 +506,188 @@ impl Database for PostgresDatabase {
     }
 
     async fn create_character(&self, character: Character) -> DbResult<()> {
-        sqlx::query(
-            "INSERT INTO characters (id, name, description, personality, scenario, first_message, example_messages) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        insert_character(&self.pool, character).await
+    }
+
+    async fn update_character(&self, character: Character) -> DbResult<()> {
+        let result = sqlx::query(
+            "UPDATE characters SET name = $1, description = $2, personality = $3, scenario = $4, first_message = $5, example_messages = $6, response_length = $7, reasoning_effort = $8, creator = $9, creator_notes = $10, tags = $11, character_version = $12, alternate_greetings = $13, greeting_mode = $14 WHERE id = $15",
         )
-        .bind(character.id)
         .bind(character.name)
         .bind(character.description)
         .bind(character.personality)
         .bind(character.scenario)
         .bind(character.first_message)
         .bind(character.example_messages)
+        .bind(character.response_length.map(|l| l.as_str()))
+        .bind(character.reasoning_effort)
+        .bind(character.metadata.creator)
+        .bind(character.metadata.creator_notes)
+        .bind(serde_json::to_value(&character.metadata.tags)?)
+        .bind(character.metadata.character_version)
+        .bind(serde_json::to_value(&character.alternate_greetings)?)
+        .bind(character.greeting_mode.as_str())
+        .bind(character.id)
         .execute(&self.pool)
         .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Character {} not found",
+                character.id
+            )));
+        }
         Ok(())
     }
 
-    async fn create_chat(&self, chat: Chat) -> DbResult<()> {
-        let participants_json = serde_json::to_value(&chat.participants)?;
-        sqlx::query("INSERT INTO chats (id, character_id, participants) VALUES ($1, $2, $3)")
-            .bind(chat.id)
-            .bind(chat.character_id)
-            .bind(participants_json)
+    async fn set_favorite(&self, character_id: Uuid, is_favorite: bool) -> DbResult<()> {
+        let result = sqlx::query("UPDATE characters SET is_favorite = $1 WHERE id = $2")
+            .bind(is_favorite)
+            .bind(character_id)
             .execute(&self.pool)
             .await?;
-
-        // Also insert initial messages if any
-        for msg in chat.messages {
-            self.append_message(chat.id, msg).await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Character {} not found",
+                character_id
+            )));
         }
         Ok(())
     }
 
-    async fn delete_character(&self, character_id: Uuid) -> DbResult<()> {
-        let text_id = character_id; // Postgres driver handles UUIDs natively
+    async fn create_chat(&self, chat: Chat) -> DbResult<()> {
+        // The chat and its opening messages are created together or not at all
+        let mut tx = self.pool.begin().await?;
+        insert_chat(&mut tx, chat).await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        // First, get all chats
-        let chats = self.get_chats(Some(character_id)).await?;
+    async fn import_character(&self, character: Character, chats: Vec<Chat>) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_character(&mut *tx, character).await?;
         for chat in chats {
-            // Delete messages for chat
-            sqlx::query("DELETE FROM messages WHERE chat_id = $1")
-                .bind(chat.id)
-                .execute(&self.pool)
-                .await?;
-            // Delete chat
-            sqlx::query("DELETE FROM chats WHERE id = $1")
-                .bind(chat.id)
+            insert_chat(&mut tx, chat).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn move_chat(
+        &self,
+        chat_id: Uuid,
+        character_id: Uuid,
+        participants: &[ChatParticipant],
+    ) -> DbResult<()> {
+        let participants_json = serde_json::to_value(participants)?;
+        let result =
+            sqlx::query("UPDATE chats SET character_id = $1, participants = $2 WHERE id = $3")
+                .bind(character_id)
+                .bind(participants_json)
+                .bind(chat_id)
                 .execute(&self.pool)
                 .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("Chat {} not found", chat_id)));
         }
+        Ok(())
+    }
 
-        sqlx::query("DELETE FROM characters WHERE id = $1")
-            .bind(text_id)
+    async fn set_participants(
+        &self,
+        chat_id: Uuid,
+        participants: &[ChatParticipant],
+    ) -> DbResult<()> {
+        let participants_json = serde_json::to_value(participants)?;
+        let result = sqlx::query("UPDATE chats SET participants = $1 WHERE id = $2")
+            .bind(participants_json)
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("Chat {} not found", chat_id)));
+        }
+        Ok(())
+    }
+
+    async fn set_scenario_override(&self, chat_id: Uuid, scenario: Option<String>) -> DbResult<()> {
+        let result = sqlx::query("UPDATE chats SET scenario_override = $1 WHERE id = $2")
+            .bind(scenario)
+            .bind(chat_id)
             .execute(&self.pool)
             .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!("Chat {} not found", chat_id)));
+        }
+        Ok(())
+    }
+
+    async fn add_chat_tag(&self, chat_id: Uuid, tag: &str) -> DbResult<Vec<String>> {
+        // Appended in one statement, so concurrent edits don't undo each other
+        let row = sqlx::query(
+            "UPDATE chats SET tags = CASE WHEN tags @> jsonb_build_array($1::text) THEN tags ELSE tags || jsonb_build_array($1::text) END WHERE id = $2 RETURNING tags",
+        )
+        .bind(tag)
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::NotFound(format!("Chat {} not found", chat_id)))?;
+        Ok(serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default())
+    }
+
+    async fn remove_chat_tag(&self, chat_id: Uuid, tag: &str) -> DbResult<Vec<String>> {
+        let row =
+            sqlx::query("UPDATE chats SET tags = tags - $1::text WHERE id = $2 RETURNING tags")
+                .bind(tag)
+                .bind(chat_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .ok_or_else(|| DbError::NotFound(format!("Chat {} not found", chat_id)))?;
+        Ok(serde_json::from_value(row.get::<Value, _>("tags")).unwrap_or_default())
+    }
+
+    async fn delete_character(&self, character_id: Uuid) -> DbResult<()> {
+        // The character goes with all its chats, or nothing goes at all
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM messages WHERE chat_id IN (SELECT id FROM chats WHERE character_id = $1)",
+        )
+        .bind(character_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "DELETE FROM chat_stats WHERE chat_id IN (SELECT id FROM chats WHERE character_id = $1)",
+        )
+        .bind(character_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM chats WHERE character_id = $1")
+            .bind(character_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM characters WHERE id = $1")
+            .bind(character_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
         Ok(())
     }
 
     async fn delete_chat(&self, chat_id: Uuid) -> DbResult<()> {
+        // Messages go first, all in one transaction so a failure can't orphan them
+        let mut tx = self.pool.begin().await?;
         sqlx::query("DELETE FROM messages WHERE chat_id = $1")
             .bind(chat_id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM chat_stats WHERE chat_id = $1")
+            .bind(chat_id)
+            .execute(&mut *tx)
             .await?;
         sqlx::query("DELETE FROM chats WHERE id = $1")
             .bind(chat_id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -325,21 +700,22 @@ impl Database for PostgresDatabase {
     }
 
     async fn append_message(&self, chat_id: Uuid, message: ChatMessage) -> DbResult<()> {
-        let alts_json = serde_json::to_value(&message.alternatives)?;
-        let sender_id = message.sender_id;
-
-        sqlx::query(
-            "INSERT INTO messages (id, chat_id, role, content, sender_id, alternatives, active_index) VALUES ($1, $2, $3, $4, $5, $6, $7)",
-        )
-        .bind(message.id)
-        .bind(chat_id)
-        .bind(message.role)
-        .bind(message.content)
-        .bind(sender_id)
-        .bind(alts_json)
-        .bind(message.active_index as i64)
-        .execute(&self.pool)
-        .await?;
+        let message_id = message.id;
+        if insert_message(&self.pool, chat_id, message).await? {
+            return Ok(());
+        }
+        // Already there from an earlier attempt, as long as it's in the same chat
+        let existing = sqlx::query("SELECT 1 FROM messages WHERE id = $1 AND chat_id = $2")
+            .bind(message_id)
+            .bind(chat_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        if existing.is_none() {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found in chat {}",
+                message_id, chat_id
+            )));
+        }
         Ok(())
     }
 
@@ -348,10 +724,14 @@ impl Database for PostgresDatabase {
         _chat_id: Uuid,
         message_id: Uuid,
         content: String,
+        sender_id: Option<Uuid>,
+        reasoning: Option<String>,
     ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
         if let Some(mut msg) = self.get_message_by_id(message_id).await? {
-            msg.alternatives.push(content);
+            msg.push_alternative(content, sender_id);
             msg.active_index = msg.alternatives.len();
+            msg.set_variant_reasoning(msg.active_index, reasoning);
             self.save_message(message_id, msg).await?;
             Ok(())
         } else {
@@ -362,12 +742,41 @@ impl Database for PostgresDatabase {
         }
     }
 
+    async fn update_alternative(
+        &self,
+        _chat_id: Uuid,
+        message_id: Uuid,
+        index: usize,
+        content: String,
+        sender_id: Option<Uuid>,
+        reasoning: Option<String>,
+    ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
+        let Some(mut msg) = self.get_message_by_id(message_id).await? else {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        };
+        if !msg.replace_variant(index, content, sender_id) {
+            return Err(DbError::NotFound(format!(
+                "Alternative {} of message {} not found",
+                index, message_id
+            )));
+        }
+        msg.set_variant_reasoning(index, reasoning);
+        msg.active_index = index;
+        self.save_message(message_id, msg).await?;
+        Ok(())
+    }
+
     async fn update_message(
         &self,
         _chat_id: Uuid,
         message_id: Uuid,
         content: String,
     ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
         if let Some(mut msg) = self.get_message_by_id(message_id).await? {
             if msg.active_index == 0 {
                 msg.content = content;
@@ -390,6 +799,7 @@ impl Database for PostgresDatabase {
         message_id: Uuid,
         index: usize,
     ) -> DbResult<()> {
+        let _guard = self.message_locks.lock(message_id).await;
         if let Some(mut msg) = self.get_message_by_id(message_id).await? {
             if index < msg.variant_count() {
                 msg.active_index = index;
@@ -403,4 +813,185 @@ impl Database for PostgresDatabase {
             )))
         }
     }
+
+    async fn set_keep_in_context(
+        &self,
+        chat_id: Uuid,
+        message_id: Uuid,
+        keep: bool,
+    ) -> DbResult<()> {
+        let result =
+            sqlx::query("UPDATE messages SET keep_in_context = $1 WHERE id = $2 AND chat_id = $3")
+                .bind(keep)
+                .bind(message_id)
+                .bind(chat_id)
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn set_hidden_from_context(
+        &self,
+        chat_id: Uuid,
+        message_id: Uuid,
+        hidden: bool,
+    ) -> DbResult<()> {
+        let result = sqlx::query(
+            "UPDATE messages SET hidden_from_context = $1 WHERE id = $2 AND chat_id = $3",
+        )
+        .bind(hidden)
+        .bind(message_id)
+        .bind(chat_id)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn replace_message(&self, chat_id: Uuid, message: ChatMessage) -> DbResult<()> {
+        let message_id = message.id;
+        let _guard = self.message_locks.lock(message_id).await;
+        let alts_json = serde_json::to_value(&message.alternatives)?;
+        let senders_json = serde_json::to_value(&message.alternative_senders)?;
+        let reasoning_json = serde_json::to_value(&message.alternative_reasoning)?;
+        let tool_calls_json = message
+            .tool_calls
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
+        let result = sqlx::query(
+            "UPDATE messages SET role = $1, content = $2, sender_id = $3, alternatives = $4, alternative_senders = $5, active_index = $6, keep_in_context = $7, hidden_from_context = $8, tool_calls = $9, tool_call_id = $10, reasoning = $11, alternative_reasoning = $12 WHERE id = $13 AND chat_id = $14",
+        )
+        .bind(message.role)
+        .bind(message.content)
+        .bind(message.sender_id)
+        .bind(alts_json)
+        .bind(senders_json)
+        .bind(message.active_index as i64)
+        .bind(message.keep_in_context)
+        .bind(message.hidden_from_context)
+        .bind(tool_calls_json)
+        .bind(message.tool_call_id)
+        .bind(message.reasoning)
+        .bind(reasoning_json)
+        .bind(message_id)
+        .bind(chat_id)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Message {} not found",
+                message_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn record_usage(
+        &self,
+        chat_id: Uuid,
+        model: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+    ) -> DbResult<()> {
+        sqlx::query(
+            "INSERT INTO chat_stats (id, chat_id, model, prompt_tokens, completion_tokens) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(Uuid::now_v7())
+        .bind(chat_id)
+        .bind(model)
+        .bind(prompt_tokens as i64)
+        .bind(completion_tokens as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_usage(&self, chat_id: Uuid) -> DbResult<Vec<ModelUsage>> {
+        let rows = sqlx::query(
+            "SELECT model, SUM(prompt_tokens)::BIGINT AS prompt_tokens, SUM(completion_tokens)::BIGINT AS completion_tokens FROM chat_stats WHERE chat_id = $1 GROUP BY model ORDER BY model",
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ModelUsage {
+                model: row.get("model"),
+                prompt_tokens: row.get::<i64, _>("prompt_tokens") as u64,
+                completion_tokens: row.get::<i64, _>("completion_tokens") as u64,
+                estimated_cost: None,
+            })
+            .collect())
+    }
+
+    async fn get_lore_entries(&self) -> DbResult<Vec<LoreEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM lore_entries ORDER BY name",
+            LORE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(lore_entry_from_row).collect())
+    }
+
+    async fn create_lore_entry(&self, entry: LoreEntry) -> DbResult<()> {
+        sqlx::query(
+            "INSERT INTO lore_entries (id, name, keys, content, enabled) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(entry.id)
+        .bind(entry.name)
+        .bind(serde_json::to_value(&entry.keys)?)
+        .bind(entry.content)
+        .bind(entry.enabled)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn update_lore_entry(&self, entry: LoreEntry) -> DbResult<()> {
+        let result = sqlx::query(
+            "UPDATE lore_entries SET name = $1, keys = $2, content = $3, enabled = $4 WHERE id = $5",
+        )
+        .bind(entry.name)
+        .bind(serde_json::to_value(&entry.keys)?)
+        .bind(entry.content)
+        .bind(entry.enabled)
+        .bind(entry.id)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Lore entry {} not found",
+                entry.id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn delete_lore_entry(&self, entry_id: Uuid) -> DbResult<()> {
+        let result = sqlx::query("DELETE FROM lore_entries WHERE id = $1")
+            .bind(entry_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound(format!(
+                "Lore entry {} not found",
+                entry_id
+            )));
+        }
+        Ok(())
+    }
 }
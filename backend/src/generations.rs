@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Chats with a generation in progress. Only one runs per chat at a time, so two
+/// clients can't interleave their replies in the same chat.
+#[derive(Clone, Default)]
+pub struct ActiveGenerations {
+    chats: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+}
+
+impl ActiveGenerations {
+    /// Claim the chat for a generation, or `None` if one is already running. The claim
+    /// is released when the guard is dropped, however the generation ends.
+    pub fn try_start(&self, chat_id: Uuid) -> Option<GenerationGuard> {
+        let mut chats = self.chats.lock().expect("active generations poisoned");
+        if chats.contains_key(&chat_id) {
+            return None;
+        }
+        let token = CancellationToken::new();
+        chats.insert(chat_id, token.clone());
+        Some(GenerationGuard {
+            chats: self.chats.clone(),
+            chat_id,
+            token,
+        })
+    }
+
+    /// Ask the generation running in a chat to stop. Returns false if there is none.
+    pub fn cancel(&self, chat_id: Uuid) -> bool {
+        let chats = self.chats.lock().expect("active generations poisoned");
+        match chats.get(&chat_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct GenerationGuard {
+    chats: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+    chat_id: Uuid,
+    token: CancellationToken,
+}
+
+impl GenerationGuard {
+    /// Cancelled once someone asks the generation to stop
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for GenerationGuard {
+    fn drop(&mut self) {
+        self.chats
+            .lock()
+            .expect("active generations poisoned")
+            .remove(&self.chat_id);
+    }
+}
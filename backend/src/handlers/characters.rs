@@ -1,7 +1,7 @@
 use crate::AppState;
 use crate::dbs::DbError;
 use axum::{Json, extract::Path, extract::State, http::StatusCode};
-use shared::models::{Character, CreateCharacterRequest};
+use shared::models::{Character, CharacterBundle, CreateCharacterRequest};
 use uuid::Uuid;
 
 pub async fn list_characters(
@@ -27,6 +27,12 @@ pub async fn create_character(
         scenario: payload.scenario,
         first_message: payload.first_message,
         example_messages: payload.example_messages,
+        alternate_greetings: payload.alternate_greetings,
+        greeting_mode: payload.greeting_mode,
+        is_favorite: false,
+        response_length: payload.response_length,
+        reasoning_effort: payload.reasoning_effort,
+        metadata: payload.metadata,
     };
 
     state.db.create_character(char.clone()).await.map_err(|e| {
@@ -37,6 +43,55 @@ pub async fn create_character(
     Ok(Json(char))
 }
 
+/// Replace a character's card, keeping its id and favorite flag
+pub async fn update_character(
+    State(state): State<AppState>,
+    Path(character_id): Path<Uuid>,
+    Json(payload): Json<CreateCharacterRequest>,
+) -> Result<Json<Character>, StatusCode> {
+    let existing = state
+        .db
+        .get_character(character_id)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to get character: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    let char = Character {
+        id: character_id,
+        name: payload.name,
+        description: payload.description,
+        personality: payload.personality,
+        scenario: payload.scenario,
+        first_message: payload.first_message,
+        example_messages: payload.example_messages,
+        alternate_greetings: payload.alternate_greetings,
+        greeting_mode: payload.greeting_mode,
+        is_favorite: existing.is_favorite,
+        response_length: payload.response_length,
+        reasoning_effort: payload.reasoning_effort,
+        metadata: payload.metadata,
+    };
+
+    state
+        .db
+        .update_character(char.clone())
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to update character: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(Json(char))
+}
+
 pub async fn delete_character(
     State(state): State<AppState>,
     Path(character_id): Path<Uuid>,
@@ -56,3 +111,87 @@ pub async fn delete_character(
     })?;
     Ok(Json(()))
 }
+
+/// Flip the favorite flag of a character
+pub async fn toggle_favorite(
+    State(state): State<AppState>,
+    Path(character_id): Path<Uuid>,
+) -> Result<Json<Character>, StatusCode> {
+    let mut char = state
+        .db
+        .get_character(character_id)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to get character: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    char.is_favorite = !char.is_favorite;
+    state
+        .db
+        .set_favorite(character_id, char.is_favorite)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to update favorite: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(char))
+}
+
+/// A character with all of its chats and their messages, to share as one file
+pub async fn export_character_full(
+    State(state): State<AppState>,
+    Path(character_id): Path<Uuid>,
+) -> Result<Json<CharacterBundle>, StatusCode> {
+    let to_status = |e: DbError| match e {
+        DbError::NotFound(_) => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Failed to export character: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    let character = state
+        .db
+        .get_character(character_id)
+        .await
+        .map_err(to_status)?;
+
+    // Listings leave messages out, so each chat is loaded in full
+    let mut chats = Vec::new();
+    for listed in state
+        .db
+        .get_chats(Some(character_id), None)
+        .await
+        .map_err(to_status)?
+    {
+        if listed.character_id == character_id {
+            chats.push(state.db.get_chat(listed.id).await.map_err(to_status)?);
+        }
+    }
+
+    Ok(Json(CharacterBundle { character, chats }))
+}
+
+/// Recreate an exported character and its chats under fresh ids
+pub async fn import_character_full(
+    State(state): State<AppState>,
+    Json(bundle): Json<CharacterBundle>,
+) -> Result<Json<Character>, StatusCode> {
+    let bundle =
+        bundle.with_fresh_ids(Uuid::new_v4(), Uuid::new_v4, || state.config.clock.new_id());
+
+    state
+        .db
+        .import_character(bundle.character.clone(), bundle.chats)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to import character: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(bundle.character))
+}
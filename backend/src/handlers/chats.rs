@@ -5,7 +5,10 @@ use axum::{
     extract::{Path, State},
     http::StatusCode,
 };
-use shared::models::{Chat, ChatMessage, ChatParticipant, CreateChatRequest};
+use shared::models::{
+    Chat, ChatParticipant, ChatStats, ChatTagRequest, CreateChatRequest, MoveChatRequest,
+    ReorderParticipantsRequest, SetScenarioRequest,
+};
 use uuid::Uuid;
 
 pub async fn list_chats(
@@ -14,8 +17,12 @@ pub async fn list_chats(
 ) -> Result<Json<Vec<Chat>>, StatusCode> {
     let char_id_str = params.get("character_id");
     let char_id = char_id_str.and_then(|s| Uuid::parse_str(s).ok());
+    let tag = params
+        .get("tag")
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty());
 
-    let result = state.db.get_chats(char_id).await.map_err(|e| {
+    let result = state.db.get_chats(char_id, tag).await.map_err(|e| {
         tracing::error!("Failed to get chats: {:?}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
@@ -30,22 +37,35 @@ pub async fn create_chat(
     let id = Uuid::new_v4();
     let mut messages = Vec::new();
 
-    let char_opt = state.db.get_character(payload.character_id).await;
-    if let Ok(char) = char_opt
-        && !char.first_message.is_empty()
-    {
-        messages.push(ChatMessage::new("assistant", char.first_message));
+    if let Ok(char) = state.db.get_character(payload.character_id).await {
+        // Seeded from the clock, so a fixed clock picks the same random greeting every time
+        let seed = state
+            .config
+            .clock
+            .now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        if let Some(greeting) = char.opening_message(seed) {
+            messages.push(
+                greeting
+                    .with_id(state.config.clock.new_id())
+                    .with_created_at(state.config.clock.now().into()),
+            );
+        }
     }
 
-    let chat = Chat {
+    let mut chat = Chat {
         id,
         character_id: payload.character_id,
+        message_count: messages.len(),
         messages,
-        participants: vec![ChatParticipant {
-            character_id: payload.character_id,
-            is_active: true,
-        }],
+        participants: Vec::new(),
+        scenario_override: None,
+        tags: Vec::new(),
+        preview: None,
     };
+    chat.add_participant(payload.character_id);
 
     state.db.create_chat(chat.clone()).await.map_err(|e| {
         tracing::error!("Failed to create chat: {:?}", e);
@@ -55,6 +75,29 @@ pub async fn create_chat(
     Ok(Json(chat))
 }
 
+/// Copy a chat and all its messages under the same character, to experiment on
+pub async fn duplicate_chat(
+    State(state): State<AppState>,
+    Path(chat_id): Path<Uuid>,
+) -> Result<Json<Chat>, StatusCode> {
+    let chat = state.db.get_chat(chat_id).await.map_err(|e| match e {
+        DbError::NotFound(_) => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Failed to get chat: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let mut copy = chat.duplicate(Uuid::new_v4(), || state.config.clock.new_id());
+    copy.message_count = copy.messages.len();
+    state.db.create_chat(copy.clone()).await.map_err(|e| {
+        tracing::error!("Failed to create chat: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(copy))
+}
+
 pub async fn delete_chat(
     State(state): State<AppState>,
     Path(chat_id): Path<Uuid>,
@@ -75,6 +118,186 @@ pub async fn delete_chat(
     Ok(Json(()))
 }
 
+pub async fn move_chat(
+    State(state): State<AppState>,
+    Path(chat_id): Path<Uuid>,
+    Json(payload): Json<MoveChatRequest>,
+) -> Result<Json<Chat>, StatusCode> {
+    let mut chat = state.db.get_chat(chat_id).await.map_err(|e| match e {
+        DbError::NotFound(_) => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Failed to get chat: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+    state
+        .db
+        .get_character(payload.character_id)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to get character: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    // The new character takes the old one's place in the speaking order
+    let previous = chat.character_id;
+    if chat
+        .participants
+        .iter()
+        .any(|p| p.character_id == payload.character_id)
+    {
+        chat.participants.retain(|p| p.character_id != previous);
+    } else if let Some(p) = chat
+        .participants
+        .iter_mut()
+        .find(|p| p.character_id == previous)
+    {
+        p.character_id = payload.character_id;
+    } else {
+        chat.add_participant(payload.character_id);
+    }
+    chat.character_id = payload.character_id;
+
+    state
+        .db
+        .move_chat(chat_id, chat.character_id, &chat.participants)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to move chat: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(chat))
+}
+
+pub async fn set_scenario(
+    State(state): State<AppState>,
+    Path(chat_id): Path<Uuid>,
+    Json(payload): Json<SetScenarioRequest>,
+) -> Result<Json<()>, StatusCode> {
+    let scenario = payload.scenario.filter(|s| !s.trim().is_empty());
+    state
+        .db
+        .set_scenario_override(chat_id, scenario)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to set scenario: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(()))
+}
+
+/// Label a chat, returning all its tags
+pub async fn add_chat_tag(
+    State(state): State<AppState>,
+    Path(chat_id): Path<Uuid>,
+    Json(payload): Json<ChatTagRequest>,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    let tag = payload.tag.trim();
+    if tag.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let tags = state
+        .db
+        .add_chat_tag(chat_id, tag)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to tag chat: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(tags))
+}
+
+/// Take a label off a chat, returning the tags left
+pub async fn remove_chat_tag(
+    State(state): State<AppState>,
+    Path((chat_id, tag)): Path<(Uuid, String)>,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    let tags = state
+        .db
+        .remove_chat_tag(chat_id, &tag)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to untag chat: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(tags))
+}
+
+/// Stop the reply being generated in a chat. The stream saves what it has so far and
+/// ends as usual.
+pub async fn cancel_generation(
+    State(state): State<AppState>,
+    Path(chat_id): Path<Uuid>,
+) -> Result<Json<()>, StatusCode> {
+    if !state.generations.cancel(chat_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(()))
+}
+
+pub async fn reorder_participants(
+    State(state): State<AppState>,
+    Path(chat_id): Path<Uuid>,
+    Json(payload): Json<ReorderParticipantsRequest>,
+) -> Result<Json<Vec<ChatParticipant>>, StatusCode> {
+    let chat = state.db.get_chat(chat_id).await.map_err(|e| match e {
+        DbError::NotFound(_) => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Failed to get chat: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    // The new order has to name every participant exactly once
+    let mut ids = payload.character_ids.clone();
+    ids.sort();
+    ids.dedup();
+    if ids.len() != payload.character_ids.len()
+        || ids.len() != chat.participants.len()
+        || !chat
+            .participants
+            .iter()
+            .all(|p| payload.character_ids.contains(&p.character_id))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let participants = payload
+        .character_ids
+        .iter()
+        .enumerate()
+        .filter_map(|(order, id)| {
+            let participant = chat.participants.iter().find(|p| p.character_id == *id)?;
+            Some(ChatParticipant {
+                order: order as i32,
+                ..participant.clone()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    state
+        .db
+        .set_participants(chat_id, &participants)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to reorder participants: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(participants))
+}
+
 pub async fn get_chat(
     State(state): State<AppState>,
     Path(chat_id): Path<Uuid>,
@@ -89,3 +312,27 @@ pub async fn get_chat(
     })?;
     Ok(Json(chat))
 }
+
+pub async fn get_chat_stats(
+    State(state): State<AppState>,
+    Path(chat_id): Path<Uuid>,
+) -> Result<Json<ChatStats>, StatusCode> {
+    if let Err(e) = state.db.get_chat(chat_id).await {
+        if matches!(e, DbError::NotFound(_)) {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        tracing::error!("Failed to get chat for stats: {:?}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let models = state.db.get_usage(chat_id).await.map_err(|e| {
+        tracing::error!("Failed to get chat usage: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(ChatStats::from_usage(
+        chat_id,
+        models,
+        &state.config.pricing,
+    )))
+}
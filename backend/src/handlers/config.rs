@@ -0,0 +1,21 @@
+use crate::AppState;
+use axum::{Json, extract::State};
+use shared::models::{ConfigDefaults, ServerInfo};
+
+pub async fn get_config_defaults() -> Json<ConfigDefaults> {
+    Json(ConfigDefaults::default())
+}
+
+pub async fn get_server_info(State(state): State<AppState>) -> Json<ServerInfo> {
+    Json(ServerInfo {
+        ephemeral: state.db.is_ephemeral(),
+    })
+}
+
+/// Liveness, and whether the plugins found at startup have finished loading
+pub async fn health(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "ok",
+        "plugins_ready": state.plugins.is_ready(),
+    }))
+}
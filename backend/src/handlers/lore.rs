@@ -0,0 +1,98 @@
+use super::check_length;
+use crate::AppState;
+use crate::dbs::DbError;
+use axum::{Json, extract::Path, extract::State, http::StatusCode};
+use shared::models::{LoreEntry, LoreEntryRequest};
+use uuid::Uuid;
+
+/// An entry from a request, with blank keys dropped. It needs a name and a key, and no
+/// part of it may be longer than a message.
+fn lore_entry(
+    state: &AppState,
+    id: Uuid,
+    payload: LoreEntryRequest,
+) -> Result<LoreEntry, StatusCode> {
+    let entry = LoreEntry {
+        id,
+        name: payload.name.trim().to_string(),
+        keys: payload
+            .keys
+            .into_iter()
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect(),
+        content: payload.content,
+        enabled: payload.enabled,
+    };
+    if entry.name.is_empty() || entry.keys.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    for text in [&entry.name, &entry.content].into_iter().chain(&entry.keys) {
+        check_length(state, text)?;
+    }
+    Ok(entry)
+}
+
+pub async fn list_lore_entries(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LoreEntry>>, StatusCode> {
+    let entries = state.db.get_lore_entries().await.map_err(|e| {
+        tracing::error!("Failed to list lore entries: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(entries))
+}
+
+pub async fn create_lore_entry(
+    State(state): State<AppState>,
+    Json(payload): Json<LoreEntryRequest>,
+) -> Result<Json<LoreEntry>, StatusCode> {
+    let entry = lore_entry(&state, state.config.clock.new_id(), payload)?;
+    state
+        .db
+        .create_lore_entry(entry.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create lore entry: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(entry))
+}
+
+pub async fn update_lore_entry(
+    State(state): State<AppState>,
+    Path(entry_id): Path<Uuid>,
+    Json(payload): Json<LoreEntryRequest>,
+) -> Result<Json<LoreEntry>, StatusCode> {
+    let entry = lore_entry(&state, entry_id, payload)?;
+    state
+        .db
+        .update_lore_entry(entry.clone())
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to update lore entry: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(entry))
+}
+
+pub async fn delete_lore_entry(
+    State(state): State<AppState>,
+    Path(entry_id): Path<Uuid>,
+) -> Result<Json<()>, StatusCode> {
+    state
+        .db
+        .delete_lore_entry(entry_id)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to delete lore entry: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(()))
+}
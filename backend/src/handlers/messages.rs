@@ -1,3 +1,4 @@
+use super::check_length;
 use crate::AppState;
 use crate::dbs::DbError;
 use axum::{
@@ -5,7 +6,10 @@ use axum::{
     extract::{Path, State},
     http::StatusCode,
 };
-use shared::models::{ChatMessage, EditMessageRequest, SwipeDirection, SwipeRequest};
+use shared::models::{
+    ChatMessage, EditMessageRequest, HiddenFromContextRequest, KeepInContextRequest,
+    ROLE_ASSISTANT, ROLE_SYSTEM, ROLE_TOOL, ROLE_USER, SwipeDirection, SwipeRequest,
+};
 use uuid::Uuid;
 
 pub async fn append_message(
@@ -13,13 +17,22 @@ pub async fn append_message(
     Path(chat_id): Path<Uuid>,
     Json(payload): Json<ChatMessage>,
 ) -> Result<Json<()>, StatusCode> {
+    check_length(&state, &payload.content)?;
+    for alternative in &payload.alternatives {
+        check_length(&state, alternative)?;
+    }
+    // Stamped with the server's time, a client clock that's off mustn't reorder the chat
+    let message = payload.with_created_at(state.config.clock.now().into());
     state
         .db
-        .append_message(chat_id, payload)
+        .append_message(chat_id, message)
         .await
-        .map_err(|e| {
-            tracing::error!("Failed to append message: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to append message: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
         })?;
     Ok(Json(()))
 }
@@ -29,6 +42,7 @@ pub async fn edit_message(
     Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
     Json(payload): Json<EditMessageRequest>,
 ) -> Result<Json<()>, StatusCode> {
+    check_length(&state, &payload.content)?;
     if let Err(e) = state.db.get_message(chat_id, message_id).await {
         if matches!(e, DbError::NotFound(_)) {
             return Err(StatusCode::NOT_FOUND);
@@ -48,6 +62,66 @@ pub async fn edit_message(
     Ok(Json(()))
 }
 
+/// Why a raw edit of message `message_id` can't be stored as is, if it can't
+fn check_raw_message(message_id: Uuid, message: &ChatMessage) -> Result<(), String> {
+    if message.id != message_id {
+        return Err("The message id can't be changed".to_string());
+    }
+    if ![ROLE_USER, ROLE_ASSISTANT, ROLE_SYSTEM, ROLE_TOOL].contains(&message.role.as_str()) {
+        return Err(format!("Unknown role \"{}\"", message.role));
+    }
+    if message.role == ROLE_TOOL && message.tool_call_id.is_none() {
+        return Err("A tool message needs a tool_call_id".to_string());
+    }
+    if message.active_index >= message.variant_count() {
+        return Err(format!(
+            "active_index {} is out of range, the message has {} variants",
+            message.active_index,
+            message.variant_count()
+        ));
+    }
+    if message.alternative_senders.len() > message.alternatives.len() {
+        return Err("alternative_senders is longer than alternatives".to_string());
+    }
+    Ok(())
+}
+
+/// Replace the whole stored message, for debugging prompts by hand
+pub async fn replace_message(
+    State(state): State<AppState>,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<ChatMessage>,
+) -> Result<Json<()>, (StatusCode, String)> {
+    check_raw_message(message_id, &payload).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    for content in std::iter::once(&payload.content).chain(&payload.alternatives) {
+        check_length(&state, content).map_err(|status| {
+            (
+                status,
+                format!(
+                    "Content is longer than {} characters",
+                    state.config.max_message_length
+                ),
+            )
+        })?;
+    }
+
+    state
+        .db
+        .replace_message(chat_id, payload)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(e) => (StatusCode::NOT_FOUND, e),
+            e => {
+                tracing::error!("Failed to replace message: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to save the message".to_string(),
+                )
+            }
+        })?;
+    Ok(Json(()))
+}
+
 pub async fn delete_message(
     State(state): State<AppState>,
     Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
@@ -101,3 +175,96 @@ pub async fn swipe_message(
         })?;
     Ok(Json(()))
 }
+
+pub async fn set_keep_in_context(
+    State(state): State<AppState>,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<KeepInContextRequest>,
+) -> Result<Json<()>, StatusCode> {
+    state
+        .db
+        .set_keep_in_context(chat_id, message_id, payload.keep)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to update message: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(()))
+}
+
+pub async fn set_hidden_from_context(
+    State(state): State<AppState>,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<HiddenFromContextRequest>,
+) -> Result<Json<()>, StatusCode> {
+    state
+        .db
+        .set_hidden_from_context(chat_id, message_id, payload.hidden)
+        .await
+        .map_err(|e| match e {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to update message: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(()))
+}
+
+pub async fn reset_message(
+    State(state): State<AppState>,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<()>, StatusCode> {
+    if let Err(e) = state.db.get_message(chat_id, message_id).await {
+        if matches!(e, DbError::NotFound(_)) {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        tracing::error!("Failed to get message for reset: {:?}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    state
+        .db
+        .set_active_alternative(chat_id, message_id, 0)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to reset message: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_raw_message_rejects_inconsistent_messages() {
+        let message = ChatMessage::new(ROLE_USER, "Hi");
+        let id = message.id;
+        assert_eq!(check_raw_message(id, &message), Ok(()));
+        assert!(check_raw_message(Uuid::now_v7(), &message).is_err());
+
+        let mut unknown = message.clone();
+        unknown.role = "narrator".to_string();
+        assert!(check_raw_message(id, &unknown).is_err());
+
+        let mut tool = message.clone();
+        tool.role = ROLE_TOOL.to_string();
+        assert!(check_raw_message(id, &tool).is_err());
+        tool.tool_call_id = Some("call".to_string());
+        assert_eq!(check_raw_message(id, &tool), Ok(()));
+
+        let mut swiped = message.clone();
+        swiped.active_index = 1;
+        assert!(check_raw_message(id, &swiped).is_err());
+        swiped.push_alternative("Hello", None);
+        assert_eq!(check_raw_message(id, &swiped), Ok(()));
+
+        swiped.alternative_senders.push(None);
+        assert!(check_raw_message(id, &swiped).is_err());
+    }
+}
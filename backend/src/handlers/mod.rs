@@ -1,9 +1,30 @@
 pub mod characters;
 pub mod chats;
+pub mod config;
+pub mod lore;
 pub mod messages;
 pub mod plugins;
 
 pub use characters::*;
 pub use chats::*;
+pub use config::*;
+pub use lore::*;
 pub use messages::*;
 pub use plugins::*;
+
+use crate::AppState;
+use axum::http::StatusCode;
+
+/// Reject content longer than the configured limit
+fn check_length(state: &AppState, content: &str) -> Result<(), StatusCode> {
+    let length = content.chars().count();
+    if length > state.config.max_message_length {
+        tracing::warn!(
+            "Rejected {} characters of content, the limit is {}",
+            length,
+            state.config.max_message_length
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
@@ -1,10 +1,11 @@
 use crate::AppState;
 use axum::{
     Json,
-    extract::{Multipart, Path, State},
+    extract::{Multipart, Path, Query, State},
     http::StatusCode,
 };
-use shared::models::PluginManifest;
+use serde::Deserialize;
+use shared::models::{DiscoveryReport, PluginManifest, Resource, ResourceContents};
 use std::path::PathBuf;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
@@ -28,13 +29,102 @@ pub async fn toggle_plugin(
     Ok(Json(()))
 }
 
+pub async fn list_plugin_resources(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<Resource>>, StatusCode> {
+    let resources = state.plugins.list_resources(&name).await.map_err(|e| {
+        tracing::error!("Failed to list resources of plugin {}: {:?}", name, e);
+        StatusCode::NOT_FOUND
+    })?;
+    Ok(Json(resources))
+}
+
+#[derive(Deserialize)]
+pub struct ResourceQuery {
+    pub uri: String,
+}
+
+pub async fn read_plugin_resource(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<ResourceQuery>,
+) -> Result<Json<ResourceContents>, StatusCode> {
+    let contents = state
+        .plugins
+        .read_resource(&name, &query.uri)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "Failed to read resource {} of plugin {}: {:?}",
+                query.uri,
+                name,
+                e
+            );
+            StatusCode::NOT_FOUND
+        })?;
+    Ok(Json(contents))
+}
+
+pub async fn discover_plugins(
+    State(state): State<AppState>,
+) -> Result<Json<DiscoveryReport>, StatusCode> {
+    let report = state
+        .plugins
+        .discover_plugins("./plugins")
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to discover plugins: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(report))
+}
+
+pub async fn reload_plugins(
+    State(state): State<AppState>,
+) -> Result<Json<DiscoveryReport>, StatusCode> {
+    let report = state
+        .plugins
+        .reload_plugins("./plugins")
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to reload plugins: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(report))
+}
+
+/// Turn a failed write into the plugins directory into a response the user can act on
+fn plugin_write_error(e: std::io::Error) -> (StatusCode, String) {
+    tracing::error!("Failed to write to the plugins directory: {:?}", e);
+    match e.kind() {
+        std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "The plugins directory is not writable. Make it writable, or place plugins there \
+             by hand and start the server with --no-plugin-install."
+                .to_string(),
+        ),
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to save the plugin: {}", e),
+        ),
+    }
+}
+
 pub async fn install_plugin(
     State(state): State<AppState>,
     mut multipart: Multipart,
-) -> Result<Json<()>, StatusCode> {
+) -> Result<Json<()>, (StatusCode, String)> {
+    if !state.config.allow_plugin_install {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Plugin installation is disabled on this server".to_string(),
+        ));
+    }
+
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         tracing::error!("Failed to get next field: {:?}", e);
-        StatusCode::BAD_REQUEST
+        (StatusCode::BAD_REQUEST, e.to_string())
     })? {
         let name = field.name().unwrap_or_default().to_string();
         let file_name = field.file_name().unwrap_or_default().to_string();
@@ -43,35 +133,30 @@ pub async fn install_plugin(
             let data = field
                 .bytes()
                 .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
             let mut path = PathBuf::from("./plugins");
             if !path.exists() {
                 tokio::fs::create_dir_all(&path)
                     .await
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                    .map_err(plugin_write_error)?;
             }
             path.push(&file_name);
 
-            let mut file = File::create(&path).await.map_err(|e| {
-                tracing::error!("Failed to create file: {:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-            file.write_all(&data)
-                .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let mut file = File::create(&path).await.map_err(plugin_write_error)?;
+            file.write_all(&data).await.map_err(plugin_write_error)?;
 
             #[cfg(unix)]
             {
                 use std::os::unix::fs::PermissionsExt;
                 let mut perms = tokio::fs::metadata(&path)
                     .await
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                    .map_err(plugin_write_error)?
                     .permissions();
                 perms.set_mode(0o755);
                 tokio::fs::set_permissions(&path, perms)
                     .await
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                    .map_err(plugin_write_error)?;
             }
 
             // Immediately discover the new plugin
@@ -79,11 +164,36 @@ pub async fn install_plugin(
                 .plugins
                 .load_plugin(path.to_str().unwrap())
                 .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                .map_err(|e| {
+                    tracing::error!("Failed to load installed plugin: {:?}", e);
+                    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+                })?;
 
             return Ok(Json(()));
         }
     }
 
-    Err(StatusCode::BAD_REQUEST)
+    Err((
+        StatusCode::BAD_REQUEST,
+        "No plugin file in the upload".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn a_read_only_plugins_directory_says_what_to_do() {
+        for kind in [ErrorKind::PermissionDenied, ErrorKind::ReadOnlyFilesystem] {
+            let (status, message) = plugin_write_error(Error::from(kind));
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert!(message.starts_with("The plugins directory is not writable."));
+            assert!(message.contains("--no-plugin-install"));
+        }
+
+        let (_, message) = plugin_write_error(Error::other("disk full"));
+        assert_eq!(message, "Failed to save the plugin: disk full");
+    }
 }
@@ -0,0 +1,135 @@
+//! Fixes for the small JSON mistakes models make in tool call arguments, so a call can run
+//! without spending a whole turn on the model correcting itself.
+
+use serde_json::Value;
+
+/// Parse `raw` as JSON, fixing common mistakes if it doesn't parse as is:
+/// an empty string, a markdown code fence around it, single-quoted strings,
+/// raw newlines or tabs inside strings, and trailing commas.
+///
+/// Returns `None` when it still isn't valid JSON after the fixes.
+pub fn repair_json(raw: &str) -> Option<Value> {
+    if let Ok(value) = serde_json::from_str(raw) {
+        return Some(value);
+    }
+    let trimmed = strip_code_fence(raw.trim());
+    if trimmed.is_empty() {
+        // Tools without parameters often get no arguments at all
+        return Some(Value::Object(Default::default()));
+    }
+    serde_json::from_str(&normalize(trimmed)).ok()
+}
+
+/// The contents of a ```json fenced block, or the input if it isn't one
+fn strip_code_fence(text: &str) -> &str {
+    let Some(inner) = text.strip_prefix("```") else {
+        return text;
+    };
+    let inner = inner.strip_suffix("```").unwrap_or(inner);
+    // Drop the language tag on the opening line
+    match inner.split_once('\n') {
+        Some((tag, body)) if !tag.trim_start().starts_with(['{', '[']) => body.trim(),
+        _ => inner.trim(),
+    }
+}
+
+/// Rewrite strings to double quotes with escaped control characters, and drop trailing commas
+fn normalize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    // The quote character of the string being read, if inside one
+    let mut quote = None;
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => match c {
+                '\\' => match chars.next() {
+                    // `\'` is only an escape in single-quoted strings
+                    Some('\'') => out.push('\''),
+                    Some(next) => {
+                        out.push('\\');
+                        out.push(next);
+                    }
+                    None => out.push_str("\\\\"),
+                },
+                c if c == q => {
+                    out.push('"');
+                    quote = None;
+                }
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            },
+            None => match c {
+                '"' | '\'' => {
+                    out.push('"');
+                    quote = Some(c);
+                }
+                ',' => {
+                    let rest = chars.clone().find(|c| !c.is_whitespace());
+                    if !matches!(rest, Some('}' | ']')) {
+                        out.push(c);
+                    }
+                }
+                c => out.push(c),
+            },
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn trailing_commas_are_dropped() {
+        assert_eq!(
+            repair_json(r#"{"a": 1, "b": [1, 2,],}"#),
+            Some(json!({ "a": 1, "b": [1, 2] }))
+        );
+        assert_eq!(repair_json("[1,\n]"), Some(json!([1])));
+        // Commas inside strings stay
+        assert_eq!(repair_json(r#"{"a": "x,}",}"#), Some(json!({ "a": "x,}" })));
+    }
+
+    #[test]
+    fn single_quotes_become_double_quotes() {
+        assert_eq!(repair_json("{'a': 'b'}"), Some(json!({ "a": "b" })));
+        assert_eq!(
+            repair_json(r#"{'quote': 'say "hi"', 'it': 'it\'s'}"#),
+            Some(json!({ "quote": "say \"hi\"", "it": "it's" }))
+        );
+    }
+
+    #[test]
+    fn raw_newlines_and_tabs_in_strings_are_escaped() {
+        assert_eq!(
+            repair_json("{\"text\": \"one\ntwo\tthree\"}"),
+            Some(json!({ "text": "one\ntwo\tthree" }))
+        );
+        // Between values they're just whitespace
+        assert_eq!(repair_json("{\n\"a\": 1\n}"), Some(json!({ "a": 1 })));
+    }
+
+    #[test]
+    fn fences_and_missing_arguments_are_handled() {
+        assert_eq!(
+            repair_json("```json\n{\"a\": 1}\n```"),
+            Some(json!({ "a": 1 }))
+        );
+        assert_eq!(repair_json("```{\"a\": 1}```"), Some(json!({ "a": 1 })));
+        assert_eq!(repair_json("  "), Some(json!({})));
+    }
+
+    #[test]
+    fn what_still_is_not_json_is_left_alone() {
+        assert_eq!(repair_json("{sides: 6}"), None);
+        assert_eq!(repair_json(r#"{"a": "#), None);
+        assert_eq!(repair_json("roll a die"), None);
+    }
+}
@@ -1,55 +1,147 @@
+mod cards;
+pub mod clock;
+pub mod config;
 mod dbs;
+mod generations;
 mod handlers;
+mod json_repair;
 mod openai;
 pub mod plugins;
+mod provider_log;
+mod seed;
 
 use crate::dbs::Database;
 use crate::dbs::local::LocalDatabase;
 use crate::dbs::postgres::PostgresDatabase;
+use crate::generations::ActiveGenerations;
 use crate::handlers::{
-    append_message, create_character, create_chat, delete_character, delete_chat, delete_message,
-    edit_message, get_chat, list_characters, list_chats, list_plugins, swipe_message,
-    toggle_plugin,
+    add_chat_tag, append_message, cancel_generation, create_character, create_chat,
+    create_lore_entry, delete_character, delete_chat, delete_lore_entry, delete_message,
+    discover_plugins, duplicate_chat, edit_message, export_character_full, get_chat,
+    get_chat_stats, get_config_defaults, get_server_info, health, import_character_full,
+    list_characters, list_chats, list_lore_entries, list_plugin_resources, list_plugins, move_chat,
+    read_plugin_resource, reload_plugins, remove_chat_tag, reorder_participants, replace_message,
+    reset_message, set_hidden_from_context, set_keep_in_context, set_scenario, swipe_message,
+    toggle_favorite, toggle_plugin, update_character, update_lore_entry,
 };
 use crate::openai::generate_response;
 use crate::plugins::PluginManager;
 use axum::{
     Router,
+    extract::DefaultBodyLimit,
     routing::{delete, get, post, put},
 };
+pub use config::Config;
 pub use dbs::DatabaseConfig;
 use std::sync::Arc;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<dyn Database>,
     pub plugins: PluginManager,
+    pub config: Arc<Config>,
+    generations: ActiveGenerations,
 }
 
-pub async fn init(router: Router<AppState>, config: DatabaseConfig) -> Router<()> {
-    let db: Arc<dyn Database> = match config {
+pub async fn init(
+    router: Router<AppState>,
+    db_config: DatabaseConfig,
+    config: Config,
+) -> Router<()> {
+    let db: Arc<dyn Database> = match db_config {
         DatabaseConfig::Local { url } => Arc::new(LocalDatabase::new(&url).await),
         DatabaseConfig::Postgres { url } => Arc::new(PostgresDatabase::new(&url).await),
+        DatabaseConfig::Memory => {
+            tracing::warn!("Running with an in-memory database, chats are lost on restart");
+            Arc::new(LocalDatabase::in_memory().await)
+        }
     };
 
-    let plugins = PluginManager::new();
-    if let Err(e) = plugins.discover_plugins("./plugins").await {
-        tracing::error!("Failed to discover plugins: {:?}", e);
+    // Before seeding, so imported cards take the place of the sample character
+    if let Some(dir) = &config.import_cards_dir {
+        match cards::import_card_dir(db.as_ref(), dir).await {
+            Ok(report) => {
+                for failure in &report.failed {
+                    tracing::warn!("Skipped card {}: {}", failure.file, failure.error);
+                }
+                tracing::info!(
+                    "Imported {} cards from {}, {} failed",
+                    report.imported.len(),
+                    dir.display(),
+                    report.failed.len()
+                );
+            }
+            Err(e) => tracing::error!("Failed to import cards from {}: {:?}", dir.display(), e),
+        }
     }
 
-    let state = AppState { db, plugins };
+    if !config.skip_seed {
+        match seed::seed_sample_character(db.as_ref()).await {
+            Ok(true) => tracing::info!("Seeded sample character"),
+            Ok(false) => {}
+            Err(e) => tracing::error!("Failed to seed sample character: {:?}", e),
+        }
+    }
+
+    let plugins = PluginManager::with_filter(config.plugin_filter.clone());
+    // Completions wait a little for this, see `generate_response`
+    plugins.discover_in_background("./plugins");
+    plugins.watch_health(config.plugin_health.clone());
+
+    // A bundle carries whole chats, well past the default body limit
+    let import_limit = DefaultBodyLimit::max(config.max_import_size);
+    let state = AppState {
+        db,
+        plugins,
+        config: Arc::new(config),
+        generations: ActiveGenerations::default(),
+    };
 
     router
-        .route("/api/health", get(|| async { "OK" }))
+        .route("/api/health", get(health))
+        .route("/api/config/defaults", get(get_config_defaults))
+        .route("/api/config/server", get(get_server_info))
         .route(
             "/api/characters",
             get(list_characters).post(create_character),
         )
-        .route("/api/characters/{character_id}", delete(delete_character))
+        .route(
+            "/api/characters/{character_id}",
+            put(update_character).delete(delete_character),
+        )
+        .route(
+            "/api/characters/{character_id}/favorite",
+            post(toggle_favorite),
+        )
+        .route(
+            "/api/characters/{character_id}/export/full",
+            get(export_character_full),
+        )
+        .route(
+            "/api/characters/import/full",
+            post(import_character_full).layer(import_limit),
+        )
+        .route("/api/lore", get(list_lore_entries).post(create_lore_entry))
+        .route(
+            "/api/lore/{entry_id}",
+            put(update_lore_entry).delete(delete_lore_entry),
+        )
         .route("/api/chats", get(list_chats).post(create_chat))
         .route("/api/chats/{chat_id}", get(get_chat).delete(delete_chat))
         .route("/api/chats/{chat_id}/message", post(append_message))
+        .route("/api/chats/{chat_id}/cancel", post(cancel_generation))
+        .route("/api/chats/{chat_id}/stats", get(get_chat_stats))
+        .route("/api/chats/{chat_id}/move", post(move_chat))
+        .route("/api/chats/{chat_id}/duplicate", post(duplicate_chat))
+        .route("/api/chats/{chat_id}/scenario", put(set_scenario))
+        .route("/api/chats/{chat_id}/tags", post(add_chat_tag))
+        .route("/api/chats/{chat_id}/tags/{tag}", delete(remove_chat_tag))
+        .route(
+            "/api/chats/{chat_id}/participants/reorder",
+            post(reorder_participants),
+        )
         .route(
             "/api/chats/{chat_id}/messages/{message_id}",
             put(edit_message).delete(delete_message),
@@ -58,21 +150,30 @@ pub async fn init(router: Router<AppState>, config: DatabaseConfig) -> Router<()
             "/api/chats/{chat_id}/messages/{message_id}/swipe",
             post(swipe_message),
         )
+        .route(
+            "/api/chats/{chat_id}/messages/{message_id}/reset",
+            post(reset_message),
+        )
+        .route(
+            "/api/chats/{chat_id}/messages/{message_id}/keep-in-context",
+            put(set_keep_in_context),
+        )
+        .route(
+            "/api/chats/{chat_id}/messages/{message_id}/hidden-from-context",
+            put(set_hidden_from_context),
+        )
+        .route(
+            "/api/chats/{chat_id}/messages/{message_id}/raw",
+            put(replace_message),
+        )
         .route("/api/completion", post(generate_response))
         .route("/api/plugins", get(list_plugins))
         .route("/api/plugins/install", post(handlers::install_plugin))
         .route("/api/plugins/{name}/toggle", post(toggle_plugin))
-        .route(
-            "/api/plugins/discover",
-            post(|state: axum::extract::State<AppState>| async move {
-                state
-                    .plugins
-                    .discover_plugins("./plugins")
-                    .await
-                    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-                Ok::<(), axum::http::StatusCode>(())
-            }),
-        )
+        .route("/api/plugins/{name}/resources", get(list_plugin_resources))
+        .route("/api/plugins/{name}/resource", get(read_plugin_resource))
+        .route("/api/plugins/discover", post(discover_plugins))
+        .route("/api/plugins/reload", post(reload_plugins))
         .route(
             "/favicon.ico",
             get(|| async {
@@ -85,6 +186,8 @@ pub async fn init(router: Router<AppState>, config: DatabaseConfig) -> Router<()
                 )
             }),
         )
+        // The default predicate skips text/event-stream, so completions still stream unbuffered
+        .layer(CompressionLayer::new())
         .layer(CorsLayer::permissive())
         .with_state(state)
 }
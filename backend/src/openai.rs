@@ -1,64 +1,186 @@
 use crate::AppState;
+use crate::json_repair::repair_json;
+use crate::provider_log::ProviderLog;
 use async_openai::{
     Client,
     config::OpenAIConfig,
+    error::OpenAIError,
     types::chat::{
         ChatCompletionMessageToolCall, ChatCompletionMessageToolCalls,
         ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestAssistantMessageContent,
         ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
         ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
-        ChatCompletionTool, ChatCompletionTools, CreateChatCompletionRequestArgs, FunctionCall,
-        FunctionObject,
+        ChatCompletionStreamOptions, ChatCompletionTool, ChatCompletionTools,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+        CreateChatCompletionStreamResponse, FunctionCall, FunctionObject, ReasoningEffort,
     },
 };
-use axum::{Json, extract::State, response::IntoResponse};
+use axum::{
+    Json,
+    extract::{State, rejection::JsonRejection},
+    response::IntoResponse,
+};
 use futures::StreamExt;
-use shared::models::{CompletionRequest, ROLE_ASSISTANT, ROLE_SYSTEM, ROLE_TOOL, ROLE_USER};
+use shared::models::{
+    CompletionRequest, DEFAULT_API_BASE, ERROR_MISSING_API_KEY, LoreEntry, MAX_ALTERNATIVES,
+    ROLE_ASSISTANT, ROLE_SYSTEM, ROLE_TOOL, ROLE_USER, ResponseLength, TokenLogprob,
+};
+use shared::text::strip_leaked_prefix;
+use shared::validation::{validate_api_base, validate_extra_body};
+use std::collections::HashMap;
 use std::io::Error;
+use std::time::Duration;
+
+/// Model calls in one completion, so a model that keeps calling tools can't loop forever
+const MAX_TOOL_ROUNDS: usize = 5;
 
-const DEFAULT_API_BASE: &str = "https://openrouter.ai/api/v1";
+/// How long a completion waits for startup plugin discovery before going ahead with the
+/// tools loaded so far
+const PLUGIN_DISCOVERY_WAIT: Duration = Duration::from_secs(10);
+
+/// Extra knobs for assembling the prompt
+#[derive(Default)]
+struct ConversationOptions {
+    /// Have the model write the user's next message instead of the character's
+    impersonate: bool,
+    /// Other characters present in a group chat
+    roster: Vec<shared::models::Character>,
+    /// Character id -> name, to attribute assistant turns in group chats
+    speakers: HashMap<uuid::Uuid, String>,
+    /// Only send this many recent non-system messages
+    history_limit: Option<usize>,
+    /// Sent as the whole system prompt instead of the one built from the character
+    system_prompt_override: Option<String>,
+    /// Cut tool results down to this many characters
+    tool_result_limit: Option<usize>,
+    /// The chat's own scenario, used in place of the character's
+    scenario_override: Option<String>,
+    /// Put the character's example messages in the system prompt
+    include_examples: bool,
+    /// Send back-to-back replies from the same sender as one assistant message
+    merge_consecutive_replies: bool,
+    /// Global lorebook entries, sent when their keys come up
+    lore: Vec<LoreEntry>,
+    /// Most tokens of lore to send
+    lore_budget: usize,
+    /// A one-off steer for this generation, sent last as a system message
+    instruction: Option<String>,
+}
+
+/// Shorten a tool result to at most `limit` characters, saying how much was cut. The note
+/// counts towards the limit; a limit too small for it gets a bare cut instead.
+fn truncate_tool_result(content: &str, limit: Option<usize>) -> String {
+    let Some(limit) = limit else {
+        return content.to_string();
+    };
+    let total = content.chars().count();
+    if total <= limit {
+        return content.to_string();
+    }
+    let note = |omitted: usize| format!("…[truncated, {} chars omitted]", omitted);
+    // No more than `total` can be omitted, so this is the longest the note gets
+    let room = note(total).chars().count();
+    if room > limit {
+        return content.chars().take(limit).collect();
+    }
+    let keep = limit - room;
+    let cut = content
+        .char_indices()
+        .nth(keep)
+        .map_or(content.len(), |(i, _)| i);
+    format!("{}{}", &content[..cut], note(total - keep))
+}
+
+/// Tool call arguments with minor JSON mistakes fixed. Saved and sent back to the model in
+/// place of the originals. Arguments that can't be fixed are kept as they are, so the parse
+/// error reaches the model.
+fn repaired_arguments(tool: &str, raw: &str) -> String {
+    if serde_json::from_str::<serde_json::Value>(raw).is_ok() {
+        return raw.to_string();
+    }
+    match repair_json(raw) {
+        Some(value) => {
+            tracing::info!("Repaired malformed arguments for tool call {}", tool);
+            value.to_string()
+        }
+        None => raw.to_string(),
+    }
+}
+
+/// Serialize a streaming completion request, merging in the caller's extra fields.
+/// Extra fields can override the standard ones, but never turn streaming off.
+fn request_body(
+    request: &CreateChatCompletionRequest,
+    extra_body: Option<&serde_json::Value>,
+) -> serde_json::Result<serde_json::Value> {
+    let mut body = serde_json::to_value(request)?;
+    if let (Some(fields), Some(serde_json::Value::Object(extra))) =
+        (body.as_object_mut(), extra_body)
+    {
+        for (key, value) in extra {
+            fields.insert(key.clone(), value.clone());
+        }
+        fields.insert("stream".to_string(), serde_json::Value::Bool(true));
+    }
+    Ok(body)
+}
 
 /// Build a conversation from chat messages, optionally truncating at a specific message
 fn build_conversation(
     messages: &[shared::models::ChatMessage],
     character: Option<&shared::models::Character>,
     truncate_at: Option<uuid::Uuid>,
+    options: &ConversationOptions,
 ) -> Vec<ChatCompletionRequestMessage> {
+    let end = truncate_at
+        .and_then(|id| messages.iter().position(|m| m.id == id))
+        .unwrap_or(messages.len());
+    let messages = history_window(&messages[..end], options.history_limit);
+    let messages = messages.as_slice();
+
+    if options.impersonate {
+        return build_impersonation(messages, character, options);
+    }
+
     let mut conversation: Vec<ChatCompletionRequestMessage> = Vec::new();
 
-    // Add system prompt if character exists
-    if let Some(char) = character {
-        let mut system_prompt = String::new();
-        system_prompt.push_str(&format!("Name: {}", char.name));
-        if !char.description.is_empty() {
-            system_prompt.push_str(&format!("\nDescription: {}", char.description));
-        }
-        if !char.personality.is_empty() {
-            system_prompt.push_str(&format!("\nPersonality: {}", char.personality));
-        }
-        if !char.scenario.is_empty() {
-            system_prompt.push_str(&format!("\nScenario: {}", char.scenario));
-        }
-        if !char.example_messages.is_empty() {
-            system_prompt.push_str(&format!("\nExample messages: {}", char.example_messages));
+    // An override replaces the character prompt, roster included
+    let system_prompt = match &options.system_prompt_override {
+        Some(prompt) => Some(prompt.clone()),
+        None => {
+            character.map(|char| character_prompt(char, options) + &roster_prompt(&options.roster))
         }
-        if let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
-            .content(system_prompt)
+    };
+    if let Some(prompt) = system_prompt
+        && let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
+            .content(prompt)
             .build()
-        {
-            conversation.push(ChatCompletionRequestMessage::System(msg));
-        }
+    {
+        conversation.push(ChatCompletionRequestMessage::System(msg));
+    }
+
+    if let Some(lore) = lore_prompt(messages, &options.lore, options.lore_budget)
+        && let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
+            .content(lore)
+            .build()
+    {
+        conversation.push(ChatCompletionRequestMessage::System(msg));
     }
 
-    // Add messages, stopping before truncate_at if specified
+    let mut previous: Option<&shared::models::ChatMessage> = None;
     for msg in messages {
-        if let Some(trunc_id) = truncate_at
-            && msg.id == trunc_id
+        let content = msg.active_content().to_string();
+        if options.merge_consecutive_replies
+            && let Some(prev) = previous.replace(msg)
+            && continues_reply(prev, msg)
+            && let Some(ChatCompletionRequestMessage::Assistant(last)) = conversation.last_mut()
+            && let Some(ChatCompletionRequestAssistantMessageContent::Text(text)) =
+                &mut last.content
         {
-            break;
+            text.push('\n');
+            text.push_str(&content);
+            continue;
         }
-
-        let content = msg.active_content().to_string();
         let req_msg = if msg.role == ROLE_USER {
             let user_msg = ChatCompletionRequestUserMessageArgs::default()
                 .content(content)
@@ -67,6 +189,13 @@ fn build_conversation(
             ChatCompletionRequestMessage::User(user_msg)
         } else if msg.role == ROLE_ASSISTANT {
             let mut assistant_msg_builder = ChatCompletionRequestAssistantMessageArgs::default();
+            if let Some(name) = msg
+                .active_sender()
+                .and_then(|id| options.speakers.get(&id))
+                .and_then(|name| speaker_name(name))
+            {
+                assistant_msg_builder.name(name);
+            }
             if !content.is_empty() {
                 assistant_msg_builder
                     .content(ChatCompletionRequestAssistantMessageContent::Text(content));
@@ -93,7 +222,7 @@ fn build_conversation(
         } else if msg.role == ROLE_TOOL {
             let tool_call_id = msg.tool_call_id.clone().unwrap_or_default();
             let tool_msg = ChatCompletionRequestToolMessageArgs::default()
-                .content(content)
+                .content(truncate_tool_result(&content, options.tool_result_limit))
                 .tool_call_id(tool_call_id)
                 .build()
                 .unwrap_or_default();
@@ -110,6 +239,264 @@ fn build_conversation(
         conversation.push(req_msg);
     }
 
+    if let Some(instruction) = &options.instruction
+        && let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
+            .content(instruction.clone())
+            .build()
+    {
+        conversation.push(ChatCompletionRequestMessage::System(msg));
+    }
+
+    conversation
+}
+
+/// Whether `msg` carries straight on from `prev`: both plain replies, without tool calls,
+/// from the same sender
+fn continues_reply(prev: &shared::models::ChatMessage, msg: &shared::models::ChatMessage) -> bool {
+    let plain_reply = |m: &shared::models::ChatMessage| {
+        m.role == ROLE_ASSISTANT && m.tool_calls.as_ref().is_none_or(|tc| tc.is_empty())
+    };
+    plain_reply(prev)
+        && plain_reply(msg)
+        && prev.active_sender() == msg.active_sender()
+        && !msg.active_content().is_empty()
+}
+
+/// How many of the latest messages are searched for lorebook keys
+const LORE_SCAN_DEPTH: usize = 8;
+
+/// The lorebook entries triggered by the latest messages, in lorebook order, as long as
+/// they fit in `budget` tokens. An entry too big for what's left is skipped so smaller
+/// ones after it still get a chance.
+fn lore_prompt(
+    messages: &[&shared::models::ChatMessage],
+    lore: &[LoreEntry],
+    budget: usize,
+) -> Option<String> {
+    if lore.is_empty() || budget == 0 {
+        return None;
+    }
+    let recent = messages
+        .iter()
+        .rev()
+        .filter(|m| m.role != ROLE_TOOL)
+        .take(LORE_SCAN_DEPTH)
+        .map(|m| m.active_content().to_lowercase())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut remaining = budget;
+    let mut selected = Vec::new();
+    for entry in lore.iter().filter(|e| e.matches(&recent)) {
+        let tokens = entry.estimated_tokens();
+        if tokens <= remaining {
+            remaining -= tokens;
+            selected.push(entry.content.trim());
+        }
+    }
+    if selected.is_empty() {
+        return None;
+    }
+    Some(format!("World info:\n{}", selected.join("\n\n")))
+}
+
+/// The chat split into what has to be sent together: an assistant message with tool calls
+/// and the results that follow it, or any other single message. Results without a call
+/// before them are grouped on their own.
+fn exchanges(messages: &[shared::models::ChatMessage]) -> Vec<&[shared::models::ChatMessage]> {
+    let calls_tools = |m: &shared::models::ChatMessage| {
+        m.role == ROLE_TOOL
+            || (m.role == ROLE_ASSISTANT && m.tool_calls.as_ref().is_some_and(|tc| !tc.is_empty()))
+    };
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for end in 1..=messages.len() {
+        let joins =
+            messages.get(end).is_some_and(|m| m.role == ROLE_TOOL) && calls_tools(&messages[start]);
+        if !joins {
+            groups.push(&messages[start..end]);
+            start = end;
+        }
+    }
+    groups
+}
+
+/// The most recent `limit` non-system messages, or all of them without a limit.
+///
+/// Tool calls and their results are kept or dropped together, since providers reject one
+/// without the other: hiding either side hides the whole exchange, and one that doesn't
+/// fit in the limit is left out. Results with no call before them are never sent. Messages
+/// marked `keep_in_context` are kept wherever they are, along with the rest of their
+/// exchange. Messages marked `hidden_from_context` don't count towards the limit.
+fn history_window(
+    messages: &[shared::models::ChatMessage],
+    limit: Option<usize>,
+) -> Vec<&shared::models::ChatMessage> {
+    let groups: Vec<_> = exchanges(messages)
+        .into_iter()
+        .filter(|g| g[0].role != ROLE_TOOL && !g.iter().any(|m| m.hidden_from_context))
+        .collect();
+    let start = match limit {
+        None => 0,
+        Some(limit) => {
+            let (mut start, mut kept) = (groups.len(), 0);
+            while start > 0 && kept < limit {
+                let counted = groups[start - 1]
+                    .iter()
+                    .filter(|m| m.role != ROLE_SYSTEM)
+                    .count();
+                if kept + counted > limit {
+                    break;
+                }
+                kept += counted;
+                start -= 1;
+            }
+            start
+        }
+    };
+    groups[..start]
+        .iter()
+        .filter(|g| g.iter().any(|m| m.keep_in_context))
+        .chain(&groups[start..])
+        .flat_map(|g| g.iter())
+        .collect()
+}
+
+/// Turn a character name into a valid message `name`, which only allows `[a-zA-Z0-9_-]{1,64}`
+fn speaker_name(name: &str) -> Option<String> {
+    let sanitized = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect::<String>();
+    sanitized.chars().any(|c| c != '_').then_some(sanitized)
+}
+
+/// Describe the character for the system prompt
+fn character_prompt(char: &shared::models::Character, options: &ConversationOptions) -> String {
+    let mut system_prompt = String::new();
+    system_prompt.push_str(&format!("Name: {}", char.name));
+    if !char.description.is_empty() {
+        system_prompt.push_str(&format!("\nDescription: {}", char.description));
+    }
+    if !char.personality.is_empty() {
+        system_prompt.push_str(&format!("\nPersonality: {}", char.personality));
+    }
+    let scenario = options
+        .scenario_override
+        .as_deref()
+        .unwrap_or(&char.scenario);
+    if !scenario.is_empty() {
+        system_prompt.push_str(&format!("\nScenario: {}", scenario));
+    }
+    if options.include_examples && !char.example_messages.is_empty() {
+        system_prompt.push_str(&format!("\nExample messages: {}", char.example_messages));
+    }
+    if let Some(length) = char.response_length {
+        system_prompt.push_str(response_length_prompt(length));
+    }
+    system_prompt
+}
+
+/// Instruction nudging replies towards a length
+fn response_length_prompt(length: ResponseLength) -> &'static str {
+    match length {
+        ResponseLength::Short => "\n\nKeep responses short: a few sentences at most.",
+        ResponseLength::Medium => "\n\nKeep responses to one or two paragraphs.",
+        ResponseLength::Long => "\n\nWrite long, detailed responses of several paragraphs.",
+    }
+}
+
+/// List the other characters in a group chat, or nothing if there are none
+fn roster_prompt(roster: &[shared::models::Character]) -> String {
+    if roster.is_empty() {
+        return String::new();
+    }
+    let mut prompt = String::from("\n\nOther characters present in this conversation:");
+    for char in roster {
+        if char.description.is_empty() {
+            prompt.push_str(&format!("\n- {}", char.name));
+        } else {
+            prompt.push_str(&format!("\n- {}: {}", char.name, char.description));
+        }
+    }
+    prompt
+}
+
+/// Build a conversation where the model plays the user.
+///
+/// User and assistant turns are swapped so the user's lines become the model's own,
+/// tool traffic is dropped, and a final instruction asks for the user's next message.
+fn build_impersonation(
+    messages: &[&shared::models::ChatMessage],
+    character: Option<&shared::models::Character>,
+    options: &ConversationOptions,
+) -> Vec<ChatCompletionRequestMessage> {
+    let char_name = character
+        .map(|c| c.name.as_str())
+        .unwrap_or("the assistant");
+    let mut conversation: Vec<ChatCompletionRequestMessage> = Vec::new();
+
+    let mut system_prompt = format!(
+        "You are writing as the user in a roleplay with {}. Your previous messages are the user's lines; the other side is {}.",
+        char_name, char_name
+    );
+    if let Some(char) = character {
+        system_prompt.push_str("\n\nFor reference, the other character:\n");
+        system_prompt.push_str(&character_prompt(char, options));
+    }
+    if let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
+        .content(system_prompt)
+        .build()
+    {
+        conversation.push(ChatCompletionRequestMessage::System(msg));
+    }
+
+    if let Some(lore) = lore_prompt(messages, &options.lore, options.lore_budget)
+        && let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
+            .content(lore)
+            .build()
+    {
+        conversation.push(ChatCompletionRequestMessage::System(msg));
+    }
+
+    for msg in messages {
+        let content = msg.active_content().to_string();
+        if content.is_empty() {
+            continue;
+        }
+        if msg.role == ROLE_USER {
+            let assistant_msg = ChatCompletionRequestAssistantMessageArgs::default()
+                .content(ChatCompletionRequestAssistantMessageContent::Text(content))
+                .build()
+                .unwrap_or_default();
+            conversation.push(ChatCompletionRequestMessage::Assistant(assistant_msg));
+        } else if msg.role == ROLE_ASSISTANT {
+            let user_msg = ChatCompletionRequestUserMessageArgs::default()
+                .content(content)
+                .build()
+                .unwrap_or_default();
+            conversation.push(ChatCompletionRequestMessage::User(user_msg));
+        }
+    }
+
+    if let Ok(msg) = ChatCompletionRequestSystemMessageArgs::default()
+        .content(format!(
+            "Write the user's next message, staying in their voice. Do not write {}'s reply. Output only the message text.",
+            char_name
+        ))
+        .build()
+    {
+        conversation.push(ChatCompletionRequestMessage::System(msg));
+    }
+
     conversation
 }
 
@@ -124,10 +511,17 @@ fn get_openai_tools(
         available_tools
             .into_iter()
             .map(|t| {
+                // Chat completions have no field for an output schema, so describe it instead
+                let description = match &t.returns {
+                    Some(schema) => {
+                        format!("{}\n\nReturns JSON matching: {}", t.description, schema)
+                    }
+                    None => t.description,
+                };
                 ChatCompletionTools::Function(ChatCompletionTool {
                     function: FunctionObject {
                         name: t.name,
-                        description: Some(t.description),
+                        description: Some(description),
                         parameters: Some(t.parameters),
                         strict: Some(false),
                     },
@@ -137,6 +531,79 @@ fn get_openai_tools(
     )
 }
 
+/// The effort setting that applies: the request's override, then the character's,
+/// then the global one
+fn resolve_reasoning_effort<'a>(
+    request_override: Option<&'a str>,
+    character: Option<&'a str>,
+    global: &'a str,
+) -> &'a str {
+    request_override.or(character).unwrap_or(global)
+}
+
+/// Map the reasoning effort setting to the request parameter.
+///
+/// `"off"` leaves the parameter out entirely, for models that reject it; `"none"` sends an
+/// explicit `none` to reasoning models that accept turning reasoning off.
+fn reasoning_effort(setting: &str) -> Option<ReasoningEffort> {
+    match setting {
+        "off" => None,
+        "none" => Some(ReasoningEffort::None),
+        "low" => Some(ReasoningEffort::Low),
+        "high" => Some(ReasoningEffort::High),
+        _ => Some(ReasoningEffort::Medium),
+    }
+}
+
+/// A readable message for a failed stream.
+///
+/// Gateways like OpenRouter report upstream failures as an `{"error": {...}}` event inside an
+/// otherwise successful stream, which surfaces as a deserialization error carrying the raw event.
+fn stream_error_message(e: &OpenAIError) -> String {
+    if let OpenAIError::JSONDeserialize(_, content) = e
+        && let Ok(value) = serde_json::from_str::<serde_json::Value>(content)
+        && let Some(error) = value.get("error")
+    {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("Unknown provider error");
+        return match error.get("code") {
+            Some(code) if !code.is_null() => format!("{} (code: {})", message, code),
+            _ => message.to_string(),
+        };
+    }
+    e.to_string()
+}
+
+/// A streamed chunk, plus the reasoning delta async-openai's types leave out. Providers
+/// name it `reasoning_content` or `reasoning`.
+struct StreamChunk {
+    response: CreateChatCompletionStreamResponse,
+    reasoning: Option<String>,
+}
+
+impl<'de> serde::Deserialize<'de> for StreamChunk {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let reasoning = value
+            .pointer("/choices/0/delta")
+            .and_then(|delta| {
+                delta
+                    .get("reasoning_content")
+                    .or_else(|| delta.get("reasoning"))
+            })
+            .and_then(|r| r.as_str())
+            .filter(|r| !r.is_empty())
+            .map(str::to_string);
+        let response = serde_json::from_value(value).map_err(serde::de::Error::custom)?;
+        Ok(Self {
+            response,
+            reasoning,
+        })
+    }
+}
+
 #[derive(Clone, Default)]
 struct ToolCallBuffer {
     id: String,
@@ -144,12 +611,83 @@ struct ToolCallBuffer {
     arguments: String,
 }
 
+/// A `{"error": {"message"}}` body naming what was wrong with the request, in place of
+/// axum's plain-text rejection
+fn rejection_response(rejection: JsonRejection) -> axum::response::Response {
+    (
+        rejection.status(),
+        Json(serde_json::json!({ "error": { "message": rejection.body_text() } })),
+    )
+        .into_response()
+}
+
+/// Store a reply: a new message under `reply_id`, or a variant of the message being
+/// regenerated
+async fn save_reply(
+    state: &AppState,
+    payload: &CompletionRequest,
+    reply_id: uuid::Uuid,
+    responder_id: uuid::Uuid,
+    content: String,
+    reasoning: String,
+) -> crate::dbs::DbResult<()> {
+    let reasoning = (!reasoning.is_empty()).then_some(reasoning);
+    if payload.regenerate
+        && let Some(msg_id) = payload.message_id
+    {
+        match payload.target_alternative_index {
+            Some(index) => {
+                state
+                    .db
+                    .update_alternative(
+                        payload.chat_id,
+                        msg_id,
+                        index,
+                        content,
+                        Some(responder_id),
+                        reasoning,
+                    )
+                    .await
+            }
+            None => {
+                state
+                    .db
+                    .append_alternative(
+                        payload.chat_id,
+                        msg_id,
+                        content,
+                        Some(responder_id),
+                        reasoning,
+                    )
+                    .await
+            }
+        }
+    } else {
+        let mut reply = shared::models::ChatMessage::new(ROLE_ASSISTANT, content)
+            .with_id(reply_id)
+            .with_created_at(state.config.clock.now().into());
+        reply.reasoning = reasoning;
+        state.db.append_message(payload.chat_id, reply).await
+    }
+}
+
 pub async fn generate_response(
     State(state): State<AppState>,
-    Json(payload): Json<CompletionRequest>,
+    payload: Result<Json<CompletionRequest>, JsonRejection>,
 ) -> axum::response::Response {
+    let payload = match payload {
+        Ok(Json(payload)) => payload,
+        Err(rejection) => return rejection_response(rejection),
+    };
     let api_key = if payload.api_key.is_empty() {
-        return (axum::http::StatusCode::UNAUTHORIZED, "Missing API Key").into_response();
+        // Coded so the client can send the user to the key field rather than just show it
+        return (
+            axum::http::StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": { "message": "Missing API Key", "code": ERROR_MISSING_API_KEY }
+            })),
+        )
+            .into_response();
     } else {
         payload.api_key.clone()
     };
@@ -159,12 +697,52 @@ pub async fn generate_response(
         .clone()
         .unwrap_or_else(|| DEFAULT_API_BASE.to_string());
 
+    match validate_api_base(&api_base) {
+        Ok(base) if base.insecure => {
+            if state.config.require_https {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "API base URL must use https",
+                )
+                    .into_response();
+            }
+            tracing::warn!(
+                "API base {} is not https, the api key is sent unencrypted",
+                base.url
+            );
+        }
+        Ok(_) => {}
+        Err(e) => return (axum::http::StatusCode::BAD_REQUEST, e).into_response(),
+    }
+
     let config = OpenAIConfig::new()
         .with_api_key(api_key)
         .with_api_base(api_base);
 
     let client = Client::with_config(config);
 
+    if let Some(extra) = &payload.extra_body
+        && let Err(e) = validate_extra_body(extra)
+    {
+        return (axum::http::StatusCode::BAD_REQUEST, e).into_response();
+    }
+
+    // Impersonation saves nothing, so it doesn't need the chat to itself
+    let generation = if payload.impersonate {
+        None
+    } else {
+        match state.generations.try_start(payload.chat_id) {
+            Some(guard) => Some(guard),
+            None => {
+                return (
+                    axum::http::StatusCode::CONFLICT,
+                    "A reply is already being generated in this chat",
+                )
+                    .into_response();
+            }
+        }
+    };
+
     // Fetch conversation history and character prompt
     let chat_res = state.db.get_chat(payload.chat_id).await;
 
@@ -187,8 +765,23 @@ pub async fn generate_response(
     let truncate_at = if payload.regenerate {
         if let Some(msg_id) = payload.message_id {
             // Check that the message exists
-            if !chat.messages.iter().any(|m| m.id == msg_id) {
+            let Some(msg) = chat.messages.iter().find(|m| m.id == msg_id) else {
                 return (axum::http::StatusCode::NOT_FOUND, "Message not found").into_response();
+            };
+            if let Some(index) = payload.target_alternative_index {
+                if index >= msg.variant_count() {
+                    return (
+                        axum::http::StatusCode::BAD_REQUEST,
+                        "Target alternative doesn't exist",
+                    )
+                        .into_response();
+                }
+            } else if msg.alternatives.len() >= MAX_ALTERNATIVES {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "Message already has the most swipes allowed",
+                )
+                    .into_response();
             }
             Some(msg_id)
         } else {
@@ -203,61 +796,209 @@ pub async fn generate_response(
     };
 
     let character = state.db.get_character(chat.character_id).await.ok();
+    let effort_setting = resolve_reasoning_effort(
+        payload.reasoning_effort_override.as_deref(),
+        character
+            .as_ref()
+            .and_then(|c| c.reasoning_effort.as_deref()),
+        &payload.reasoning_effort,
+    )
+    .to_string();
+
+    // Other participants, only worth resolving for group chats
+    let others = if chat.participants.len() > 1 {
+        let ids = chat
+            .ordered_participants()
+            .into_iter()
+            .map(|p| p.character_id)
+            .filter(|id| *id != chat.character_id)
+            .collect::<Vec<_>>();
+        let mut others = state
+            .db
+            .get_characters_by_ids(&ids)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to resolve chat participants: {:?}", e);
+                Vec::new()
+            });
+        others.sort_by_key(|c| ids.iter().position(|id| *id == c.id));
+        others
+    } else {
+        Vec::new()
+    };
+
+    let speakers = if others.is_empty() {
+        HashMap::new()
+    } else {
+        others
+            .iter()
+            .chain(character.as_ref())
+            .map(|c| (c.id, c.name.clone()))
+            .collect()
+    };
+    let roster = if payload.include_roster {
+        others
+    } else {
+        Vec::new()
+    };
+
+    let lore = state.db.get_lore_entries().await.unwrap_or_else(|e| {
+        tracing::warn!("Failed to load the lorebook: {:?}", e);
+        Vec::new()
+    });
 
     // Initial conversation build
-    let conversation = build_conversation(&chat.messages, character.as_ref(), truncate_at);
+    let options = ConversationOptions {
+        impersonate: payload.impersonate,
+        roster,
+        speakers,
+        history_limit: payload.history_limit,
+        system_prompt_override: payload.system_prompt_override.clone(),
+        tool_result_limit: payload.tool_result_limit,
+        scenario_override: chat.scenario_override.clone(),
+        include_examples: payload.include_examples,
+        merge_consecutive_replies: payload.merge_consecutive_replies,
+        lore,
+        lore_budget: payload.lore_budget,
+        instruction: payload
+            .regen_instruction
+            .as_deref()
+            .map(str::trim)
+            .filter(|i| !i.is_empty())
+            .map(str::to_string),
+    };
+    let conversation =
+        build_conversation(&chat.messages, character.as_ref(), truncate_at, &options);
 
-    // Fetch available tools
-    let available_tools = state.plugins.get_all_tools().await;
-    let openai_tools = get_openai_tools(available_tools);
+    // Fetch available tools (impersonation is plain text only)
+    let openai_tools = if payload.impersonate {
+        None
+    } else {
+        if !state.plugins.wait_until_ready(PLUGIN_DISCOVERY_WAIT).await {
+            tracing::warn!(
+                "Plugin discovery still running, generating with the tools loaded so far"
+            );
+        }
+        get_openai_tools(state.plugins.get_all_tools().await)
+    };
+
+    let responder_id = chat.character_id;
+    let char_name = character
+        .as_ref()
+        .map(|c| c.name.clone())
+        .unwrap_or_default();
+    let mut provider_log = ProviderLog::start(state.config.debug_provider_dir.as_deref(), &payload);
 
     let body = axum::body::Body::from_stream(async_stream::stream! {
+        // Held until the stream ends or the client goes away
+        let cancel = generation.as_ref().map(|g| g.token()).unwrap_or_default();
+        let _generation = generation;
         let mut current_conversation = conversation;
 
-        for _turn in 0..5 {
+        for _turn in 0..MAX_TOOL_ROUNDS {
+            // Stopped while tools ran: what they did is saved, there's no reply to write
+            if cancel.is_cancelled() {
+                yield Ok("data: [DONE]\n\n".to_string());
+                return;
+            }
+
+            // Tell the client which id this turn's reply will be saved under
+            let reply_id = state.config.clock.new_id();
+            if !payload.regenerate && !payload.impersonate {
+                yield Ok(format!("data: [MESSAGE_ID] {}\n\n", reply_id));
+            }
+
             let mut builder = CreateChatCompletionRequestArgs::default();
             builder
                 .model(payload.model.clone())
                 .messages(current_conversation.clone())
                 .temperature(payload.temperature.unwrap_or(0.7))
-                .max_tokens(payload.max_tokens.unwrap_or(4096));
+                .max_tokens(payload.max_tokens.unwrap_or(4096))
+                .stream(true)
+                .stream_options(ChatCompletionStreamOptions {
+                    include_usage: Some(true),
+                    include_obfuscation: None,
+                });
 
             if let Some(tools) = &openai_tools {
                 builder.tools(tools.clone());
             }
 
-            let effort = match payload.reasoning_effort.as_str() {
-                "low" => async_openai::types::chat::ReasoningEffort::Low,
-                "medium" => async_openai::types::chat::ReasoningEffort::Medium,
-                "high" => async_openai::types::chat::ReasoningEffort::High,
-                "none" => async_openai::types::chat::ReasoningEffort::None,
-                _ => async_openai::types::chat::ReasoningEffort::Medium,
-            };
-            builder.reasoning_effort(effort);
+            if let Some(effort) = reasoning_effort(&effort_setting) {
+                builder.reasoning_effort(effort);
+            }
+
+            if payload.logprobs {
+                builder.logprobs(true);
+            }
 
             let request = match builder.build() {
                 Ok(req) => req,
                 Err(e) => {
                     yield Ok::<String, Error>(format!("data: [ERROR] Failed to build completion request: {}\n\n", e));
+                    yield Ok("data: [DONE]\n\n".to_string());
+                    return;
+                }
+            };
+
+            let request = match request_body(&request, payload.extra_body.as_ref()) {
+                Ok(body) => body,
+                Err(e) => {
+                    yield Ok::<String, Error>(format!("data: [ERROR] Failed to build completion request: {}\n\n", e));
+                    yield Ok("data: [DONE]\n\n".to_string());
                     return;
                 }
             };
 
-            let mut stream = match client.chat().create_stream(request).await {
+            let mut stream = match client.chat().create_stream_byot::<_, StreamChunk>(request).await {
                 Ok(s) => s,
                 Err(e) => {
                     yield Ok(format!("data: [ERROR] OpenAI Error: {}\n\n", e));
+                    yield Ok("data: [DONE]\n\n".to_string());
                     return;
                 }
             };
 
             let mut full_response = String::new();
-            let mut tool_calls_map: std::collections::HashMap<u32, ToolCallBuffer> = std::collections::HashMap::new();
+            let mut full_reasoning = String::new();
+            let mut tool_calls_map: HashMap<u32, ToolCallBuffer> = HashMap::new();
+            let mut usage = None;
+            let mut cancelled = false;
 
-            while let Some(result) = stream.next().await {
+            loop {
+                let result = tokio::select! {
+                    _ = cancel.cancelled() => {
+                        cancelled = true;
+                        break;
+                    }
+                    next = stream.next() => match next {
+                        Some(result) => result,
+                        None => break,
+                    },
+                };
                 match result {
-                    Ok(response) => {
+                    Ok(StreamChunk { response, reasoning }) => {
+                        if let Some(reasoning) = reasoning {
+                            full_reasoning.push_str(&reasoning);
+                            yield Ok(format!("data: [REASONING] {}\n\n", serde_json::to_string(&reasoning).unwrap_or_default()));
+                        }
+                        if let Some(log) = &mut provider_log {
+                            log.record(&response);
+                        }
+                        if response.usage.is_some() {
+                            usage = response.usage;
+                        }
                         if let Some(choice) = response.choices.first() {
+                            if payload.logprobs
+                                && let Some(tokens) = choice.logprobs.as_ref().and_then(|l| l.content.as_ref())
+                                && !tokens.is_empty()
+                            {
+                                let tokens = tokens.iter().map(|t| TokenLogprob {
+                                    token: t.token.clone(),
+                                    logprob: t.logprob,
+                                }).collect::<Vec<_>>();
+                                yield Ok(format!("data: [LOGPROBS] {}\n\n", serde_json::to_string(&tokens).unwrap_or_default()));
+                            }
                             if let Some(content) = &choice.delta.content {
                                 full_response.push_str(content);
                                 let encoded = serde_json::to_string(content).unwrap_or_else(|_| format!("\"{}\"", content.replace('"', "\\\"")));
@@ -265,23 +1006,76 @@ pub async fn generate_response(
                             }
 
                             if let Some(tcs) = &choice.delta.tool_calls {
+                                let mut started = Vec::new();
                                 for tc in tcs {
                                     let entry = tool_calls_map.entry(tc.index).or_default();
+                                    let named = !entry.name.is_empty();
                                     if let Some(id) = &tc.id { entry.id.push_str(id); }
                                     if let Some(function) = &tc.function {
                                         if let Some(name) = &function.name { entry.name.push_str(name); }
                                         if let Some(args) = &function.arguments { entry.arguments.push_str(args); }
                                     }
+                                    if !named && !entry.name.is_empty() {
+                                        started.push(serde_json::json!({ "index": tc.index, "id": entry.id, "name": entry.name }));
+                                    }
+                                }
+                                // Lets the UI show which tool is coming while its arguments still stream
+                                for call in started {
+                                    yield Ok(format!("data: [TOOL_CALL_START] {}\n\n", call));
                                 }
                             }
                         }
                     }
                     Err(e) => {
-                         yield Ok(format!("data: [ERROR] {}\n\n", e));
+                         if let Some(log) = &mut provider_log {
+                             log.record(&serde_json::json!({ "error": e.to_string() }));
+                             log.flush().await;
+                         }
+                         tracing::warn!("Completion stream failed: {}", e);
+                         // Keep what arrived, the client shows the error beside it
+                         if (!full_response.is_empty() || !full_reasoning.is_empty()) && !payload.impersonate
+                             && let Err(e) = save_reply(&state, &payload, reply_id, responder_id, full_response, full_reasoning).await
+                         {
+                             tracing::error!("Failed to save partial response: {:?}", e);
+                         }
+                         yield Ok(format!("data: [ERROR] {}\n\n", stream_error_message(&e)));
+                         yield Ok("data: [DONE]\n\n".to_string());
+                         return;
                     }
                 }
             }
 
+            if let Some(log) = &provider_log {
+                log.flush().await;
+            }
+
+            if let Some(usage) = usage {
+                tracing::info!(
+                    chat_id = %payload.chat_id,
+                    model = %payload.model,
+                    prompt_tokens = usage.prompt_tokens,
+                    completion_tokens = usage.completion_tokens,
+                    "Completion usage"
+                );
+                if let Err(e) = state
+                    .db
+                    .record_usage(
+                        payload.chat_id,
+                        &payload.model,
+                        usage.prompt_tokens as u64,
+                        usage.completion_tokens as u64,
+                    )
+                    .await
+                {
+                    tracing::error!("Failed to record usage: {:?}", e);
+                }
+            }
+
+            if cancelled {
+                // Half-streamed calls can't be run; the text so far is kept as the reply
+                tool_calls_map.clear();
+            }
+
             if !tool_calls_map.is_empty() {
                 let mut indices: Vec<u32> = tool_calls_map.keys().cloned().collect();
                 indices.sort();
@@ -293,7 +1087,7 @@ pub async fn generate_response(
                             id: buf.id.clone(),
                             function: FunctionCall {
                                 name: buf.name.clone(),
-                                arguments: buf.arguments.clone(),
+                                arguments: repaired_arguments(&buf.name, &buf.arguments),
                             },
                         });
                     }
@@ -303,10 +1097,15 @@ pub async fn generate_response(
                     ChatCompletionMessageToolCalls::Function(tc.clone())
                 }).collect();
 
-                let assistant_msg_req = ChatCompletionRequestAssistantMessageArgs::default()
-                    .tool_calls(tool_calls_enum)
-                    .build()
-                    .unwrap();
+                let mut assistant_msg_builder = ChatCompletionRequestAssistantMessageArgs::default();
+                assistant_msg_builder.tool_calls(tool_calls_enum);
+                // Text written alongside the calls stays in context for the next round
+                if !full_response.is_empty() {
+                    assistant_msg_builder.content(ChatCompletionRequestAssistantMessageContent::Text(
+                        full_response.clone(),
+                    ));
+                }
+                let assistant_msg_req = assistant_msg_builder.build().unwrap();
                 current_conversation.push(ChatCompletionRequestMessage::Assistant(assistant_msg_req));
 
                 let tool_calls_model: Vec<shared::models::ToolCall> = tool_calls_buffer.iter().map(|tc| {
@@ -325,82 +1124,76 @@ pub async fn generate_response(
                 }
 
                 let assistant_chat_msg = {
-                    let mut m = shared::models::ChatMessage::new(ROLE_ASSISTANT, full_response.clone());
+                    let mut m = shared::models::ChatMessage::new(ROLE_ASSISTANT, full_response.clone())
+                        .with_id(reply_id)
+                        .with_created_at(state.config.clock.now().into());
                     m.tool_calls = Some(tool_calls_model);
+                    m.reasoning = (!full_reasoning.is_empty()).then(|| full_reasoning.clone());
                     m
                 };
                 if let Err(e) = state.db.append_message(payload.chat_id, assistant_chat_msg).await {
                      yield Ok(format!("data: [ERROR] Failed to save tool calls: {}\n\n", e));
                 }
 
+                // Parse every call first so same-plugin calls can be sent together
+                let mut outcomes: Vec<Option<Result<String, String>>> = Vec::new();
+                let mut calls = Vec::new();
                 for tc in &tool_calls_buffer {
-                    let args = match serde_json::from_str::<serde_json::Value>(&tc.function.arguments) {
-                        Ok(a) => a,
-                        Err(e) => {
-                             let content = format!("Error parsing arguments: {}", e);
-                             current_conversation.push(ChatCompletionRequestMessage::Tool(
-                                 ChatCompletionRequestToolMessageArgs::default()
-                                    .content(content.clone())
-                                    .tool_call_id(tc.id.clone())
-                                    .build()
-                                    .unwrap()
-                             ));
-
-                             let _ = state.db.append_message(payload.chat_id, {
-                                 let mut m = shared::models::ChatMessage::new(ROLE_TOOL, content.clone());
-                                 m.tool_call_id = Some(tc.id.clone());
-                                 m
-                             }).await;
-                             yield Ok(format!("data: [TOOL_RESULT] {}\n\n", serde_json::to_string(&serde_json::json!({"id": tc.id, "error": content})).unwrap()));
-                             continue;
+                    match serde_json::from_str::<serde_json::Value>(&tc.function.arguments) {
+                        Ok(args) => {
+                            calls.push((tc.function.name.clone(), args));
+                            outcomes.push(None);
                         }
+                        Err(e) => outcomes.push(Some(Err(format!("Error parsing arguments: {}", e)))),
+                    }
+                }
+                let mut results = state.plugins.call_tools(calls).await.into_iter();
+                let outcomes = outcomes.into_iter().map(|o| {
+                    o.unwrap_or_else(|| match results.next().expect("one result per call") {
+                        Ok(result) => Ok(result.to_string()),
+                        Err(e) => Err(format!("Error executing tool: {}", e)),
+                    })
+                }).collect::<Vec<_>>();
+
+                for (tc, outcome) in tool_calls_buffer.iter().zip(outcomes) {
+                    let (content, key) = match outcome {
+                        Ok(content) => (content, "result"),
+                        Err(content) => (content, "error"),
                     };
+                    // The model gets a capped copy, the chat keeps the whole result
+                    current_conversation.push(ChatCompletionRequestMessage::Tool(
+                        ChatCompletionRequestToolMessageArgs::default()
+                           .content(truncate_tool_result(&content, payload.tool_result_limit))
+                           .tool_call_id(tc.id.clone())
+                           .build()
+                           .unwrap()
+                    ));
 
-                    match state.plugins.call_tool(&tc.function.name, args).await {
-                         Ok(result) => {
-                             let content = result.to_string();
-                             current_conversation.push(ChatCompletionRequestMessage::Tool(
-                                 ChatCompletionRequestToolMessageArgs::default()
-                                    .content(content.clone())
-                                    .tool_call_id(tc.id.clone())
-                                    .build()
-                                    .unwrap()
-                             ));
-
-                             let _ = state.db.append_message(payload.chat_id, {
-                                 let mut m = shared::models::ChatMessage::new(ROLE_TOOL, content.clone());
-                                 m.tool_call_id = Some(tc.id.clone());
-                                 m
-                             }).await;
-                             yield Ok(format!("data: [TOOL_RESULT] {}\n\n", serde_json::to_string(&serde_json::json!({"id": tc.id, "result": content})).unwrap()));
-                         }
-                         Err(e) => {
-                             let content = format!("Error executing tool: {}", e);
-                             current_conversation.push(ChatCompletionRequestMessage::Tool(
-                                 ChatCompletionRequestToolMessageArgs::default()
-                                    .content(content.clone())
-                                    .tool_call_id(tc.id.clone())
-                                    .build()
-                                    .unwrap()
-                             ));
-
-                             let _ = state.db.append_message(payload.chat_id, {
-                                 let mut m = shared::models::ChatMessage::new(ROLE_TOOL, content.clone());
-                                 m.tool_call_id = Some(tc.id.clone());
-                                 m
-                             }).await;
-                             yield Ok(format!("data: [TOOL_RESULT] {}\n\n", serde_json::to_string(&serde_json::json!({"id": tc.id, "error": content})).unwrap()));
-                         }
-                    }
+                    let tool_msg_id = state.config.clock.new_id();
+                    // Stored results are held to the same limit as messages from clients
+                    let content = truncate_tool_result(&content, Some(state.config.max_message_length));
+                    let _ = state.db.append_message(payload.chat_id, {
+                        let mut m = shared::models::ChatMessage::new(ROLE_TOOL, content.clone())
+                            .with_id(tool_msg_id)
+                            .with_created_at(state.config.clock.now().into());
+                        m.tool_call_id = Some(tc.id.clone());
+                        m
+                    }).await;
+                    yield Ok(format!("data: [TOOL_RESULT] {}\n\n", serde_json::to_string(&serde_json::json!({"id": tc.id, "message_id": tool_msg_id, key: content})).unwrap()));
                 }
                 continue;
             } else {
-                if !full_response.is_empty() {
-                    let res = if payload.regenerate && let Some(msg_id) = payload.message_id {
-                         state.db.append_alternative(payload.chat_id, msg_id, full_response).await
-                    } else {
-                         state.db.append_message(payload.chat_id, shared::models::ChatMessage::new(ROLE_ASSISTANT, full_response)).await
-                    };
+                if !payload.impersonate {
+                    let stripped = strip_leaked_prefix(&full_response, &payload.strip_prefixes, &char_name);
+                    if stripped.len() != full_response.len() {
+                        full_response = stripped.to_string();
+                        yield Ok(format!("data: [FINAL_CONTENT] {}\n\n", serde_json::to_string(&full_response).unwrap()));
+                    }
+                }
+                // A reply stopped while the model was still thinking keeps its trace
+                let thought_only = cancelled && !full_reasoning.is_empty();
+                if (!full_response.is_empty() || thought_only) && !payload.impersonate {
+                    let res = save_reply(&state, &payload, reply_id, responder_id, full_response, full_reasoning).await;
 
                     if let Err(e) = res {
                          yield Ok(format!("data: [ERROR] Failed to save response: {}\n\n", e));
@@ -411,12 +1204,571 @@ pub async fn generate_response(
                 return;
             }
         }
+
+        // Every round ended in tool calls. What ran is saved, but there's no final reply.
+        yield Ok(format!("data: [ERROR] Stopped after {} rounds of tool calls without a reply\n\n", MAX_TOOL_ROUNDS));
+        yield Ok("data: [DONE]\n\n".to_string());
     });
 
     axum::response::Response::builder()
-        .header("Content-Type", "text/event-stream")
+        .header("Content-Type", "text/event-stream; charset=utf-8")
         .header("Cache-Control", "no-cache")
-        .header("Connection", "keep-alive")
+        // No Connection header: HTTP/1.1 keeps the connection by default, and the header is
+        // not allowed in HTTP/2
         .body(body)
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::models::{Character, ChatMessage};
+    use uuid::Uuid;
+
+    fn character() -> Character {
+        Character {
+            id: Uuid::now_v7(),
+            name: "Ada".to_string(),
+            description: String::new(),
+            personality: String::new(),
+            scenario: "A quiet library".to_string(),
+            first_message: String::new(),
+            example_messages: String::new(),
+            alternate_greetings: Vec::new(),
+            greeting_mode: Default::default(),
+            is_favorite: false,
+            response_length: None,
+            reasoning_effort: None,
+            metadata: Default::default(),
+        }
+    }
+
+    fn reply(sender: Uuid, content: &str) -> ChatMessage {
+        ChatMessage::new_from_sender(ROLE_ASSISTANT, content, sender)
+    }
+
+    fn lore(keys: &[&str], content: &str) -> LoreEntry {
+        LoreEntry {
+            id: Uuid::now_v7(),
+            name: content.to_string(),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            content: content.to_string(),
+            enabled: true,
+        }
+    }
+
+    /// Each message sent, as `role: content`
+    fn sent(conversation: &[ChatCompletionRequestMessage]) -> Vec<String> {
+        conversation
+            .iter()
+            .map(|m| {
+                let m = serde_json::to_value(m).unwrap();
+                format!(
+                    "{}: {}",
+                    m["role"].as_str().unwrap(),
+                    m["content"].as_str().unwrap_or_default()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_scenario_override_replaces_the_characters_scenario() {
+        let char = character();
+        let options = ConversationOptions {
+            scenario_override: Some("A stormy harbour".to_string()),
+            ..Default::default()
+        };
+        let conversation = build_conversation(&[], Some(&char), None, &options);
+        let system = &sent(&conversation)[0];
+        assert!(system.contains("Scenario: A stormy harbour"));
+        assert!(!system.contains("A quiet library"));
+        assert_eq!(char.scenario, "A quiet library");
+    }
+
+    #[test]
+    fn group_replies_are_named_after_their_speaker() {
+        let (ada, bea, odd, stranger) = (
+            Uuid::now_v7(),
+            Uuid::now_v7(),
+            Uuid::now_v7(),
+            Uuid::now_v7(),
+        );
+        let options = ConversationOptions {
+            speakers: HashMap::from([
+                (ada, "Ada Lovelace".to_string()),
+                (bea, "Zoë!".to_string()),
+                (odd, "???".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let mut swiped = reply(ada, "One");
+        swiped.push_alternative("Two", Some(bea));
+        swiped.active_index = 1;
+        let messages = [
+            ChatMessage::new(ROLE_USER, "Hello"),
+            reply(ada, "Hi"),
+            reply(bea, "Hey"),
+            swiped,
+            reply(odd, "Hm"),
+            reply(stranger, "Who?"),
+        ];
+
+        let names: Vec<_> = build_conversation(&messages, None, None, &options)
+            .iter()
+            .map(|m| {
+                serde_json::to_value(m).unwrap()["name"]
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .collect();
+        let expected = [
+            None,
+            Some("Ada_Lovelace"),
+            Some("Zo__"),
+            Some("Zo__"),
+            None,
+            None,
+        ];
+        assert_eq!(names, expected.map(|n| n.map(str::to_string)));
+    }
+
+    #[test]
+    fn each_response_length_asks_for_its_own_length() {
+        let system = |response_length| {
+            let char = Character {
+                response_length,
+                ..character()
+            };
+            sent(&build_conversation(
+                &[],
+                Some(&char),
+                None,
+                &Default::default(),
+            ))
+            .remove(0)
+        };
+
+        assert!(system(Some(ResponseLength::Short)).ends_with("a few sentences at most."));
+        assert!(system(Some(ResponseLength::Medium)).ends_with("one or two paragraphs."));
+        assert!(system(Some(ResponseLength::Long)).ends_with("of several paragraphs."));
+        assert!(!system(None).contains("Keep responses"));
+        assert!(!system(None).contains("Write long"));
+    }
+
+    #[test]
+    fn a_system_prompt_override_replaces_the_whole_character_prompt() {
+        let options = ConversationOptions {
+            system_prompt_override: Some("You are a test.".to_string()),
+            roster: vec![character()],
+            ..Default::default()
+        };
+        let messages = [ChatMessage::new(ROLE_USER, "Hello")];
+        let conversation = build_conversation(&messages, Some(&character()), None, &options);
+        assert_eq!(
+            sent(&conversation),
+            ["system: You are a test.", "user: Hello"]
+        );
+
+        // Even without a character
+        let conversation = build_conversation(&messages, None, None, &options);
+        assert_eq!(sent(&conversation)[0], "system: You are a test.");
+    }
+
+    #[test]
+    fn the_request_then_the_character_then_the_settings_pick_the_effort() {
+        assert_eq!(
+            resolve_reasoning_effort(Some("none"), Some("high"), "low"),
+            "none"
+        );
+        assert_eq!(resolve_reasoning_effort(None, Some("high"), "low"), "high");
+        assert_eq!(resolve_reasoning_effort(None, None, "low"), "low");
+    }
+
+    #[test]
+    fn a_regeneration_instruction_comes_after_the_history() {
+        let options = ConversationOptions {
+            instruction: Some("Make it shorter".to_string()),
+            ..Default::default()
+        };
+        let messages = [
+            ChatMessage::new(ROLE_USER, "Hello"),
+            reply(Uuid::now_v7(), "Hi there"),
+        ];
+        let conversation = build_conversation(&messages, None, None, &options);
+        assert_eq!(
+            sent(&conversation),
+            [
+                "user: Hello",
+                "assistant: Hi there",
+                "system: Make it shorter"
+            ]
+        );
+    }
+
+    #[test]
+    fn example_messages_are_sent_only_when_included() {
+        let char = Character {
+            example_messages: "<START>Ada: Shh, this is a library.".to_string(),
+            ..character()
+        };
+        let system = |include_examples, impersonate| {
+            let options = ConversationOptions {
+                include_examples,
+                impersonate,
+                ..Default::default()
+            };
+            sent(&build_conversation(&[], Some(&char), None, &options)).remove(0)
+        };
+
+        for impersonate in [false, true] {
+            assert!(system(true, impersonate).contains("Example messages: <START>Ada: Shh"));
+            assert!(!system(false, impersonate).contains("Example messages"));
+            assert!(system(false, impersonate).contains("Scenario: A quiet library"));
+        }
+    }
+
+    #[test]
+    fn a_tools_output_schema_is_described_to_the_model() {
+        let tool = |returns| shared::models::Tool {
+            name: "roll".to_string(),
+            description: "Roll dice".to_string(),
+            parameters: serde_json::json!({}),
+            returns,
+        };
+        let descriptions: Vec<_> = get_openai_tools(vec![
+            tool(Some(serde_json::json!({ "type": "integer" }))),
+            tool(None),
+        ])
+        .unwrap()
+        .into_iter()
+        .map(|t| match t {
+            ChatCompletionTools::Function(f) => f.function.description.unwrap(),
+            _ => unreachable!(),
+        })
+        .collect();
+        assert_eq!(
+            descriptions,
+            [
+                "Roll dice\n\nReturns JSON matching: {\"type\":\"integer\"}",
+                "Roll dice"
+            ]
+        );
+    }
+
+    #[test]
+    fn consecutive_replies_from_one_sender_are_merged() {
+        let (ada, bea) = (Uuid::now_v7(), Uuid::now_v7());
+        let messages = [
+            ChatMessage::new(ROLE_USER, "Hello"),
+            reply(ada, "One"),
+            reply(ada, "Two"),
+            reply(bea, "Three"),
+            ChatMessage::new(ROLE_USER, "And?"),
+            reply(bea, "Four"),
+        ];
+        let options = ConversationOptions {
+            merge_consecutive_replies: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sent(&build_conversation(&messages, None, None, &options)),
+            [
+                "user: Hello",
+                "assistant: One\nTwo",
+                "assistant: Three",
+                "user: And?",
+                "assistant: Four"
+            ]
+        );
+
+        let separate = build_conversation(&messages, None, None, &Default::default());
+        assert_eq!(separate.len(), 6);
+    }
+
+    #[test]
+    fn continues_reply_needs_plain_replies_from_the_same_sender() {
+        let ada = Uuid::now_v7();
+        let prev = reply(ada, "One");
+        assert!(continues_reply(&prev, &reply(ada, "Two")));
+        assert!(!continues_reply(&prev, &reply(Uuid::now_v7(), "Two")));
+        assert!(!continues_reply(&prev, &reply(ada, "")));
+        assert!(!continues_reply(&prev, &ChatMessage::new(ROLE_USER, "Two")));
+
+        let mut calling = reply(ada, "Two");
+        calling.tool_calls = Some(vec![shared::models::ToolCall {
+            id: "call".to_string(),
+            r#type: "function".to_string(),
+            function: shared::models::FunctionCall {
+                name: "search".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        assert!(!continues_reply(&prev, &calling));
+        assert!(!continues_reply(&calling, &prev));
+    }
+
+    #[test]
+    fn the_roster_lists_the_other_characters_after_the_character() {
+        let char = character();
+        let bea = Character {
+            id: Uuid::now_v7(),
+            name: "Bea".to_string(),
+            description: "A travelling bard".to_string(),
+            ..character()
+        };
+        let cid = Character {
+            id: Uuid::now_v7(),
+            name: "Cid".to_string(),
+            ..character()
+        };
+        let options = ConversationOptions {
+            roster: vec![bea, cid],
+            ..Default::default()
+        };
+        let system = &sent(&build_conversation(&[], Some(&char), None, &options))[0];
+        assert!(system.starts_with("system: Name: Ada"));
+        assert!(system.ends_with(
+            "\n\nOther characters present in this conversation:\n- Bea: A travelling bard\n- Cid"
+        ));
+
+        let alone = &sent(&build_conversation(
+            &[],
+            Some(&char),
+            None,
+            &Default::default(),
+        ))[0];
+        assert!(!alone.contains("Other characters"));
+    }
+
+    #[test]
+    fn impersonations_swap_the_roles_and_ask_for_the_users_line() {
+        let char = character();
+        let messages = [
+            ChatMessage::new(ROLE_USER, "Hello"),
+            reply(char.id, "Welcome in."),
+        ];
+        let options = ConversationOptions {
+            impersonate: true,
+            ..Default::default()
+        };
+        let conversation = sent(&build_conversation(&messages, Some(&char), None, &options));
+        assert_eq!(conversation.len(), 4);
+        assert!(conversation[0].starts_with("system: You are writing as the user"));
+        assert!(conversation[0].contains("Name: Ada"));
+        assert_eq!(conversation[1], "assistant: Hello");
+        assert_eq!(conversation[2], "user: Welcome in.");
+        assert!(conversation[3].starts_with("system: Write the user's next message"));
+        assert!(conversation[3].contains("Do not write Ada's reply"));
+    }
+
+    #[test]
+    fn triggered_lore_follows_the_system_prompt() {
+        let char = character();
+        let messages = [ChatMessage::new(ROLE_USER, "Tell me about the Dragon")];
+        let options = ConversationOptions {
+            lore: vec![
+                lore(&["dragon"], "Dragons hoard books."),
+                lore(&["elf"], "Elves."),
+            ],
+            lore_budget: 100,
+            ..Default::default()
+        };
+        let conversation = sent(&build_conversation(&messages, Some(&char), None, &options));
+        assert_eq!(conversation.len(), 3);
+        assert!(conversation[0].starts_with("system: Name: Ada"));
+        assert_eq!(conversation[1], "system: World info:\nDragons hoard books.");
+    }
+
+    #[test]
+    fn lore_prompt_keeps_to_its_budget() {
+        let message = ChatMessage::new(ROLE_USER, "dragon and elf");
+        let messages = [&message];
+        let entries = [
+            lore(&["dragon"], &"d".repeat(40)),
+            lore(&["elf"], "Elves."),
+            lore(&["orc"], "Orcs."),
+        ];
+        // The first entry alone is over budget, so the smaller one after it still fits
+        assert_eq!(
+            lore_prompt(&messages, &entries, 5).as_deref(),
+            Some("World info:\nElves.")
+        );
+        assert_eq!(lore_prompt(&messages, &entries, 0), None);
+
+        let mut disabled = lore(&["elf"], "Elves.");
+        disabled.enabled = false;
+        assert_eq!(lore_prompt(&messages, &[disabled], 100), None);
+    }
+
+    #[test]
+    fn lore_prompt_only_scans_recent_messages() {
+        let old = ChatMessage::new(ROLE_USER, "dragon");
+        let filler = ChatMessage::new(ROLE_USER, "nothing");
+        let messages: Vec<&ChatMessage> = std::iter::once(&old)
+            .chain(std::iter::repeat_n(&filler, LORE_SCAN_DEPTH))
+            .collect();
+        let entries = [lore(&["dragon"], "Dragons.")];
+        assert_eq!(lore_prompt(&messages, &entries, 100), None);
+        assert!(lore_prompt(&messages[..LORE_SCAN_DEPTH], &entries, 100).is_some());
+    }
+
+    #[test]
+    fn impersonations_get_triggered_lore_too() {
+        let char = character();
+        let messages = [reply(char.id, "The dragon stirs.")];
+        let options = ConversationOptions {
+            impersonate: true,
+            lore: vec![lore(&["dragon"], "Dragons hoard books.")],
+            lore_budget: 100,
+            ..Default::default()
+        };
+        let conversation = sent(&build_conversation(&messages, Some(&char), None, &options));
+        assert!(conversation[0].starts_with("system: You are writing as the user"));
+        assert_eq!(conversation[1], "system: World info:\nDragons hoard books.");
+    }
+
+    #[test]
+    fn truncated_tool_results_stay_within_the_limit() {
+        let content = "é".repeat(500);
+        assert_eq!(truncate_tool_result(&content, None), content);
+        assert_eq!(truncate_tool_result(&content, Some(500)), content);
+
+        let cut = truncate_tool_result(&content, Some(100));
+        assert_eq!(cut.chars().count(), 100);
+        assert!(cut.ends_with("…[truncated, 431 chars omitted]"));
+
+        assert_eq!(truncate_tool_result(&content, Some(10)), "é".repeat(10));
+    }
+
+    fn tool_exchange(content: &str) -> [ChatMessage; 2] {
+        let mut call = ChatMessage::new(ROLE_ASSISTANT, "");
+        call.tool_calls = Some(vec![shared::models::ToolCall {
+            id: content.to_string(),
+            r#type: "function".to_string(),
+            function: shared::models::FunctionCall {
+                name: "search".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        let mut result = ChatMessage::new(ROLE_TOOL, content);
+        result.tool_call_id = Some(content.to_string());
+        [call, result]
+    }
+
+    #[test]
+    fn the_history_limit_keeps_the_latest_messages_after_the_system_prompt() {
+        let messages: Vec<_> = ["a", "b", "c", "d"]
+            .map(|c| ChatMessage::new(ROLE_USER, c))
+            .into();
+        let options = ConversationOptions {
+            history_limit: Some(2),
+            ..Default::default()
+        };
+        let conversation = build_conversation(&messages, Some(&character()), None, &options);
+        let sent = sent(&conversation);
+        assert_eq!(sent.len(), 3);
+        assert!(sent[0].starts_with("system: Name: Ada"));
+        assert_eq!(sent[1..], ["user: c", "user: d"]);
+
+        let whole = build_conversation(&messages, Some(&character()), None, &Default::default());
+        assert_eq!(whole.len(), 5);
+    }
+
+    #[test]
+    fn messages_kept_in_context_are_sent_from_outside_the_history_limit() {
+        let mut messages: Vec<_> = ["a", "b", "c", "d"]
+            .map(|c| ChatMessage::new(ROLE_USER, c))
+            .into();
+        messages[1].keep_in_context = true;
+        let options = ConversationOptions {
+            history_limit: Some(1),
+            ..Default::default()
+        };
+        let conversation = build_conversation(&messages, None, None, &options);
+        assert_eq!(sent(&conversation), ["user: b", "user: d"]);
+
+        // Inside the window it's sent once, like any other message
+        messages[3].keep_in_context = true;
+        let conversation = build_conversation(&messages, None, None, &options);
+        assert_eq!(sent(&conversation), ["user: b", "user: d"]);
+    }
+
+    #[test]
+    fn text_written_alongside_tool_calls_is_sent_with_them() {
+        let [mut call, result] = tool_exchange("4");
+        call.content = "Let me roll.".to_string();
+        let messages = [ChatMessage::new(ROLE_USER, "Roll a die"), call, result];
+        let sent: Vec<_> = build_conversation(&messages, None, None, &Default::default())
+            .iter()
+            .map(|m| serde_json::to_value(m).unwrap())
+            .collect();
+
+        assert_eq!(sent[1]["content"], "Let me roll.");
+        assert_eq!(sent[1]["tool_calls"][0]["id"], "4");
+        assert_eq!(sent[2]["tool_call_id"], "4");
+    }
+
+    #[test]
+    fn history_window_keeps_the_latest_messages() {
+        let mut messages: Vec<ChatMessage> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|c| ChatMessage::new(ROLE_USER, *c))
+            .collect();
+        messages[0].keep_in_context = true;
+        messages[3].hidden_from_context = true;
+        let contents = |window: Vec<&ChatMessage>| -> Vec<String> {
+            window.iter().map(|m| m.content.clone()).collect()
+        };
+        // The hidden message neither shows up nor uses up the limit
+        assert_eq!(contents(history_window(&messages, Some(1))), ["a", "c"]);
+        assert_eq!(contents(history_window(&messages, None)), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn history_window_keeps_tool_calls_with_their_results() {
+        let [call, result] = tool_exchange("result");
+        let mut messages = vec![
+            ChatMessage::new(ROLE_USER, "a"),
+            call,
+            result,
+            ChatMessage::new(ROLE_ASSISTANT, "b"),
+        ];
+        let roles = |window: Vec<&ChatMessage>| -> Vec<String> {
+            window.iter().map(|m| m.role.clone()).collect()
+        };
+        // Only half of the exchange fits, so none of it is sent
+        assert_eq!(roles(history_window(&messages, Some(2))), [ROLE_ASSISTANT]);
+        assert_eq!(history_window(&messages, Some(3)).len(), 3);
+
+        messages[1].keep_in_context = true;
+        assert_eq!(
+            roles(history_window(&messages, Some(1))),
+            [ROLE_ASSISTANT, ROLE_TOOL, ROLE_ASSISTANT]
+        );
+
+        // Hiding the result hides the call, and the other way round
+        messages[2].hidden_from_context = true;
+        assert_eq!(
+            roles(history_window(&messages, None)),
+            [ROLE_USER, ROLE_ASSISTANT]
+        );
+        messages[2].hidden_from_context = false;
+        messages[1].hidden_from_context = true;
+        assert_eq!(
+            roles(history_window(&messages, None)),
+            [ROLE_USER, ROLE_ASSISTANT]
+        );
+    }
+
+    #[test]
+    fn history_window_drops_results_without_a_call() {
+        let [_, result] = tool_exchange("result");
+        let messages = [
+            ChatMessage::new(ROLE_USER, "a"),
+            result,
+            ChatMessage::new(ROLE_ASSISTANT, "b"),
+        ];
+        assert_eq!(history_window(&messages, None).len(), 2);
+    }
+}
@@ -1,11 +1,14 @@
-use shared::models::Tool;
-use std::collections::HashMap;
+use crate::config::{PluginFilter, PluginHealthCheck};
+use shared::models::{DiscoveryFailure, DiscoveryReport, Resource, ResourceContents, Tool};
+use std::collections::{HashMap, VecDeque};
 use std::path::Path;
 use std::process::Stdio;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
-use tokio::sync::{Mutex, RwLock, oneshot};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, RwLock, Semaphore, oneshot, watch};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
@@ -16,18 +19,31 @@ use protocol::*;
 pub struct PluginManager {
     plugins: Arc<RwLock<HashMap<String, Arc<PluginInstance>>>>,
     tools: Arc<RwLock<HashMap<String, String>>>, // Tool Name -> Plugin Name
+    filter: Arc<PluginFilter>,
+    /// False while a background discovery is running
+    ready: Arc<watch::Sender<bool>>,
 }
 
 struct PluginInstance {
+    /// The executable, to start the plugin again after it hangs
+    path: String,
     name: RwLock<String>,
     version: RwLock<String>,
     description: RwLock<String>,
     enabled: Arc<RwLock<bool>>,
-    #[allow(dead_code)]
     process: Mutex<Child>,
     stdin: Mutex<tokio::process::ChildStdin>,
     tools: RwLock<Vec<Tool>>,
+    resources: RwLock<Vec<Resource>>,
     pending_requests: Arc<Mutex<HashMap<PluginRequestId, oneshot::Sender<JsonRpcResponse>>>>,
+    /// Ids of each batch still waiting for its answer
+    pending_batches: Arc<Mutex<Vec<Vec<PluginRequestId>>>>,
+    limits: RwLock<CallLimits>,
+    capabilities: RwLock<PluginCapabilities>,
+    call_slots: RwLock<Option<Arc<Semaphore>>>,
+    recent_calls: Mutex<VecDeque<Instant>>,
+    /// Pings in a row the plugin didn't answer
+    missed_pings: AtomicU32,
 }
 
 impl Default for PluginManager {
@@ -38,16 +54,24 @@ impl Default for PluginManager {
 
 impl PluginManager {
     pub fn new() -> Self {
+        Self::with_filter(PluginFilter::default())
+    }
+
+    /// A manager that only keeps the plugins `filter` allows
+    pub fn with_filter(filter: PluginFilter) -> Self {
         Self {
             plugins: Arc::new(RwLock::new(HashMap::new())),
             tools: Arc::new(RwLock::new(HashMap::new())),
+            filter: Arc::new(filter),
+            ready: Arc::new(watch::Sender::new(true)),
         }
     }
 
+    /// Start a plugin and register its tools, returning the plugin's name
     pub async fn load_plugin(
         &self,
         path: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         let mut command = Command::new(path);
         command
             .stdin(Stdio::piped())
@@ -61,8 +85,11 @@ impl PluginManager {
 
         let pending_requests = Arc::new(Mutex::new(HashMap::new()));
         let pending_requests_clone = pending_requests.clone();
+        let pending_batches = Arc::new(Mutex::new(Vec::new()));
+        let pending_batches_clone = pending_batches.clone();
 
         let instance = Arc::new(PluginInstance {
+            path: path.to_string(),
             name: RwLock::new(String::new()),
             version: RwLock::new(String::new()),
             description: RwLock::new(String::new()),
@@ -70,7 +97,14 @@ impl PluginManager {
             process: Mutex::new(child),
             stdin: Mutex::new(stdin),
             tools: RwLock::new(Vec::new()),
+            resources: RwLock::new(Vec::new()),
             pending_requests,
+            pending_batches,
+            limits: RwLock::new(CallLimits::default()),
+            capabilities: RwLock::new(PluginCapabilities::default()),
+            call_slots: RwLock::new(None),
+            recent_calls: Mutex::new(VecDeque::new()),
+            missed_pings: AtomicU32::new(0),
         });
 
         // Start background listener
@@ -85,7 +119,31 @@ impl PluginManager {
                         break;
                     }
                     Ok(_) => {
-                        if let Ok(message) = serde_json::from_str::<PluginMessage>(&line) {
+                        let messages = match parse_messages(&line) {
+                            Ok(messages) => messages,
+                            Err(e) => {
+                                warn!(
+                                    "Unparseable line from plugin {}: {} ({})",
+                                    instance_name_for_task,
+                                    line.trim_end(),
+                                    e
+                                );
+                                // Whoever waits on this answer gets the error instead of hanging
+                                let mut pending = pending_requests_clone.lock().await;
+                                for id in salvage_ids(&line) {
+                                    if let Some(tx) = pending.remove(&id) {
+                                        let _ = tx.send(JsonRpcResponse::error(
+                                            id,
+                                            PARSE_ERROR,
+                                            format!("Unparseable response from plugin: {}", e),
+                                        ));
+                                    }
+                                }
+                                continue;
+                            }
+                        };
+                        let mut answered = Vec::new();
+                        for message in messages {
                             match message {
                                 PluginMessage::Response(resp) => {
                                     if let Some(id) = resp.id.clone() {
@@ -93,6 +151,7 @@ impl PluginManager {
                                         if let Some(tx) = pending.remove(&id) {
                                             let _ = tx.send(resp);
                                         }
+                                        answered.push(id);
                                     }
                                 }
                                 PluginMessage::Notification(notif) => {
@@ -106,6 +165,14 @@ impl PluginManager {
                                 }
                             }
                         }
+                        if is_batch_answer(&line) {
+                            fail_unanswered(
+                                &pending_batches_clone,
+                                &pending_requests_clone,
+                                &answered,
+                            )
+                            .await;
+                        }
                     }
                     Err(e) => {
                         error!("Error reading from plugin stdout: {:?}", e);
@@ -122,6 +189,7 @@ impl PluginManager {
             params: Some(serde_json::to_value(InitializeParams {
                 host: "renoma".to_string(),
                 version: "0.1.0".to_string(),
+                protocol_version: PROTOCOL_VERSION.to_string(),
             })?),
             id: Some(PluginRequestId::Number(1)),
         };
@@ -131,16 +199,45 @@ impl PluginManager {
         if let Some(result) = response.result {
             let init_result: InitializeResult = serde_json::from_value(result)?;
 
+            if let Some(version) = &init_result.protocol_version
+                && !protocol_compatible(version)
+            {
+                instance.process.lock().await.kill().await?;
+                return Err(format!(
+                    "Plugin {} speaks protocol {}, but this host speaks {}",
+                    init_result.name, version, PROTOCOL_VERSION
+                )
+                .into());
+            }
+
+            // The name is only known after the handshake, so a disallowed plugin is stopped here
+            if !self.filter.allows(&init_result.name) {
+                info!("Plugin {} is not allowed, stopping it", init_result.name);
+                instance.process.lock().await.kill().await?;
+                return Err(format!("Plugin not allowed: {}", init_result.name).into());
+            }
+
             {
                 let mut name = instance.name.write().await;
                 let mut version = instance.version.write().await;
                 let mut description = instance.description.write().await;
                 let mut tools_list = instance.tools.write().await;
+                let mut resources = instance.resources.write().await;
+                let mut limits = instance.limits.write().await;
+                let mut capabilities = instance.capabilities.write().await;
+                let mut call_slots = instance.call_slots.write().await;
 
                 *name = init_result.name.clone();
                 *version = init_result.version.clone();
                 *description = init_result.description.clone();
                 *tools_list = init_result.tools.clone();
+                *resources = init_result.resources.clone();
+                *call_slots = init_result
+                    .limits
+                    .concurrency()
+                    .map(|n| Arc::new(Semaphore::new(n)));
+                *limits = init_result.limits.clone();
+                *capabilities = init_result.capabilities.clone();
             }
 
             let plugin_name = init_result.name.clone();
@@ -165,13 +262,13 @@ impl PluginManager {
                     tools.insert(tool.name, plugin_name.clone());
                 }
             }
+
+            Ok(plugin_name)
         } else if let Some(err) = response.error {
-            return Err(format!("Plugin initialization failed: {}", err.message).into());
+            Err(format!("Plugin initialization failed: {}", err.message).into())
         } else {
-            return Err("Plugin initialization failed: Unknown error".into());
+            Err("Plugin initialization failed: Unknown error".into())
         }
-
-        Ok(())
     }
 
     pub async fn get_all_tools(&self) -> Vec<Tool> {
@@ -204,36 +301,166 @@ impl PluginManager {
         tool_name: &str,
         args: serde_json::Value,
     ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
-        let plugin_name = {
-            let tools = self.tools.read().await;
-            tools.get(tool_name).cloned()
-        };
+        let (plugin_name, plugin) = self.plugin_for_tool(tool_name).await?;
+        // Held until the call finishes
+        let _permit = plugin
+            .acquire_call_slot()
+            .await
+            .map_err(|e| format!("Plugin {}: {}", plugin_name, e))?;
+        let response = plugin.send_request(tool_request(tool_name, args)?).await?;
+        tool_result(response)
+    }
+
+    /// Run several tool calls from one turn, in order.
+    ///
+    /// Calls that go to the same batch-capable plugin are sent as a single JSON-RPC batch;
+    /// everything else falls back to one request per call.
+    pub async fn call_tools(
+        &self,
+        calls: Vec<(String, serde_json::Value)>,
+    ) -> Vec<Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut results: Vec<Option<Result<_, _>>> = calls.iter().map(|_| None).collect();
+        let mut batches: HashMap<String, Vec<usize>> = HashMap::new();
 
-        if let Some(plugin_name) = plugin_name {
-            let plugins = self.plugins.read().await;
-            if let Some(plugin) = plugins.get(&plugin_name) {
-                if !*plugin.enabled.read().await {
-                    return Err(format!("Plugin {} is disabled", plugin_name).into());
+        for (i, (tool_name, _)) in calls.iter().enumerate() {
+            match self.plugin_for_tool(tool_name).await {
+                Ok((plugin_name, plugin)) if plugin.capabilities.read().await.batch => {
+                    batches.entry(plugin_name).or_default().push(i);
                 }
-                let req = JsonRpcRequest {
-                    json_rpc: "2.0".to_string(),
-                    method: "call_tool".to_string(),
-                    params: Some(serde_json::to_value(CallToolParams {
-                        name: tool_name.to_string(),
-                        arguments: args,
-                    })?),
-                    id: Some(PluginRequestId::Number(Uuid::now_v7().as_u128() as i64)),
-                };
-                let response = plugin.send_request(req).await?;
-                if let Some(result) = response.result {
-                    return Ok(result);
-                } else if let Some(err) = response.error {
-                    return Err(format!("Tool execution error: {}", err.message).into());
+                Ok(_) => {}
+                Err(e) => results[i] = Some(Err(e)),
+            }
+        }
+
+        for (plugin_name, indices) in batches.into_iter().filter(|(_, v)| v.len() > 1) {
+            let batch = indices
+                .iter()
+                .map(|&i| (calls[i].0.as_str(), calls[i].1.clone()))
+                .collect::<Vec<_>>();
+            let outcomes = self.call_batch(&plugin_name, batch).await;
+            for (i, outcome) in indices.into_iter().zip(outcomes) {
+                results[i] = Some(outcome);
+            }
+        }
+
+        let mut out = Vec::with_capacity(calls.len());
+        for ((tool_name, args), result) in calls.into_iter().zip(results) {
+            out.push(match result {
+                Some(result) => result,
+                None => self.call_tool(&tool_name, args).await,
+            });
+        }
+        out
+    }
+
+    async fn call_batch(
+        &self,
+        plugin_name: &str,
+        calls: Vec<(&str, serde_json::Value)>,
+    ) -> Vec<Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>>> {
+        let plugin = match self.plugins.read().await.get(plugin_name).cloned() {
+            Some(plugin) => plugin,
+            None => {
+                return calls
+                    .iter()
+                    .map(|_| Err(format!("Plugin not found: {}", plugin_name).into()))
+                    .collect();
+            }
+        };
+
+        // Every call in the batch counts against the plugin's limits
+        let mut permits = Vec::new();
+        let mut requests = Vec::new();
+        let mut results: Vec<Option<Result<_, _>>> = Vec::new();
+        for (tool_name, args) in calls {
+            let prepared = match plugin.acquire_call_slot().await {
+                Ok(permit) => tool_request(tool_name, args).map(|req| (permit, req)),
+                Err(e) => Err(format!("Plugin {}: {}", plugin_name, e).into()),
+            };
+            match prepared {
+                Ok((permit, req)) => {
+                    permits.push(permit);
+                    requests.push(req);
+                    results.push(None);
                 }
+                Err(e) => results.push(Some(Err(e))),
             }
         }
 
-        Err(format!("Tool not found: {}", tool_name).into())
+        let mut responses = plugin.send_batch(requests).await.into_iter();
+        results
+            .into_iter()
+            .map(|r| match r {
+                Some(r) => r,
+                None => responses
+                    .next()
+                    .expect("one response per request")
+                    .and_then(tool_result),
+            })
+            .collect()
+    }
+
+    async fn plugin_for_tool(
+        &self,
+        tool_name: &str,
+    ) -> Result<(String, Arc<PluginInstance>), Box<dyn std::error::Error + Send + Sync>> {
+        let plugin_name = self
+            .tools
+            .read()
+            .await
+            .get(tool_name)
+            .cloned()
+            .ok_or_else(|| format!("Tool not found: {}", tool_name))?;
+        let plugin = self.enabled_plugin(&plugin_name).await?;
+        Ok((plugin_name, plugin))
+    }
+
+    /// Resources a plugin exposes, asking the plugin for a fresh list when it supports that
+    pub async fn list_resources(
+        &self,
+        plugin_name: &str,
+    ) -> Result<Vec<Resource>, Box<dyn std::error::Error + Send + Sync>> {
+        let plugin = self.enabled_plugin(plugin_name).await?;
+        if !plugin.capabilities.read().await.resources {
+            return Ok(plugin.resources.read().await.clone());
+        }
+
+        let result: ListResourcesResult = plugin.call("list_resources", None).await?;
+        *plugin.resources.write().await = result.resources.clone();
+        Ok(result.resources)
+    }
+
+    pub async fn read_resource(
+        &self,
+        plugin_name: &str,
+        uri: &str,
+    ) -> Result<ResourceContents, Box<dyn std::error::Error + Send + Sync>> {
+        let plugin = self.enabled_plugin(plugin_name).await?;
+        if !plugin.capabilities.read().await.resources {
+            return Err(format!("Plugin {} does not serve resources", plugin_name).into());
+        }
+
+        let params = serde_json::to_value(ReadResourceParams {
+            uri: uri.to_string(),
+        })?;
+        plugin.call("read_resource", Some(params)).await
+    }
+
+    async fn enabled_plugin(
+        &self,
+        plugin_name: &str,
+    ) -> Result<Arc<PluginInstance>, Box<dyn std::error::Error + Send + Sync>> {
+        let plugin = self
+            .plugins
+            .read()
+            .await
+            .get(plugin_name)
+            .cloned()
+            .ok_or_else(|| format!("Plugin not found: {}", plugin_name))?;
+        if !*plugin.enabled.read().await {
+            return Err(format!("Plugin {} is disabled", plugin_name).into());
+        }
+        Ok(plugin)
     }
 
     pub async fn toggle_plugin(
@@ -250,27 +477,163 @@ impl PluginManager {
         }
     }
 
+    /// Load every executable in `dir`, reporting which plugins loaded and which files failed
     pub async fn discover_plugins(
         &self,
         dir: impl AsRef<Path>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<DiscoveryReport, Box<dyn std::error::Error + Send + Sync>> {
         let dir = dir.as_ref();
+        let mut report = DiscoveryReport::default();
         if !dir.exists() {
-            return Ok(());
+            return Ok(report);
         }
 
-        let mut entries = tokio::fs::read_dir(dir).await?;
+        let mut entries = tokio::fs::read_dir(dir).await.map_err(|e| {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                format!("Plugins directory {} is not readable", dir.display()).into()
+            } else {
+                Box::<dyn std::error::Error + Send + Sync>::from(e)
+            }
+        })?;
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
-            if path.is_file()
-                && is_executable::is_executable(&path)
-                && let Err(e) = self.load_plugin(path.to_str().unwrap()).await
-            {
-                error!("Failed to load plugin from {:?}: {:?}", path, e);
+            if !path.is_file() {
+                continue;
+            }
+            let file = entry.file_name().to_string_lossy().into_owned();
+            if !is_executable::is_executable(&path) {
+                report.failed.push(DiscoveryFailure {
+                    file,
+                    error: "File is not executable".to_string(),
+                });
+                continue;
+            }
+            match self.load_plugin(&path.to_string_lossy()).await {
+                Ok(name) => report.loaded.push(name),
+                Err(e) => {
+                    error!("Failed to load plugin from {:?}: {:?}", path, e);
+                    report.failed.push(DiscoveryFailure {
+                        file,
+                        error: e.to_string(),
+                    });
+                }
             }
         }
 
-        Ok(())
+        Ok(report)
+    }
+
+    /// Discover `dir` in a background task so the server can start serving right away.
+    /// Tools show up as their plugins load, and [`Self::is_ready`] turns true once all
+    /// of them have.
+    pub fn discover_in_background(&self, dir: impl AsRef<Path>) {
+        let manager = self.clone();
+        let dir = dir.as_ref().to_path_buf();
+        self.ready.send_replace(false);
+        tokio::spawn(async move {
+            match manager.discover_plugins(&dir).await {
+                Ok(report) => info!(
+                    "Plugin discovery finished: {} loaded, {} failed",
+                    report.loaded.len(),
+                    report.failed.len()
+                ),
+                Err(e) => error!("Failed to discover plugins: {:?}", e),
+            }
+            manager.ready.send_replace(true);
+        });
+    }
+
+    /// Whether no background discovery is running
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Wait up to `timeout` for a background discovery to finish, returning whether it did
+    pub async fn wait_until_ready(&self, timeout: Duration) -> bool {
+        let mut ready = self.ready.subscribe();
+        tokio::time::timeout(timeout, ready.wait_for(|ready| *ready))
+            .await
+            .is_ok_and(|r| r.is_ok())
+    }
+
+    /// Ping every plugin in the background as `check` says, see [`PluginHealthCheck`]
+    pub fn watch_health(&self, check: PluginHealthCheck) {
+        let Some(interval) = check.interval else {
+            return;
+        };
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick is immediate, and plugins are still starting then
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                manager.check_health(&check).await;
+            }
+        });
+    }
+
+    /// Ping every loaded plugin once, unloading the ones that have missed too many pings
+    async fn check_health(&self, check: &PluginHealthCheck) {
+        let plugins = self
+            .plugins
+            .read()
+            .await
+            .iter()
+            .map(|(name, plugin)| (name.clone(), plugin.clone()))
+            .collect::<Vec<_>>();
+        let answers =
+            futures::future::join_all(plugins.iter().map(|(_, p)| p.ping(check.timeout))).await;
+
+        for ((name, plugin), answered) in plugins.into_iter().zip(answers) {
+            if answered {
+                plugin.missed_pings.store(0, Ordering::Relaxed);
+                continue;
+            }
+            let missed = plugin.missed_pings.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(
+                "Plugin {} didn't answer a ping ({} of {})",
+                name, missed, check.max_missed
+            );
+            if missed < check.max_missed {
+                continue;
+            }
+
+            error!("Plugin {} stopped responding, unloading it", name);
+            if !self.remove_hung(&name, &plugin).await {
+                continue;
+            }
+            if check.restart {
+                let enabled = *plugin.enabled.read().await;
+                match self.load_plugin(&plugin.path).await {
+                    Ok(name) => {
+                        info!("Restarted plugin {}", name);
+                        if !enabled {
+                            let _ = self.toggle_plugin(&name).await;
+                        }
+                    }
+                    Err(e) => error!("Failed to restart plugin {}: {:?}", name, e),
+                }
+            }
+        }
+    }
+
+    /// Take a hung plugin and its tools out and kill it without waiting for its calls.
+    /// Returns false if the plugin was already replaced, e.g. by a reload.
+    async fn remove_hung(&self, name: &str, plugin: &Arc<PluginInstance>) -> bool {
+        {
+            let mut plugins = self.plugins.write().await;
+            if !plugins.get(name).is_some_and(|p| Arc::ptr_eq(p, plugin)) {
+                return false;
+            }
+            plugins.remove(name);
+        }
+        self.tools.write().await.retain(|_, owner| owner != name);
+        if let Err(e) = plugin.kill().await {
+            warn!("Failed to kill plugin {}: {:?}", name, e);
+        }
+        true
     }
 
     pub async fn unload_plugin(
@@ -279,17 +642,131 @@ impl PluginManager {
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut plugins = self.plugins.write().await;
         if let Some(plugin) = plugins.remove(name) {
-            let mut child = plugin.process.lock().await;
-            child.kill().await?;
+            plugin.stop().await?;
             info!("Unloaded plugin: {}", name);
             Ok(())
         } else {
             Err(format!("Plugin not found: {}", name).into())
         }
     }
+
+    /// Stop every loaded plugin and discover `dir` again from scratch, so changed plugin
+    /// binaries are picked up without restarting the server
+    pub async fn reload_plugins(
+        &self,
+        dir: impl AsRef<Path>,
+    ) -> Result<DiscoveryReport, Box<dyn std::error::Error + Send + Sync>> {
+        // Taken out first so no new calls reach the old processes
+        let old = self.plugins.write().await.drain().collect::<Vec<_>>();
+        self.tools.write().await.clear();
+        for (name, plugin) in old {
+            if let Err(e) = plugin.stop().await {
+                warn!("Failed to stop plugin {}: {:?}", name, e);
+            }
+        }
+        info!("Unloaded all plugins, rediscovering");
+        self.discover_plugins(dir).await
+    }
 }
 
 impl PluginInstance {
+    /// How long a stopping plugin gets to answer the calls already sent to it
+    const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Wait for in-flight calls to finish, then kill the process. Calls still unanswered
+    /// after [`Self::DRAIN_TIMEOUT`] fail instead of waiting forever.
+    async fn stop(&self) -> std::io::Result<()> {
+        let deadline = Instant::now() + Self::DRAIN_TIMEOUT;
+        while !self.pending_requests.lock().await.is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        self.kill().await
+    }
+
+    /// Kill the process right away, failing the calls still waiting on it
+    async fn kill(&self) -> std::io::Result<()> {
+        let result = self.process.lock().await.kill().await;
+        // Dropping the senders wakes anyone still waiting with an error
+        self.pending_requests.lock().await.clear();
+        result
+    }
+
+    /// Whether the plugin answers a `ping` within `timeout`. Any answer counts, an error
+    /// included, so plugins written before `ping` existed still pass.
+    async fn ping(&self, timeout: Duration) -> bool {
+        let id = PluginRequestId::Number(Uuid::now_v7().as_u128() as i64);
+        let req = JsonRpcRequest {
+            json_rpc: "2.0".to_string(),
+            method: "ping".to_string(),
+            params: None,
+            id: Some(id.clone()),
+        };
+        let answered = tokio::time::timeout(timeout, self.send_request(req))
+            .await
+            .is_ok_and(|response| response.is_ok());
+        if !answered {
+            self.pending_requests.lock().await.remove(&id);
+        }
+        answered
+    }
+
+    /// Enforce the plugin's call limits, rejecting calls beyond them
+    async fn acquire_call_slot(&self) -> Result<Option<OwnedSemaphorePermit>, String> {
+        let limits = self.limits.read().await.clone();
+
+        let permit = match self.call_slots.read().await.clone() {
+            Some(slots) => Some(slots.try_acquire_owned().map_err(|_| {
+                format!(
+                    "concurrency limit reached ({} calls in flight)",
+                    limits.concurrency().unwrap_or_default()
+                )
+            })?),
+            None => None,
+        };
+
+        if let Some((max_calls, window)) = limits.rate() {
+            let now = Instant::now();
+            let mut recent = self.recent_calls.lock().await;
+            while recent
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= window)
+            {
+                recent.pop_front();
+            }
+            if recent.len() >= max_calls {
+                return Err(format!(
+                    "rate limit exceeded ({} calls per {}s)",
+                    max_calls, limits.window_secs
+                ));
+            }
+            recent.push_back(now);
+        }
+
+        Ok(permit)
+    }
+
+    /// Send a request and decode its result
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        let req = JsonRpcRequest {
+            json_rpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: Some(PluginRequestId::Number(Uuid::now_v7().as_u128() as i64)),
+        };
+        let response = self.send_request(req).await?;
+        if let Some(result) = response.result {
+            Ok(serde_json::from_value(result)?)
+        } else if let Some(err) = response.error {
+            Err(format!("{} failed: {}", method, err.message).into())
+        } else {
+            Err(format!("{} failed: empty response", method).into())
+        }
+    }
+
     async fn send_request(
         &self,
         req: JsonRpcRequest,
@@ -313,4 +790,244 @@ impl PluginInstance {
 
         Ok(rx.await?)
     }
+
+    /// Send requests as one JSON-RPC batch, returning the responses in request order
+    async fn send_batch(
+        &self,
+        reqs: Vec<JsonRpcRequest>,
+    ) -> Vec<Result<JsonRpcResponse, Box<dyn std::error::Error + Send + Sync>>> {
+        if reqs.is_empty() {
+            return Vec::new();
+        }
+
+        let ids: Vec<PluginRequestId> = reqs.iter().filter_map(|req| req.id.clone()).collect();
+        let mut receivers = Vec::new();
+        {
+            let mut pending = self.pending_requests.lock().await;
+            for req in &reqs {
+                let (tx, rx) = oneshot::channel();
+                if let Some(id) = req.id.clone() {
+                    pending.insert(id, tx);
+                }
+                receivers.push(rx);
+            }
+        }
+        self.pending_batches.lock().await.push(ids.clone());
+
+        let written = async {
+            let mut batch_json = serde_json::to_string(&reqs)?;
+            batch_json.push('\n');
+            let mut stdin = self.stdin.lock().await;
+            stdin.write_all(batch_json.as_bytes()).await?;
+            stdin.flush().await?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+        }
+        .await;
+
+        if let Err(e) = written {
+            let mut pending = self.pending_requests.lock().await;
+            for id in &ids {
+                pending.remove(id);
+            }
+            self.pending_batches
+                .lock()
+                .await
+                .retain(|batch| *batch != ids);
+            let message = e.to_string();
+            return reqs.iter().map(|_| Err(message.clone().into())).collect();
+        }
+
+        let mut responses = Vec::with_capacity(receivers.len());
+        for rx in receivers {
+            responses.push(rx.await.map_err(|e| e.into()));
+        }
+        // Still listed if the plugin answered each request on its own line
+        self.pending_batches
+            .lock()
+            .await
+            .retain(|batch| *batch != ids);
+        responses
+    }
+}
+
+/// The messages on one line of plugin output. A batch answer is an array of responses,
+/// each routed by its id. Some runtimes start their output with a byte order mark or end
+/// lines with CRLF, so both are let through.
+fn parse_messages(line: &str) -> Result<Vec<PluginMessage>, serde_json::Error> {
+    let line = line.trim_start_matches('\u{feff}').trim();
+    if line.is_empty() {
+        Ok(Vec::new())
+    } else if line.starts_with('[') {
+        serde_json::from_str(line)
+    } else {
+        serde_json::from_str(line).map(|message| vec![message])
+    }
+}
+
+/// Whether a line of plugin output answers a batch, i.e. is an array
+fn is_batch_answer(line: &str) -> bool {
+    line.trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('[')
+}
+
+/// Fail the requests of the batch a batch answer was for that the answer left out, so
+/// their callers get an error instead of waiting forever
+async fn fail_unanswered(
+    batches: &Mutex<Vec<Vec<PluginRequestId>>>,
+    pending: &Mutex<HashMap<PluginRequestId, oneshot::Sender<JsonRpcResponse>>>,
+    answered: &[PluginRequestId],
+) {
+    let batch = {
+        let mut batches = batches.lock().await;
+        let Some(at) = batches
+            .iter()
+            .position(|batch| batch.iter().any(|id| answered.contains(id)))
+        else {
+            return;
+        };
+        batches.remove(at)
+    };
+    let mut pending = pending.lock().await;
+    for id in batch.into_iter().filter(|id| !answered.contains(id)) {
+        if let Some(tx) = pending.remove(&id) {
+            let _ = tx.send(JsonRpcResponse::error(
+                id,
+                INTERNAL_ERROR,
+                "Missing from the plugin's batch answer".to_string(),
+            ));
+        }
+    }
+}
+
+/// Ids of the requests an unparseable line was meant to answer. They come from the JSON
+/// when only its shape is wrong, otherwise from any `"id":` key in the raw text.
+fn salvage_ids(line: &str) -> Vec<PluginRequestId> {
+    let line = line.trim_start_matches('\u{feff}').trim();
+    let id_of = |value: &serde_json::Value| {
+        value
+            .get("id")
+            .and_then(|id| serde_json::from_value(id.clone()).ok())
+    };
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(serde_json::Value::Array(items)) => items.iter().filter_map(id_of).collect(),
+        Ok(value) => id_of(&value).into_iter().collect(),
+        Err(_) => line
+            .match_indices("\"id\"")
+            .filter_map(|(at, key)| {
+                let rest = line[at + key.len()..].trim_start().strip_prefix(':')?;
+                let rest = rest.trim_start();
+                if let Some(rest) = rest.strip_prefix('"') {
+                    let end = rest.find('"')?;
+                    Some(PluginRequestId::String(rest[..end].to_string()))
+                } else {
+                    let end = rest
+                        .find(|c: char| !(c == '-' || c.is_ascii_digit()))
+                        .unwrap_or(rest.len());
+                    rest[..end].parse().ok().map(PluginRequestId::Number)
+                }
+            })
+            .collect(),
+    }
+}
+
+fn tool_request(
+    tool_name: &str,
+    args: serde_json::Value,
+) -> Result<JsonRpcRequest, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(JsonRpcRequest {
+        json_rpc: "2.0".to_string(),
+        method: "call_tool".to_string(),
+        params: Some(serde_json::to_value(CallToolParams {
+            name: tool_name.to_string(),
+            arguments: args,
+        })?),
+        id: Some(PluginRequestId::Number(Uuid::now_v7().as_u128() as i64)),
+    })
+}
+
+fn tool_result(
+    response: JsonRpcResponse,
+) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(result) = response.result {
+        Ok(result)
+    } else if let Some(err) = response.error {
+        Err(format!("Tool execution error: {}", err.message).into())
+    } else {
+        Err("Tool execution error: empty response".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_messages_reads_batch_answers() {
+        let single = r#"{"json_rpc":"2.0","result":1,"error":null,"id":1}"#;
+        assert!(matches!(
+            parse_messages(single).unwrap().as_slice(),
+            [PluginMessage::Response(JsonRpcResponse {
+                id: Some(PluginRequestId::Number(1)),
+                ..
+            })]
+        ));
+        assert!(!is_batch_answer(single));
+
+        let batch = r#"[{"json_rpc":"2.0","result":1,"error":null,"id":1},
+            {"json_rpc":"2.0","result":2,"error":null,"id":"two"}]"#;
+        assert_eq!(parse_messages(batch).unwrap().len(), 2);
+        assert!(is_batch_answer(batch));
+    }
+
+    #[test]
+    fn parse_messages_skips_a_bom_and_crlf() {
+        let single = "\u{feff}{\"json_rpc\":\"2.0\",\"result\":1,\"error\":null,\"id\":1}\r\n";
+        assert!(matches!(
+            parse_messages(single).unwrap().as_slice(),
+            [PluginMessage::Response(JsonRpcResponse {
+                id: Some(PluginRequestId::Number(1)),
+                ..
+            })]
+        ));
+
+        assert!(parse_messages("  \r\n").unwrap().is_empty());
+        assert!(parse_messages("{\"id\":").is_err());
+    }
+
+    #[test]
+    fn salvage_ids_finds_ids_in_unparseable_lines() {
+        assert_eq!(
+            salvage_ids(r#"[{"id":1,"oops":true},{"id":"two"},{}]"#),
+            [
+                PluginRequestId::Number(1),
+                PluginRequestId::String("two".into())
+            ]
+        );
+        assert_eq!(
+            salvage_ids(r#"{"json_rpc":"2.0","id": -3,"result":"#),
+            [PluginRequestId::Number(-3)]
+        );
+        assert_eq!(
+            salvage_ids(r#"{"id" : "abc", "result": nope}"#),
+            [PluginRequestId::String("abc".into())]
+        );
+        assert!(salvage_ids("not json at all").is_empty());
+    }
+
+    #[test]
+    fn zero_call_limits_mean_no_limit() {
+        let limits = |json| serde_json::from_value::<CallLimits>(json).unwrap();
+
+        let zero = limits(serde_json::json!({ "max_concurrent": 0, "max_calls": 0 }));
+        assert_eq!(zero.concurrency(), None);
+        assert_eq!(zero.rate(), None);
+
+        let no_window = limits(serde_json::json!({ "max_calls": 5, "window_secs": 0 }));
+        assert_eq!(no_window.rate(), None);
+
+        let capped = limits(serde_json::json!({ "max_concurrent": 2, "max_calls": 5 }));
+        assert_eq!(capped.concurrency(), Some(2));
+        assert_eq!(capped.rate(), Some((5, Duration::from_secs(60))));
+    }
 }
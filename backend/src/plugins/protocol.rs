@@ -1,5 +1,19 @@
 use serde::{Deserialize, Serialize};
-use shared::models::Tool;
+use shared::models::{Resource, Tool};
+use std::time::Duration;
+
+/// The plugin protocol this host speaks. Plugins must share its major version.
+pub const PROTOCOL_VERSION: &str = "1.0";
+
+/// Whether a plugin's protocol version is one this host can talk to
+pub fn protocol_compatible(version: &str) -> bool {
+    let major = |v: &str| {
+        v.split('.')
+            .next()
+            .and_then(|m| m.trim().parse::<u64>().ok())
+    };
+    major(version).is_some_and(|m| Some(m) == major(PROTOCOL_VERSION))
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JsonRpcRequest {
@@ -39,6 +53,27 @@ pub struct JsonRpcResponse {
     pub id: Option<PluginRequestId>,
 }
 
+impl JsonRpcResponse {
+    /// A failed answer to the request with `id`
+    pub fn error(id: PluginRequestId, code: i64, message: String) -> Self {
+        Self {
+            json_rpc: "2.0".to_string(),
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message,
+                data: None,
+            }),
+            id: Some(id),
+        }
+    }
+}
+
+/// JSON-RPC's code for a message that isn't valid
+pub const PARSE_ERROR: i64 = -32700;
+/// JSON-RPC's code for a request the other side failed to handle
+pub const INTERNAL_ERROR: i64 = -32603;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JsonRpcError {
     pub code: i64,
@@ -50,14 +85,69 @@ pub struct JsonRpcError {
 pub struct InitializeParams {
     pub host: String,
     pub version: String,
+    pub protocol_version: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitializeResult {
     pub name: String,
     pub version: String,
+    /// Protocol the plugin was written against, absent for plugins older than negotiation
+    #[serde(default)]
+    pub protocol_version: Option<String>,
     pub description: String,
     pub tools: Vec<Tool>,
+    #[serde(default)]
+    pub limits: CallLimits,
+    #[serde(default)]
+    pub capabilities: PluginCapabilities,
+    /// Resources known at startup; plugins with the `resources` capability can list more later
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+}
+
+/// Optional protocol features a plugin supports
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PluginCapabilities {
+    /// Accepts a JSON-RPC batch (an array of requests on one line) and answers with an array
+    #[serde(default)]
+    pub batch: bool,
+    /// Answers `list_resources` and `read_resource`
+    #[serde(default)]
+    pub resources: bool,
+}
+
+/// Optional caps a plugin can ask the host to enforce on its tool calls. A cap of 0 means
+/// no cap rather than no calls.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CallLimits {
+    /// Maximum number of tool calls in flight at once
+    pub max_concurrent: Option<usize>,
+    /// Maximum number of tool calls per `window_secs`
+    pub max_calls: Option<usize>,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+}
+
+fn default_window_secs() -> u64 {
+    60
+}
+
+impl CallLimits {
+    /// How many calls may run at once, if that is capped
+    pub fn concurrency(&self) -> Option<usize> {
+        self.max_concurrent.filter(|&n| n > 0)
+    }
+
+    /// How many calls may start per window, if that is capped
+    pub fn rate(&self) -> Option<(usize, Duration)> {
+        match self.max_calls {
+            Some(max_calls) if max_calls > 0 && self.window_secs > 0 => {
+                Some((max_calls, Duration::from_secs(self.window_secs)))
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -65,3 +155,13 @@ pub struct CallToolParams {
     pub name: String,
     pub arguments: serde_json::Value,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListResourcesResult {
+    pub resources: Vec<Resource>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadResourceParams {
+    pub uri: String,
+}
@@ -0,0 +1,56 @@
+use serde::Serialize;
+use shared::models::CompletionRequest;
+use std::path::{Path, PathBuf};
+
+/// Raw provider traffic of one completion request, kept for debugging odd model behavior.
+///
+/// Each entry is one JSON line: the (redacted) request first, then every chunk as received.
+pub struct ProviderLog {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl ProviderLog {
+    /// Start a log for `payload`, or `None` if provider debugging is off
+    pub fn start(dir: Option<&Path>, payload: &CompletionRequest) -> Option<Self> {
+        let dir = dir?;
+        let path = dir.join(format!(
+            "{}-{}.jsonl",
+            payload.chat_id,
+            uuid::Uuid::now_v7()
+        ));
+        let mut log = Self {
+            path,
+            lines: Vec::new(),
+        };
+        let mut request = payload.clone();
+        request.api_key = "[REDACTED]".to_string();
+        log.record(&request);
+        Some(log)
+    }
+
+    pub fn record(&mut self, entry: &impl Serialize) {
+        match serde_json::to_string(entry) {
+            Ok(line) => {
+                tracing::trace!(target: "renoma::provider", "{}", line);
+                self.lines.push(line);
+            }
+            Err(e) => tracing::warn!("Failed to serialize provider chunk: {:?}", e),
+        }
+    }
+
+    /// Write everything recorded so far, replacing any earlier flush
+    pub async fn flush(&self) {
+        if let Some(dir) = self.path.parent()
+            && let Err(e) = tokio::fs::create_dir_all(dir).await
+        {
+            tracing::error!("Failed to create provider debug dir {:?}: {:?}", dir, e);
+            return;
+        }
+        let mut data = self.lines.join("\n");
+        data.push('\n');
+        if let Err(e) = tokio::fs::write(&self.path, data).await {
+            tracing::error!("Failed to write provider log {:?}: {:?}", self.path, e);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use crate::dbs::{Database, DbResult};
+use shared::models::Character;
+use uuid::Uuid;
+
+/// Give a fresh install something to chat with. Does nothing if any character exists.
+pub async fn seed_sample_character(db: &dyn Database) -> DbResult<bool> {
+    if !db.get_characters().await?.is_empty() {
+        return Ok(false);
+    }
+
+    db.create_character(Character {
+        id: Uuid::new_v4(),
+        name: "Assistant".to_string(),
+        description: "A friendly, general-purpose helper.".to_string(),
+        personality: "Warm, curious and concise. Explains things clearly and admits when unsure."
+            .to_string(),
+        scenario: "You are chatting with the user in Renoma.".to_string(),
+        first_message: "Hi! I'm your assistant. Ask me anything, or create your own characters from the sidebar.".to_string(),
+        example_messages: String::new(),
+        alternate_greetings: Vec::new(),
+        greeting_mode: Default::default(),
+        is_favorite: false,
+        response_length: None,
+        reasoning_effort: None,
+        metadata: Default::default(),
+    })
+    .await?;
+    Ok(true)
+}
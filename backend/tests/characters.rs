@@ -0,0 +1,179 @@
+mod common;
+
+use axum::Router;
+use axum::http::Method;
+use backend::{Config, DatabaseConfig};
+use common::{app, send};
+use serde_json::{Value, json};
+
+#[tokio::test]
+async fn bundles_past_the_default_body_limit_import() {
+    let app = app().await;
+    let character_id = uuid::Uuid::now_v7();
+    let long = "a".repeat(100_000);
+    let messages: Vec<Value> = (0..40)
+        .map(|_| {
+            json!({
+                "id": uuid::Uuid::now_v7(),
+                "role": "user",
+                "content": long,
+            })
+        })
+        .collect();
+    let bundle = json!({
+        "character": {
+            "id": character_id,
+            "name": "Archivist",
+            "description": "",
+            "personality": "",
+            "scenario": "",
+            "first_message": "Hi",
+            "example_messages": "",
+        },
+        "chats": [{
+            "id": uuid::Uuid::now_v7(),
+            "character_id": character_id,
+            "messages": messages,
+        }],
+    });
+    assert!(bundle.to_string().len() > 2 * 1024 * 1024);
+
+    let imported: Value = serde_json::from_str(
+        &send(
+            &app,
+            Method::POST,
+            "/api/characters/import/full",
+            Some(bundle),
+        )
+        .await,
+    )
+    .unwrap();
+    assert_eq!(imported["name"], "Archivist");
+
+    let chats: Value =
+        serde_json::from_str(&send(&app, Method::GET, "/api/chats", None).await).unwrap();
+    assert_eq!(chats[0]["message_count"], 40);
+}
+
+#[tokio::test]
+async fn a_fresh_install_gets_a_sample_character_unless_told_not_to() {
+    let names = |skip_seed: bool| async move {
+        let config = Config {
+            skip_seed,
+            ..Default::default()
+        };
+        let app = backend::init(Router::new(), DatabaseConfig::Memory, config).await;
+        let characters: Value =
+            serde_json::from_str(&send(&app, Method::GET, "/api/characters", None).await).unwrap();
+        characters
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(names(false).await, ["Assistant"]);
+    assert!(names(true).await.is_empty());
+}
+
+#[tokio::test]
+async fn card_metadata_survives_import_and_export() {
+    let cards = tempfile::tempdir().unwrap();
+    let card = json!({
+        "spec": "chara_card_v2",
+        "data": {
+            "name": "Ada",
+            "first_mes": "Hello.",
+            "creator": "someone",
+            "creator_notes": "Best with low temperature",
+            "tags": ["history", "science"],
+            "character_version": "1.2",
+        },
+    });
+    std::fs::write(cards.path().join("ada.json"), card.to_string()).unwrap();
+    let app = common::app_with(Config {
+        import_cards_dir: Some(cards.path().to_path_buf()),
+        ..Default::default()
+    })
+    .await;
+
+    let characters: Value =
+        serde_json::from_str(&send(&app, Method::GET, "/api/characters", None).await).unwrap();
+    let ada = characters[0].clone();
+    assert_eq!(ada["creator"], "someone");
+    assert_eq!(ada["creator_notes"], "Best with low temperature");
+    assert_eq!(ada["tags"], json!(["history", "science"]));
+    assert_eq!(ada["character_version"], "1.2");
+
+    let uri = format!(
+        "/api/characters/{}/export/full",
+        ada["id"].as_str().unwrap()
+    );
+    let bundle: Value = serde_json::from_str(&send(&app, Method::GET, &uri, None).await).unwrap();
+    send(
+        &app,
+        Method::POST,
+        "/api/characters/import/full",
+        Some(bundle),
+    )
+    .await;
+    let characters: Value =
+        serde_json::from_str(&send(&app, Method::GET, "/api/characters", None).await).unwrap();
+    let characters = characters.as_array().unwrap();
+    assert_eq!(characters.len(), 2);
+    let imported = characters.iter().find(|c| c["id"] != ada["id"]).unwrap();
+    for field in ["creator", "creator_notes", "tags", "character_version"] {
+        assert_eq!(imported[field], ada[field]);
+    }
+}
+
+#[tokio::test]
+async fn a_card_directory_imports_the_valid_cards_and_skips_the_rest() {
+    use base64::Engine;
+
+    let cards = tempfile::tempdir().unwrap();
+    let write = |file: &str, bytes: &[u8]| std::fs::write(cards.path().join(file), bytes).unwrap();
+    let v2 = json!({ "spec": "chara_card_v2", "data": { "name": "Mira", "first_mes": "Hi" } });
+    write("a-v2.json", v2.to_string().as_bytes());
+    write(
+        "b-v1.json",
+        json!({ "name": "Mira" }).to_string().as_bytes(),
+    );
+    // The card sits base64-encoded in a `chara` text chunk
+    let mut chunk = b"chara\0".to_vec();
+    chunk.extend(
+        base64::engine::general_purpose::STANDARD
+            .encode(json!({ "name": "Pix" }).to_string())
+            .bytes(),
+    );
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend((chunk.len() as u32).to_be_bytes());
+    png.extend(b"tEXt");
+    png.extend(chunk);
+    png.extend([0; 4]);
+    write("c.png", &png);
+    write("broken.json", b"{ \"name\": ");
+    write(
+        "nameless.json",
+        json!({ "description": "Who?" }).to_string().as_bytes(),
+    );
+    write("blank.png", b"\x89PNG\r\n\x1a\n");
+    write("notes.txt", b"not a card");
+
+    let app = common::app_with(Config {
+        import_cards_dir: Some(cards.path().to_path_buf()),
+        ..Default::default()
+    })
+    .await;
+    let characters: Value =
+        serde_json::from_str(&send(&app, Method::GET, "/api/characters", None).await).unwrap();
+    let mut names: Vec<_> = characters
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Mira", "Mira (2)", "Pix"]);
+}
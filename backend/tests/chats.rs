@@ -0,0 +1,334 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use backend::Config;
+use backend::clock::FixedClock;
+use common::{
+    app, app_with, chat_with, chunk, completion, file_app, get_chat, new_character, new_chat,
+    recording_provider, request, send,
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn listings_preview_the_latest_message_as_plaintext() {
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    send(
+        &app,
+        Method::POST,
+        &format!("/api/chats/{}/message", chat_id),
+        Some(json!({
+            "id": uuid::Uuid::now_v7(),
+            "role": "user",
+            "content": "**Look** at `this`",
+            "alternatives": [],
+            "alternative_senders": [],
+        })),
+    )
+    .await;
+
+    let chats: Value =
+        serde_json::from_str(&send(&app, Method::GET, "/api/chats", None).await).unwrap();
+    assert_eq!(chats[0]["preview"], "Look at this");
+}
+
+#[tokio::test]
+async fn participants_speak_in_the_order_they_were_put_in() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, url) = file_app(dir.path()).await;
+    let chat_id = new_chat(&app).await;
+    let tester = get_chat(&app, &chat_id).await["character_id"].clone();
+    let bea = new_character(&app, "Bea").await;
+
+    // No endpoint makes group chats yet, so add Bea to the chat in the database
+    let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+    let participants = json!([
+        { "character_id": tester, "is_active": true, "order": 0 },
+        { "character_id": bea, "is_active": true, "order": 1 },
+    ]);
+    sqlx::query("UPDATE chats SET participants = ? WHERE id = ?")
+        .bind(participants)
+        .bind(&chat_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let reorder = |ids: Value| {
+        let app = app.clone();
+        let uri = format!("/api/chats/{}/participants/reorder", chat_id);
+        async move {
+            let body = json!({ "character_ids": ids });
+            let response = app.oneshot(request(Method::POST, &uri, Some(body)));
+            response.await.unwrap().status()
+        }
+    };
+    let order = || async {
+        let chat = get_chat(&app, &chat_id).await;
+        let participants = chat["participants"].as_array().unwrap().clone();
+        participants
+            .iter()
+            .map(|p| (p["character_id"].clone(), p["order"].clone()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(reorder(json!([bea, tester])).await, StatusCode::OK);
+    let expected = vec![(bea.clone(), json!(0)), (tester.clone(), json!(1))];
+    assert_eq!(order().await, expected);
+
+    // Anything but each participant exactly once leaves the order alone
+    for invalid in [
+        json!([bea]),
+        json!([bea, bea]),
+        json!([tester, bea, uuid::Uuid::now_v7()]),
+        json!([tester, uuid::Uuid::now_v7()]),
+    ] {
+        assert_eq!(reorder(invalid).await, StatusCode::BAD_REQUEST);
+    }
+    assert_eq!(order().await, expected);
+}
+
+#[tokio::test]
+async fn a_moved_chat_answers_as_its_new_character() {
+    let (api_base, received) = recording_provider(format!("{}data: [DONE]\n\n", chunk("Hi"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let bea = new_character(&app, "Bea").await;
+    let uri = format!("/api/chats/{}/move", chat_id);
+
+    let nobody = json!({ "character_id": uuid::Uuid::now_v7() });
+    let response = app
+        .clone()
+        .oneshot(request(Method::POST, &uri, Some(nobody)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    send(
+        &app,
+        Method::POST,
+        &uri,
+        Some(json!({ "character_id": bea })),
+    )
+    .await;
+    let chat = get_chat(&app, &chat_id).await;
+    assert_eq!(chat["character_id"], bea);
+    assert_eq!(chat["participants"].as_array().unwrap().len(), 1);
+    assert_eq!(chat["participants"][0]["character_id"], bea);
+
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+    let received = received.lock().unwrap();
+    let system = received[0]["messages"][0]["content"].as_str().unwrap();
+    assert!(system.starts_with("Name: Bea"));
+}
+
+#[tokio::test]
+async fn listings_count_each_chats_messages() {
+    let app = app().await;
+    let tester = new_character(&app, "Tester").await;
+    let bea = new_character(&app, "Bea").await;
+    let long = chat_with(&app, &tester).await;
+    let short = chat_with(&app, &tester).await;
+    let other = chat_with(&app, &bea).await;
+    for (chat_id, extra) in [(&long, 2), (&other, 1)] {
+        for _ in 0..extra {
+            let uri = format!("/api/chats/{}/message", chat_id);
+            let message = json!({ "id": uuid::Uuid::now_v7(), "role": "user", "content": "More" });
+            send(&app, Method::POST, &uri, Some(message)).await;
+        }
+    }
+
+    let counts = |query: String| {
+        let app = app.clone();
+        async move {
+            let chats: Value = serde_json::from_str(
+                &send(&app, Method::GET, &format!("/api/chats{}", query), None).await,
+            )
+            .unwrap();
+            let mut counts: Vec<_> = chats
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| {
+                    (
+                        c["id"].as_str().unwrap().to_string(),
+                        c["message_count"].as_u64().unwrap(),
+                    )
+                })
+                .collect();
+            counts.sort();
+            counts
+        }
+    };
+
+    let mut expected = vec![(long.clone(), 3), (short.clone(), 1)];
+    expected.sort();
+    assert_eq!(
+        counts(format!("?character_id={}", tester.as_str().unwrap())).await,
+        expected
+    );
+    expected.push((other, 2));
+    expected.sort();
+    assert_eq!(counts(String::new()).await, expected);
+}
+
+#[tokio::test]
+async fn a_duplicate_has_the_same_messages_under_new_ids() {
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    send(
+        &app,
+        Method::POST,
+        &format!("/api/chats/{}/message", chat_id),
+        Some(json!({
+            "id": uuid::Uuid::now_v7(),
+            "role": "assistant",
+            "content": "One",
+            "alternatives": ["Two"],
+            "active_index": 1,
+        })),
+    )
+    .await;
+    let original = get_chat(&app, &chat_id).await;
+
+    let copy: Value = serde_json::from_str(
+        &send(
+            &app,
+            Method::POST,
+            &format!("/api/chats/{}/duplicate", chat_id),
+            None,
+        )
+        .await,
+    )
+    .unwrap();
+    let copy = get_chat(&app, copy["id"].as_str().unwrap()).await;
+
+    assert_ne!(copy["id"], original["id"]);
+    assert_eq!(copy["character_id"], original["character_id"]);
+    let (originals, copies) = (
+        original["messages"].as_array().unwrap(),
+        copy["messages"].as_array().unwrap(),
+    );
+    assert_eq!(copies.len(), 2);
+    for (original, copy) in originals.iter().zip(copies) {
+        assert_ne!(copy["id"], original["id"]);
+        for field in ["role", "content", "alternatives", "active_index"] {
+            assert_eq!(copy[field], original[field]);
+        }
+    }
+    assert_eq!(get_chat(&app, &chat_id).await, original);
+}
+
+#[tokio::test]
+async fn a_fixed_clock_opens_every_chat_with_the_same_random_greeting() {
+    let clock = FixedClock::new(std::time::UNIX_EPOCH);
+    let app = app_with(Config {
+        clock: Arc::new(clock),
+        ..Default::default()
+    })
+    .await;
+    let character: Value = serde_json::from_str(
+        &send(
+            &app,
+            Method::POST,
+            "/api/characters",
+            Some(json!({
+                "name": "Ada",
+                "description": "",
+                "personality": "",
+                "scenario": "",
+                "first_message": "One",
+                "example_messages": "",
+                "alternate_greetings": ["Two", "Three"],
+                "greeting_mode": "random",
+            })),
+        )
+        .await,
+    )
+    .unwrap();
+
+    let mut openings = Vec::new();
+    for _ in 0..3 {
+        let chat_id = chat_with(&app, &character["id"]).await;
+        let chat = get_chat(&app, &chat_id).await;
+        assert_eq!(chat["messages"].as_array().unwrap().len(), 1);
+        openings.push(chat["messages"][0]["content"].clone());
+    }
+    assert!(openings.iter().all(|o| *o == openings[0]));
+    assert!(["One", "Two", "Three"].contains(&openings[0].as_str().unwrap()));
+}
+
+#[tokio::test]
+async fn listings_filter_chats_by_tag() {
+    let app = app().await;
+    let ada = new_character(&app, "Ada").await;
+    let (first, second) = (chat_with(&app, &ada).await, chat_with(&app, &ada).await);
+    let other = new_chat(&app).await;
+    let tag = |chat_id: &str, tag: &str| {
+        let uri = format!("/api/chats/{}/tags", chat_id);
+        let request = request(Method::POST, &uri, Some(json!({ "tag": tag })));
+        let app = app.clone();
+        async move { app.oneshot(request).await.unwrap().status() }
+    };
+    let listed = |query: String| {
+        let app = app.clone();
+        async move {
+            let uri = format!("/api/chats?{}", query);
+            let chats: Value =
+                serde_json::from_str(&send(&app, Method::GET, &uri, None).await).unwrap();
+            let mut ids: Vec<_> = chats
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c["id"].as_str().unwrap().to_string())
+                .collect();
+            ids.sort();
+            ids
+        }
+    };
+    let sorted = |mut ids: Vec<&String>| {
+        ids.sort();
+        ids.into_iter().cloned().collect::<Vec<_>>()
+    };
+
+    for (chat_id, label) in [
+        (&first, "canon"),
+        (&first, "two words"),
+        (&second, "canon"),
+        (&other, "canon"),
+    ] {
+        assert_eq!(tag(chat_id, label).await, StatusCode::OK);
+    }
+    // Tagging again changes nothing, and a blank tag is no tag
+    let uri = format!("/api/chats/{}/tags", first);
+    let tags = send(&app, Method::POST, &uri, Some(json!({ "tag": " canon " }))).await;
+    let tags: Value = serde_json::from_str(&tags).unwrap();
+    assert_eq!(tags, json!(["canon", "two words"]));
+    assert_eq!(tag(&first, "  ").await, StatusCode::BAD_REQUEST);
+
+    assert_eq!(
+        listed("tag=canon".to_string()).await,
+        sorted(vec![&first, &second, &other])
+    );
+    assert_eq!(
+        listed(format!("tag=canon&character_id={}", ada.as_str().unwrap())).await,
+        sorted(vec![&first, &second])
+    );
+    assert_eq!(
+        listed("tag=two%20words".to_string()).await,
+        sorted(vec![&first])
+    );
+    assert_eq!(listed("tag=unused".to_string()).await, Vec::<String>::new());
+
+    let uri = format!("/api/chats/{}/tags/two%20words", first);
+    let tags: Value = serde_json::from_str(&send(&app, Method::DELETE, &uri, None).await).unwrap();
+    assert_eq!(tags, json!(["canon"]));
+    assert!(listed("tag=two%20words".to_string()).await.is_empty());
+}
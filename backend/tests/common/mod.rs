@@ -0,0 +1,239 @@
+// Not every test file uses every helper
+#![allow(dead_code)]
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Method, Request};
+use backend::{Config, DatabaseConfig};
+use futures::StreamExt;
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+/// A provider answering every completion with `events` as its event stream
+pub async fn fake_provider(events: String) -> String {
+    let provider = Router::new().route(
+        "/v1/chat/completions",
+        axum::routing::post(
+            move || async move { ([("content-type", "text/event-stream")], events) },
+        ),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, provider).await.unwrap() });
+    format!("http://{}/v1", addr)
+}
+
+/// A streamed call of tool `name` with raw `arguments`
+pub fn tool_call_chunk(id: &str, name: &str, arguments: &str) -> String {
+    delta_chunk(json!({
+        "tool_calls": [{
+            "index": 0,
+            "id": id,
+            "type": "function",
+            "function": { "name": name, "arguments": arguments },
+        }],
+    }))
+}
+
+/// Request bodies a provider received, oldest first
+pub type Received = Arc<Mutex<Vec<Value>>>;
+
+/// A provider like [`fake_provider`] that also keeps every request body it gets
+pub async fn recording_provider(events: String) -> (String, Received) {
+    scripted_provider(vec![events]).await
+}
+
+/// A recording provider answering its first request with `turns[0]`, the next with
+/// `turns[1]` and so on, repeating the last once it runs out
+pub async fn scripted_provider(turns: Vec<String>) -> (String, Received) {
+    let received = Received::default();
+    let log = received.clone();
+    let provider = Router::new().route(
+        "/v1/chat/completions",
+        axum::routing::post(move |axum::Json(body): axum::Json<Value>| async move {
+            let mut log = log.lock().unwrap();
+            log.push(body);
+            let events = turns[(log.len() - 1).min(turns.len() - 1)].clone();
+            ([("content-type", "text/event-stream")], events)
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, provider).await.unwrap() });
+    (format!("http://{}/v1", addr), received)
+}
+
+/// A provider that sends `events` and then keeps the stream open without another word
+pub async fn stalled_provider(events: String) -> String {
+    let provider = Router::new().route(
+        "/v1/chat/completions",
+        axum::routing::post(move || async move {
+            let body = futures::stream::once(async move { Ok::<_, std::io::Error>(events) })
+                .chain(futures::stream::pending());
+            (
+                [("content-type", "text/event-stream")],
+                Body::from_stream(body),
+            )
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, provider).await.unwrap() });
+    format!("http://{}/v1", addr)
+}
+
+fn delta_chunk(delta: Value) -> String {
+    let chunk = json!({
+        "id": "x",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "m",
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": null }],
+    });
+    format!("data: {}\n\n", chunk)
+}
+
+pub fn chunk(content: &str) -> String {
+    delta_chunk(json!({ "content": content }))
+}
+
+pub fn reasoning_chunk(reasoning: &str) -> String {
+    delta_chunk(json!({ "reasoning_content": reasoning }))
+}
+
+pub async fn app() -> Router {
+    app_with(Config::default()).await
+}
+
+/// An app on an empty in-memory database running with `config`
+pub async fn app_with(config: Config) -> Router {
+    let config = Config {
+        skip_seed: true,
+        ..config
+    };
+    backend::init(Router::new(), DatabaseConfig::Memory, config).await
+}
+
+/// An app on an SQLite file in `dir`, returning the database url alongside it so a test
+/// can reach into the file directly
+pub async fn file_app(dir: &std::path::Path) -> (Router, String) {
+    let url = format!("sqlite:{}?mode=rwc", dir.join("renoma.db").display());
+    let config = Config {
+        skip_seed: true,
+        ..Default::default()
+    };
+    let db = DatabaseConfig::Local { url: url.clone() };
+    (backend::init(Router::new(), db, config).await, url)
+}
+
+pub fn request(method: Method, uri: &str, body: Option<Value>) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+        .unwrap()
+}
+
+pub async fn send(app: &Router, method: Method, uri: &str, body: Option<Value>) -> String {
+    let response = app
+        .clone()
+        .oneshot(request(method, uri, body))
+        .await
+        .unwrap();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+/// Create a character called `name` that greets with "Hi", returning its id
+pub async fn new_character(app: &Router, name: &str) -> Value {
+    let character: Value = serde_json::from_str(
+        &send(
+            app,
+            Method::POST,
+            "/api/characters",
+            Some(json!({
+                "name": name,
+                "description": "",
+                "personality": "",
+                "scenario": "",
+                "first_message": "Hi",
+                "example_messages": "",
+            })),
+        )
+        .await,
+    )
+    .unwrap();
+    character["id"].clone()
+}
+
+pub async fn new_chat(app: &Router) -> String {
+    let character_id = new_character(app, "Tester").await;
+    chat_with(app, &character_id).await
+}
+
+/// Start a chat with an existing character, returning its id
+pub async fn chat_with(app: &Router, character_id: &Value) -> String {
+    let chat: Value = serde_json::from_str(
+        &send(
+            app,
+            Method::POST,
+            "/api/chats",
+            Some(json!({ "character_id": character_id })),
+        )
+        .await,
+    )
+    .unwrap();
+    chat["id"].as_str().unwrap().to_string()
+}
+
+pub async fn get_chat(app: &Router, chat_id: &str) -> Value {
+    serde_json::from_str(&send(app, Method::GET, &format!("/api/chats/{}", chat_id), None).await)
+        .unwrap()
+}
+
+pub fn completion(chat_id: &str, api_base: &str) -> Value {
+    json!({
+        "chat_id": chat_id,
+        "regenerate": false,
+        "message_id": null,
+        "api_key": "key",
+        "api_base": api_base,
+        "model": "m",
+        "temperature": null,
+        "max_tokens": null,
+    })
+}
+
+/// A temporary plugins directory filled with copies of the stub plugin
+pub struct StubPlugins {
+    root: tempfile::TempDir,
+}
+
+impl StubPlugins {
+    pub fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("plugins")).unwrap();
+        Self { root }
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.root.path().join("plugins")
+    }
+
+    /// Add a stub called `name` that acts as `behaviour` says, returning its path. Its one
+    /// tool is `<name>_echo` unless the behaviour's `init` lists others.
+    pub fn add(&self, name: &str, behaviour: Value) -> String {
+        let path = self.dir().join(name);
+        std::fs::copy(env!("CARGO_BIN_EXE_stub_plugin"), &path).unwrap();
+        std::fs::write(
+            self.root.path().join(format!("{}.json", name)),
+            behaviour.to_string(),
+        )
+        .unwrap();
+        path.to_string_lossy().into_owned()
+    }
+}
@@ -0,0 +1,663 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use backend::Config;
+use common::{
+    app, app_with, chunk, completion, fake_provider, get_chat, new_chat, reasoning_chunk,
+    recording_provider, request, scripted_provider, send, stalled_provider, tool_call_chunk,
+};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn a_failed_stream_ends_once_and_keeps_its_partial_reply() {
+    let api_base = fake_provider(format!("{}data: {{broken\n\n", chunk("Hel"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let stream = send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+    let events: Vec<&str> = stream.lines().filter(|l| !l.is_empty()).collect();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|e| e.starts_with("data: [ERROR]"))
+            .count(),
+        1
+    );
+    assert_eq!(events.last(), Some(&"data: [DONE]"));
+
+    let chat: Value = serde_json::from_str(
+        &send(&app, Method::GET, &format!("/api/chats/{}", chat_id), None).await,
+    )
+    .unwrap();
+    let contents: Vec<&str> = chat["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["content"].as_str().unwrap())
+        .collect();
+    assert_eq!(contents, ["Hi", "Hel"]);
+}
+
+#[tokio::test]
+async fn regenerating_into_a_variant_overwrites_it_in_place() {
+    let api_base = fake_provider(format!("{}data: [DONE]\n\n", chunk("Hello"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let message_id = get_chat(&app, &chat_id).await["messages"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let mut request = completion(&chat_id, &api_base);
+    request["regenerate"] = true.into();
+    request["message_id"] = message_id.clone().into();
+    send(&app, Method::POST, "/api/completion", Some(request.clone())).await;
+
+    request["target_alternative_index"] = 1.into();
+    send(&app, Method::POST, "/api/completion", Some(request.clone())).await;
+    let message = get_chat(&app, &chat_id).await["messages"][0].clone();
+    assert_eq!(message["content"], "Hi");
+    assert_eq!(message["alternatives"], serde_json::json!(["Hello"]));
+    assert_eq!(message["active_index"], 1);
+    assert_eq!(
+        message["alternative_senders"][0],
+        get_chat(&app, &chat_id).await["character_id"]
+    );
+
+    request["target_alternative_index"] = 2.into();
+    let response = send(&app, Method::POST, "/api/completion", Some(request)).await;
+    assert_eq!(response, "Target alternative doesn't exist");
+}
+
+#[tokio::test]
+async fn each_swipe_keeps_its_own_reasoning() {
+    let api_base = fake_provider(format!(
+        "{}{}data: [DONE]\n\n",
+        reasoning_chunk("Thinking"),
+        chunk("Hello")
+    ))
+    .await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let mut request = completion(&chat_id, &api_base);
+    request["regenerate"] = true.into();
+    request["message_id"] = get_chat(&app, &chat_id).await["messages"][0]["id"].clone();
+    send(&app, Method::POST, "/api/completion", Some(request)).await;
+
+    let message = get_chat(&app, &chat_id).await["messages"][0].clone();
+    assert_eq!(message["alternatives"], serde_json::json!(["Hello"]));
+    assert_eq!(
+        message["alternative_reasoning"],
+        serde_json::json!(["Thinking"])
+    );
+    assert!(message["reasoning"].is_null());
+}
+
+#[tokio::test]
+async fn a_reply_stopped_while_thinking_keeps_its_trace() {
+    let api_base = stalled_provider(reasoning_chunk("Pondering")).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            Method::POST,
+            "/api/completion",
+            Some(completion(&chat_id, &api_base)),
+        ))
+        .await
+        .unwrap();
+    let mut body = response.into_body();
+    // Stop only once the trace has made it through
+    while let Some(frame) = body.frame().await {
+        let frame = frame.unwrap();
+        if frame
+            .data_ref()
+            .is_some_and(|data| String::from_utf8_lossy(data).contains("[REASONING]"))
+        {
+            break;
+        }
+    }
+    send(
+        &app,
+        Method::POST,
+        &format!("/api/chats/{}/cancel", chat_id),
+        None,
+    )
+    .await;
+    let rest = body.collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&rest).ends_with("data: [DONE]\n\n"));
+
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    assert_eq!(messages.as_array().unwrap().len(), 2);
+    assert_eq!(messages[1]["content"], "");
+    assert_eq!(messages[1]["reasoning"], "Pondering");
+}
+
+#[tokio::test]
+async fn a_chat_is_free_again_once_its_client_goes_away() {
+    let api_base = stalled_provider(chunk("Hel")).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let start = || {
+        app.clone().oneshot(request(
+            Method::POST,
+            "/api/completion",
+            Some(completion(&chat_id, &api_base)),
+        ))
+    };
+
+    let first = start().await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(start().await.unwrap().status(), StatusCode::CONFLICT);
+
+    // A dropped connection drops the response body with it
+    drop(first);
+    assert_eq!(start().await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn impersonations_stream_without_saving_anything() {
+    let api_base = fake_provider(format!("{}data: [DONE]\n\n", chunk("Hi back"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let mut request = completion(&chat_id, &api_base);
+    request["impersonate"] = true.into();
+    let stream = send(&app, Method::POST, "/api/completion", Some(request)).await;
+    assert!(stream.contains("Hi back"));
+
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    assert_eq!(messages.as_array().unwrap().len(), 1);
+    assert_eq!(messages[0]["content"], "Hi");
+}
+
+#[tokio::test]
+async fn completions_are_utf8_event_streams() {
+    let api_base = fake_provider(format!("{}data: [DONE]\n\n", chunk("🦀"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            Method::POST,
+            "/api/completion",
+            Some(completion(&chat_id, &api_base)),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/event-stream; charset=utf-8"
+    );
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8(body.to_vec()).unwrap().contains("🦀"));
+}
+
+#[tokio::test]
+async fn a_chat_with_one_character_gets_no_roster() {
+    let (api_base, received) =
+        recording_provider(format!("{}data: [DONE]\n\n", chunk("Hey"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let mut request = completion(&chat_id, &api_base);
+    request["include_roster"] = true.into();
+    send(&app, Method::POST, "/api/completion", Some(request)).await;
+
+    let received = received.lock().unwrap();
+    let system = received[0]["messages"][0]["content"].as_str().unwrap();
+    assert!(system.starts_with("Name: Tester"));
+    assert!(!system.contains("Other characters"));
+}
+
+#[tokio::test]
+async fn provider_traffic_is_logged_only_when_asked_for() {
+    let api_base = fake_provider(format!("{}data: [DONE]\n\n", chunk("Logged"))).await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    let app = app_with(Config {
+        debug_provider_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    })
+    .await;
+    let chat_id = new_chat(&app).await;
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+
+    let logs: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+    assert_eq!(logs.len(), 1);
+    let log = std::fs::read_to_string(logs[0].as_ref().unwrap().path()).unwrap();
+    let lines: Vec<Value> = log
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines[0]["api_key"], "[REDACTED]");
+    assert!(lines[1..].iter().any(|l| l.to_string().contains("Logged")));
+}
+
+#[tokio::test]
+async fn malformed_and_insecure_api_bases_are_turned_away() {
+    let status = |app: axum::Router, body: Value| async move {
+        let request = request(Method::POST, "/api/completion", Some(body));
+        app.oneshot(request).await.unwrap().status()
+    };
+
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let malformed = completion(&chat_id, "api.example.com/v1");
+    assert_eq!(status(app, malformed).await, StatusCode::BAD_REQUEST);
+
+    let app = app_with(Config {
+        require_https: true,
+        ..Default::default()
+    })
+    .await;
+    let chat_id = new_chat(&app).await;
+    let insecure = completion(&chat_id, "http://api.example.com/v1");
+    assert_eq!(status(app.clone(), insecure).await, StatusCode::BAD_REQUEST);
+
+    // A provider on this machine never leaves it, so plain http is fine
+    let api_base = fake_provider(format!("{}data: [DONE]\n\n", chunk("Hi"))).await;
+    let local = completion(&chat_id, &api_base);
+    assert_eq!(status(app, local).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn reasoning_effort_off_leaves_the_parameter_out() {
+    let (api_base, received) = recording_provider(format!("{}data: [DONE]\n\n", chunk("Hi"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    for effort in ["off", "none", "high"] {
+        let mut request = completion(&chat_id, &api_base);
+        request["reasoning_effort"] = effort.into();
+        send(&app, Method::POST, "/api/completion", Some(request)).await;
+    }
+
+    let received = received.lock().unwrap();
+    let sent: Vec<_> = received.iter().map(|r| r.get("reasoning_effort")).collect();
+    assert_eq!(sent, [None, Some(&"none".into()), Some(&"high".into())]);
+}
+
+#[tokio::test]
+async fn the_saved_id_of_a_reply_is_sent_before_its_text() {
+    let api_base = fake_provider(format!("{}data: [DONE]\n\n", chunk("Hello"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let stream = send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+    let first = stream.lines().find(|l| l.starts_with("data: ")).unwrap();
+    let id = first.strip_prefix("data: [MESSAGE_ID] ").unwrap();
+    assert!(stream.find(first).unwrap() < stream.find("Hello").unwrap());
+
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    assert_eq!(messages[1]["id"], id);
+    assert_eq!(messages[1]["content"], "Hello");
+}
+
+#[tokio::test]
+async fn extra_parameters_are_merged_into_the_request() {
+    let (api_base, received) = recording_provider(format!("{}data: [DONE]\n\n", chunk("Hi"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let mut request = completion(&chat_id, &api_base);
+    request["extra_body"] =
+        serde_json::json!({ "min_p": 0.1, "top_k": 40, "temperature": 1.5, "stream": false });
+    send(&app, Method::POST, "/api/completion", Some(request)).await;
+    let sent = received.lock().unwrap()[0].clone();
+    assert_eq!(sent["min_p"], 0.1);
+    assert_eq!(sent["top_k"], 40);
+    assert_eq!(sent["temperature"], 1.5);
+    // A reply is always streamed
+    assert_eq!(sent["stream"], true);
+    assert_eq!(sent["model"], "m");
+
+    let mut request = completion(&chat_id, &api_base);
+    request["extra_body"] = serde_json::json!([1, 2]);
+    let response = app
+        .oneshot(common::request(
+            Method::POST,
+            "/api/completion",
+            Some(request),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(received.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn logprobs_are_asked_for_and_passed_on_only_when_wanted() {
+    let scored = serde_json::json!({
+        "id": "x",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "m",
+        "choices": [{
+            "index": 0,
+            "delta": { "content": "Hi" },
+            "logprobs": { "content": [
+                { "token": "Hi", "logprob": -0.5, "bytes": null, "top_logprobs": [] },
+            ] },
+            "finish_reason": null,
+        }],
+    });
+    let (api_base, received) =
+        recording_provider(format!("data: {}\n\ndata: [DONE]\n\n", scored)).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+
+    let plain = send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+    assert!(!plain.contains("[LOGPROBS]"));
+
+    let mut request = completion(&chat_id, &api_base);
+    request["logprobs"] = true.into();
+    let scored = send(&app, Method::POST, "/api/completion", Some(request)).await;
+    assert!(scored.contains(r#"data: [LOGPROBS] [{"token":"Hi","logprob":-0.5}]"#));
+
+    let received = received.lock().unwrap();
+    assert_eq!(received[0].get("logprobs"), None);
+    assert_eq!(received[1]["logprobs"], true);
+}
+
+#[tokio::test]
+async fn a_characters_reasoning_effort_beats_the_settings_but_not_the_request() {
+    let (api_base, received) = recording_provider(format!("{}data: [DONE]\n\n", chunk("Hi"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let character_id = get_chat(&app, &chat_id).await["character_id"].clone();
+    let character = serde_json::json!({
+        "id": character_id,
+        "name": "Tester",
+        "description": "",
+        "personality": "",
+        "scenario": "",
+        "first_message": "Hi",
+        "example_messages": "",
+        "reasoning_effort": "high",
+    });
+    let uri = format!("/api/characters/{}", character_id.as_str().unwrap());
+    send(&app, Method::PUT, &uri, Some(character)).await;
+
+    let mut request = completion(&chat_id, &api_base);
+    request["reasoning_effort"] = "low".into();
+    send(&app, Method::POST, "/api/completion", Some(request.clone())).await;
+    request["reasoning_effort_override"] = "none".into();
+    send(&app, Method::POST, "/api/completion", Some(request)).await;
+
+    let received = received.lock().unwrap();
+    assert_eq!(received[0]["reasoning_effort"], "high");
+    assert_eq!(received[1]["reasoning_effort"], "none");
+}
+
+#[tokio::test]
+async fn arguments_past_repair_are_fed_back_to_the_model() {
+    let (api_base, received) = scripted_provider(vec![
+        format!(
+            "{}data: [DONE]\n\n",
+            tool_call_chunk("call_1", "roll", "{'sides': 6,}")
+        ),
+        format!(
+            "{}data: [DONE]\n\n",
+            tool_call_chunk("call_2", "roll", "{sides: 6}")
+        ),
+        format!("{}data: [DONE]\n\n", chunk("Done")),
+    ])
+    .await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 3);
+    let messages = received[2]["messages"].as_array().unwrap();
+    let calls: Vec<_> = messages
+        .iter()
+        .filter_map(|m| m["tool_calls"].get(0))
+        .map(|c| c["function"]["arguments"].as_str().unwrap())
+        .collect();
+    // The repaired call went out as valid JSON, the other one as the model wrote it
+    assert_eq!(calls, [r#"{"sides":6}"#, "{sides: 6}"]);
+    let results: Vec<_> = messages
+        .iter()
+        .filter(|m| m["role"] == "tool")
+        .map(|m| m["content"].as_str().unwrap())
+        .collect();
+    assert!(results[0].starts_with("Error executing tool: "));
+    assert!(results[1].starts_with("Error parsing arguments: "));
+}
+
+#[tokio::test]
+async fn malformed_requests_say_what_is_wrong() {
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let rejected = |body: Value| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(request(Method::POST, "/api/completion", Some(body)))
+                .await
+                .unwrap();
+            let status = response.status();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            let body: Value = serde_json::from_slice(&bytes).unwrap();
+            (
+                status,
+                body["error"]["message"].as_str().unwrap().to_string(),
+            )
+        }
+    };
+
+    let mut missing = completion(&chat_id, "http://127.0.0.1:1/v1");
+    missing.as_object_mut().unwrap().remove("chat_id");
+    let (status, message) = rejected(missing).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(message.contains("missing field `chat_id`"), "{}", message);
+
+    let mut mistyped = completion(&chat_id, "http://127.0.0.1:1/v1");
+    mistyped["temperature"] = "warm".into();
+    let (status, message) = rejected(mistyped).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(message.contains("temperature"), "{}", message);
+}
+
+#[tokio::test]
+async fn text_before_a_tool_call_is_in_the_next_turns_context() {
+    let (api_base, received) = scripted_provider(vec![
+        format!(
+            "{}{}data: [DONE]\n\n",
+            chunk("Let me roll."),
+            tool_call_chunk("call_1", "roll", "{}")
+        ),
+        format!("{}data: [DONE]\n\n", chunk("A four.")),
+    ])
+    .await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+
+    let received = received.lock().unwrap();
+    let messages = received[1]["messages"].as_array().unwrap();
+    let call = messages
+        .iter()
+        .find(|m| m.get("tool_calls").is_some())
+        .unwrap();
+    assert_eq!(call["content"], "Let me roll.");
+    assert_eq!(call["tool_calls"][0]["id"], "call_1");
+}
+
+#[tokio::test]
+async fn a_tool_turn_saves_one_row_per_message() {
+    let (api_base, _) = scripted_provider(vec![
+        format!(
+            "{}data: [DONE]\n\n",
+            tool_call_chunk("call_1", "roll", "{}")
+        ),
+        format!("{}data: [DONE]\n\n", chunk("A four.")),
+    ])
+    .await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let stream = send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    let rows: Vec<_> = messages
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| (m["role"].as_str().unwrap(), m["content"].as_str().unwrap()))
+        .collect();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0], ("assistant", "Hi"));
+    assert_eq!(rows[1], ("assistant", ""));
+    assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+    assert_eq!(rows[2].0, "tool");
+    assert_eq!(rows[3], ("assistant", "A four."));
+
+    // Saved under the ids the stream announced
+    let announced: Vec<_> = stream
+        .lines()
+        .filter_map(|l| l.strip_prefix("data: [MESSAGE_ID] "))
+        .collect();
+    assert_eq!(announced, [&messages[1]["id"], &messages[3]["id"]]);
+}
+
+#[tokio::test]
+async fn a_regeneration_instruction_is_sent_but_never_saved() {
+    let (api_base, received) =
+        recording_provider(format!("{}data: [DONE]\n\n", chunk("Short"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let mut request = completion(&chat_id, &api_base);
+    request["regenerate"] = true.into();
+    request["message_id"] = get_chat(&app, &chat_id).await["messages"][0]["id"].clone();
+    request["regen_instruction"] = "  Make it shorter  ".into();
+    send(&app, Method::POST, "/api/completion", Some(request)).await;
+
+    let messages = received.lock().unwrap()[0]["messages"].clone();
+    let last = messages.as_array().unwrap().last().unwrap();
+    assert_eq!(last["role"], "system");
+    assert_eq!(last["content"], "Make it shorter");
+
+    let chat = get_chat(&app, &chat_id).await;
+    assert_eq!(chat["messages"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        chat["messages"][0]["alternatives"],
+        serde_json::json!(["Short"])
+    );
+    assert!(!chat.to_string().contains("Make it shorter"));
+}
+
+#[tokio::test]
+async fn a_missing_api_key_is_reported_with_its_code() {
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let mut request_body = completion(&chat_id, "http://127.0.0.1:9/v1");
+    request_body["api_key"] = "".into();
+
+    let response = app
+        .oneshot(request(Method::POST, "/api/completion", Some(request_body)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        error["error"]["code"],
+        shared::models::ERROR_MISSING_API_KEY
+    );
+    assert_eq!(error["error"]["message"], "Missing API Key");
+}
+
+#[tokio::test]
+async fn a_tool_call_is_announced_once_before_it_runs() {
+    let (api_base, _) = scripted_provider(vec![
+        format!(
+            "{}{}data: [DONE]\n\n",
+            tool_call_chunk("call_1", "roll", r#"{"sides": "#),
+            tool_call_chunk("", "", "6}")
+        ),
+        format!("{}data: [DONE]\n\n", chunk("Rolled")),
+    ])
+    .await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let stream = send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+
+    let events: Vec<&str> = stream.lines().filter(|l| !l.is_empty()).collect();
+    let position = |prefix: &str| events.iter().position(|e| e.starts_with(prefix)).unwrap();
+    let starts: Vec<_> = events
+        .iter()
+        .filter_map(|e| e.strip_prefix("data: [TOOL_CALL_START] "))
+        .collect();
+    assert_eq!(starts.len(), 1);
+    let start: Value = serde_json::from_str(starts[0]).unwrap();
+    assert_eq!(start["id"], "call_1");
+    assert_eq!(start["name"], "roll");
+    assert!(position("data: [TOOL_CALL_START]") < position("data: [TOOL_CALLS]"));
+    assert!(position("data: [TOOL_CALLS]") < position("data: [TOOL_RESULT]"));
+}
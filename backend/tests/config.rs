@@ -0,0 +1,49 @@
+mod common;
+
+use axum::http::Method;
+use common::{app, file_app, new_chat, send};
+use serde_json::{Value, json};
+use shared::models::{DEFAULT_API_BASE, DEFAULT_MODEL, DEFAULT_REASONING_EFFORT};
+
+#[tokio::test]
+async fn the_defaults_endpoint_serves_the_shared_defaults() {
+    let app = app().await;
+    let defaults: Value =
+        serde_json::from_str(&send(&app, Method::GET, "/api/config/defaults", None).await).unwrap();
+    assert_eq!(
+        defaults,
+        json!({
+            "api_base": DEFAULT_API_BASE,
+            "model": DEFAULT_MODEL,
+            "reasoning_effort": DEFAULT_REASONING_EFFORT,
+        })
+    );
+}
+
+#[tokio::test]
+async fn in_memory_chats_are_gone_after_a_restart() {
+    let chat_count = |app: axum::Router| async move {
+        let chats: Value =
+            serde_json::from_str(&send(&app, Method::GET, "/api/chats", None).await).unwrap();
+        chats.as_array().unwrap().len()
+    };
+    let ephemeral = |app: axum::Router| async move {
+        let info: Value =
+            serde_json::from_str(&send(&app, Method::GET, "/api/config/server", None).await)
+                .unwrap();
+        info["ephemeral"].clone()
+    };
+
+    let app = app().await;
+    new_chat(&app).await;
+    assert_eq!(ephemeral(app.clone()).await, true);
+    assert_eq!(chat_count(app).await, 1);
+    assert_eq!(chat_count(common::app().await).await, 0);
+
+    // A database file keeps them
+    let dir = tempfile::tempdir().unwrap();
+    let (app, _) = file_app(dir.path()).await;
+    new_chat(&app).await;
+    assert_eq!(ephemeral(app).await, false);
+    assert_eq!(chat_count(file_app(dir.path()).await.0).await, 1);
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use backend::config::DEFAULT_MAX_MESSAGE_LENGTH;
+use common::{app, chat_with, chunk, completion, new_character, recording_provider, request, send};
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+async fn create(app: &axum::Router, entry: Value) -> StatusCode {
+    app.clone()
+        .oneshot(request(Method::POST, "/api/lore", Some(entry)))
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn entries_need_a_name_and_a_key() {
+    let app = app().await;
+    let entry = |name: &str, keys: &[&str]| json!({ "name": name, "keys": keys, "content": "Dragons hoard books." });
+
+    assert_eq!(
+        create(&app, entry("Dragons", &["dragon"])).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        create(&app, entry("  ", &["dragon"])).await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        create(&app, entry("Dragons", &[" ", ""])).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn entries_longer_than_a_message_are_rejected() {
+    let app = app().await;
+    let long = "a".repeat(DEFAULT_MAX_MESSAGE_LENGTH + 1);
+
+    assert_eq!(
+        create(
+            &app,
+            json!({ "name": "Dragons", "keys": ["dragon"], "content": long })
+        )
+        .await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        create(
+            &app,
+            json!({ "name": "Dragons", "keys": [long], "content": "" })
+        )
+        .await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn global_entries_reach_every_characters_chats() {
+    let (api_base, received) =
+        recording_provider(format!("{}data: [DONE]\n\n", chunk("Indeed"))).await;
+    let app = app().await;
+    create(
+        &app,
+        json!({ "name": "Dragons", "keys": ["dragon"], "content": "Dragons hoard books." }),
+    )
+    .await;
+
+    for name in ["Ada", "Bea"] {
+        let chat_id = chat_with(&app, &new_character(&app, name).await).await;
+        let message = json!({ "id": uuid::Uuid::now_v7(), "role": "user", "content": "A dragon!" });
+        send(
+            &app,
+            Method::POST,
+            &format!("/api/chats/{}/message", chat_id),
+            Some(message),
+        )
+        .await;
+        send(
+            &app,
+            Method::POST,
+            "/api/completion",
+            Some(completion(&chat_id, &api_base)),
+        )
+        .await;
+    }
+    let chat_id = chat_with(&app, &new_character(&app, "Cid").await).await;
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+
+    let received = received.lock().unwrap();
+    let world_info = |request: &Value| {
+        request["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|m| m["content"] == "World info:\nDragons hoard books.")
+    };
+    assert!(world_info(&received[0]));
+    assert!(world_info(&received[1]));
+    // Nothing in Cid's chat mentions a dragon
+    assert!(!world_info(&received[2]));
+}
@@ -0,0 +1,273 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use backend::Config;
+use common::{
+    app, app_with, chunk, completion, fake_provider, get_chat, new_chat, recording_provider,
+    request, scripted_provider, send, tool_call_chunk,
+};
+use serde_json::json;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn resetting_a_message_shows_its_primary_content_again() {
+    let api_base = fake_provider(format!("{}data: [DONE]\n\n", chunk("Hello"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let message_id = get_chat(&app, &chat_id).await["messages"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let mut request = completion(&chat_id, &api_base);
+    request["regenerate"] = true.into();
+    request["message_id"] = message_id.clone().into();
+    send(&app, Method::POST, "/api/completion", Some(request)).await;
+    assert_eq!(
+        get_chat(&app, &chat_id).await["messages"][0]["active_index"],
+        1
+    );
+
+    send(
+        &app,
+        Method::POST,
+        &format!("/api/chats/{}/messages/{}/reset", chat_id, message_id),
+        None,
+    )
+    .await;
+    let message = get_chat(&app, &chat_id).await["messages"][0].clone();
+    assert_eq!(message["active_index"], 0);
+    assert_eq!(message["content"], "Hi");
+    assert_eq!(message["alternatives"], serde_json::json!(["Hello"]));
+}
+
+#[tokio::test]
+async fn appending_to_a_missing_chat_is_not_found() {
+    let app = app().await;
+    let uri = format!("/api/chats/{}/message", uuid::Uuid::now_v7());
+    let message = json!({
+        "id": uuid::Uuid::now_v7(),
+        "role": "user",
+        "content": "Anyone there?",
+    });
+    let response = app
+        .oneshot(request(Method::POST, &uri, Some(message)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn content_up_to_the_length_limit_is_stored_and_no_more() {
+    const LIMIT: usize = 32;
+    let app = app_with(Config {
+        max_message_length: LIMIT,
+        ..Default::default()
+    })
+    .await;
+    let chat_id = new_chat(&app).await;
+    let status = |method: Method, uri: String, body: serde_json::Value| {
+        let app = app.clone();
+        async move {
+            app.oneshot(request(method, &uri, Some(body)))
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+    let append = |content: String| {
+        let body = json!({ "id": uuid::Uuid::now_v7(), "role": "user", "content": content });
+        status(
+            Method::POST,
+            format!("/api/chats/{}/message", chat_id),
+            body,
+        )
+    };
+
+    // Characters, not bytes
+    assert_eq!(append("é".repeat(LIMIT)).await, StatusCode::OK);
+    assert_eq!(append("é".repeat(LIMIT + 1)).await, StatusCode::BAD_REQUEST);
+
+    let message_id = get_chat(&app, &chat_id).await["messages"][1]["id"].clone();
+    let edit = |content: String| {
+        let uri = format!(
+            "/api/chats/{}/messages/{}",
+            chat_id,
+            message_id.as_str().unwrap()
+        );
+        status(Method::PUT, uri, json!({ "content": content }))
+    };
+    assert_eq!(edit("a".repeat(LIMIT)).await, StatusCode::OK);
+    assert_eq!(edit("a".repeat(LIMIT + 1)).await, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        get_chat(&app, &chat_id).await["messages"][1]["content"],
+        "a".repeat(LIMIT)
+    );
+}
+
+#[tokio::test]
+async fn stored_tool_results_are_cut_to_the_length_limit() {
+    const LIMIT: usize = 32;
+    let (api_base, _) = scripted_provider(vec![
+        format!(
+            "{}data: [DONE]\n\n",
+            tool_call_chunk("call_1", "roll", "not json")
+        ),
+        format!("{}data: [DONE]\n\n", chunk("Done")),
+    ])
+    .await;
+    let app = app_with(Config {
+        max_message_length: LIMIT,
+        ..Default::default()
+    })
+    .await;
+    let chat_id = new_chat(&app).await;
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    let result = messages
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["role"] == "tool")
+        .unwrap();
+    let content = result["content"].as_str().unwrap();
+    // "Error parsing arguments: ..." with a note of how much is missing
+    assert!(content.starts_with("Er…[truncated, "));
+    assert_eq!(content.chars().count(), LIMIT);
+}
+
+#[tokio::test]
+async fn hidden_messages_stay_in_the_chat_but_out_of_the_prompt() {
+    let (api_base, received) =
+        recording_provider(format!("{}data: [DONE]\n\n", chunk("Noted"))).await;
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let note = json!({ "id": uuid::Uuid::now_v7(), "role": "user", "content": "Note to self" });
+    send(
+        &app,
+        Method::POST,
+        &format!("/api/chats/{}/message", chat_id),
+        Some(note.clone()),
+    )
+    .await;
+    send(
+        &app,
+        Method::PUT,
+        &format!(
+            "/api/chats/{}/messages/{}/hidden-from-context",
+            chat_id,
+            note["id"].as_str().unwrap()
+        ),
+        Some(json!({ "hidden": true })),
+    )
+    .await;
+
+    send(
+        &app,
+        Method::POST,
+        "/api/completion",
+        Some(completion(&chat_id, &api_base)),
+    )
+    .await;
+    let sent = received.lock().unwrap()[0]["messages"].to_string();
+    assert!(sent.contains("Hi"));
+    assert!(!sent.contains("Note to self"));
+
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    assert_eq!(messages[1]["content"], "Note to self");
+    assert_eq!(messages[1]["hidden_from_context"], true);
+}
+
+#[tokio::test]
+async fn appending_the_same_message_twice_stores_it_once() {
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let other_chat_id = new_chat(&app).await;
+    let message = json!({ "id": uuid::Uuid::now_v7(), "role": "user", "content": "Hello?" });
+    let append = |chat_id: &str| {
+        let uri = format!("/api/chats/{}/message", chat_id);
+        let request = request(Method::POST, &uri, Some(message.clone()));
+        let app = app.clone();
+        async move { app.oneshot(request).await.unwrap().status() }
+    };
+
+    // A retry after a response that never arrived
+    assert_eq!(append(&chat_id).await, StatusCode::OK);
+    assert_eq!(append(&chat_id).await, StatusCode::OK);
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    assert_eq!(messages.as_array().unwrap().len(), 2);
+    assert_eq!(messages[1]["content"], "Hello?");
+
+    assert_eq!(append(&other_chat_id).await, StatusCode::NOT_FOUND);
+    let messages = get_chat(&app, &other_chat_id).await["messages"].clone();
+    assert_eq!(messages.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn a_raw_edit_replaces_the_whole_stored_message() {
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    let mut message = get_chat(&app, &chat_id).await["messages"][0].clone();
+    let uri = format!(
+        "/api/chats/{}/messages/{}/raw",
+        chat_id,
+        message["id"].as_str().unwrap()
+    );
+    message["content"] = "Edited".into();
+    message["alternatives"] = json!(["Other"]);
+    message["alternative_senders"] = json!([null]);
+    message["active_index"] = 1.into();
+    message["keep_in_context"] = true.into();
+
+    let response = app
+        .clone()
+        .oneshot(request(Method::PUT, &uri, Some(message.clone())))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(get_chat(&app, &chat_id).await["messages"][0], message);
+
+    let mut renamed = message.clone();
+    renamed["role"] = "narrator".into();
+    let response = app
+        .clone()
+        .oneshot(request(Method::PUT, &uri, Some(renamed)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(get_chat(&app, &chat_id).await["messages"][0], message);
+}
+
+#[tokio::test]
+async fn appended_messages_are_stamped_with_the_servers_clock() {
+    let app = app().await;
+    let chat_id = new_chat(&app).await;
+    // A client clock far ahead, and an id that sorts before the greeting's
+    let message = json!({
+        "id": "00000000-0000-7000-8000-000000000001",
+        "role": "user",
+        "content": "Hello from the future",
+        "created_at": "2999-01-01T00:00:00Z",
+    });
+    let before = chrono::Utc::now();
+    send(
+        &app,
+        Method::POST,
+        &format!("/api/chats/{}/message", chat_id),
+        Some(message),
+    )
+    .await;
+
+    let messages = get_chat(&app, &chat_id).await["messages"].clone();
+    assert_eq!(messages[0]["content"], "Hi");
+    assert_eq!(messages[1]["content"], "Hello from the future");
+    let created_at: chrono::DateTime<chrono::Utc> =
+        serde_json::from_value(messages[1]["created_at"].clone()).unwrap();
+    assert!(created_at >= before && created_at <= chrono::Utc::now());
+}
@@ -0,0 +1,400 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode};
+use backend::Config;
+use backend::config::{PluginFilter, PluginHealthCheck};
+use backend::plugins::PluginManager;
+use common::{StubPlugins, app_with};
+use serde_json::json;
+use std::time::Duration;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn calls_beyond_the_concurrency_cap_are_rejected() {
+    let stubs = StubPlugins::new();
+    let path = stubs.add(
+        "slow",
+        json!({ "init": { "limits": { "max_concurrent": 2 } }, "call_delay_ms": 300 }),
+    );
+    let manager = PluginManager::new();
+    manager.load_plugin(&path).await.unwrap();
+
+    let results = futures::future::join_all(
+        (0..6).map(|i| manager.call_tool("slow_echo", json!({ "call": i }))),
+    )
+    .await;
+    let (done, rejected): (Vec<_>, Vec<_>) = results.into_iter().partition(|r| r.is_ok());
+    assert_eq!(done.len(), 2);
+    for error in rejected {
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Plugin slow: concurrency limit reached (2 calls in flight)"
+        );
+    }
+
+    // The slots are free again once the calls are done
+    assert!(manager.call_tool("slow_echo", json!({})).await.is_ok());
+}
+
+#[tokio::test]
+async fn calls_beyond_the_rate_are_rejected_until_the_window_passes() {
+    let stubs = StubPlugins::new();
+    let path = stubs.add(
+        "busy",
+        json!({ "init": { "limits": { "max_calls": 3, "window_secs": 1 } } }),
+    );
+    let manager = PluginManager::new();
+    manager.load_plugin(&path).await.unwrap();
+
+    for _ in 0..3 {
+        assert!(manager.call_tool("busy_echo", json!({})).await.is_ok());
+    }
+    let error = manager.call_tool("busy_echo", json!({})).await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Plugin busy: rate limit exceeded (3 calls per 1s)"
+    );
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(manager.call_tool("busy_echo", json!({})).await.is_ok());
+}
+
+/// Three calls to `<name>_echo`, numbered
+fn echo_calls(name: &str) -> Vec<(String, serde_json::Value)> {
+    (0..3)
+        .map(|i| (format!("{}_echo", name), json!({ "call": i })))
+        .collect()
+}
+
+#[tokio::test]
+async fn batch_answers_reach_their_calls_in_any_order() {
+    let stubs = StubPlugins::new();
+    let path = stubs.add(
+        "batcher",
+        json!({ "init": { "capabilities": { "batch": true } }, "reverse_batches": true }),
+    );
+    let manager = PluginManager::new();
+    manager.load_plugin(&path).await.unwrap();
+
+    let results = manager.call_tools(echo_calls("batcher")).await;
+    for (i, result) in results.into_iter().enumerate() {
+        let result = result.unwrap();
+        assert_eq!(result["arguments"]["call"], i);
+        assert_eq!(result["batched"], true);
+    }
+}
+
+#[tokio::test]
+async fn calls_left_out_of_a_batch_answer_fail_instead_of_hanging() {
+    let stubs = StubPlugins::new();
+    let path = stubs.add(
+        "forgetful",
+        json!({ "init": { "capabilities": { "batch": true } }, "drop_last_in_batch": true }),
+    );
+    let manager = PluginManager::new();
+    manager.load_plugin(&path).await.unwrap();
+
+    let results = tokio::time::timeout(
+        Duration::from_secs(5),
+        manager.call_tools(echo_calls("forgetful")),
+    )
+    .await
+    .expect("a call left out of the answer kept waiting");
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert_eq!(
+        results[2].as_ref().unwrap_err().to_string(),
+        "Tool execution error: Missing from the plugin's batch answer"
+    );
+}
+
+#[tokio::test]
+async fn plugins_without_batch_support_get_one_request_per_call() {
+    let stubs = StubPlugins::new();
+    let path = stubs.add("single", json!({}));
+    let manager = PluginManager::new();
+    manager.load_plugin(&path).await.unwrap();
+
+    let results = manager.call_tools(echo_calls("single")).await;
+    for (i, result) in results.into_iter().enumerate() {
+        let result = result.unwrap();
+        assert_eq!(result["arguments"]["call"], i);
+        assert_eq!(result["batched"], false);
+    }
+}
+
+#[tokio::test]
+async fn discovery_reports_what_loaded_and_why_the_rest_did_not() {
+    let stubs = StubPlugins::new();
+    stubs.add("good", json!({}));
+    stubs.add("broken", json!({ "init": { "tools": "none" } }));
+    std::fs::write(stubs.dir().join("notes.txt"), "not a plugin").unwrap();
+
+    let manager = PluginManager::new();
+    let mut report = manager.discover_plugins(stubs.dir()).await.unwrap();
+    report.failed.sort_by(|a, b| a.file.cmp(&b.file));
+
+    assert_eq!(report.loaded, ["good"]);
+    let files: Vec<_> = report.failed.iter().map(|f| f.file.as_str()).collect();
+    assert_eq!(files, ["broken", "notes.txt"]);
+    assert_eq!(report.failed[1].error, "File is not executable");
+    assert!(manager.call_tool("good_echo", json!({})).await.is_ok());
+}
+
+#[tokio::test]
+async fn tools_become_available_once_background_discovery_finishes() {
+    let stubs = StubPlugins::new();
+    stubs.add("slow", json!({ "init_delay_ms": 500 }));
+    let manager = PluginManager::new();
+    assert!(manager.is_ready());
+
+    manager.discover_in_background(stubs.dir());
+    assert!(!manager.is_ready());
+    assert!(manager.get_all_tools().await.is_empty());
+    assert!(!manager.wait_until_ready(Duration::from_millis(50)).await);
+
+    assert!(manager.wait_until_ready(Duration::from_secs(5)).await);
+    assert!(manager.is_ready());
+    let tools: Vec<_> = manager
+        .get_all_tools()
+        .await
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    assert_eq!(tools, ["slow_echo"]);
+}
+
+#[tokio::test]
+async fn resources_are_listed_and_read_from_plugins_that_serve_them() {
+    let stubs = StubPlugins::new();
+    let guide = json!({ "uri": "kb://guide", "name": "Guide" });
+    let docs = stubs.add(
+        "docs",
+        json!({ "init": { "capabilities": { "resources": true }, "resources": [guide] } }),
+    );
+    let plain = stubs.add("plain", json!({ "init": { "resources": [guide] } }));
+    let manager = PluginManager::new();
+    manager.load_plugin(&docs).await.unwrap();
+    manager.load_plugin(&plain).await.unwrap();
+
+    let listed = manager.list_resources("docs").await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].uri, "kb://guide");
+    let read = manager.read_resource("docs", "kb://guide").await.unwrap();
+    assert_eq!(read.text, "Contents of kb://guide");
+
+    // Without the capability only the resources named at startup are known, none readable
+    assert_eq!(manager.list_resources("plain").await.unwrap().len(), 1);
+    let error = manager
+        .read_resource("plain", "kb://guide")
+        .await
+        .unwrap_err();
+    assert_eq!(error.to_string(), "Plugin plain does not serve resources");
+    assert!(manager.call_tool("plain_echo", json!({})).await.is_ok());
+}
+
+#[tokio::test]
+async fn plugins_the_filter_rules_out_are_stopped_after_the_handshake() {
+    let stubs = StubPlugins::new();
+    for name in ["alpha", "beta", "gamma"] {
+        stubs.add(name, json!({}));
+    }
+    let loaded = |filter: PluginFilter| {
+        let dir = stubs.dir();
+        async move {
+            let manager = PluginManager::with_filter(filter);
+            let report = manager.discover_plugins(dir).await.unwrap();
+            let mut names: Vec<_> = manager
+                .get_plugins()
+                .await
+                .into_iter()
+                .map(|p| p.name)
+                .collect();
+            names.sort();
+            (names, report)
+        }
+    };
+
+    let (names, report) = loaded(PluginFilter {
+        allow: None,
+        deny: ["beta".to_string()].into(),
+    })
+    .await;
+    assert_eq!(names, ["alpha", "gamma"]);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].error, "Plugin not allowed: beta");
+
+    // The denylist wins over the allowlist
+    let (names, _) = loaded(PluginFilter {
+        allow: Some(["alpha".to_string(), "beta".to_string()].into()),
+        deny: ["beta".to_string()].into(),
+    })
+    .await;
+    assert_eq!(names, ["alpha"]);
+}
+
+#[tokio::test]
+async fn plugins_on_another_major_protocol_version_are_turned_away() {
+    let stubs = StubPlugins::new();
+    let future = stubs.add("future", json!({ "init": { "protocol_version": "2.0" } }));
+    let newer = stubs.add("newer", json!({ "init": { "protocol_version": "1.4" } }));
+    let legacy = stubs.add("legacy", json!({ "init": { "protocol_version": null } }));
+    let manager = PluginManager::new();
+
+    let error = manager.load_plugin(&future).await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Plugin future speaks protocol 2.0, but this host speaks 1.0"
+    );
+    // Same major version, and plugins from before negotiation, are fine
+    assert_eq!(manager.load_plugin(&newer).await.unwrap(), "newer");
+    assert_eq!(manager.load_plugin(&legacy).await.unwrap(), "legacy");
+
+    let mut names: Vec<_> = manager
+        .get_plugins()
+        .await
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, ["legacy", "newer"]);
+}
+
+#[tokio::test]
+async fn installs_are_refused_when_turned_off() {
+    let app = app_with(Config {
+        allow_plugin_install: false,
+        ..Default::default()
+    })
+    .await;
+    let upload = "--x\r\nContent-Disposition: form-data; name=\"plugin\"; filename=\"p\"\r\n\r\nbin\r\n--x--\r\n";
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/plugins/install")
+        .header("content-type", "multipart/form-data; boundary=x")
+        .body(Body::from(upload))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    // Nothing was written on the way
+    assert!(!std::path::Path::new("./plugins/p").exists());
+}
+
+#[tokio::test]
+async fn reloading_leaves_one_fresh_instance_of_each_plugin() {
+    let stubs = StubPlugins::new();
+    stubs.add("echo", json!({ "call_delay_ms": 300 }));
+    let gone = stubs.add("gone", json!({}));
+    let manager = PluginManager::new();
+    manager.discover_plugins(stubs.dir()).await.unwrap();
+    std::fs::remove_file(gone).unwrap();
+
+    // A call already running when the reload starts still gets its answer
+    let (in_flight, report) = tokio::join!(manager.call_tool("echo_echo", json!({})), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        manager.reload_plugins(stubs.dir()).await.unwrap()
+    });
+    assert!(in_flight.is_ok());
+
+    assert_eq!(report.loaded, ["echo"]);
+    let plugins = manager.get_plugins().await;
+    assert_eq!(plugins.len(), 1);
+    assert_eq!(plugins[0].name, "echo");
+    let tools: Vec<_> = manager
+        .get_all_tools()
+        .await
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    assert_eq!(tools, ["echo_echo"]);
+    assert!(manager.call_tool("echo_echo", json!({})).await.is_ok());
+}
+
+#[tokio::test]
+async fn a_tools_output_schema_comes_through_from_the_handshake() {
+    let stubs = StubPlugins::new();
+    let returns = json!({ "type": "object", "properties": { "total": { "type": "integer" } } });
+    let path = stubs.add(
+        "dice",
+        json!({ "init": { "tools": [
+            { "name": "roll", "description": "Roll dice", "parameters": {}, "returns": returns },
+            { "name": "flip", "description": "Flip a coin", "parameters": {} },
+        ] } }),
+    );
+    let manager = PluginManager::new();
+    manager.load_plugin(&path).await.unwrap();
+
+    let mut tools = manager.get_all_tools().await;
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(tools[0].name, "flip");
+    assert_eq!(tools[0].returns, None);
+    assert_eq!(tools[1].name, "roll");
+    assert_eq!(tools[1].returns, Some(returns));
+}
+
+#[tokio::test]
+async fn plugins_that_stop_answering_pings_are_unloaded() {
+    let stubs = StubPlugins::new();
+    stubs.add("well", json!({}));
+    stubs.add("hung", json!({ "ignore_pings": true }));
+    let manager = PluginManager::new();
+    manager.discover_plugins(stubs.dir()).await.unwrap();
+    assert_eq!(manager.get_plugins().await.len(), 2);
+
+    manager.watch_health(PluginHealthCheck {
+        interval: Some(Duration::from_millis(100)),
+        timeout: Duration::from_millis(50),
+        max_missed: 2,
+        restart: false,
+    });
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while manager.get_plugins().await.len() > 1 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "hung plugin was kept"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let plugins = manager.get_plugins().await;
+    assert_eq!(plugins[0].name, "well");
+    assert!(manager.call_tool("hung_echo", json!({})).await.is_err());
+    assert!(manager.call_tool("well_echo", json!({})).await.is_ok());
+}
+
+#[tokio::test]
+async fn output_with_a_bom_and_crlf_line_endings_is_understood() {
+    let stubs = StubPlugins::new();
+    let path = stubs.add("windows", json!({ "bom_crlf": true }));
+    let manager = PluginManager::new();
+    assert_eq!(manager.load_plugin(&path).await.unwrap(), "windows");
+
+    let result = manager
+        .call_tool("windows_echo", json!({ "line": 1 }))
+        .await
+        .unwrap();
+    assert_eq!(result["arguments"], json!({ "line": 1 }));
+}
+
+#[tokio::test]
+async fn a_garbled_answer_fails_its_call_instead_of_hanging() {
+    let stubs = StubPlugins::new();
+    let path = stubs.add("garbled", json!({ "garble_calls": true }));
+    let manager = PluginManager::new();
+    manager.load_plugin(&path).await.unwrap();
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        manager.call_tool("garbled_echo", json!({})),
+    )
+    .await
+    .expect("the call was left hanging");
+    let error = result.unwrap_err().to_string();
+    assert!(
+        error.contains("Unparseable response from plugin"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,133 @@
+//! A plugin for tests. It reads what to do from `<name>.json` in the directory above its
+//! own, so the plugins directory itself only holds executables. See `Behaviour`.
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Behaviour {
+    /// Merged over the default initialize result
+    init: Value,
+    /// How long the handshake takes
+    init_delay_ms: u64,
+    /// How long each tool call takes
+    call_delay_ms: u64,
+    /// Answer batches back to front
+    reverse_batches: bool,
+    /// Leave the last response out of each batch answer
+    drop_last_in_batch: bool,
+    /// Never answer pings, as if hung
+    ignore_pings: bool,
+    /// Answer tool calls with a line that isn't valid JSON
+    garble_calls: bool,
+    /// Start the output with a byte order mark and end lines with CRLF
+    bom_crlf: bool,
+}
+
+fn main() {
+    let exe = std::env::current_exe().unwrap();
+    let name = exe.file_stem().unwrap().to_string_lossy().into_owned();
+    let config = exe
+        .parent()
+        .unwrap()
+        .with_file_name(format!("{}.json", name));
+    let behaviour: Behaviour = std::fs::read_to_string(config)
+        .map(|data| serde_json::from_str(&data).unwrap())
+        .unwrap_or_default();
+    let behaviour = Arc::new(behaviour);
+
+    let out = Arc::new(Mutex::new(std::io::stdout()));
+    if behaviour.bom_crlf {
+        out.lock()
+            .unwrap()
+            .write_all("\u{feff}".as_bytes())
+            .unwrap();
+    }
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line.unwrap();
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        let (behaviour, out, name) = (behaviour.clone(), out.clone(), name.clone());
+        // Each line is answered on its own thread, so slow calls overlap like real ones
+        std::thread::spawn(move || {
+            let answer = match message {
+                Value::Array(requests) => {
+                    let mut responses: Vec<Value> = requests
+                        .iter()
+                        .map(|r| answer(&behaviour, &name, r, true))
+                        .collect();
+                    if behaviour.reverse_batches {
+                        responses.reverse();
+                    }
+                    if behaviour.drop_last_in_batch {
+                        responses.pop();
+                    }
+                    Some(Value::Array(responses).to_string())
+                }
+                request => {
+                    if behaviour.ignore_pings && request["method"] == "ping" {
+                        return;
+                    }
+                    if behaviour.garble_calls && request["method"] == "call_tool" {
+                        Some(format!("{{\"id\":{}, oops", request["id"]))
+                    } else {
+                        Some(answer(&behaviour, &name, &request, false).to_string())
+                    }
+                }
+            };
+            if let Some(answer) = answer {
+                let ending = if behaviour.bom_crlf { "\r\n" } else { "\n" };
+                let mut out = out.lock().unwrap();
+                write!(out, "{}{}", answer, ending).unwrap();
+                out.flush().unwrap();
+            }
+        });
+    }
+}
+
+fn answer(behaviour: &Behaviour, name: &str, request: &Value, batched: bool) -> Value {
+    let params = &request["params"];
+    let result = match request["method"].as_str().unwrap_or_default() {
+        "initialize" => {
+            std::thread::sleep(Duration::from_millis(behaviour.init_delay_ms));
+            let mut init = json!({
+                "name": name,
+                "version": "1.0.0",
+                "protocol_version": "1.0",
+                "description": "A stub plugin",
+                "tools": [{ "name": format!("{}_echo", name), "description": "", "parameters": {} }],
+            });
+            if let Value::Object(overrides) = &behaviour.init {
+                for (key, value) in overrides {
+                    init[key] = value.clone();
+                }
+            }
+            init
+        }
+        "ping" => json!({}),
+        "call_tool" => {
+            std::thread::sleep(Duration::from_millis(behaviour.call_delay_ms));
+            json!({ "tool": params["name"], "arguments": params["arguments"], "batched": batched })
+        }
+        "list_resources" => json!({ "resources": behaviour.init["resources"] }),
+        "read_resource" => json!({
+            "uri": params["uri"],
+            "text": format!("Contents of {}", params["uri"].as_str().unwrap_or_default()),
+        }),
+        method => {
+            return json!({
+                "json_rpc": "2.0",
+                "result": null,
+                "error": { "code": -32601, "message": format!("Unknown method {}", method), "data": null },
+                "id": request["id"],
+            });
+        }
+    };
+    json!({ "json_rpc": "2.0", "result": result, "error": null, "id": request["id"] })
+}
@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::io::{self, BufRead, Write};
 
+/// The Renoma plugin protocol this plugin implements
+const PROTOCOL_VERSION: &str = "1.0";
+
 #[derive(Debug, Serialize, Deserialize)]
 struct JsonRpcRequest {
     json_rpc: String,
@@ -61,6 +64,7 @@ fn main() -> io::Result<()> {
                     result: Some(json!({
                         "name": "dice_roll",
                         "version": "0.1.0",
+                        "protocol_version": PROTOCOL_VERSION,
                         "description": "Roll dice for RPG games",
                         "tools": [
                             {
@@ -75,6 +79,22 @@ fn main() -> io::Result<()> {
                                         }
                                     },
                                     "required": ["notation"]
+                                },
+                                "returns": {
+                                    "type": "object",
+                                    "properties": {
+                                        "notation": { "type": "string", "title": "Notation" },
+                                        "count": { "type": "integer", "title": "Dice" },
+                                        "sides": { "type": "integer", "title": "Sides" },
+                                        "rolls": {
+                                            "type": "array",
+                                            "items": { "type": "integer" },
+                                            "title": "Rolls"
+                                        },
+                                        "modifier": { "type": "integer", "title": "Modifier" },
+                                        "total": { "type": "integer", "title": "Total" }
+                                    },
+                                    "required": ["notation", "count", "sides", "modifier", "rolls", "total"]
                                 }
                             }
                         ]
@@ -86,6 +106,18 @@ fn main() -> io::Result<()> {
                 stdout.write_all(b"\n")?;
                 stdout.flush()?;
             }
+            // The host pings now and then to check the plugin hasn't hung
+            "ping" => {
+                let resp = JsonRpcResponse {
+                    json_rpc: "2.0".to_string(),
+                    result: Some(json!({})),
+                    error: None,
+                    id: req.id,
+                };
+                serde_json::to_writer(&mut stdout, &resp)?;
+                stdout.write_all(b"\n")?;
+                stdout.flush()?;
+            }
             "call_tool" => {
                 let params = req.params.unwrap_or(json!({}));
                 let tool_name = params.get("name").and_then(|v| v.as_str()).unwrap_or("");
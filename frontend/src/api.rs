@@ -4,6 +4,22 @@ use uuid::Uuid;
 
 const API_BASE: &str = "/api";
 
+pub async fn fetch_config_defaults() -> Result<ConfigDefaults, gloo_net::Error> {
+    Request::get(&format!("{}/config/defaults", API_BASE))
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+pub async fn fetch_server_info() -> Result<ServerInfo, gloo_net::Error> {
+    Request::get(&format!("{}/config/server", API_BASE))
+        .send()
+        .await?
+        .json()
+        .await
+}
+
 pub async fn fetch_characters() -> Result<Vec<Character>, gloo_net::Error> {
     Request::get(&format!("{}/characters", API_BASE))
         .send()
@@ -21,6 +37,18 @@ pub async fn create_character(char: CreateCharacterRequest) -> Result<Character,
         .await
 }
 
+pub async fn update_character(
+    id: Uuid,
+    char: CreateCharacterRequest,
+) -> Result<Character, gloo_net::Error> {
+    Request::put(&format!("{}/characters/{}", API_BASE, id))
+        .json(&char)?
+        .send()
+        .await?
+        .json()
+        .await
+}
+
 pub async fn delete_character(id: Uuid) -> Result<(), gloo_net::Error> {
     Request::delete(&format!("{}/characters/{}", API_BASE, id))
         .send()
@@ -28,6 +56,35 @@ pub async fn delete_character(id: Uuid) -> Result<(), gloo_net::Error> {
     Ok(())
 }
 
+pub async fn toggle_favorite(id: Uuid) -> Result<Character, gloo_net::Error> {
+    Request::post(&format!("{}/characters/{}/favorite", API_BASE, id))
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+/// A character with all its chats, to save as a file
+pub async fn export_character_bundle(id: Uuid) -> Result<CharacterBundle, gloo_net::Error> {
+    Request::get(&format!("{}/characters/{}/export/full", API_BASE, id))
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+/// Recreate an exported character and its chats, returning the new character
+pub async fn import_character_bundle(
+    bundle: &CharacterBundle,
+) -> Result<Character, gloo_net::Error> {
+    Request::post(&format!("{}/characters/import/full", API_BASE))
+        .json(bundle)?
+        .send()
+        .await?
+        .json()
+        .await
+}
+
 pub async fn fetch_chats(char_id: Uuid) -> Result<Vec<Chat>, gloo_net::Error> {
     Request::get(&format!("{}/chats?character_id={}", API_BASE, char_id))
         .send()
@@ -55,6 +112,59 @@ pub async fn create_chat(char_id: Uuid) -> Result<Chat, gloo_net::Error> {
         .await
 }
 
+pub async fn move_chat(chat_id: Uuid, character_id: Uuid) -> Result<Chat, gloo_net::Error> {
+    Request::post(&format!("{}/chats/{}/move", API_BASE, chat_id))
+        .json(&MoveChatRequest { character_id })?
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+pub async fn duplicate_chat(chat_id: Uuid) -> Result<Chat, gloo_net::Error> {
+    Request::post(&format!("{}/chats/{}/duplicate", API_BASE, chat_id))
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+pub async fn set_scenario(chat_id: Uuid, scenario: Option<String>) -> Result<(), gloo_net::Error> {
+    Request::put(&format!("{}/chats/{}/scenario", API_BASE, chat_id))
+        .json(&SetScenarioRequest { scenario })?
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Label a chat, returning all its tags
+pub async fn add_chat_tag(chat_id: Uuid, tag: String) -> Result<Vec<String>, gloo_net::Error> {
+    Request::post(&format!("{}/chats/{}/tags", API_BASE, chat_id))
+        .json(&ChatTagRequest { tag })?
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+/// Take a label off a chat, returning the tags left
+pub async fn remove_chat_tag(chat_id: Uuid, tag: &str) -> Result<Vec<String>, gloo_net::Error> {
+    let tag = String::from(web_sys::js_sys::encode_uri_component(tag));
+    Request::delete(&format!("{}/chats/{}/tags/{}", API_BASE, chat_id, tag))
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+/// Stop the reply being generated in a chat. Its stream ends with what was written so far.
+pub async fn cancel_generation(chat_id: Uuid) -> Result<(), gloo_net::Error> {
+    Request::post(&format!("{}/chats/{}/cancel", API_BASE, chat_id))
+        .send()
+        .await?;
+    Ok(())
+}
+
 pub async fn delete_chat(chat_id: Uuid) -> Result<(), gloo_net::Error> {
     Request::delete(&format!("{}/chats/{}", API_BASE, chat_id))
         .send()
@@ -62,11 +172,10 @@ pub async fn delete_chat(chat_id: Uuid) -> Result<(), gloo_net::Error> {
     Ok(())
 }
 
-pub async fn send_message(chat_id: Uuid, content: String) -> Result<(), gloo_net::Error> {
-    let msg = ChatMessage::new(ROLE_USER, content);
-
+/// Save a message. Its id makes retries safe: sending the same message again is a no-op.
+pub async fn send_message(chat_id: Uuid, msg: &ChatMessage) -> Result<(), gloo_net::Error> {
     Request::post(&format!("{}/chats/{}/message", API_BASE, chat_id))
-        .json(&msg)?
+        .json(msg)?
         .send()
         .await?;
     Ok(())
@@ -111,6 +220,100 @@ pub async fn swipe_message(
     .await?;
     Ok(())
 }
+
+pub async fn set_keep_in_context(
+    chat_id: Uuid,
+    message_id: Uuid,
+    keep: bool,
+) -> Result<(), gloo_net::Error> {
+    Request::put(&format!(
+        "{}/chats/{}/messages/{}/keep-in-context",
+        API_BASE, chat_id, message_id
+    ))
+    .json(&KeepInContextRequest { keep })?
+    .send()
+    .await?;
+    Ok(())
+}
+
+pub async fn set_hidden_from_context(
+    chat_id: Uuid,
+    message_id: Uuid,
+    hidden: bool,
+) -> Result<(), gloo_net::Error> {
+    Request::put(&format!(
+        "{}/chats/{}/messages/{}/hidden-from-context",
+        API_BASE, chat_id, message_id
+    ))
+    .json(&HiddenFromContextRequest { hidden })?
+    .send()
+    .await?;
+    Ok(())
+}
+
+/// Overwrite the whole stored message. The server's reason is returned when it refuses.
+pub async fn replace_message(chat_id: Uuid, msg: &ChatMessage) -> Result<(), gloo_net::Error> {
+    let resp = Request::put(&format!(
+        "{}/chats/{}/messages/{}/raw",
+        API_BASE, chat_id, msg.id
+    ))
+    .json(msg)?
+    .send()
+    .await?;
+    if !resp.ok() {
+        return Err(gloo_net::Error::GlooError(
+            resp.text().await.unwrap_or_default(),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn reset_message(chat_id: Uuid, message_id: Uuid) -> Result<(), gloo_net::Error> {
+    Request::post(&format!(
+        "{}/chats/{}/messages/{}/reset",
+        API_BASE, chat_id, message_id
+    ))
+    .send()
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_lore_entries() -> Result<Vec<LoreEntry>, gloo_net::Error> {
+    Request::get(&format!("{}/lore", API_BASE))
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+pub async fn create_lore_entry(entry: LoreEntryRequest) -> Result<LoreEntry, gloo_net::Error> {
+    Request::post(&format!("{}/lore", API_BASE))
+        .json(&entry)?
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+pub async fn update_lore_entry(
+    id: Uuid,
+    entry: LoreEntryRequest,
+) -> Result<LoreEntry, gloo_net::Error> {
+    Request::put(&format!("{}/lore/{}", API_BASE, id))
+        .json(&entry)?
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+pub async fn delete_lore_entry(id: Uuid) -> Result<(), gloo_net::Error> {
+    Request::delete(&format!("{}/lore/{}", API_BASE, id))
+        .send()
+        .await?;
+    Ok(())
+}
+
 pub async fn fetch_plugins() -> Result<Vec<PluginManifest>, gloo_net::Error> {
     Request::get(&format!("{}/plugins", API_BASE))
         .send()
@@ -126,11 +329,21 @@ pub async fn toggle_plugin(name: &str) -> Result<(), gloo_net::Error> {
     Ok(())
 }
 
-pub async fn discover_plugins() -> Result<(), gloo_net::Error> {
+pub async fn discover_plugins() -> Result<DiscoveryReport, gloo_net::Error> {
     Request::post(&format!("{}/plugins/discover", API_BASE))
         .send()
-        .await?;
-    Ok(())
+        .await?
+        .json()
+        .await
+}
+
+/// Restart every plugin from the plugins directory
+pub async fn reload_plugins() -> Result<DiscoveryReport, gloo_net::Error> {
+    Request::post(&format!("{}/plugins/reload", API_BASE))
+        .send()
+        .await?
+        .json()
+        .await
 }
 
 pub async fn install_plugin(file: web_sys::File) -> Result<(), gloo_net::Error> {
@@ -140,9 +353,15 @@ pub async fn install_plugin(file: web_sys::File) -> Result<(), gloo_net::Error>
         .append_with_blob_and_filename("plugin", &file, &file.name())
         .map_err(|_| gloo_net::Error::GlooError("Failed to append file to FormData".to_string()))?;
 
-    Request::post(&format!("{}/plugins/install", API_BASE))
+    let resp = Request::post(&format!("{}/plugins/install", API_BASE))
         .body(form_data)?
         .send()
         .await?;
+    if !resp.ok() {
+        // The server explains what went wrong, e.g. a read-only plugins directory
+        return Err(gloo_net::Error::GlooError(
+            resp.text().await.unwrap_or_default(),
+        ));
+    }
     Ok(())
 }
@@ -1,19 +1,65 @@
 use crate::api;
 use crate::store::{Action, StoreContext};
-use shared::models::CreateCharacterRequest;
+use shared::models::{
+    Character, CharacterBundle, CreateCharacterRequest, GreetingMode, ResponseLength,
+};
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_futures::wasm_bindgen::JsCast;
 use yew::prelude::*;
 
+#[derive(Properties, PartialEq)]
+pub struct CharModalProps {
+    /// The character to edit, `None` to create a new one
+    #[prop_or_default]
+    pub character: Option<Character>,
+}
+
 #[function_component(CharModal)]
-pub fn char_modal() -> Html {
+pub fn char_modal(props: &CharModalProps) -> Html {
     let store = use_context::<StoreContext>().expect("Store context not found");
+    let editing = props.character.as_ref().map(|c| c.id);
+    // Not editable here, but saved back so editing doesn't drop it
+    let metadata = props
+        .character
+        .as_ref()
+        .map(|c| c.metadata.clone())
+        .unwrap_or_default();
 
-    // State for inputs
-    let name = use_state(String::new);
-    let desc = use_state(String::new);
-    let personality = use_state(String::new);
-    let scenario = use_state(String::new);
-    let first_message = use_state(String::new);
-    let example_messages = use_state(String::new);
+    // State for inputs, pre-filled when editing
+    let field = |get: fn(&Character) -> &String| {
+        props
+            .character
+            .as_ref()
+            .map(|c| get(c).clone())
+            .unwrap_or_default()
+    };
+    let name = use_state(|| field(|c| &c.name));
+    let desc = use_state(|| field(|c| &c.description));
+    let personality = use_state(|| field(|c| &c.personality));
+    let scenario = use_state(|| field(|c| &c.scenario));
+    let first_message = use_state(|| field(|c| &c.first_message));
+    let example_messages = use_state(|| field(|c| &c.example_messages));
+    let alternate_greetings = use_state(|| {
+        props
+            .character
+            .as_ref()
+            .map(|c| c.alternate_greetings.clone())
+            .unwrap_or_default()
+    });
+    let greeting_mode = use_state(|| {
+        props
+            .character
+            .as_ref()
+            .map(|c| c.greeting_mode)
+            .unwrap_or_default()
+    });
+    let response_length = use_state(|| props.character.as_ref().and_then(|c| c.response_length));
+    let reasoning_effort = use_state(|| {
+        props
+            .character
+            .as_ref()
+            .and_then(|c| c.reasoning_effort.clone())
+    });
 
     let on_save = {
         let store = store.clone();
@@ -23,6 +69,11 @@ pub fn char_modal() -> Html {
         let scenario = scenario.clone();
         let first_message = first_message.clone();
         let example_messages = example_messages.clone();
+        let alternate_greetings = alternate_greetings.clone();
+        let greeting_mode = greeting_mode.clone();
+        let response_length = response_length.clone();
+        let reasoning_effort = reasoning_effort.clone();
+        let metadata = metadata.clone();
 
         Callback::from(move |_| {
             let req = CreateCharacterRequest {
@@ -32,11 +83,31 @@ pub fn char_modal() -> Html {
                 scenario: (*scenario).clone(),
                 first_message: (*first_message).clone(),
                 example_messages: (*example_messages).clone(),
+                // Blank greetings are never used, so they aren't kept either
+                alternate_greetings: alternate_greetings
+                    .iter()
+                    .filter(|g| !g.trim().is_empty())
+                    .cloned()
+                    .collect(),
+                greeting_mode: *greeting_mode,
+                response_length: *response_length,
+                reasoning_effort: (*reasoning_effort).clone(),
+                metadata: metadata.clone(),
             };
 
             let store = store.clone();
             yew::platform::spawn_local(async move {
-                if let Ok(new_char) = api::create_character(req).await {
+                if let Some(id) = editing {
+                    // The backend reads the character on every completion, so the chat
+                    // picks up the change with its next reply
+                    match api::update_character(id, req).await {
+                        Ok(updated) => {
+                            store.dispatch(Action::UpdateCharacter(updated));
+                            store.dispatch(Action::CloseModal);
+                        }
+                        Err(e) => tracing::error!("Failed to update character: {:?}", e),
+                    }
+                } else if let Ok(new_char) = api::create_character(req).await {
                     if let Ok(chars) = api::fetch_characters().await {
                         store.dispatch(Action::SetCharacters(chars));
                     }
@@ -57,18 +128,119 @@ pub fn char_modal() -> Html {
         Callback::from(move |_| store.dispatch(Action::CloseModal))
     };
 
+    // Sharing the character along with its chats
+    let bundle_error = use_state(|| None::<String>);
+
+    let on_export = {
+        let bundle_error = bundle_error.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(id) = editing else {
+                return;
+            };
+            let bundle_error = bundle_error.clone();
+            yew::platform::spawn_local(async move {
+                match api::export_character_bundle(id).await {
+                    Ok(bundle) => {
+                        let json = serde_json::to_string_pretty(&bundle).unwrap_or_default();
+                        download(&format!("{}.json", bundle.character.name), &json);
+                        bundle_error.set(None);
+                    }
+                    Err(e) => bundle_error.set(Some(format!("Export failed: {}", e))),
+                }
+            });
+        })
+    };
+
+    let on_import = {
+        let store = store.clone();
+        let bundle_error = bundle_error.clone();
+        Callback::from(move |e: Event| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            let store = store.clone();
+            let bundle_error = bundle_error.clone();
+            yew::platform::spawn_local(async move {
+                let text = JsFuture::from(file.text())
+                    .await
+                    .ok()
+                    .and_then(|text| text.as_string())
+                    .unwrap_or_default();
+                let bundle = match serde_json::from_str::<CharacterBundle>(&text) {
+                    Ok(bundle) => bundle,
+                    Err(e) => {
+                        bundle_error.set(Some(format!("Not a character export: {}", e)));
+                        return;
+                    }
+                };
+                match api::import_character_bundle(&bundle).await {
+                    Ok(imported) => {
+                        if let Ok(chars) = api::fetch_characters().await {
+                            store.dispatch(Action::SetCharacters(chars));
+                        }
+                        store.dispatch(Action::SelectCharacter(imported.id));
+                        store.dispatch(Action::CloseModal);
+                    }
+                    Err(e) => bundle_error.set(Some(format!("Import failed: {}", e))),
+                }
+            });
+        })
+    };
+
+    let on_add_greeting = {
+        let alternate_greetings = alternate_greetings.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut greetings = (*alternate_greetings).clone();
+            greetings.push(String::new());
+            alternate_greetings.set(greetings);
+        })
+    };
+
+    let current_length = *response_length;
+    let current_mode = *greeting_mode;
+    let current_effort = (*reasoning_effort).clone().unwrap_or_default();
+
     html! {
         <div class="modal-overlay" onclick={on_close}>
             <div class="modal-content" onclick={|e: MouseEvent| e.stop_propagation()}>
                 <div class="modal-header">
-                    <h2 class="modal-title">{"Create New Character"}</h2>
+                    <h2 class="modal-title">{if editing.is_some() { "Edit Character" } else { "Create New Character" }}</h2>
                     <button class="close-btn" onclick={on_cancel.clone()}>{"×"}</button>
                 </div>
 
                 <div class="modal-body">
+                    if !metadata.is_empty() {
+                        <div class="card-metadata">
+                            if let Some(creator) = &metadata.creator {
+                                <div class="card-metadata-row">
+                                    <span class="card-metadata-label">{"Creator"}</span>
+                                    {creator}
+                                </div>
+                            }
+                            if let Some(version) = &metadata.character_version {
+                                <div class="card-metadata-row">
+                                    <span class="card-metadata-label">{"Version"}</span>
+                                    {version}
+                                </div>
+                            }
+                            if !metadata.tags.is_empty() {
+                                <div class="card-tags">
+                                    { for metadata.tags.iter().map(|tag| html! { <span class="card-tag">{tag}</span> }) }
+                                </div>
+                            }
+                            if let Some(notes) = &metadata.creator_notes {
+                                <details class="card-notes">
+                                    <summary>{"Creator's Notes"}</summary>
+                                    <div class="card-notes-body">{notes}</div>
+                                </details>
+                            }
+                        </div>
+                    }
+
                     <div class="form-group">
                         <label class="form-label">{"Name"}</label>
-                        <input class="form-input" type="text" placeholder="e.g. Seraphina" oninput={Callback::from(move |e: InputEvent| {
+                        <input class="form-input" type="text" placeholder="e.g. Seraphina" value={(*name).clone()} oninput={Callback::from(move |e: InputEvent| {
                             let i: web_sys::HtmlInputElement = e.target_unchecked_into();
                             name.set(i.value());
                         })} />
@@ -76,7 +248,7 @@ pub fn char_modal() -> Html {
 
                     <div class="form-group">
                         <label class="form-label">{"Description"}</label>
-                        <textarea class="form-textarea" rows="2" placeholder="A brief summary of who they are..." oninput={Callback::from(move |e: InputEvent| {
+                        <textarea class="form-textarea" rows="2" placeholder="A brief summary of who they are..." value={(*desc).clone()} oninput={Callback::from(move |e: InputEvent| {
                             let i: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
                             desc.set(i.value());
                         })} />
@@ -84,7 +256,7 @@ pub fn char_modal() -> Html {
 
                     <div class="form-group">
                         <label class="form-label">{"Personality"}</label>
-                        <textarea class="form-textarea" rows="3" placeholder="Detailed personality traits, likes, dislikes..." oninput={Callback::from(move |e: InputEvent| {
+                        <textarea class="form-textarea" rows="3" placeholder="Detailed personality traits, likes, dislikes..." value={(*personality).clone()} oninput={Callback::from(move |e: InputEvent| {
                             let i: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
                             personality.set(i.value());
                         })} />
@@ -92,7 +264,7 @@ pub fn char_modal() -> Html {
 
                     <div class="form-group">
                         <label class="form-label">{"Scenario"}</label>
-                        <textarea class="form-textarea" rows="2" placeholder="The setting or current situation..." oninput={Callback::from(move |e: InputEvent| {
+                        <textarea class="form-textarea" rows="2" placeholder="The setting or current situation..." value={(*scenario).clone()} oninput={Callback::from(move |e: InputEvent| {
                             let i: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
                             scenario.set(i.value());
                         })} />
@@ -100,26 +272,130 @@ pub fn char_modal() -> Html {
 
                     <div class="form-group">
                         <label class="form-label">{"First Message"}</label>
-                        <textarea class="form-textarea" rows="2" placeholder="The very first thing the character says..." oninput={Callback::from(move |e: InputEvent| {
+                        <textarea class="form-textarea" rows="2" placeholder="The very first thing the character says..." value={(*first_message).clone()} oninput={Callback::from(move |e: InputEvent| {
                             let i: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
                             first_message.set(i.value());
                         })} />
                     </div>
 
+                    <div class="form-group">
+                        <label class="form-label">{"Alternate Greetings"}</label>
+                        { for alternate_greetings.iter().enumerate().map(|(i, greeting)| {
+                            let on_input = {
+                                let alternate_greetings = alternate_greetings.clone();
+                                Callback::from(move |e: InputEvent| {
+                                    let input: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
+                                    let mut greetings = (*alternate_greetings).clone();
+                                    greetings[i] = input.value();
+                                    alternate_greetings.set(greetings);
+                                })
+                            };
+                            let on_remove = {
+                                let alternate_greetings = alternate_greetings.clone();
+                                Callback::from(move |_: MouseEvent| {
+                                    let mut greetings = (*alternate_greetings).clone();
+                                    greetings.remove(i);
+                                    alternate_greetings.set(greetings);
+                                })
+                            };
+                            html! {
+                                <div class="alternate-greeting">
+                                    <textarea class="form-textarea" rows="2" placeholder="Another way the character could open..." value={greeting.clone()} oninput={on_input} />
+                                    <button class="icon-btn" title="Remove greeting" onclick={on_remove}>{"×"}</button>
+                                </div>
+                            }
+                        })}
+                        <button class="btn btn-secondary btn-sm" onclick={on_add_greeting}>{"Add Greeting"}</button>
+                    </div>
+
+                    if !alternate_greetings.is_empty() {
+                        <div class="form-group">
+                            <label class="form-label">{"New Chats Open With"}</label>
+                            <select class="form-select" onchange={Callback::from(move |e: Event| {
+                                let i: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                                greeting_mode.set(GreetingMode::parse(&i.value()).unwrap_or_default());
+                            })}>
+                                <option value="first" selected={current_mode == GreetingMode::First}>{"The first message"}</option>
+                                <option value="random" selected={current_mode == GreetingMode::Random}>{"A random greeting"}</option>
+                                <option value="swipes" selected={current_mode == GreetingMode::Swipes}>{"Every greeting, as swipes"}</option>
+                            </select>
+                        </div>
+                    }
+
                     <div class="form-group">
                         <label class="form-label">{"Example Messages"}</label>
-                        <textarea class="form-textarea" rows="5" placeholder={"<START>\nUser: Hello!\nChar: Hi there! How can I help you today?"} oninput={Callback::from(move |e: InputEvent| {
+                        <textarea class="form-textarea" rows="5" placeholder={"<START>\nUser: Hello!\nChar: Hi there! How can I help you today?"} value={(*example_messages).clone()} oninput={Callback::from(move |e: InputEvent| {
                             let i: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
                             example_messages.set(i.value());
                         })} />
                     </div>
 
+                    <div class="form-group">
+                        <label class="form-label">{"Response Length"}</label>
+                        <select class="form-select" onchange={Callback::from(move |e: Event| {
+                            let i: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                            response_length.set(ResponseLength::parse(&i.value()));
+                        })}>
+                            <option value="" selected={current_length.is_none()}>{"Model default"}</option>
+                            <option value="short" selected={current_length == Some(ResponseLength::Short)}>{"Short"}</option>
+                            <option value="medium" selected={current_length == Some(ResponseLength::Medium)}>{"Medium"}</option>
+                            <option value="long" selected={current_length == Some(ResponseLength::Long)}>{"Long"}</option>
+                        </select>
+                    </div>
+
+                    <div class="form-group">
+                        <label class="form-label">{"Reasoning Effort"}</label>
+                        <select class="form-select" onchange={Callback::from(move |e: Event| {
+                            let i: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                            let value = i.value();
+                            reasoning_effort.set((!value.is_empty()).then_some(value));
+                        })}>
+                            <option value="" selected={current_effort.is_empty()}>{"Use global setting"}</option>
+                            <option value="off" selected={current_effort == "off"} title="Don't send the parameter (for models that reject it)">{"Off"}</option>
+                            <option value="none" selected={current_effort == "none"}>{"None"}</option>
+                            <option value="low" selected={current_effort == "low"}>{"Low"}</option>
+                            <option value="medium" selected={current_effort == "medium"}>{"Medium"}</option>
+                            <option value="high" selected={current_effort == "high"}>{"High"}</option>
+                        </select>
+                    </div>
+
+                    if let Some(e) = &*bundle_error {
+                        <div class="form-error">{e}</div>
+                    }
                     <div class="form-actions">
+                        if editing.is_some() {
+                            <button
+                                class="btn btn-secondary bundle-btn"
+                                onclick={on_export}
+                                title="Download this character with all of its chats"
+                            >
+                                {"Export with Chats"}
+                            </button>
+                        } else {
+                            <label class="btn btn-secondary bundle-btn" title="Add a character and its chats from an export">
+                                {"Import with Chats"}
+                                <input type="file" accept=".json,application/json" style="display: none;" onchange={on_import} />
+                            </label>
+                        }
                         <button class="btn btn-secondary" onclick={on_cancel}>{"Cancel"}</button>
-                        <button class="btn btn-primary" onclick={on_save}>{"Create Character"}</button>
+                        <button class="btn btn-primary" onclick={on_save}>{if editing.is_some() { "Save Character" } else { "Create Character" }}</button>
                     </div>
                 </div>
             </div>
         </div>
     }
 }
+
+/// Have the browser save `contents` as a file called `filename`
+fn download(filename: &str, contents: &str) -> Option<()> {
+    let document = web_sys::window()?.document()?;
+    let link = document.create_element("a").ok()?;
+    let href = format!(
+        "data:application/json;charset=utf-8,{}",
+        js_sys::encode_uri_component(contents)
+    );
+    link.set_attribute("href", &href).ok()?;
+    link.set_attribute("download", filename).ok()?;
+    link.dyn_into::<web_sys::HtmlElement>().ok()?.click();
+    Some(())
+}
@@ -1,14 +1,202 @@
 use crate::api;
-use crate::store::{Action, StoreContext, StreamingContext};
+use crate::store::{
+    Action, FailedGeneration, ModalType, StoreContext, StreamingContext, SwipeBatch,
+};
 use futures::StreamExt;
 use gloo_net::http::Request;
+use gloo_storage::{LocalStorage, Storage};
 use shared::models::{
-    ChatMessage, CompletionRequest, ROLE_ASSISTANT, ROLE_TOOL, ROLE_USER, ToolCall,
+    AppSettings, Chat, ChatMessage, CompletionRequest, ERROR_MISSING_API_KEY, FunctionCall,
+    MAX_ALTERNATIVES, PluginManifest, ROLE_ASSISTANT, ROLE_TOOL, ROLE_USER, TokenLogprob, ToolCall,
 };
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::Duration;
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{Element, HtmlTextAreaElement, js_sys};
+use wasm_bindgen_futures::wasm_bindgen::{JsCast, closure::Closure};
+use web_sys::{
+    Element, HtmlTextAreaElement, ScrollBehavior, ScrollIntoViewOptions, ScrollLogicalPosition,
+    js_sys,
+};
 use yew::prelude::*;
 
+thread_local! {
+    /// Messages that have a completion stream running.
+    /// Checked synchronously, so a double click can't start a second stream before re-render.
+    static STREAMING: RefCell<HashSet<uuid::Uuid>> = RefCell::new(HashSet::new());
+
+    /// Chat id -> abort switch for the requests streaming into that chat
+    static STREAM_ABORTS: RefCell<HashMap<uuid::Uuid, web_sys::AbortController>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Mark a message as streaming, returning `false` if it already is
+fn claim_stream(message_id: uuid::Uuid) -> bool {
+    STREAMING.with(|s| s.borrow_mut().insert(message_id))
+}
+
+fn release_stream(message_id: uuid::Uuid) {
+    STREAMING.with(|s| s.borrow_mut().remove(&message_id));
+}
+
+/// Signal that aborts requests streaming into `chat_id` once the chat is left.
+/// A chat whose streams were cancelled gets a fresh signal, so new requests aren't born dead.
+fn abort_signal(chat_id: uuid::Uuid) -> Option<web_sys::AbortSignal> {
+    STREAM_ABORTS.with(|aborts| {
+        let mut aborts = aborts.borrow_mut();
+        if let Some(controller) = aborts.get(&chat_id)
+            && !controller.signal().aborted()
+        {
+            return Some(controller.signal());
+        }
+        let controller = web_sys::AbortController::new().ok()?;
+        let signal = controller.signal();
+        aborts.insert(chat_id, controller);
+        Some(signal)
+    })
+}
+
+fn aborted(signal: &Option<web_sys::AbortSignal>) -> bool {
+    signal.as_ref().is_some_and(|s| s.aborted())
+}
+
+/// Abort every stream that isn't for the chat `keep`
+fn cancel_streams_except(keep: Option<uuid::Uuid>) {
+    STREAM_ABORTS.with(|aborts| {
+        aborts.borrow_mut().retain(|id, controller| {
+            if Some(*id) == keep {
+                return true;
+            }
+            controller.abort();
+            false
+        });
+    });
+}
+
+/// How long a message linked to with `#msg-{id}` stays highlighted
+const HIGHLIGHT_MS: u64 = 2000;
+
+/// Element id of a message bubble, so it can be linked to as `#msg-{id}`
+pub fn message_anchor(id: uuid::Uuid) -> String {
+    format!("msg-{}", id)
+}
+
+/// A message named by the URL fragment, either `#msg-{id}` for a message in the
+/// open chat or `#chat-{chat_id}/msg-{id}` for one that may need loading first
+#[derive(Clone, Copy, PartialEq)]
+struct AnchorTarget {
+    chat_id: Option<uuid::Uuid>,
+    message_id: uuid::Uuid,
+}
+
+fn parse_anchor(hash: &str) -> Option<AnchorTarget> {
+    let hash = hash.strip_prefix('#').unwrap_or(hash);
+    let (chat_id, message) = match hash.split_once('/') {
+        Some((chat, message)) => (Some(chat.strip_prefix("chat-")?.parse().ok()?), message),
+        None => (None, hash),
+    };
+    Some(AnchorTarget {
+        chat_id,
+        message_id: message.strip_prefix("msg-")?.parse().ok()?,
+    })
+}
+
+fn current_anchor() -> Option<AnchorTarget> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    parse_anchor(&hash)
+}
+
+/// Scroll a message into view and flash it, returning `false` if it isn't rendered yet
+fn jump_to_message(message_id: uuid::Uuid) -> bool {
+    let Some(bubble) = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.get_element_by_id(&message_anchor(message_id)))
+    else {
+        return false;
+    };
+
+    let options = ScrollIntoViewOptions::new();
+    options.set_behavior(ScrollBehavior::Smooth);
+    options.set_block(ScrollLogicalPosition::Center);
+    bubble.scroll_into_view_with_scroll_into_view_options(&options);
+
+    let _ = bubble.class_list().add_1("message-highlight");
+    yew::platform::spawn_local(async move {
+        yew::platform::time::sleep(Duration::from_millis(HIGHLIGHT_MS)).await;
+        let _ = bubble.class_list().remove_1("message-highlight");
+    });
+    true
+}
+
+/// Chats up to this long render every message, longer ones only those near the viewport
+const VIRTUALIZE_AFTER: usize = 150;
+/// How far above and below the viewport messages are still rendered
+const OVERSCAN_PX: f64 = 1200.0;
+/// Height assumed for a bubble that hasn't been rendered yet
+const ESTIMATED_BUBBLE_PX: f64 = 120.0;
+
+/// The range of messages that gets rendered, the rest are replaced by spacers
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct RenderWindow {
+    start: usize,
+    end: usize,
+}
+
+/// Measured height of a bubble, or an estimate if it hasn't been rendered
+fn bubble_height(heights: &HashMap<uuid::Uuid, f64>, message: &ChatMessage) -> f64 {
+    if message.role == ROLE_TOOL {
+        return 0.0; // Rendered inside the assistant bubble
+    }
+    heights
+        .get(&message.id)
+        .copied()
+        .unwrap_or(ESTIMATED_BUBBLE_PX)
+}
+
+/// Messages that overlap the viewport at `scroll_top`, plus the overscan on either side
+fn render_window(
+    messages: &[ChatMessage],
+    heights: &HashMap<uuid::Uuid, f64>,
+    (scroll_top, viewport): (f64, f64),
+) -> RenderWindow {
+    let len = messages.len();
+    if len <= VIRTUALIZE_AFTER {
+        return RenderWindow { start: 0, end: len };
+    }
+    let top = scroll_top - OVERSCAN_PX;
+    let bottom = scroll_top + viewport + OVERSCAN_PX;
+    let mut offset = 0.0;
+    let mut start = None;
+    let mut end = len;
+    for (i, message) in messages.iter().enumerate() {
+        if offset > bottom {
+            end = i;
+            break;
+        }
+        offset += bubble_height(heights, message);
+        if start.is_none() && offset >= top {
+            start = Some(i);
+        }
+    }
+    RenderWindow {
+        start: start.unwrap_or(end).min(end),
+        end,
+    }
+}
+
+/// Offset of a message from the top of the list
+fn message_offset(
+    messages: &[ChatMessage],
+    heights: &HashMap<uuid::Uuid, f64>,
+    index: usize,
+) -> f64 {
+    messages[..index]
+        .iter()
+        .map(|m| bubble_height(heights, m))
+        .sum()
+}
+
 /// Props for an individual message bubble component
 #[derive(Properties, PartialEq)]
 pub struct MessageBubbleProps {
@@ -16,6 +204,9 @@ pub struct MessageBubbleProps {
     pub char_name: String,
     pub is_last_assistant: bool,
     pub is_generating: bool,
+    /// Set when the last generation for this message failed
+    #[prop_or_default]
+    pub failure: Option<FailedGeneration>,
 }
 
 /// Individual message bubble with actions
@@ -24,16 +215,34 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
     let store = use_context::<StoreContext>().expect("Store context not found");
     let is_editing = use_state(|| false);
     let edit_content = use_state(|| props.message.content.clone());
+    // The stored record as JSON while editing it raw, and why the last save failed
+    let raw_edit = use_state(|| None::<String>);
+    let raw_error = use_state(|| None::<String>);
 
     let is_user = props.message.role == ROLE_USER;
+    // In group chats each swipe may come from a different character
     let name = if is_user {
         "You".to_string()
     } else {
-        props.char_name.clone()
+        props
+            .message
+            .active_sender()
+            .and_then(|id| store.characters.iter().find(|c| c.id == id))
+            .map(|c| c.name.clone())
+            .unwrap_or_else(|| props.char_name.clone())
     };
 
     // Get the currently displayed content (considering swipes)
     let display_content = props.message.active_content().to_string();
+    // Only while the tokens still spell out what's shown, not after an edit or a swipe
+    let logprobs = store
+        .logprobs
+        .get(&props.message.id)
+        .filter(|_| store.settings.developer_mode && store.settings.show_logprobs)
+        .filter(|tokens| {
+            tokens.iter().map(|t| t.token.as_str()).collect::<String>() == display_content
+        })
+        .cloned();
     let variant_count = props.message.variant_count();
     let active_index = props.message.active_index;
 
@@ -108,6 +317,64 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
         })
     };
 
+    let on_raw_edit_click = {
+        let raw_edit = raw_edit.clone();
+        let raw_error = raw_error.clone();
+        let message = props.message.clone();
+        Callback::from(move |_: MouseEvent| {
+            raw_edit.set(serde_json::to_string_pretty(&message).ok());
+            raw_error.set(None);
+        })
+    };
+
+    let on_raw_edit_change = {
+        let raw_edit = raw_edit.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(textarea) = e.target_dyn_into::<HtmlTextAreaElement>() {
+                raw_edit.set(Some(textarea.value()));
+            }
+        })
+    };
+
+    // Saved only once the server accepts it, since it checks the record
+    let on_raw_edit_save = {
+        let raw_edit = raw_edit.clone();
+        let raw_error = raw_error.clone();
+        let store = store.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(text) = (*raw_edit).clone() else {
+                return;
+            };
+            let message = match serde_json::from_str::<ChatMessage>(&text) {
+                Ok(message) => message,
+                Err(e) => {
+                    raw_error.set(Some(format!("Invalid message JSON: {}", e)));
+                    return;
+                }
+            };
+            let Some(chat_id) = store.active_chat.as_ref().map(|c| c.id) else {
+                return;
+            };
+            let raw_edit = raw_edit.clone();
+            let raw_error = raw_error.clone();
+            let store = store.clone();
+            yew::platform::spawn_local(async move {
+                match api::replace_message(chat_id, &message).await {
+                    Ok(()) => {
+                        store.dispatch(Action::ReplaceMessage(message));
+                        raw_edit.set(None);
+                    }
+                    Err(e) => raw_error.set(Some(e.to_string())),
+                }
+            });
+        })
+    };
+
+    let on_raw_edit_cancel = {
+        let raw_edit = raw_edit.clone();
+        Callback::from(move |_: MouseEvent| raw_edit.set(None))
+    };
+
     // Delete handler
     let on_delete = {
         let store = store.clone();
@@ -142,29 +409,19 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                         {
                             // Regenerate the existing assistant message
                             let next_msg_id = next_msg.id;
-                            store.dispatch(Action::SetStream(Some(
-                                StreamingContext::Regeneration(next_msg_id),
-                            )));
-                            yew::platform::spawn_local(process_completion_stream(
+                            start_regeneration(
                                 store,
-                                CompletionRequest {
-                                    chat_id: chat.id,
-                                    regenerate: true,
-                                    message_id: Some(next_msg_id),
-                                    api_key: settings.api_key,
-                                    api_base: Some(settings.api_base),
-                                    model: settings.model,
-                                    temperature: Some(settings.temperature),
-                                    max_tokens: Some(settings.max_tokens),
-                                    reasoning_effort: settings.reasoning_effort.clone(),
-                                },
+                                completion_request(&settings, chat.id, true, Some(next_msg_id)),
                                 next_msg_id,
-                            ));
+                            );
                             return;
                         }
                     }
 
                     // No assistant message to regenerate, so create a new one
+                    if !claim_stream(message_id) {
+                        return;
+                    }
                     let assistant_msg = ChatMessage::new(ROLE_ASSISTANT, "");
                     let assistant_msg_id = assistant_msg.id;
                     store.dispatch(Action::AppendMessage(assistant_msg));
@@ -173,47 +430,167 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                     ))));
 
                     let chat_id = chat.id;
-                    yew::platform::spawn_local(process_completion_stream(
-                        store,
-                        CompletionRequest {
-                            chat_id,
-                            regenerate: false,
-                            message_id: None,
-                            api_key: settings.api_key,
-                            api_base: Some(settings.api_base),
-                            model: settings.model,
-                            temperature: Some(settings.temperature),
-                            max_tokens: Some(settings.max_tokens),
-                            reasoning_effort: settings.reasoning_effort.clone(),
-                        },
-                        assistant_msg_id,
-                    ));
+                    yew::platform::spawn_local(async move {
+                        process_completion_stream(
+                            store,
+                            completion_request(&settings, chat_id, false, None),
+                            assistant_msg_id,
+                        )
+                        .await;
+                        release_stream(message_id);
+                    });
                 } else {
                     // Regular assistant message regeneration
-                    store.dispatch(Action::SetStream(Some(StreamingContext::Regeneration(
-                        message_id,
-                    ))));
-
-                    yew::platform::spawn_local(process_completion_stream(
+                    start_regeneration(
                         store,
-                        CompletionRequest {
-                            chat_id: chat.id,
-                            regenerate: true,
-                            message_id: Some(message_id),
-                            api_key: settings.api_key,
-                            api_base: Some(settings.api_base),
-                            model: settings.model,
-                            temperature: Some(settings.temperature),
-                            max_tokens: Some(settings.max_tokens),
-                            reasoning_effort: settings.reasoning_effort.clone(),
-                        },
+                        completion_request(&settings, chat.id, true, Some(message_id)),
                         message_id,
-                    ));
+                    );
                 }
             }
         })
     };
 
+    // Regenerate with a one-off instruction. `None` while the instruction box is closed.
+    let instruction = use_state(|| None::<String>);
+
+    let on_instruction_toggle = {
+        let instruction = instruction.clone();
+        Callback::from(move |_: MouseEvent| {
+            instruction.set(match *instruction {
+                Some(_) => None,
+                None => Some(String::new()),
+            });
+        })
+    };
+
+    let on_instruction_input = {
+        let instruction = instruction.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            instruction.set(Some(input.value()));
+        })
+    };
+
+    let submit_instruction = {
+        let store = store.clone();
+        let instruction = instruction.clone();
+        let message_id = props.message.id;
+        Callback::from(move |_: ()| {
+            let Some(text) = (*instruction).clone().filter(|t| !t.trim().is_empty()) else {
+                return;
+            };
+            let Some(chat_id) = store.active_chat.as_ref().map(|c| c.id) else {
+                return;
+            };
+            if store.active_stream.is_some() {
+                return;
+            }
+            let mut payload = completion_request(&store.settings, chat_id, true, Some(message_id));
+            payload.regen_instruction = Some(text);
+            instruction.set(None);
+            start_regeneration(store.clone(), payload, message_id);
+        })
+    };
+
+    let on_instruction_keydown = {
+        let instruction = instruction.clone();
+        let submit_instruction = submit_instruction.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            if e.key() == "Escape" {
+                instruction.set(None);
+            } else if e.key() == "Enter" {
+                e.prevent_default();
+                submit_instruction.emit(());
+            }
+        })
+    };
+
+    // Regenerate this message several times in a row
+    let room_for_swipes = MAX_ALTERNATIVES.saturating_sub(props.message.alternatives.len());
+    let on_generate_swipes = {
+        let store = store.clone();
+        let message_id = props.message.id;
+        Callback::from(move |_: MouseEvent| {
+            let Some(chat_id) = store.active_chat.as_ref().map(|c| c.id) else {
+                return;
+            };
+            let Some(count) = web_sys::window()
+                .and_then(|w| {
+                    w.prompt_with_message_and_default(
+                        &format!("How many swipes? (up to {})", room_for_swipes),
+                        "3",
+                    )
+                    .ok()
+                })
+                .flatten()
+                .and_then(|n| n.trim().parse::<usize>().ok())
+                .map(|n| n.min(room_for_swipes))
+                .filter(|n| *n > 0)
+            else {
+                return;
+            };
+            if store.active_stream.is_some() || !claim_stream(message_id) {
+                return;
+            }
+            yew::platform::spawn_local(generate_swipes(store.clone(), chat_id, message_id, count));
+        })
+    };
+
+    let on_compare = {
+        let store = store.clone();
+        let message_id = props.message.id;
+        Callback::from(move |_: MouseEvent| {
+            store.dispatch(Action::OpenModal(ModalType::CompareSwipes(message_id)))
+        })
+    };
+
+    // Retry a failed generation for this turn
+    let on_retry = {
+        let store = store.clone();
+        let message_id = props.message.id;
+        let regenerate = props.failure.as_ref().is_some_and(|f| f.regenerate);
+        let slot = props.failure.as_ref().and_then(|f| f.slot);
+        Callback::from(move |_: MouseEvent| {
+            let Some(chat_id) = store.active_chat.as_ref().map(|c| c.id) else {
+                return;
+            };
+            if store.active_stream.is_some() || !claim_stream(message_id) {
+                return;
+            }
+            let settings = store.settings.clone();
+
+            if regenerate {
+                // Write over whatever the failed attempt left behind rather than next to it
+                store.dispatch(Action::SetStream(Some(StreamingContext::Regeneration(
+                    message_id, slot,
+                ))));
+                yew::platform::spawn_local(process_completion_stream(
+                    store.clone(),
+                    CompletionRequest {
+                        target_alternative_index: slot,
+                        ..completion_request(&settings, chat_id, true, Some(message_id))
+                    },
+                    message_id,
+                ));
+            } else {
+                // The reply was never saved, so generating again continues from the same turn
+                store.dispatch(Action::UpdateMessageContent {
+                    message_id,
+                    content: String::new(),
+                });
+                store.dispatch(Action::SetStream(Some(StreamingContext::Generation(
+                    message_id,
+                ))));
+                yew::platform::spawn_local(process_completion_stream(
+                    store.clone(),
+                    completion_request(&settings, chat_id, false, None),
+                    message_id,
+                ));
+            }
+        })
+    };
+
     // Swipe handlers
     let on_swipe_left = {
         let store = store.clone();
@@ -245,6 +622,44 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
         })
     };
 
+    let on_reset = {
+        let store = store.clone();
+        let message_id = props.message.id;
+        Callback::from(move |_: MouseEvent| {
+            store.dispatch(Action::ResetMessage(message_id));
+
+            persist(&store, move |chat_id| {
+                api::reset_message(chat_id, message_id)
+            });
+        })
+    };
+
+    let on_keep_toggle = {
+        let store = store.clone();
+        let message_id = props.message.id;
+        let keep = !props.message.keep_in_context;
+        Callback::from(move |_: MouseEvent| {
+            store.dispatch(Action::SetKeepInContext { message_id, keep });
+
+            persist(&store, move |chat_id| {
+                api::set_keep_in_context(chat_id, message_id, keep)
+            });
+        })
+    };
+
+    let on_hidden_toggle = {
+        let store = store.clone();
+        let message_id = props.message.id;
+        let hidden = !props.message.hidden_from_context;
+        Callback::from(move |_: MouseEvent| {
+            store.dispatch(Action::SetHiddenFromContext { message_id, hidden });
+
+            persist(&store, move |chat_id| {
+                api::set_hidden_from_context(chat_id, message_id, hidden)
+            });
+        })
+    };
+
     // Copy handler
     let on_copy = {
         let content = display_content.clone();
@@ -260,8 +675,19 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
         })
     };
 
-    let is_regenerating =
-        store.active_stream == Some(StreamingContext::Regeneration(props.message.id));
+    // The reply is on its way but no text has come in yet
+    let is_awaiting = !store.stream_started
+        && matches!(
+            store.active_stream,
+            Some(StreamingContext::Generation(id) | StreamingContext::Regeneration(id, _))
+                if id == props.message.id
+        );
+
+    let thinking_text = store
+        .settings
+        .thinking_text
+        .trim()
+        .replace("{{char}}", &name);
 
     if props.message.role == ROLE_TOOL {
         return html! {}; // Hidden from main list, rendered inside assistant bubble if needed
@@ -269,7 +695,13 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
 
     html! {
         <div
-            class={classes!("message", if is_user { "message-user" } else { "message-assistant" })}
+            id={message_anchor(props.message.id)}
+            class={classes!(
+                "message",
+                if is_user { "message-user" } else { "message-assistant" },
+                props.failure.is_some().then_some("message-failed"),
+                props.message.hidden_from_context.then_some("message-hidden"),
+            )}
         >
             if !is_user {
                 <div class="avatar bot" title={name.clone()}>
@@ -277,9 +709,37 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                 </div>
             }
             <div class="message-content">
-                <div class="message-role">{&name}</div>
+                <div class="message-role">
+                    {&name}
+                    if props.message.hidden_from_context {
+                        <span class="excluded-badge" title="Not sent to the model">{"excluded"}</span>
+                    }
+                    if props.message.has_created_at() {
+                        <span class="message-time" title={full_time(props.message.created_at.timestamp_millis())}>
+                            {relative_time(props.message.created_at.timestamp_millis())}
+                        </span>
+                    }
+                </div>
 
-                if *is_editing {
+                if let Some(raw) = &*raw_edit {
+                    <div class="message-edit-container">
+                        <textarea
+                            class="message-edit-textarea message-raw-textarea"
+                            value={raw.clone()}
+                            oninput={on_raw_edit_change}
+                            spellcheck="false"
+                        />
+                        if let Some(error) = &*raw_error {
+                            <div class="message-error">
+                                <span class="message-error-text">{error}</span>
+                            </div>
+                        }
+                        <div class="message-edit-actions">
+                            <button class="btn btn-primary btn-sm" onclick={on_raw_edit_save}>{"Save"}</button>
+                            <button class="btn btn-secondary btn-sm" onclick={on_raw_edit_cancel}>{"Cancel"}</button>
+                        </div>
+                    </div>
+                } else if *is_editing {
                     <div class="message-edit-container">
                         <textarea
                             class="message-edit-textarea"
@@ -291,17 +751,47 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                             <button class="btn btn-primary btn-sm" onclick={on_edit_save}>{"Save"}</button>
                             <button class="btn btn-secondary btn-sm" onclick={on_edit_cancel}>{"Cancel"}</button>
                         </div>
-                        <div class="message-edit-hint">{"Ctrl+Enter to save, Escape to cancel"}</div>
+                        <div class="message-edit-hint">
+                            {"Ctrl+Enter to save, Escape to cancel"}
+                            <span class="text-count">{text_count(&edit_content)}</span>
+                        </div>
                     </div>
                 } else {
+                    if let Some(failure) = &props.failure {
+                        <div class="message-error">
+                            <span class="message-error-text">{&failure.error}</span>
+                            <button
+                                class="btn btn-secondary btn-sm retry-btn"
+                                onclick={on_retry}
+                                disabled={props.is_generating}
+                            >
+                                {"Retry"}
+                            </button>
+                        </div>
+                    }
+                    if let Some(reasoning) = props.message.active_reasoning().filter(|r| !r.is_empty()) {
+                        <details class="message-reasoning">
+                            <summary>{"Reasoning"}</summary>
+                            <div class="message-reasoning-body">{reasoning}</div>
+                        </details>
+                    }
                     <div class="message-text">
-                        if is_regenerating && display_content.is_empty() && (props.message.tool_calls.as_ref().map(|tc| tc.is_empty()).unwrap_or(true)) {
-                            <div class="regenerating-dots">{"..."}</div>
+                        if is_awaiting && display_content.is_empty() && (props.message.tool_calls.as_ref().map(|tc| tc.is_empty()).unwrap_or(true)) {
+                            <div class="thinking">
+                                <span class="thinking-dots"><span></span><span></span><span></span></span>
+                                if !thinking_text.is_empty() {
+                                    <span class="thinking-text">{thinking_text}</span>
+                                }
+                            </div>
                         } else {
-                            <super::markdown::Markdown content={display_content} />
+                            <super::markdown::Markdown content={display_content.clone()} />
                         }
                     </div>
 
+                    if let Some(tokens) = logprobs {
+                        <super::token_heatmap::TokenHeatmap tokens={tokens} />
+                    }
+
                     // Tool calls feedback
                     if let Some(tool_calls) = &props.message.tool_calls {
                         <div class="tool-calls-container">
@@ -318,17 +808,29 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                                                 <path d="M22.7 19l-9.1-9.1c.9-2.3.4-5-1.5-6.9-2-2-5-2.4-7.4-1.3L9 6 6 9 1.6 4.7C.5 7.1.9 10.1 2.9 12.1c1.9 1.9 4.6 2.4 6.9 1.5l9.1 9.1c.4.4 1 .4 1.4 0l2.3-2.3c.5-.4.5-1.1.1-1.4z"/>
                                             </svg>
                                             <span>{"Tool: "}{&tc.function.name}</span>
-                                            if result.is_none() {
+                                            if result.is_none() && tc.function.arguments.is_empty() {
+                                                <span class="tool-status working">{"Calling…"}</span>
+                                            } else if result.is_none() {
                                                 <span class="tool-status working">{"Working..."}</span>
                                             } else {
                                                 <span class="tool-status done">{"Done"}</span>
                                             }
                                         </div>
                                         if let Some(res) = result {
-                                            <div class="tool-result-preview">
-                                                {res.content.chars().take(200).collect::<String>()}
-                                                if res.content.len() > 200 { {"..."} }
-                                            </div>
+                                            if let Some(fields) = tool_result_fields(&store.plugins, &tc.function.name, &res.content) {
+                                                <dl class="tool-result-fields">
+                                                    {for fields.into_iter().map(|(label, value)| html! {
+                                                        <>
+                                                            <dt>{label}</dt>
+                                                            <dd>{value}</dd>
+                                                        </>
+                                                    })}
+                                                </dl>
+                                            } else {
+                                                <div class="tool-result-preview">
+                                                    {shared::text::truncate(&res.content, 200)}
+                                                </div>
+                                            }
                                         }
                                     </div>
                                 }
@@ -338,7 +840,7 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                 }
 
                 // Swipe navigation (if alternatives exist)
-                if variant_count > 1 && !*is_editing {
+                if variant_count > 1 && !*is_editing && raw_edit.is_none() {
                     <div class="swipe-nav">
                         <button
                             class="swipe-btn"
@@ -357,22 +859,77 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                         >
                             {"▶"}
                         </button>
+                        if active_index != 0 {
+                            <button
+                                class="swipe-btn swipe-reset-btn"
+                                onclick={on_reset}
+                                title="Reset to original"
+                            >
+                                {"⟲"}
+                            </button>
+                        }
+                    </div>
+                }
+
+                if let Some(text) = &*instruction && !props.is_generating {
+                    <div class="regen-instruction">
+                        <input
+                            type="text"
+                            class="form-input"
+                            value={text.clone()}
+                            oninput={on_instruction_input}
+                            onkeydown={on_instruction_keydown}
+                            placeholder="Make it shorter, more dramatic…"
+                            title="Used for this regeneration only and not saved. Enter to regenerate, Escape to cancel."
+                        />
+                        <button
+                            class="btn btn-primary btn-sm"
+                            onclick={submit_instruction.reform(|_| ())}
+                            disabled={text.trim().is_empty()}
+                        >
+                            {"Regenerate"}
+                        </button>
                     </div>
                 }
 
                 // Action toolbar (visibility controlled by CSS)
-                if !*is_editing && !props.is_generating {
+                if !*is_editing && raw_edit.is_none() && !props.is_generating {
                     <div class="message-actions">
                         <button class="message-action-btn" onclick={on_copy} title="Copy">
                             <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
                                 <path d="M16 1H4c-1.1 0-2 .9-2 2v14h2V3h12V1zm3 4H8c-1.1 0-2 .9-2 2v14c0 1.1.9 2 2 2h11c1.1 0 2-.9 2-2V7c0-1.1-.9-2-2-2zm0 16H8V7h11v14z"/>
                             </svg>
                         </button>
+                        <button
+                            class={classes!("message-action-btn", props.message.keep_in_context.then_some("active"))}
+                            onclick={on_keep_toggle}
+                            title={if props.message.keep_in_context { "Stop always sending to the model" } else { "Always send to the model, even past the history limit" }}
+                        >
+                            <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
+                                <path d="M16 9V4h1c.55 0 1-.45 1-1s-.45-1-1-1H7c-.55 0-1 .45-1 1s.45 1 1 1h1v5c0 1.66-1.34 3-3 3v2h5.97v7l1 1 1-1v-7H19v-2c-1.66 0-3-1.34-3-3z"/>
+                            </svg>
+                        </button>
+                        <button
+                            class={classes!("message-action-btn", props.message.hidden_from_context.then_some("active"))}
+                            onclick={on_hidden_toggle}
+                            title={if props.message.hidden_from_context { "Send to the model again" } else { "Keep in the chat but don't send to the model" }}
+                        >
+                            <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
+                                <path d="M12 7c2.76 0 5 2.24 5 5 0 .65-.13 1.26-.36 1.83l2.92 2.92c1.51-1.26 2.7-2.89 3.43-4.75-1.73-4.39-6-7.5-11-7.5-1.4 0-2.74.25-3.98.7l2.16 2.16C10.74 7.13 11.35 7 12 7zM2 4.27l2.28 2.28.46.46C3.08 8.3 1.78 10.02 1 12c1.73 4.39 6 7.5 11 7.5 1.55 0 3.03-.3 4.38-.84l.42.42L19.73 22 21 20.73 3.27 3 2 4.27zM7.53 9.8l1.55 1.55c-.05.21-.08.43-.08.65 0 1.66 1.34 3 3 3 .22 0 .44-.03.65-.08l1.55 1.55c-.67.33-1.41.53-2.2.53-2.76 0-5-2.24-5-5 0-.79.2-1.53.53-2.2zm4.31-.78l3.15 3.15.02-.16c0-1.66-1.34-3-3-3l-.17.01z"/>
+                            </svg>
+                        </button>
                         <button class="message-action-btn" onclick={on_edit_click} title="Edit">
                             <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
                                 <path d="M3 17.25V21h3.75L17.81 9.94l-3.75-3.75L3 17.25zM20.71 7.04c.39-.39.39-1.02 0-1.41l-2.34-2.34c-.39-.39-1.02-.39-1.41 0l-1.83 1.83 3.75 3.75 1.83-1.83z"/>
                             </svg>
                         </button>
+                        if store.settings.developer_mode {
+                            <button class="message-action-btn" onclick={on_raw_edit_click} title="Edit the stored record as JSON">
+                                <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
+                                    <path d="M9.4 16.6 4.8 12l4.6-4.6L8 6l-6 6 6 6 1.4-1.4zm5.2 0 4.6-4.6-4.6-4.6L16 6l6 6-6 6-1.4-1.4z"/>
+                                </svg>
+                            </button>
+                        }
                         <button
                             class="message-action-btn"
                             onclick={on_regenerate}
@@ -383,6 +940,35 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
                                 <path d="M17.65 6.35C16.2 4.9 14.21 4 12 4c-4.42 0-7.99 3.58-7.99 8s3.57 8 7.99 8c3.73 0 6.84-2.55 7.73-6h-2.08c-.82 2.33-3.04 4-5.65 4-3.31 0-6-2.69-6-6s2.69-6 6-6c1.66 0 3.14.69 4.22 1.78L13 11h7V4l-2.35 2.35z"/>
                             </svg>
                         </button>
+                        if !is_user {
+                            <button
+                                class={classes!("message-action-btn", instruction.is_some().then_some("active"))}
+                                onclick={on_instruction_toggle}
+                                title="Regenerate with an instruction"
+                                disabled={props.is_generating}
+                            >
+                                <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
+                                    <path d="M20 2H4c-1.1 0-2 .9-2 2v18l4-4h14c1.1 0 2-.9 2-2V4c0-1.1-.9-2-2-2zm-2 12H6v-2h12v2zm0-3H6V9h12v2zm0-3H6V6h12v2z"/>
+                                </svg>
+                            </button>
+                            <button
+                                class="message-action-btn"
+                                onclick={on_generate_swipes}
+                                title="Generate several swipes"
+                                disabled={props.is_generating || room_for_swipes == 0}
+                            >
+                                <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
+                                    <path d="M4 6H2v14c0 1.1.9 2 2 2h14v-2H4V6zm16-4H8c-1.1 0-2 .9-2 2v12c0 1.1.9 2 2 2h12c1.1 0 2-.9 2-2V4c0-1.1-.9-2-2-2zm-1 9h-4v4h-2v-4H9V9h4V5h2v4h4v2z"/>
+                                </svg>
+                            </button>
+                            if variant_count > 1 {
+                                <button class="message-action-btn" onclick={on_compare} title="Compare swipes">
+                                    <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
+                                        <path d="M10 18h5V5h-5v13zm-6 0h5V5H4v13zM16 5v13h5V5h-5z"/>
+                                    </svg>
+                                </button>
+                            }
+                        }
                         <button class="message-action-btn message-action-btn-danger" onclick={on_delete} title="Delete">
                             <svg viewBox="0 0 24 24" width="16" height="16" fill="currentColor">
                                 <path d="M6 19c0 1.1.9 2 2 2h8c1.1 0 2-.9 2-2V7H6v12zM19 4h-3.5l-1-1h-5l-1 1H5v2h14V4z"/>
@@ -395,6 +981,88 @@ pub fn message_bubble(props: &MessageBubbleProps) -> Html {
     }
 }
 
+/// "just now", "5m ago", "3h ago" or "2d ago", for a time `millis` since the epoch.
+/// Anything older than a week is shown as a date.
+fn relative_time(millis: i64) -> String {
+    let seconds = ((js_sys::Date::now() as i64 - millis) / 1000).max(0);
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3_600 => format!("{}m ago", seconds / 60),
+        3_600..86_400 => format!("{}h ago", seconds / 3_600),
+        86_400..604_800 => format!("{}d ago", seconds / 86_400),
+        _ => String::from(
+            js_sys::Date::new(&(millis as f64).into()).to_locale_date_string(
+                "default",
+                &wasm_bindgen_futures::wasm_bindgen::JsValue::UNDEFINED,
+            ),
+        ),
+    }
+}
+
+/// The full local date and time for a time `millis` since the epoch
+fn full_time(millis: i64) -> String {
+    String::from(js_sys::Date::new(&(millis as f64).into()).to_locale_string(
+        "default",
+        &wasm_bindgen_futures::wasm_bindgen::JsValue::UNDEFINED,
+    ))
+}
+
+/// "12 words · 64 chars" label for a text field
+fn text_count(text: &str) -> String {
+    let words = text.split_whitespace().count();
+    let chars = text.chars().count();
+    format!(
+        "{} {} · {} {}",
+        words,
+        if words == 1 { "word" } else { "words" },
+        chars,
+        if chars == 1 { "char" } else { "chars" }
+    )
+}
+
+/// Label and value of each field of a tool result, in the order the tool's declared
+/// `returns` schema lists them. `None` when the tool has no object schema or the result
+/// isn't a JSON object, and the result is shown as plain text instead.
+fn tool_result_fields(
+    plugins: &[PluginManifest],
+    tool_name: &str,
+    result: &str,
+) -> Option<Vec<(String, String)>> {
+    let schema = plugins
+        .iter()
+        .flat_map(|p| &p.tools)
+        .find(|t| t.name == tool_name)?
+        .returns
+        .as_ref()?;
+    let properties = schema.get("properties")?.as_object()?;
+    let value: serde_json::Value = serde_json::from_str(result).ok()?;
+    let object = value.as_object()?;
+    let fields: Vec<_> = properties
+        .iter()
+        .filter_map(|(key, property)| {
+            let label = property
+                .get("title")
+                .and_then(|t| t.as_str())
+                .unwrap_or(key)
+                .to_string();
+            let value = match object.get(key)? {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Array(items) => items
+                    .iter()
+                    .map(|item| match item {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                other => other.to_string(),
+            };
+            Some((label, value))
+        })
+        .collect();
+    (!fields.is_empty()).then_some(fields)
+}
+
 /// Helper to persist a change to the backend
 fn persist<F, Fut>(store: &StoreContext, f: F)
 where
@@ -410,11 +1078,57 @@ where
     }
 }
 
+/// How long typing has to pause before the compose box is saved as a draft
+const DRAFT_SAVE_DELAY_MS: u64 = 500;
+
+/// Local storage key for a chat's unsent message
+fn draft_key(chat_id: uuid::Uuid) -> String {
+    format!("renoma.draft.{}", chat_id)
+}
+
+fn save_draft(chat_id: uuid::Uuid, text: &str) {
+    if text.trim().is_empty() {
+        LocalStorage::delete(draft_key(chat_id));
+    } else if let Err(e) = LocalStorage::set(draft_key(chat_id), text) {
+        tracing::warn!("Failed to save draft: {:?}", e);
+    }
+}
+
 #[function_component(ChatStage)]
 pub fn chat_stage() -> Html {
     let store = use_context::<StoreContext>().expect("Store context not found");
     let input_ref = use_node_ref();
     let container_ref = use_node_ref();
+    // Long chats only mount the bubbles near the viewport. Heights of bubbles that have been
+    // rendered are remembered so the spacers standing in for the others stay accurate.
+    let bubble_heights = use_mut_ref(HashMap::<uuid::Uuid, f64>::new);
+    let scroll_position = use_mut_ref(|| (0.0f64, 0.0f64));
+    let window = use_state(RenderWindow::default);
+    // Set by auto-scroll, keeps the list at the bottom while new bubbles replace their estimates
+    let follow_bottom = use_mut_ref(|| false);
+    let compose_text = use_state(String::new);
+    // The chat's scenario while it's being edited in the header
+    let scenario_draft = use_state(|| None::<String>);
+    // Bumped on every edit, so only the last of a burst of keystrokes saves the draft
+    let draft_edits = use_mut_ref(|| 0u64);
+
+    // Restore the chat's draft when it's opened, replacing the previous chat's text
+    {
+        let input_ref = input_ref.clone();
+        let compose_text = compose_text.clone();
+        let chat_id = store.active_chat.as_ref().map(|c| c.id);
+        let bubble_heights = bubble_heights.clone();
+        use_effect_with(chat_id, move |chat_id| {
+            bubble_heights.borrow_mut().clear();
+            let draft = chat_id
+                .and_then(|id| LocalStorage::get::<String>(draft_key(id)).ok())
+                .unwrap_or_default();
+            if let Some(input) = input_ref.cast::<HtmlTextAreaElement>() {
+                input.set_value(&draft);
+            }
+            compose_text.set(draft);
+        });
+    }
 
     // Auto-scroll on message change
     {
@@ -424,35 +1138,196 @@ pub fn chat_stage() -> Html {
             .as_ref()
             .map(|c| c.messages.len())
             .unwrap_or(0);
+        let follow_bottom = follow_bottom.clone();
+        let store = store.clone();
+        let bubble_heights = bubble_heights.clone();
+        let scroll_position = scroll_position.clone();
+        let window = window.clone();
         use_effect_with(messages_len, move |_| {
             if let Some(div) = container_ref.cast::<Element>() {
                 div.set_scroll_top(div.scroll_height());
+                *follow_bottom.borrow_mut() = true;
+                let position = (div.scroll_top() as f64, div.client_height() as f64);
+                *scroll_position.borrow_mut() = position;
+                if let Some(chat) = &store.active_chat {
+                    let next = render_window(&chat.messages, &bubble_heights.borrow(), position);
+                    if *window != next {
+                        window.set(next);
+                    }
+                }
             }
         });
     }
 
-    let on_send = {
+    // Leaving a chat cancels generations still streaming into it. They're lost, the server
+    // saves nothing for a reply that never finished.
+    {
         let store = store.clone();
-        let input_ref = input_ref.clone();
-
-        Callback::from(move |_| {
-            if let Some(input) = input_ref.cast::<HtmlTextAreaElement>() {
-                let text = input.value().trim().to_string();
+        let chat_id = store.active_chat.as_ref().map(|c| c.id);
+        let streaming = store.active_stream.is_some();
+        use_effect_with(chat_id, move |chat_id| {
+            cancel_streams_except(*chat_id);
+            if streaming {
+                store.dispatch(Action::SetSwipeBatch(None));
+                store.dispatch(Action::SetStream(None));
+            }
+        });
+    }
 
-                if text.is_empty() || store.active_stream.is_some() || store.active_chat.is_none() {
+    // Track the scroll position, only re-rendering when a different range of messages is needed
+    let on_scroll = {
+        let store = store.clone();
+        let bubble_heights = bubble_heights.clone();
+        let scroll_position = scroll_position.clone();
+        let window = window.clone();
+        Callback::from(move |e: Event| {
+            let list: Element = e.target_unchecked_into();
+            let position = (list.scroll_top() as f64, list.client_height() as f64);
+            *scroll_position.borrow_mut() = position;
+            if let Some(chat) = &store.active_chat {
+                let next = render_window(&chat.messages, &bubble_heights.borrow(), position);
+                if *window != next {
+                    window.set(next);
+                }
+            }
+        })
+    };
+
+    // Measure the bubbles that were just rendered, replacing their estimated heights
+    {
+        let store = store.clone();
+        let container_ref = container_ref.clone();
+        let bubble_heights = bubble_heights.clone();
+        let scroll_position = scroll_position.clone();
+        let follow_bottom = follow_bottom.clone();
+        let window = window.clone();
+        use_effect(move || {
+            let Some(chat) = &store.active_chat else {
+                return;
+            };
+            let Some(document) = web_sys::window().and_then(|w| w.document()) else {
+                return;
+            };
+            let shown = render_window(
+                &chat.messages,
+                &bubble_heights.borrow(),
+                *scroll_position.borrow(),
+            );
+            let mut heights = bubble_heights.borrow_mut();
+            let mut changed = false;
+            for message in &chat.messages[shown.start..shown.end] {
+                if let Some(bubble) = document.get_element_by_id(&message_anchor(message.id)) {
+                    let height = bubble.client_height() as f64;
+                    changed |= heights.insert(message.id, height) != Some(height);
+                }
+            }
+            if changed
+                && *follow_bottom.borrow()
+                && let Some(list) = container_ref.cast::<Element>()
+            {
+                list.set_scroll_top(list.scroll_height());
+                *scroll_position.borrow_mut() =
+                    (list.scroll_top() as f64, list.client_height() as f64);
+            }
+            let next = render_window(&chat.messages, &heights, *scroll_position.borrow());
+            if *window != next {
+                window.set(next);
+            } else {
+                // Settled, the right bubbles are mounted
+                *follow_bottom.borrow_mut() = false;
+            }
+        });
+    }
+
+    // Follow `#msg-{id}` links, both the one the page was opened with and later hash changes
+    let anchor = use_state(current_anchor);
+    {
+        let anchor = anchor.clone();
+        use_effect_with((), move |_| {
+            let window = web_sys::window();
+            let on_hash_change = Closure::<dyn Fn()>::new(move || anchor.set(current_anchor()));
+            if let Some(window) = &window {
+                window.set_onhashchange(Some(on_hash_change.as_ref().unchecked_ref()));
+            }
+            move || {
+                if let Some(window) = window {
+                    window.set_onhashchange(None);
+                }
+                drop(on_hash_change);
+            }
+        });
+    }
+
+    // Declared after the auto-scroll, so a pending jump wins over scrolling to the bottom
+    {
+        let store = store.clone();
+        let anchor = anchor.clone();
+        let container_ref = container_ref.clone();
+        let bubble_heights = bubble_heights.clone();
+        let loaded = store.active_chat.as_ref().map(|c| (c.id, c.messages.len()));
+        use_effect_with((*anchor, loaded, *window), move |(target, loaded, _)| {
+            let Some(target) = *target else {
+                return;
+            };
+            let active_chat = loaded.map(|(id, _)| id);
+            if let Some(chat_id) = target.chat_id.filter(|id| Some(*id) != active_chat) {
+                // The message is in a chat that isn't open, load it and retry once it renders
+                yew::platform::spawn_local(async move {
+                    match api::get_chat(chat_id).await {
+                        Ok(chat) => store.dispatch(Action::SetActiveChat(chat)),
+                        Err(e) => tracing::warn!("Failed to load linked chat: {:?}", e),
+                    }
+                });
+                return;
+            }
+            if jump_to_message(target.message_id) {
+                anchor.set(None);
+                return;
+            }
+            // A message outside the rendered window gets scrolled to, which renders it and
+            // retries. Otherwise keep waiting until the message shows up.
+            if let Some(chat) = &store.active_chat
+                && let Some(index) = chat.messages.iter().position(|m| m.id == target.message_id)
+                && let Some(list) = container_ref.cast::<Element>()
+            {
+                let offset = message_offset(&chat.messages, &bubble_heights.borrow(), index);
+                list.set_scroll_top(offset as i32);
+            }
+        });
+    }
+
+    let on_send = {
+        let store = store.clone();
+        let input_ref = input_ref.clone();
+        let compose_text = compose_text.clone();
+        let draft_edits = draft_edits.clone();
+
+        Callback::from(move |_| {
+            if let Some(input) = input_ref.cast::<HtmlTextAreaElement>() {
+                let text = input.value().trim().to_string();
+
+                if text.is_empty() || store.active_stream.is_some() || store.active_chat.is_none() {
                     return;
                 }
 
                 input.set_value("");
+                compose_text.set(String::new());
 
                 let chat_id = store.active_chat.as_ref().unwrap().id;
+                *draft_edits.borrow_mut() += 1;
+                LocalStorage::delete(draft_key(chat_id));
                 let settings = store.settings.clone();
 
-                // 1. Update UI with a user message
-                store.dispatch(Action::AppendMessage(ChatMessage::new(
-                    ROLE_USER,
-                    text.clone(),
-                )));
+                // Failed new replies were never saved, drop them before moving on
+                for (id, failure) in &store.failed_generations {
+                    if !failure.regenerate {
+                        store.dispatch(Action::DeleteMessage(*id));
+                    }
+                }
+
+                // 1. Update UI with a user message, saved below under the same id
+                let user_msg = ChatMessage::new(ROLE_USER, text);
+                store.dispatch(Action::AppendMessage(user_msg.clone()));
 
                 // 2. Add a placeholder assistant message
                 let assistant_msg = ChatMessage::new(ROLE_ASSISTANT, "");
@@ -466,7 +1341,7 @@ pub fn chat_stage() -> Html {
                 let store = store.clone();
                 yew::platform::spawn_local(async move {
                     // Save user message to backend
-                    if let Err(e) = api::send_message(chat_id, text).await {
+                    if let Err(e) = api::send_message(chat_id, &user_msg).await {
                         tracing::error!("Failed to send message: {:?}", e);
                         store.dispatch(Action::SetStream(None));
                         return;
@@ -475,17 +1350,7 @@ pub fn chat_stage() -> Html {
                     // Start Stream
                     process_completion_stream(
                         store,
-                        CompletionRequest {
-                            chat_id,
-                            regenerate: false,
-                            message_id: None,
-                            api_key: settings.api_key,
-                            api_base: Some(settings.api_base),
-                            model: settings.model,
-                            temperature: Some(settings.temperature),
-                            max_tokens: Some(settings.max_tokens),
-                            reasoning_effort: settings.reasoning_effort.clone(),
-                        },
+                        completion_request(&settings, chat_id, false, None),
                         assistant_msg_id,
                     )
                     .await;
@@ -494,6 +1359,85 @@ pub fn chat_stage() -> Html {
         })
     };
 
+    let on_impersonate = {
+        let store = store.clone();
+        let input_ref = input_ref.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            let Some(input) = input_ref.cast::<HtmlTextAreaElement>() else {
+                return;
+            };
+            let Some(chat_id) = store.active_chat.as_ref().map(|c| c.id) else {
+                return;
+            };
+            if store.active_stream.is_some() {
+                return;
+            }
+
+            input.set_value("");
+            store.dispatch(Action::SetStream(Some(StreamingContext::Impersonation)));
+
+            let mut payload = completion_request(&store.settings, chat_id, false, None);
+            payload.impersonate = true;
+            yew::platform::spawn_local(process_impersonation_stream(store.clone(), payload, input));
+        })
+    };
+
+    let on_stop = {
+        let store = store.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(chat_id) = store.active_chat.as_ref().map(|c| c.id) else {
+                return;
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(e) = api::cancel_generation(chat_id).await {
+                    tracing::error!("Failed to stop generation: {:?}", e);
+                }
+            });
+        })
+    };
+    // Impersonation isn't tracked by the server, so only replies can be stopped
+    let stoppable = matches!(
+        store.active_stream,
+        Some(StreamingContext::Generation(_) | StreamingContext::Regeneration(..))
+    );
+
+    let active_preset = store.settings.active_preset().map(|p| &p.name);
+    let on_preset_change = {
+        let store = store.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            store.dispatch(Action::ApplyPreset(select.value()));
+        })
+    };
+
+    let on_compose_input = {
+        let compose_text = compose_text.clone();
+        let chat_id = store.active_chat.as_ref().map(|c| c.id);
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            let text = input.value();
+            compose_text.set(text.clone());
+
+            let Some(chat_id) = chat_id else {
+                return;
+            };
+            let edit = {
+                let mut edits = draft_edits.borrow_mut();
+                *edits += 1;
+                *edits
+            };
+            let draft_edits = draft_edits.clone();
+            yew::platform::spawn_local(async move {
+                yew::platform::time::sleep(std::time::Duration::from_millis(DRAFT_SAVE_DELAY_MS))
+                    .await;
+                if *draft_edits.borrow() == edit {
+                    save_draft(chat_id, &text);
+                }
+            });
+        })
+    };
+
     let on_keydown = {
         let on_send = on_send.clone();
         Callback::from(move |e: KeyboardEvent| {
@@ -511,6 +1455,69 @@ pub fn chat_stage() -> Html {
         .map(|c| c.name.clone())
         .unwrap_or("AI".to_string());
 
+    // A chat switch leaves the scenario editor
+    {
+        let scenario_draft = scenario_draft.clone();
+        let chat_id = store.active_chat.as_ref().map(|c| c.id);
+        use_effect_with(chat_id, move |_| scenario_draft.set(None));
+    }
+
+    let on_edit_scenario = {
+        let store = store.clone();
+        let scenario_draft = scenario_draft.clone();
+        Callback::from(move |_: MouseEvent| {
+            if scenario_draft.is_some() {
+                scenario_draft.set(None);
+                return;
+            }
+            let Some(chat) = &store.active_chat else {
+                return;
+            };
+            // Start from the character's scenario so it can be tweaked rather than retyped
+            let current = chat.scenario_override.clone().unwrap_or_else(|| {
+                store
+                    .characters
+                    .iter()
+                    .find(|c| c.id == chat.character_id)
+                    .map(|c| c.scenario.clone())
+                    .unwrap_or_default()
+            });
+            scenario_draft.set(Some(current));
+        })
+    };
+
+    let on_scenario_input = {
+        let scenario_draft = scenario_draft.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            scenario_draft.set(Some(input.value()));
+        })
+    };
+
+    // Saving None goes back to the character's scenario
+    let save_scenario = {
+        let store = store.clone();
+        let scenario_draft = scenario_draft.clone();
+        Callback::from(move |scenario: Option<String>| {
+            let Some(mut chat) = store.active_chat.clone() else {
+                return;
+            };
+            let scenario = scenario.filter(|s| !s.trim().is_empty());
+            let store = store.clone();
+            let scenario_draft = scenario_draft.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::set_scenario(chat.id, scenario.clone()).await {
+                    Ok(()) => {
+                        chat.scenario_override = scenario;
+                        store.dispatch(Action::SetActiveChat(chat));
+                        scenario_draft.set(None);
+                    }
+                    Err(e) => tracing::error!("Failed to set scenario: {:?}", e),
+                }
+            });
+        })
+    };
+
     // Find the last assistant message index for regenerate button visibility
     let last_assistant_idx = store.active_chat.as_ref().and_then(|chat| {
         chat.messages
@@ -521,10 +1528,25 @@ pub fn chat_stage() -> Html {
             .map(|(i, _)| i)
     });
 
+    // Which messages to mount, and the space taken by those above and below them
+    let (shown, spacers) = match &store.active_chat {
+        Some(chat) => {
+            let heights = bubble_heights.borrow();
+            let shown = render_window(&chat.messages, &heights, *scroll_position.borrow());
+            let above = message_offset(&chat.messages, &heights, shown.start);
+            let below = chat.messages[shown.end..]
+                .iter()
+                .map(|m| bubble_height(&heights, m))
+                .sum::<f64>();
+            (shown, (above, below))
+        }
+        None => (RenderWindow::default(), (0.0, 0.0)),
+    };
+
     html! {
         <div class="main-stage">
             // Header
-            if let Some(_) = store.active_chat {
+            if let Some(chat) = &store.active_chat {
                 <div class="chat-header">
                     <button
                         class="icon-btn back-btn"
@@ -538,10 +1560,84 @@ pub fn chat_stage() -> Html {
                         </svg>
                     </button>
                     <div class="chat-title">{&char_name}</div>
+                    <button
+                        class={classes!(
+                            "btn", "btn-secondary", "btn-sm", "scenario-btn",
+                            chat.scenario_override.is_some().then_some("scenario-active"),
+                        )}
+                        title={if chat.scenario_override.is_some() {
+                            "This chat has its own scenario"
+                        } else {
+                            "Give this chat its own scenario"
+                        }}
+                        onclick={on_edit_scenario}
+                    >
+                        {"Scenario"}
+                    </button>
+                    <button
+                        class="icon-btn edit-character-btn"
+                        title="Edit character"
+                        onclick={
+                            let store = store.clone();
+                            let character_id = chat.character_id;
+                            move |_| store.dispatch(Action::OpenModal(ModalType::EditCharacter(character_id)))
+                        }
+                    >
+                        <svg viewBox="0 0 24 24" width="20" height="20" fill="currentColor">
+                            <path d="M3 17.25V21h3.75L17.81 9.94l-3.75-3.75L3 17.25zM20.71 7.04a1 1 0 0 0 0-1.41l-2.34-2.34a1 1 0 0 0-1.41 0l-1.83 1.83 3.75 3.75 1.83-1.83z"></path>
+                        </svg>
+                    </button>
                 </div>
+                if let Some(draft) = &*scenario_draft {
+                    <div class="scenario-editor">
+                        <textarea
+                            class="message-edit-textarea"
+                            placeholder="Scenario for this chat only"
+                            value={draft.clone()}
+                            oninput={on_scenario_input}
+                        />
+                        <div class="message-edit-actions">
+                            <button
+                                class="btn btn-primary btn-sm"
+                                onclick={
+                                    let save_scenario = save_scenario.clone();
+                                    let draft = draft.clone();
+                                    move |_| save_scenario.emit(Some(draft.clone()))
+                                }
+                            >
+                                {"Save"}
+                            </button>
+                            if chat.scenario_override.is_some() {
+                                <button
+                                    class="btn btn-secondary btn-sm"
+                                    title="Use the character's scenario again"
+                                    onclick={
+                                        let save_scenario = save_scenario.clone();
+                                        move |_| save_scenario.emit(None)
+                                    }
+                                >
+                                    {"Reset"}
+                                </button>
+                            }
+                            <button
+                                class="btn btn-secondary btn-sm"
+                                onclick={
+                                    let scenario_draft = scenario_draft.clone();
+                                    move |_| scenario_draft.set(None)
+                                }
+                            >
+                                {"Cancel"}
+                            </button>
+                        </div>
+                    </div>
+                }
             }
 
-            <div class={classes!("chat-message-list")} ref={container_ref}>
+            <div
+                class={classes!("chat-message-list", format!("density-{}", store.settings.density.as_str()))}
+                ref={container_ref}
+                onscroll={on_scroll}
+            >
                 if store.active_chat.is_none() {
                     <div class="chat-placeholder">
                         if store.active_character_id.is_some() {
@@ -551,7 +1647,10 @@ pub fn chat_stage() -> Html {
                         }
                     </div>
                 } else {
-                    { for store.active_chat.as_ref().unwrap().messages.iter().enumerate().map(|(idx, msg)| {
+                    if spacers.0 > 0.0 {
+                        <div class="message-spacer" style={format!("height: {}px", spacers.0)} />
+                    }
+                    { for store.active_chat.as_ref().unwrap().messages.iter().enumerate().skip(shown.start).take(shown.end - shown.start).map(|(idx, msg)| {
                         let is_last_assistant = Some(idx) == last_assistant_idx;
                         html! {
                             <MessageBubble
@@ -559,67 +1658,438 @@ pub fn chat_stage() -> Html {
                                 char_name={char_name.clone()}
                                 is_last_assistant={is_last_assistant}
                                 is_generating={store.active_stream.is_some()}
+                                failure={store.failed_generations.get(&msg.id).cloned()}
                             />
                         }
                     })}
+                    if spacers.1 > 0.0 {
+                        <div class="message-spacer" style={format!("height: {}px", spacers.1)} />
+                    }
 
-                    if store.active_stream.is_some() {
+                    if store.active_stream.is_some() && store.active_stream != Some(StreamingContext::Impersonation) {
                         <div class="typing-indicator">
                             <span></span>
                             <span></span>
                             <span></span>
+                            if let Some(batch) = &store.swipe_batch {
+                                <small class="swipe-progress">{format!("Swipe {} of {}", batch.done + 1, batch.total)}</small>
+                            }
+                            if store.settings.developer_mode && let Some(rate) = store.stream_rate {
+                                <small class="stream-rate">{format!("{:.1} tok/s", rate)}</small>
+                            }
                         </div>
                     }
                 }
             </div>
 
             <div class="input-area">
+                if !store.settings.presets.is_empty() {
+                    <div class="preset-switcher">
+                        <select onchange={on_preset_change} title="Model preset" disabled={store.active_stream.is_some()}>
+                            if active_preset.is_none() {
+                                <option selected=true disabled=true>{"Custom"}</option>
+                            }
+                            {for store.settings.presets.iter().map(|p| html! {
+                                <option value={p.name.clone()} selected={active_preset == Some(&p.name)}>{&p.name}</option>
+                            })}
+                        </select>
+                    </div>
+                }
                 <div class="input-box">
                     <textarea
                         class="chat-input"
                         ref={input_ref}
                         placeholder={"Type a message... (Ctrl+Enter to send)"}
                         onkeydown={on_keydown}
+                        oninput={on_compose_input}
                     />
-                    <button class="send-btn" onclick={move |_| on_send.emit(())} disabled={store.active_stream.is_some()}>
-                         <svg viewBox="0 0 24 24" width="20" height="20" fill="currentColor"><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path></svg>
+                    if !compose_text.is_empty() {
+                        <div class="compose-count text-count">{text_count(&compose_text)}</div>
+                    }
+                    <button
+                        class="impersonate-btn"
+                        onclick={on_impersonate}
+                        disabled={store.active_stream.is_some() || store.active_chat.is_none()}
+                        title="Impersonate: write your next message for you"
+                    >
+                        {"✍"}
                     </button>
+                    if stoppable {
+                        <button class="send-btn stop-btn" onclick={on_stop} title="Stop generating">
+                            <svg viewBox="0 0 24 24" width="20" height="20" fill="currentColor"><rect x="6" y="6" width="12" height="12" rx="1"></rect></svg>
+                        </button>
+                    } else {
+                        <button class="send-btn" onclick={move |_| on_send.emit(())} disabled={store.active_stream.is_some()}>
+                             <svg viewBox="0 0 24 24" width="20" height="20" fill="currentColor"><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path></svg>
+                        </button>
+                    }
                 </div>
             </div>
         </div>
     }
 }
 
+/// Measures streaming throughput, counting each content chunk as one token
+#[derive(Default)]
+struct StreamMeter {
+    first_chunk_at: Option<f64>,
+    last_report_at: f64,
+    chunks: u32,
+}
+
+impl StreamMeter {
+    /// How often the live figure is pushed to the store, in milliseconds
+    const REPORT_INTERVAL: f64 = 250.0;
+
+    fn record(&mut self) {
+        let now = js_sys::Date::now();
+        self.first_chunk_at.get_or_insert(now);
+        self.chunks += 1;
+    }
+
+    /// Tokens per second since the first chunk
+    fn rate(&self) -> Option<f64> {
+        let elapsed = js_sys::Date::now() - self.first_chunk_at?;
+        (elapsed > 0.0).then(|| self.chunks as f64 * 1000.0 / elapsed)
+    }
+
+    /// The current rate, at most once per report interval
+    fn report(&mut self) -> Option<f64> {
+        let now = js_sys::Date::now();
+        if now - self.last_report_at < Self::REPORT_INTERVAL {
+            return None;
+        }
+        self.last_report_at = now;
+        self.rate()
+    }
+}
+
+/// Reveals a streaming reply at a capped number of characters per second, so bursts of
+/// tokens type out evenly. A stream slower than the cap is shown as it arrives.
+struct Typewriter {
+    target: uuid::Uuid,
+    text: String,
+    /// Characters of `text` on screen
+    shown: usize,
+    chars_per_second: f64,
+    /// When the first text arrived, to tell whether the stream outpaces the cap
+    started_at: Option<f64>,
+    last_tick: f64,
+    /// Fractional characters left over from earlier ticks
+    budget: f64,
+    ticking: bool,
+    /// Set once the stream ended, the timer stops without touching the message again
+    stopped: bool,
+}
+
+/// What to do with text handed to the [`Typewriter`]
+enum Reveal {
+    Show(String),
+    StartTimer,
+    Wait,
+}
+
+impl Typewriter {
+    const TICK_MS: u64 = 30;
+
+    fn new(target: uuid::Uuid, chars_per_second: u32) -> Self {
+        Self {
+            target,
+            text: String::new(),
+            shown: 0,
+            chars_per_second: chars_per_second as f64,
+            started_at: None,
+            last_tick: 0.0,
+            budget: 0.0,
+            ticking: false,
+            stopped: false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn visible(&self) -> String {
+        self.text.chars().take(self.shown).collect()
+    }
+
+    fn receive(&mut self, text: &str) -> Reveal {
+        let now = js_sys::Date::now();
+        let started_at = *self.started_at.get_or_insert(now);
+        let caught_up = self.shown >= self.len();
+        self.text = text.to_string();
+
+        let elapsed = (now - started_at) / 1000.0;
+        let outpacing = self.len() as f64 > self.chars_per_second * elapsed;
+        if caught_up && !outpacing {
+            // Throttling a slow stream would only add latency
+            self.shown = self.len();
+            Reveal::Show(self.text.clone())
+        } else if !self.ticking {
+            self.ticking = true;
+            self.last_tick = now;
+            self.budget = 0.0;
+            Reveal::StartTimer
+        } else {
+            Reveal::Wait
+        }
+    }
+
+    /// Advances by the time since the last tick, returning the text to show if it grew
+    fn tick(&mut self) -> Option<String> {
+        let now = js_sys::Date::now();
+        self.budget += (now - self.last_tick) * self.chars_per_second / 1000.0;
+        self.last_tick = now;
+        let step = self.budget.floor();
+        self.budget -= step;
+
+        let before = self.shown;
+        self.shown = (self.shown + step as usize).min(self.len());
+        if self.shown >= self.len() {
+            self.ticking = false;
+        }
+        (self.shown > before).then(|| self.visible())
+    }
+
+    /// Everything held back, if anything is
+    fn flush(&mut self) -> Option<String> {
+        (self.shown < self.len()).then(|| {
+            self.shown = self.len();
+            self.text.clone()
+        })
+    }
+
+    fn reset(&mut self) {
+        self.text.clear();
+        self.shown = 0;
+        self.started_at = None;
+    }
+}
+
+/// Shows `content` in the target message through the typewriter
+fn type_out(store: &StoreContext, typewriter: &Rc<RefCell<Typewriter>>, content: &str) {
+    let reveal = typewriter.borrow_mut().receive(content);
+    match reveal {
+        Reveal::Show(content) => {
+            let message_id = typewriter.borrow().target;
+            store.dispatch(Action::UpdateMessageContent {
+                message_id,
+                content,
+            });
+        }
+        Reveal::StartTimer => {
+            let store = store.clone();
+            let typewriter = typewriter.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                loop {
+                    yew::platform::time::sleep(Duration::from_millis(Typewriter::TICK_MS)).await;
+                    let (message_id, content, ticking) = {
+                        let mut tw = typewriter.borrow_mut();
+                        if tw.stopped {
+                            tw.ticking = false;
+                            return;
+                        }
+                        let content = tw.tick();
+                        (tw.target, content, tw.ticking)
+                    };
+                    if let Some(content) = content {
+                        store.dispatch(Action::UpdateMessageContent {
+                            message_id,
+                            content,
+                        });
+                    }
+                    if !ticking {
+                        return;
+                    }
+                }
+            });
+        }
+        Reveal::Wait => {}
+    }
+}
+
+/// Everything a completion stream tracks between lines
+struct StreamState {
+    /// The message the reply is streamed into, which takes the server's id once it arrives
+    target: uuid::Uuid,
+    full_response: String,
+    error: Option<String>,
+    meter: StreamMeter,
+    /// Set once tool results came in, so the next reply gets a message of its own
+    tool_round: bool,
+    /// Whether the target got log probabilities yet, older ones are dropped first
+    logprobs_started: bool,
+    /// Tool calls announced for the target so far, shown until the full calls arrive
+    started_calls: Vec<ToolCall>,
+    /// The target's reasoning trace so far, for models that send one
+    reasoning: String,
+    /// Whether any reply text came in yet
+    started: bool,
+    /// Paces the reveal when a typing speed is set
+    typewriter: Option<Rc<RefCell<Typewriter>>>,
+}
+
+impl StreamState {
+    fn new(target: uuid::Uuid, typing_speed: u32) -> Self {
+        Self {
+            target,
+            full_response: String::new(),
+            error: None,
+            meter: StreamMeter::default(),
+            tool_round: false,
+            logprobs_started: false,
+            started_calls: Vec::new(),
+            reasoning: String::new(),
+            started: false,
+            typewriter: (typing_speed > 0)
+                .then(|| Rc::new(RefCell::new(Typewriter::new(target, typing_speed)))),
+        }
+    }
+
+    /// Stops the typewriter's timer, the caller shows the final text
+    fn stop_typing(&self) {
+        if let Some(typewriter) = &self.typewriter {
+            typewriter.borrow_mut().stopped = true;
+        }
+    }
+}
+
+/// Takes down the announced tool calls of a stream that ended before their arguments came,
+/// e.g. because it was stopped. Those calls never ran and aren't saved.
+fn drop_started_calls(store: &StoreContext, state: &mut StreamState) {
+    if !state.started_calls.is_empty() {
+        state.started_calls.clear();
+        store.dispatch(Action::UpdateMessageToolCalls {
+            message_id: state.target,
+            tool_calls: Vec::new(),
+        });
+    }
+}
+
 /// Processes a single line of SSE data and updates the store
-fn handle_sse_line(
-    store: &StoreContext,
-    message_id: uuid::Uuid,
-    full_response: &mut String,
-    line: &str,
-) -> bool {
+fn handle_sse_line(store: &StoreContext, state: &mut StreamState, line: &str) -> bool {
     let Some(data) = line.strip_prefix("data: ") else {
         return true;
     };
     let data = data.trim_end();
 
     if data == "[DONE]" {
+        drop_started_calls(store, state);
         return false;
     }
 
-    if data.starts_with("[ERROR]") {
+    if let Some(message) = data.strip_prefix("[ERROR]") {
         tracing::error!("Backend error in stream: {}", data);
-        full_response.push_str(data);
-        store.dispatch(Action::UpdateMessageContent {
-            message_id,
-            content: full_response.clone(),
-        });
+        state.error = Some(message.trim().to_string());
+        drop_started_calls(store, state);
         return false;
     }
 
+    if let Some(id) = data.strip_prefix("[MESSAGE_ID] ") {
+        if let Ok(id) = uuid::Uuid::parse_str(id) {
+            if let Some(typewriter) = &state.typewriter {
+                let mut tw = typewriter.borrow_mut();
+                if state.tool_round {
+                    // Finish the previous turn's text before moving on to the new message
+                    if let Some(content) = tw.flush() {
+                        store.dispatch(Action::UpdateMessageContent {
+                            message_id: tw.target,
+                            content,
+                        });
+                    }
+                    tw.reset();
+                }
+                tw.target = id;
+            }
+            if state.tool_round {
+                // The previous turn's message holds the tool calls, so continue in a new one
+                store.dispatch(Action::AppendMessage(
+                    ChatMessage::new(ROLE_ASSISTANT, "").with_id(id),
+                ));
+                store.dispatch(Action::ContinueStream(id));
+                state.full_response.clear();
+                state.tool_round = false;
+                state.started = false;
+            } else {
+                store.dispatch(Action::ReplaceMessageId {
+                    old: state.target,
+                    new: id,
+                });
+            }
+            state.target = id;
+            state.logprobs_started = false;
+            state.started_calls.clear();
+            state.reasoning.clear();
+        }
+        return true;
+    }
+
+    // The reply as it was saved, once the server has cleaned it up
+    if let Some(content_json) = data.strip_prefix("[FINAL_CONTENT] ") {
+        if let Ok(content) = serde_json::from_str::<String>(content_json) {
+            state.stop_typing();
+            state.full_response = content.clone();
+            store.dispatch(Action::UpdateMessageContent {
+                message_id: state.target,
+                content,
+            });
+        }
+        return true;
+    }
+
+    // What the model thinks before it answers, shown apart from the reply
+    if let Some(reasoning_json) = data.strip_prefix("[REASONING] ") {
+        if let Ok(reasoning) = serde_json::from_str::<String>(reasoning_json) {
+            state.reasoning.push_str(&reasoning);
+            store.dispatch(Action::UpdateMessageReasoning {
+                message_id: state.target,
+                reasoning: state.reasoning.clone(),
+            });
+        }
+        return true;
+    }
+
+    if let Some(tokens_json) = data.strip_prefix("[LOGPROBS] ") {
+        if let Ok(tokens) = serde_json::from_str::<Vec<TokenLogprob>>(tokens_json) {
+            if !state.logprobs_started {
+                store.dispatch(Action::ClearLogprobs(state.target));
+                state.logprobs_started = true;
+            }
+            store.dispatch(Action::AppendLogprobs {
+                message_id: state.target,
+                tokens,
+            });
+        }
+        return true;
+    }
+
+    // A tool call is on its way, its arguments follow with [TOOL_CALLS]
+    if let Some(call_json) = data.strip_prefix("[TOOL_CALL_START] ") {
+        if let Ok(val) = serde_json::from_str::<serde_json::Value>(call_json) {
+            let name = val.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+            let id = val.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+            state.started_calls.push(ToolCall {
+                id: id.to_string(),
+                r#type: "function".to_string(),
+                function: FunctionCall {
+                    name: name.to_string(),
+                    arguments: String::new(),
+                },
+            });
+            store.dispatch(Action::UpdateMessageToolCalls {
+                message_id: state.target,
+                tool_calls: state.started_calls.clone(),
+            });
+        }
+        return true;
+    }
+
     if let Some(calls_json) = data.strip_prefix("[TOOL_CALLS] ") {
         if let Ok(tool_calls) = serde_json::from_str::<Vec<ToolCall>>(calls_json) {
+            state.started_calls.clear();
             store.dispatch(Action::UpdateMessageToolCalls {
-                message_id,
+                message_id: state.target,
                 tool_calls,
             });
         }
@@ -640,61 +2110,189 @@ fn handle_sse_line(
                 .to_string();
 
             let mut msg = ChatMessage::new(ROLE_TOOL, content);
+            if let Some(id) = val
+                .get("message_id")
+                .and_then(|v| v.as_str())
+                .and_then(|s| uuid::Uuid::parse_str(s).ok())
+            {
+                msg = msg.with_id(id);
+            }
             msg.tool_call_id = tool_call_id;
             store.dispatch(Action::AppendMessage(msg));
+            state.tool_round = true;
         }
         return true;
     }
 
+    // The first text takes the place of the thinking placeholder
+    if !state.started {
+        state.started = true;
+        store.dispatch(Action::StreamStarted);
+    }
+
     // Parse the JSON-encoded chunk from the backend
     if let Ok(content_chunk) = serde_json::from_str::<String>(data) {
-        full_response.push_str(&content_chunk);
+        state.full_response.push_str(&content_chunk);
     } else {
-        full_response.push_str(data);
+        state.full_response.push_str(data);
     }
 
-    store.dispatch(Action::UpdateMessageContent {
-        message_id,
-        content: full_response.clone(),
-    });
+    let content = if store.settings.smooth_streaming {
+        super::markdown::stable_prefix(&state.full_response)
+    } else {
+        state.full_response.as_str()
+    };
+    match &state.typewriter {
+        Some(typewriter) => type_out(store, typewriter, content),
+        None => store.dispatch(Action::UpdateMessageContent {
+            message_id: state.target,
+            content: content.to_string(),
+        }),
+    }
+
+    state.meter.record();
+    if store.settings.developer_mode
+        && let Some(rate) = state.meter.report()
+    {
+        store.dispatch(Action::SetStreamRate(rate));
+    }
 
     true
 }
 
-/// Helper to process the completion stream and update the store
-async fn process_completion_stream(
-    store: StoreContext,
-    payload: CompletionRequest,
-    message_id: uuid::Uuid,
-) {
-    let req = match Request::post("/api/completion").json(&payload) {
-        Ok(req) => req,
-        Err(e) => {
-            tracing::error!("Failed to create request: {:?}", e);
-            store.dispatch(Action::SetStream(None));
-            return;
+/// Build a completion request from the current settings
+fn completion_request(
+    settings: &AppSettings,
+    chat_id: uuid::Uuid,
+    regenerate: bool,
+    message_id: Option<uuid::Uuid>,
+) -> CompletionRequest {
+    CompletionRequest {
+        chat_id,
+        regenerate,
+        message_id,
+        api_key: settings.api_key.clone(),
+        api_base: Some(settings.api_base.clone()),
+        model: settings.model.clone(),
+        temperature: Some(settings.temperature),
+        max_tokens: Some(settings.max_tokens),
+        reasoning_effort: settings.reasoning_effort.clone(),
+        reasoning_effort_override: None,
+        impersonate: false,
+        include_roster: settings.include_roster,
+        include_examples: settings.include_examples,
+        merge_consecutive_replies: settings.merge_consecutive_replies,
+        lore_budget: settings.lore_budget,
+        history_limit: settings.history_limit,
+        tool_result_limit: settings.tool_result_limit,
+        extra_body: settings.extra_body.clone(),
+        logprobs: settings.developer_mode && settings.show_logprobs,
+        target_alternative_index: None,
+        regen_instruction: None,
+        strip_prefixes: settings.strip_prefixes.clone(),
+        system_prompt_override: None,
+    }
+}
+
+/// How a single completion request ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StreamEnd {
+    /// `on_line` asked to stop, i.e. a terminal event arrived
+    Finished,
+    /// The connection closed before a terminal event
+    Dropped,
+}
+
+/// Why a completion request failed
+#[derive(Debug)]
+enum CompletionError {
+    /// The server refused it for lack of an API key, the user has to set one first
+    MissingApiKey,
+    /// Another generation holds the chat (409). Right after a dropped connection that is
+    /// usually our own, which the server lets go once it notices the client is gone.
+    Busy,
+    Request(gloo_net::Error),
+}
+
+impl From<gloo_net::Error> for CompletionError {
+    fn from(e: gloo_net::Error) -> Self {
+        Self::Request(e)
+    }
+}
+
+impl std::fmt::Display for CompletionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingApiKey => write!(f, "No API key set, add one in Settings"),
+            Self::Busy => write!(f, "A reply is already being generated in this chat"),
+            Self::Request(e) => write!(f, "{}", e),
         }
-    };
+    }
+}
 
-    let resp = match req.send().await {
-        Ok(resp) => resp,
-        Err(e) => {
-            tracing::error!("Failed to send request: {:?}", e);
-            store.dispatch(Action::UpdateMessageContent {
-                message_id,
-                content: format!("[Error: {}]", e),
-            });
-            store.dispatch(Action::SetStream(None));
-            return;
+impl CompletionError {
+    /// The modal that lets the user fix what went wrong, if there is one
+    fn modal(&self) -> Option<ModalType> {
+        match self {
+            Self::MissingApiKey => Some(ModalType::MissingApiKey),
+            Self::Busy | Self::Request(_) => None,
         }
-    };
+    }
+}
+
+/// Why the server turned a completion request down, from the response's status and body
+fn rejection(status: u16, body: &str) -> CompletionError {
+    if error_code(body).as_deref() == Some(ERROR_MISSING_API_KEY) {
+        return CompletionError::MissingApiKey;
+    }
+    if status == 409 {
+        return CompletionError::Busy;
+    }
+    CompletionError::Request(gloo_net::Error::GlooError(format!(
+        "{} {}",
+        status,
+        error_message(body)
+    )))
+}
+
+/// The message out of a `{"error": {"message"}}` response body, or the body as it is
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// The `error.code` of a `{"error": {"code"}}` response body, if it has one
+fn error_code(body: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["code"].as_str().map(str::to_string))
+}
+
+/// Sends a completion request and feeds each complete SSE line to `on_line`
+/// until it returns `false` or the stream ends
+async fn read_completion_stream(
+    payload: &CompletionRequest,
+    signal: Option<&web_sys::AbortSignal>,
+    mut on_line: impl FnMut(&str) -> bool,
+) -> Result<StreamEnd, CompletionError> {
+    let resp = Request::post("/api/completion")
+        .abort_signal(signal)
+        .json(payload)?
+        .send()
+        .await?;
+
+    if !resp.ok() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(rejection(resp.status(), &body));
+    }
 
     if let Some(body) = resp.body() {
         let mut stream = wasm_streams::ReadableStream::from_raw(body).into_stream();
-        let mut full_response = String::new();
-        let mut buffer = Vec::new();
+        let mut lines = LineBuffer::default();
 
-        'outer: while let Some(result) = stream.next().await {
+        while let Some(result) = stream.next().await {
             let chunk = match result {
                 Ok(chunk) => chunk,
                 Err(e) => {
@@ -703,26 +2301,444 @@ async fn process_completion_stream(
                 }
             };
 
-            let bytes = js_sys::Uint8Array::new(&chunk).to_vec();
-            buffer.extend_from_slice(&bytes);
+            lines.push(&js_sys::Uint8Array::new(&chunk).to_vec());
+            while let Some(line) = lines.next_line() {
+                if !on_line(&line) {
+                    return Ok(StreamEnd::Finished);
+                }
+            }
+        }
+
+        if let Some(line) = lines.finish()
+            && !on_line(&line)
+        {
+            return Ok(StreamEnd::Finished);
+        }
+    }
+
+    Ok(StreamEnd::Dropped)
+}
 
-            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                let line_bytes = buffer.drain(..pos + 1).collect::<Vec<u8>>();
-                let line = String::from_utf8_lossy(&line_bytes);
+/// What `stream_completion` reports to its handler
+enum StreamEvent<'a> {
+    Line(&'a str),
+    /// The connection dropped; carries the chat as the server has it now.
+    /// Return `false` to stop instead of sending the request again.
+    Reconnecting(Chat),
+}
 
-                if !handle_sse_line(&store, message_id, &mut full_response, &line) {
-                    break 'outer;
+/// Reconnect attempts after a dropped connection, with the delay doubling from the first
+const RECONNECT_ATTEMPTS: u32 = 3;
+const RECONNECT_DELAY_MS: u64 = 500;
+
+/// Runs a completion request, sending it again with backoff when the connection drops
+/// before the server finished.
+///
+/// Nothing is saved until a reply is complete, so a retry starts the turn over. The handler
+/// gets the saved chat first to catch a reply that was stored just before the drop.
+async fn stream_completion(
+    payload: &CompletionRequest,
+    signal: &Option<web_sys::AbortSignal>,
+    mut on_event: impl FnMut(StreamEvent<'_>) -> bool,
+) -> Result<(), CompletionError> {
+    let mut attempt = 0;
+    loop {
+        let end = read_completion_stream(payload, signal.as_ref(), |line| {
+            on_event(StreamEvent::Line(line))
+        })
+        .await;
+        if aborted(signal) {
+            return Ok(());
+        }
+        // A request that never got through is only retried once we know the server was up,
+        // and a busy chat only once it may be our own dropped request still holding it
+        let retryable = matches!(end, Ok(StreamEnd::Dropped))
+            || (attempt > 0
+                && matches!(
+                    end,
+                    Err(CompletionError::Request(_) | CompletionError::Busy)
+                ));
+        if !retryable || attempt == RECONNECT_ATTEMPTS {
+            return end.map(|_| ());
+        }
+
+        yew::platform::time::sleep(std::time::Duration::from_millis(
+            RECONNECT_DELAY_MS << attempt,
+        ))
+        .await;
+        if aborted(signal) {
+            return Ok(());
+        }
+        attempt += 1;
+        tracing::warn!(
+            "Completion stream dropped, reconnecting (attempt {})",
+            attempt
+        );
+
+        match api::get_chat(payload.chat_id).await {
+            Ok(chat) => {
+                if !on_event(StreamEvent::Reconnecting(chat)) {
+                    return Ok(());
                 }
             }
+            // Still offline, try again after the next delay
+            Err(e) if attempt < RECONNECT_ATTEMPTS => {
+                tracing::warn!("Failed to reload chat before reconnecting: {:?}", e);
+            }
+            Err(e) => return Err(e.into()),
         }
+    }
+}
 
-        if payload.regenerate && !full_response.is_empty() {
-            store.dispatch(Action::AppendAlternative {
-                message_id,
-                content: full_response,
+/// Splits a byte stream into lines.
+///
+/// Bytes are only decoded once a whole line has arrived, so a multi-byte character split
+/// across two network chunks stays intact (`\n` never occurs inside a UTF-8 sequence).
+#[derive(Default)]
+struct LineBuffer {
+    buffer: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        let pos = self.buffer.iter().position(|&b| b == b'\n')?;
+        let line = self.buffer.drain(..pos + 1).collect::<Vec<u8>>();
+        Some(decode_line(line))
+    }
+
+    /// Whatever is left once the stream ends without a trailing newline
+    fn finish(self) -> Option<String> {
+        (!self.buffer.is_empty()).then(|| decode_line(self.buffer))
+    }
+}
+
+fn decode_line(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| {
+        tracing::warn!("Invalid UTF-8 in stream: {}", e);
+        String::from_utf8_lossy(e.as_bytes()).into_owned()
+    })
+}
+
+/// Streams a new variant into an existing reply, unless the reply is already streaming
+fn start_regeneration(store: StoreContext, payload: CompletionRequest, message_id: uuid::Uuid) {
+    if !claim_stream(message_id) {
+        return;
+    }
+    store.dispatch(Action::SetStream(Some(StreamingContext::Regeneration(
+        message_id, None,
+    ))));
+    yew::platform::spawn_local(process_completion_stream(store, payload, message_id));
+}
+
+/// Helper to process the completion stream and update the store
+async fn process_completion_stream(
+    store: StoreContext,
+    payload: CompletionRequest,
+    message_id: uuid::Uuid,
+) {
+    run_completion_stream(store, payload, message_id).await;
+}
+
+/// Streams one completion into the store, returning the error if it failed
+async fn run_completion_stream(
+    store: StoreContext,
+    payload: CompletionRequest,
+    message_id: uuid::Uuid,
+) -> Option<String> {
+    let mut state = StreamState::new(message_id, store.settings.typing_speed);
+    let signal = abort_signal(payload.chat_id);
+    let result = stream_completion(&payload, &signal, |event| match event {
+        StreamEvent::Line(line) => handle_sse_line(&store, &mut state, line),
+        StreamEvent::Reconnecting(chat) => {
+            let saved = reply_saved(&store, &chat, &payload, state.target);
+            store.dispatch(Action::SetActiveChat(chat));
+            if saved {
+                // The saved reply is on screen now, don't overwrite it below
+                state.full_response.clear();
+                return false;
+            }
+
+            // Start the reply over from the saved state
+            state.full_response.clear();
+            if let Some(typewriter) = &state.typewriter {
+                typewriter.borrow_mut().reset();
+            }
+            state.meter = StreamMeter::default();
+            state.tool_round = false;
+            if payload.regenerate {
+                store.dispatch(Action::SetStream(Some(StreamingContext::Regeneration(
+                    message_id,
+                    payload.target_alternative_index,
+                ))));
+            } else {
+                store.dispatch(Action::AppendMessage(
+                    ChatMessage::new(ROLE_ASSISTANT, "").with_id(state.target),
+                ));
+            }
+            true
+        }
+    })
+    .await;
+    state.stop_typing();
+
+    if let Some(rate) = state.meter.rate() {
+        tracing::info!(
+            "Stream finished: {} tokens at {:.1} tok/s",
+            state.meter.chunks,
+            rate
+        );
+    }
+
+    if aborted(&signal) {
+        // The chat was left. The generating state was already reset, and another stream
+        // may have started since.
+        tracing::info!("Completion stream cancelled");
+        release_stream(message_id);
+        return None;
+    }
+
+    if let Err(e) = result {
+        tracing::error!("Failed to send request: {:?}", e);
+        if let Some(modal) = e.modal() {
+            store.dispatch(Action::OpenModal(modal));
+        }
+        state.error = Some(e.to_string());
+    }
+
+    if let Some(error) = state.error.clone() {
+        // The server keeps a reply cut short by the provider, so show what it saved
+        let saved = if payload.regenerate || !state.full_response.is_empty() {
+            api::get_chat(payload.chat_id).await.ok()
+        } else {
+            None
+        };
+        let saved_message = saved
+            .as_ref()
+            .and_then(|chat| chat.messages.iter().find(|m| m.id == state.target));
+        let partial_saved = !payload.regenerate && saved_message.is_some();
+        let slot = if payload.regenerate {
+            payload.target_alternative_index.or_else(|| {
+                // `store` is from before the stream, so a longer saved message got the partial
+                let before = store
+                    .active_chat
+                    .as_ref()?
+                    .messages
+                    .iter()
+                    .find(|m| m.id == state.target)?
+                    .variant_count();
+                saved_message
+                    .map(|m| m.variant_count())
+                    .filter(|&count| count > before)
+                    .map(|count| count - 1)
+            })
+        } else {
+            partial_saved.then_some(0)
+        };
+        if let Some(chat) = saved.filter(|_| payload.regenerate || partial_saved) {
+            store.dispatch(Action::SetActiveChat(chat));
+        } else if !payload.regenerate {
+            store.dispatch(Action::UpdateMessageContent {
+                message_id: state.target,
+                content: String::new(),
             });
         }
+        store.dispatch(Action::MarkFailed {
+            message_id: state.target,
+            failure: FailedGeneration {
+                error,
+                // A saved partial reply gets another variant rather than a second message
+                regenerate: payload.regenerate || partial_saved,
+                slot,
+            },
+        });
+    } else if (store.settings.smooth_streaming || state.typewriter.is_some())
+        && !state.full_response.is_empty()
+    {
+        // Show whatever the smoothing or the typewriter was still holding back
+        store.dispatch(Action::UpdateMessageContent {
+            message_id: state.target,
+            content: state.full_response,
+        });
+    }
+
+    release_stream(message_id);
+    store.dispatch(Action::SetStream(None));
+    state.error
+}
+
+/// Regenerates a message `count` times in a row, one stream at a time so rate limits hold.
+///
+/// A failed swipe doesn't stop the batch; the failures are reported on the message at the end.
+async fn generate_swipes(
+    store: StoreContext,
+    chat_id: uuid::Uuid,
+    message_id: uuid::Uuid,
+    count: usize,
+) {
+    let mut batch = SwipeBatch {
+        message_id,
+        total: count,
+        done: 0,
+        failed: 0,
+    };
+    let mut last_error = None;
+    let signal = abort_signal(chat_id);
+
+    while batch.done < batch.total {
+        if aborted(&signal) {
+            return;
+        }
+        // Each run releases the claim when it ends, so take it again for the next one
+        if batch.done > 0 && !claim_stream(message_id) {
+            break;
+        }
+        store.dispatch(Action::SetSwipeBatch(Some(batch.clone())));
+        store.dispatch(Action::SetStream(Some(StreamingContext::Regeneration(
+            message_id, None,
+        ))));
+        let payload = completion_request(&store.settings, chat_id, true, Some(message_id));
+        if let Some(error) = run_completion_stream(store.clone(), payload, message_id).await {
+            batch.failed += 1;
+            last_error = Some(error);
+        }
+        batch.done += 1;
+    }
+    store.dispatch(Action::SetSwipeBatch(None));
+
+    if let Some(error) = last_error {
+        tracing::warn!("{} of {} swipes failed", batch.failed, batch.total);
+        store.dispatch(Action::MarkFailed {
+            message_id,
+            failure: FailedGeneration {
+                error: format!(
+                    "{} of {} swipes failed: {}",
+                    batch.failed, batch.total, error
+                ),
+                regenerate: true,
+                slot: None,
+            },
+        });
+    }
+}
+
+/// Whether the server stored the reply of a stream that dropped before its last event.
+///
+/// `store` still holds the state from before the stream started.
+fn reply_saved(
+    store: &StoreContext,
+    saved: &Chat,
+    payload: &CompletionRequest,
+    message_id: uuid::Uuid,
+) -> bool {
+    let before = store.active_chat.as_ref().map(|c| c.messages.as_slice());
+    if payload.regenerate {
+        let variants = |messages: &[ChatMessage]| {
+            messages
+                .iter()
+                .find(|m| m.id == message_id)
+                .map(|m| m.variant_count())
+                .unwrap_or(0)
+        };
+        return variants(&saved.messages) > before.map(variants).unwrap_or(0);
+    }
+
+    // A finished reply is a new assistant message that isn't waiting on tool results
+    saved.messages.last().is_some_and(|last| {
+        last.role == ROLE_ASSISTANT
+            && last.tool_calls.as_ref().is_none_or(|tc| tc.is_empty())
+            && !before.is_some_and(|messages| messages.iter().any(|m| m.id == last.id))
+    })
+}
+
+/// Streams an impersonated user message into the compose box
+async fn process_impersonation_stream(
+    store: StoreContext,
+    payload: CompletionRequest,
+    input: HtmlTextAreaElement,
+) {
+    let mut text = String::new();
+    let signal = abort_signal(payload.chat_id);
+    let result = read_completion_stream(&payload, signal.as_ref(), |line| {
+        let Some(data) = line.strip_prefix("data: ") else {
+            return true;
+        };
+        let data = data.trim_end();
+
+        if data == "[DONE]" {
+            return false;
+        }
+        if data.starts_with("[ERROR]") {
+            tracing::error!("Backend error in impersonation stream: {}", data);
+            return false;
+        }
+        // Content chunks are JSON strings, anything else is a control event
+        if let Ok(chunk) = serde_json::from_str::<String>(data) {
+            text.push_str(&chunk);
+            input.set_value(&text);
+        }
+        true
+    })
+    .await;
+
+    if aborted(&signal) {
+        return;
+    }
+    if let Err(e) = result {
+        tracing::error!("Failed to send impersonation request: {:?}", e);
+        if let Some(modal) = e.modal() {
+            store.dispatch(Action::OpenModal(modal));
+        }
+    }
+
+    // The text was set programmatically, let listeners (the length count) catch up
+    if let Ok(event) = web_sys::InputEvent::new("input") {
+        let _ = input.dispatch_event(&event);
     }
 
     store.dispatch(Action::SetStream(None));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_character_split_across_chunks_decodes_whole() {
+        let line = "data: 🦀\n".as_bytes();
+        // The crab's four bytes start at 6, so this cuts it in half
+        let (first, second) = line.split_at(8);
+        let mut lines = LineBuffer::default();
+
+        lines.push(first);
+        assert_eq!(lines.next_line(), None);
+        lines.push(second);
+        assert_eq!(lines.next_line().as_deref(), Some("data: 🦀\n"));
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn a_missing_api_key_opens_the_settings() {
+        let body = r#"{"error":{"message":"Missing API Key","code":"missing_api_key"}}"#;
+        let error = rejection(401, body);
+        assert!(matches!(error, CompletionError::MissingApiKey));
+        assert_eq!(error.modal(), Some(ModalType::MissingApiKey));
+
+        let error = rejection(401, r#"{"error":{"message":"Invalid key"}}"#);
+        assert_eq!(error.to_string(), "401 Invalid key");
+        assert_eq!(error.modal(), None);
+    }
+
+    #[test]
+    fn the_last_line_needs_no_newline() {
+        let mut lines = LineBuffer::default();
+        lines.push("data: one\ndata: tw".as_bytes());
+        assert_eq!(lines.next_line().as_deref(), Some("data: one\n"));
+        assert_eq!(lines.next_line(), None);
+        lines.push("o".as_bytes());
+        assert_eq!(lines.finish().as_deref(), Some("data: two"));
+    }
+}
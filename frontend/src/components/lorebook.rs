@@ -0,0 +1,271 @@
+use crate::api;
+use shared::models::{LoreEntry, LoreEntryRequest};
+use uuid::Uuid;
+use yew::prelude::*;
+
+/// An entry being written, with its keys still as typed
+#[derive(Clone, PartialEq, Default)]
+struct LoreDraft {
+    /// `None` for a new entry
+    id: Option<Uuid>,
+    name: String,
+    keys: String,
+    content: String,
+    enabled: bool,
+}
+
+impl LoreDraft {
+    fn from_entry(entry: &LoreEntry) -> Self {
+        Self {
+            id: Some(entry.id),
+            name: entry.name.clone(),
+            keys: entry.keys.join(", "),
+            content: entry.content.clone(),
+            enabled: entry.enabled,
+        }
+    }
+
+    fn request(&self) -> LoreEntryRequest {
+        LoreEntryRequest {
+            name: self.name.clone(),
+            keys: split_keys(&self.keys),
+            content: self.content.clone(),
+            enabled: self.enabled,
+        }
+    }
+}
+
+fn split_keys(keys: &str) -> Vec<String> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn entry_request(entry: &LoreEntry) -> LoreEntryRequest {
+    LoreEntryRequest {
+        name: entry.name.clone(),
+        keys: entry.keys.clone(),
+        content: entry.content.clone(),
+        enabled: entry.enabled,
+    }
+}
+
+/// World info shared by every character. Changes are saved as they're made, apart from
+/// the settings modal's own Save.
+#[function_component(LorebookPanel)]
+pub fn lorebook_panel() -> Html {
+    let entries = use_state(Vec::<LoreEntry>::new);
+    let draft = use_state(|| None::<LoreDraft>);
+    let error = use_state(|| None::<String>);
+
+    {
+        let entries = entries.clone();
+        let error = error.clone();
+        use_effect_with((), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::fetch_lore_entries().await {
+                    Ok(list) => entries.set(list),
+                    Err(e) => error.set(Some(format!("Failed to load the lorebook: {}", e))),
+                }
+            });
+            || ()
+        });
+    }
+
+    let on_new = {
+        let draft = draft.clone();
+        Callback::from(move |_| {
+            draft.set(Some(LoreDraft {
+                enabled: true,
+                ..Default::default()
+            }))
+        })
+    };
+
+    let on_cancel = {
+        let draft = draft.clone();
+        Callback::from(move |_| draft.set(None))
+    };
+
+    let edit_draft = |update: fn(&mut LoreDraft, String)| {
+        let draft = draft.clone();
+        Callback::from(move |e: InputEvent| {
+            let value = e
+                .target_unchecked_into::<web_sys::HtmlInputElement>()
+                .value();
+            if let Some(mut d) = (*draft).clone() {
+                update(&mut d, value);
+                draft.set(Some(d));
+            }
+        })
+    };
+    let on_name_input = edit_draft(|d, v| d.name = v);
+    let on_keys_input = edit_draft(|d, v| d.keys = v);
+    let on_content_input = {
+        let draft = draft.clone();
+        Callback::from(move |e: InputEvent| {
+            let value = e
+                .target_unchecked_into::<web_sys::HtmlTextAreaElement>()
+                .value();
+            if let Some(mut d) = (*draft).clone() {
+                d.content = value;
+                draft.set(Some(d));
+            }
+        })
+    };
+
+    let on_save = {
+        let entries = entries.clone();
+        let draft = draft.clone();
+        let error = error.clone();
+        Callback::from(move |_| {
+            let Some(d) = (*draft).clone() else {
+                return;
+            };
+            let entries = entries.clone();
+            let draft = draft.clone();
+            let error = error.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let saved = match d.id {
+                    Some(id) => api::update_lore_entry(id, d.request()).await,
+                    None => api::create_lore_entry(d.request()).await,
+                };
+                match saved {
+                    Ok(entry) => {
+                        let mut list = (*entries).clone();
+                        match list.iter_mut().find(|e| e.id == entry.id) {
+                            Some(existing) => *existing = entry,
+                            None => list.push(entry),
+                        }
+                        list.sort_by(|a, b| a.name.cmp(&b.name));
+                        entries.set(list);
+                        draft.set(None);
+                        error.set(None);
+                    }
+                    Err(e) => error.set(Some(format!("Failed to save the entry: {}", e))),
+                }
+            });
+        })
+    };
+
+    let on_toggle = {
+        let entries = entries.clone();
+        let error = error.clone();
+        Callback::from(move |id: Uuid| {
+            let Some(mut entry) = entries.iter().find(|e| e.id == id).cloned() else {
+                return;
+            };
+            entry.enabled = !entry.enabled;
+            let entries = entries.clone();
+            let error = error.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::update_lore_entry(id, entry_request(&entry)).await {
+                    Ok(saved) => {
+                        let list = entries
+                            .iter()
+                            .map(|e| if e.id == id { saved.clone() } else { e.clone() })
+                            .collect();
+                        entries.set(list);
+                    }
+                    Err(e) => error.set(Some(format!("Failed to update the entry: {}", e))),
+                }
+            });
+        })
+    };
+
+    let on_delete = {
+        let entries = entries.clone();
+        let error = error.clone();
+        Callback::from(move |id: Uuid| {
+            let entries = entries.clone();
+            let error = error.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::delete_lore_entry(id).await {
+                    Ok(()) => {
+                        let list = entries.iter().filter(|e| e.id != id).cloned().collect();
+                        entries.set(list);
+                    }
+                    Err(e) => error.set(Some(format!("Failed to delete the entry: {}", e))),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="lorebook">
+            <div class="plugin-actions">
+                <button class="btn btn-secondary btn-sm" onclick={on_new} disabled={draft.is_some()}>
+                    {"New Entry"}
+                </button>
+            </div>
+            if let Some(e) = &*error {
+                <div class="form-error">{e}</div>
+            }
+            if let Some(d) = &*draft {
+                <div class="lore-editor">
+                    <input type="text" class="form-input"
+                        value={d.name.clone()}
+                        oninput={on_name_input}
+                        placeholder="Name"
+                    />
+                    <input type="text" class="form-input"
+                        value={d.keys.clone()}
+                        oninput={on_keys_input}
+                        placeholder="Keys, separated by commas"
+                        title="The entry is sent when any of these comes up in the latest messages, ignoring case"
+                    />
+                    <textarea class="form-textarea" rows="4"
+                        value={d.content.clone()}
+                        oninput={on_content_input}
+                        placeholder="What the model should know"
+                    />
+                    <div class="share-actions">
+                        <button class="btn btn-secondary btn-sm" onclick={on_cancel}>{"Cancel"}</button>
+                        <button class="btn btn-primary btn-sm" onclick={on_save}
+                            disabled={d.name.trim().is_empty() || split_keys(&d.keys).is_empty()}
+                        >
+                            {"Save Entry"}
+                        </button>
+                    </div>
+                </div>
+            }
+            <div class="plugin-list">
+                if entries.is_empty() {
+                    <div class="discovery-empty">{"No entries yet."}</div>
+                }
+                {for entries.iter().map(|entry| {
+                    let id = entry.id;
+                    let on_edit = {
+                        let draft = draft.clone();
+                        let entry = entry.clone();
+                        Callback::from(move |_| draft.set(Some(LoreDraft::from_entry(&entry))))
+                    };
+                    html! {
+                        <div class="plugin-item">
+                            <div class="plugin-info">
+                                <div class="plugin-name">
+                                    {&entry.name}
+                                    <span class="plugin-version">{format!("~{} tokens", entry.estimated_tokens())}</span>
+                                </div>
+                                <div class="tool-list">
+                                    {for entry.keys.iter().map(|k| html! { <span class="tool-tag">{k}</span> })}
+                                </div>
+                                <div class="plugin-desc lore-content">{&entry.content}</div>
+                                <div class="share-actions">
+                                    <button class="btn btn-secondary btn-sm" onclick={on_edit}>{"Edit"}</button>
+                                    <button class="btn btn-secondary btn-sm" onclick={on_delete.reform(move |_| id)}>{"Delete"}</button>
+                                </div>
+                            </div>
+                            <label class="switch" title="Send this entry when its keys come up">
+                                <input type="checkbox" checked={entry.enabled} onclick={on_toggle.reform(move |_| id)} />
+                                <span class="slider round"></span>
+                            </label>
+                        </div>
+                    }
+                })}
+            </div>
+        </div>
+    }
+}
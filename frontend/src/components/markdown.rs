@@ -109,3 +109,96 @@ fn render_tag<'a>(tag: Tag<'a>, events: &mut impl Iterator<Item = Event<'a>>) ->
         _ => html! { {content} },
     }
 }
+
+/// Longest stretch of trailing text held back while waiting for its closing marker
+const MAX_HELD_BACK: usize = 64;
+
+/// The part of a streaming reply that is safe to render.
+///
+/// An unmatched `*`, `` ` `` or `[` in the last line would make the text flicker between
+/// literal and formatted as more arrives, so everything from it onwards is held back until
+/// it is closed, the line ends, or too much text piles up behind it.
+pub fn stable_prefix(text: &str) -> &str {
+    let line_start = text.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = &text[line_start..];
+
+    let mut code: Option<(usize, usize)> = None; // (start, backtick count)
+    let mut emphasis: Option<usize> = None;
+    let mut link: Option<usize> = None;
+
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let run = bytes[i..].iter().take_while(|b| **b == bytes[i]).count();
+        match bytes[i] {
+            b'`' => match code {
+                Some((_, len)) if len == run => code = None,
+                None => code = Some((i, run)),
+                _ => {}
+            },
+            _ if code.is_some() => {}
+            b'*' => {
+                if emphasis.is_some() {
+                    emphasis = None;
+                } else if bytes.get(i + run).is_none_or(|b| !b.is_ascii_whitespace()) {
+                    // A star followed by a space is a bullet or multiplication, not an opener
+                    emphasis = Some(i);
+                }
+            }
+            b'[' => {
+                link.get_or_insert(i);
+            }
+            b']' if link.is_some() && bytes.get(i + 1).is_some_and(|b| *b != b'(') => {
+                link = None;
+            }
+            b')' => link = None,
+            _ => {}
+        }
+        i += run;
+    }
+
+    let held = [code.map(|(start, _)| start), emphasis, link]
+        .into_iter()
+        .flatten()
+        .min();
+    match held {
+        Some(start) if line.len() - start <= MAX_HELD_BACK => &text[..line_start + start],
+        _ => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_bold_and_code_spans_wait_for_their_close() {
+        assert_eq!(stable_prefix("Say **hel"), "Say ");
+        assert_eq!(stable_prefix("Say **hello**"), "Say **hello**");
+        assert_eq!(stable_prefix("Run `cargo te"), "Run ");
+        assert_eq!(
+            stable_prefix("Run `cargo test` now"),
+            "Run `cargo test` now"
+        );
+        // Stars inside code are code
+        assert_eq!(
+            stable_prefix("Run ``a * b`` then *go"),
+            "Run ``a * b`` then "
+        );
+        assert_eq!(stable_prefix("See [the do"), "See ");
+        assert_eq!(stable_prefix("See [the docs]("), "See ");
+        assert_eq!(
+            stable_prefix("See [the docs](x) too"),
+            "See [the docs](x) too"
+        );
+    }
+
+    #[test]
+    fn bullets_earlier_lines_and_long_spans_are_shown() {
+        assert_eq!(stable_prefix("* one\n* tw"), "* one\n* tw");
+        assert_eq!(stable_prefix("2 * 3"), "2 * 3");
+        assert_eq!(stable_prefix("**Done\nNext *it"), "**Done\nNext ");
+        let runaway = format!("*{}", "a".repeat(MAX_HELD_BACK));
+        assert_eq!(stable_prefix(&runaway), runaway);
+    }
+}
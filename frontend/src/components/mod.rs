@@ -1,5 +1,8 @@
 pub mod char_modal;
 pub mod chat_stage;
+pub mod lorebook;
 pub mod markdown;
 pub mod settings_modal;
 pub mod sidebar;
+pub mod swipe_compare;
+pub mod token_heatmap;
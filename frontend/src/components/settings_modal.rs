@@ -1,19 +1,58 @@
 use crate::api;
 use crate::store::{Action, StoreContext};
+use shared::models::{AppSettings, ModelPreset};
+use shared::models::{Density, DiscoveryReport};
+use shared::validation::{parse_extra_body, validate_api_base};
 use yew::prelude::*;
 
+/// The extra request parameters as they're shown for editing
+fn format_extra_body(settings: &AppSettings) -> String {
+    settings
+        .extra_body
+        .as_ref()
+        .and_then(|body| serde_json::to_string_pretty(body).ok())
+        .unwrap_or_default()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SettingsModalProps {
+    /// Point the user at the API key field, a request just failed without one
+    #[prop_or_default]
+    pub missing_api_key: bool,
+}
+
 #[function_component(SettingsModal)]
-pub fn settings_modal() -> Html {
+pub fn settings_modal(props: &SettingsModalProps) -> Html {
     let store = use_context::<StoreContext>().expect("Store context not found");
+    let api_key_ref = use_node_ref();
+
+    {
+        let api_key_ref = api_key_ref.clone();
+        use_effect_with(props.missing_api_key, move |missing| {
+            if *missing && let Some(input) = api_key_ref.cast::<web_sys::HtmlInputElement>() {
+                let _ = input.focus();
+            }
+            || ()
+        });
+    }
 
     // Local state for form fields to avoid global dispatch on every keystroke
     let local_state = use_state(|| store.settings.clone());
 
+    let api_base_check = validate_api_base(&local_state.api_base);
+    // Edited as text, only valid JSON objects make it into the settings
+    let extra_body_text = use_state(|| format_extra_body(&local_state));
+    let extra_body_check = parse_extra_body(&extra_body_text);
+
     let on_submit = {
         let store = store.clone();
         let local_state = local_state.clone();
+        let valid = api_base_check.is_ok() && extra_body_check.is_ok();
         Callback::from(move |e: MouseEvent| {
             e.prevent_default();
+            if !valid {
+                return;
+            }
             store.dispatch(Action::UpdateSettings((*local_state).clone()));
             store.dispatch(Action::CloseModal);
         })
@@ -87,6 +126,86 @@ pub fn settings_modal() -> Html {
         })
     };
 
+    let on_history_limit_input = {
+        let local_state = local_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let mut s = (*local_state).clone();
+            // Empty (or zero) means no limit
+            s.history_limit = input.value().parse::<usize>().ok().filter(|n| *n > 0);
+            local_state.set(s);
+        })
+    };
+
+    let on_tool_result_limit_input = {
+        let local_state = local_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let mut s = (*local_state).clone();
+            // Empty (or zero) sends tool results whole
+            s.tool_result_limit = input.value().parse::<usize>().ok().filter(|n| *n > 0);
+            local_state.set(s);
+        })
+    };
+
+    let on_lore_budget_input = {
+        let local_state = local_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let mut s = (*local_state).clone();
+            // Empty sends no world info at all
+            s.lore_budget = input.value().parse::<usize>().unwrap_or(0);
+            local_state.set(s);
+        })
+    };
+
+    let on_typing_speed_input = {
+        let local_state = local_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let mut s = (*local_state).clone();
+            // Empty turns the throttle off
+            s.typing_speed = input.value().parse::<u32>().unwrap_or(0);
+            local_state.set(s);
+        })
+    };
+
+    let on_thinking_text_input = {
+        let local_state = local_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let mut s = (*local_state).clone();
+            s.thinking_text = input.value();
+            local_state.set(s);
+        })
+    };
+
+    let on_strip_prefixes_input = {
+        let local_state = local_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
+            let mut s = (*local_state).clone();
+            // One pattern per line, blank lines are dropped when the reply is cleaned up
+            s.strip_prefixes = input.value().split('\n').map(str::to_string).collect();
+            local_state.set(s);
+        })
+    };
+
+    let on_extra_body_input = {
+        let local_state = local_state.clone();
+        let extra_body_text = extra_body_text.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
+            let text = input.value();
+            if let Ok(body) = parse_extra_body(&text) {
+                let mut s = (*local_state).clone();
+                s.extra_body = body;
+                local_state.set(s);
+            }
+            extra_body_text.set(text);
+        })
+    };
+
     let on_reasoning_change = {
         let local_state = local_state.clone();
         Callback::from(move |e: Event| {
@@ -98,6 +217,163 @@ pub fn settings_modal() -> Html {
         })
     };
 
+    let on_density_change = {
+        let local_state = local_state.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            let mut s = (*local_state).clone();
+            s.density = match select.value().as_str() {
+                "compact" => Density::Compact,
+                _ => Density::Comfortable,
+            };
+            local_state.set(s);
+        })
+    };
+
+    let on_roster_toggle = {
+        let local_state = local_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut s = (*local_state).clone();
+            s.include_roster = !s.include_roster;
+            local_state.set(s);
+        })
+    };
+
+    let on_examples_toggle = {
+        let local_state = local_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut s = (*local_state).clone();
+            s.include_examples = !s.include_examples;
+            local_state.set(s);
+        })
+    };
+
+    let on_merge_replies_toggle = {
+        let local_state = local_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut s = (*local_state).clone();
+            s.merge_consecutive_replies = !s.merge_consecutive_replies;
+            local_state.set(s);
+        })
+    };
+
+    let on_smoothing_toggle = {
+        let local_state = local_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut s = (*local_state).clone();
+            s.smooth_streaming = !s.smooth_streaming;
+            local_state.set(s);
+        })
+    };
+
+    let on_developer_toggle = {
+        let local_state = local_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut s = (*local_state).clone();
+            s.developer_mode = !s.developer_mode;
+            local_state.set(s);
+        })
+    };
+
+    let on_logprobs_toggle = {
+        let local_state = local_state.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut s = (*local_state).clone();
+            s.show_logprobs = !s.show_logprobs;
+            local_state.set(s);
+        })
+    };
+
+    // Presets: save the model parameters under a name, or switch to saved ones
+    let preset_name = use_state(String::new);
+
+    let on_preset_name_input = {
+        let preset_name = preset_name.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            preset_name.set(input.value());
+        })
+    };
+
+    let on_save_preset = {
+        let local_state = local_state.clone();
+        let preset_name = preset_name.clone();
+        Callback::from(move |_: MouseEvent| {
+            let name = preset_name.trim();
+            if name.is_empty() {
+                return;
+            }
+            let mut s = (*local_state).clone();
+            s.save_preset(name);
+            local_state.set(s);
+            preset_name.set(String::new());
+        })
+    };
+
+    let on_apply_preset = {
+        let local_state = local_state.clone();
+        let extra_body_text = extra_body_text.clone();
+        Callback::from(move |preset: ModelPreset| {
+            let mut s = (*local_state).clone();
+            s.apply_preset(&preset);
+            extra_body_text.set(format_extra_body(&s));
+            local_state.set(s);
+        })
+    };
+
+    let on_delete_preset = {
+        let local_state = local_state.clone();
+        Callback::from(move |name: String| {
+            let mut s = (*local_state).clone();
+            s.presets.retain(|p| p.name != name);
+            local_state.set(s);
+        })
+    };
+
+    // Sharing: export the settings without the key, or merge in someone else's
+    let share_snippet = use_state(String::new);
+    let import_error = use_state(|| None::<String>);
+
+    let on_share = {
+        let local_state = local_state.clone();
+        let share_snippet = share_snippet.clone();
+        Callback::from(move |_: MouseEvent| {
+            let snippet = local_state.to_share_snippet();
+            share_snippet.set(snippet.clone());
+            yew::platform::spawn_local(async move {
+                if let Some(window) = web_sys::window() {
+                    let promise = window.navigator().clipboard().write_text(&snippet);
+                    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+                }
+            });
+        })
+    };
+
+    let on_snippet_input = {
+        let share_snippet = share_snippet.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
+            share_snippet.set(input.value());
+        })
+    };
+
+    let on_import = {
+        let local_state = local_state.clone();
+        let share_snippet = share_snippet.clone();
+        let import_error = import_error.clone();
+        let extra_body_text = extra_body_text.clone();
+        Callback::from(
+            move |_: MouseEvent| match local_state.merge_share_snippet(&share_snippet) {
+                Ok(settings) => {
+                    extra_body_text.set(format_extra_body(&settings));
+                    local_state.set(settings);
+                    import_error.set(None);
+                }
+                Err(e) => import_error.set(Some(e)),
+            },
+        )
+    };
+
     // Plugin effects and callbacks
     {
         let store = store.clone();
@@ -125,33 +401,52 @@ pub fn settings_modal() -> Html {
         })
     };
 
-    let on_discover = {
+    let discovery_report = use_state(|| None::<DiscoveryReport>);
+
+    // `true` restarts the loaded plugins too, `false` only adds new ones
+    let run_discovery = {
         let store = store.clone();
-        Callback::from(move |_| {
+        let discovery_report = discovery_report.clone();
+        Callback::from(move |reload: bool| {
             let store = store.clone();
+            let discovery_report = discovery_report.clone();
             wasm_bindgen_futures::spawn_local(async move {
-                if api::discover_plugins().await.is_ok()
-                    && let Ok(plugins) = api::fetch_plugins().await
-                {
-                    store.dispatch(Action::SetPlugins(plugins));
+                let report = if reload {
+                    api::reload_plugins().await
+                } else {
+                    api::discover_plugins().await
+                };
+                if let Ok(report) = report {
+                    discovery_report.set(Some(report));
+                    if let Ok(plugins) = api::fetch_plugins().await {
+                        store.dispatch(Action::SetPlugins(plugins));
+                    }
                 }
             });
         })
     };
 
+    let install_error = use_state(|| None::<String>);
+
     let on_install = {
         let store = store.clone();
+        let install_error = install_error.clone();
         Callback::from(move |e: Event| {
             let input: web_sys::HtmlInputElement = e.target_unchecked_into();
             if let Some(files) = input.files()
                 && let Some(file) = files.get(0)
             {
                 let store = store.clone();
+                let install_error = install_error.clone();
                 wasm_bindgen_futures::spawn_local(async move {
-                    if api::install_plugin(file).await.is_ok()
-                        && let Ok(plugins) = api::fetch_plugins().await
-                    {
-                        store.dispatch(Action::SetPlugins(plugins));
+                    match api::install_plugin(file).await {
+                        Ok(()) => {
+                            install_error.set(None);
+                            if let Ok(plugins) = api::fetch_plugins().await {
+                                store.dispatch(Action::SetPlugins(plugins));
+                            }
+                        }
+                        Err(e) => install_error.set(Some(e.to_string())),
                     }
                 });
             }
@@ -170,10 +465,16 @@ pub fn settings_modal() -> Html {
                     <div class="form-group">
                         <label class="form-label">{"API Key"}</label>
                         <input type="password" class="form-input"
+                            ref={api_key_ref}
                             value={local_state.api_key.clone()}
                             oninput={on_api_key_input}
                             placeholder="sk-..."
                         />
+                        if props.missing_api_key && local_state.api_key.is_empty() {
+                            <div class="form-warning">
+                                {"Replies need an API key from your provider. Paste it here and save, then retry the message."}
+                            </div>
+                        }
                     </div>
 
                     <div class="form-group">
@@ -183,11 +484,72 @@ pub fn settings_modal() -> Html {
                             oninput={on_api_base_input}
                             placeholder="https://openrouter.ai/api/v1"
                         />
+                        {match &api_base_check {
+                            Err(e) => html! { <div class="form-error">{e}</div> },
+                            Ok(base) if base.insecure => html! {
+                                <div class="form-warning">{"Not https: your API key will be sent unencrypted."}</div>
+                            },
+                            Ok(_) => html! {},
+                        }}
+                    </div>
+
+                    <div class="form-group">
+                        <label class="form-label">{"Message Density"}</label>
+                        <select class="form-select" onchange={on_density_change}>
+                            <option value="comfortable" selected={local_state.density == Density::Comfortable}>{"Comfortable"}</option>
+                            <option value="compact" selected={local_state.density == Density::Compact}>{"Compact"}</option>
+                        </select>
                     </div>
 
                     <details class="model-config-section">
                         <summary>{"Model Configuration"}</summary>
                         <div class="model-config-content">
+                            <div class="form-group">
+                                <label class="form-label">{"Presets"}</label>
+                                if !local_state.presets.is_empty() {
+                                    <div class="preset-list">
+                                        {for local_state.presets.iter().map(|preset| {
+                                            let active = local_state.active_preset().map(|p| &p.name) == Some(&preset.name);
+                                            let apply = {
+                                                let preset = preset.clone();
+                                                on_apply_preset.reform(move |_: MouseEvent| preset.clone())
+                                            };
+                                            let delete = {
+                                                let name = preset.name.clone();
+                                                on_delete_preset.reform(move |_: MouseEvent| name.clone())
+                                            };
+                                            html! {
+                                                <div class={classes!("preset-item", active.then_some("preset-active"))}>
+                                                    <div class="preset-info">
+                                                        <div class="preset-name">{&preset.name}</div>
+                                                        <div class="preset-desc">
+                                                            {format!("{} · temp {} · {} tokens · {}", preset.model, preset.temperature, preset.max_tokens, preset.reasoning_effort)}
+                                                        </div>
+                                                    </div>
+                                                    <button class="btn btn-secondary btn-sm" onclick={apply} disabled={active}>{"Apply"}</button>
+                                                    <button class="btn btn-secondary btn-sm" onclick={delete}>{"Delete"}</button>
+                                                </div>
+                                            }
+                                        })}
+                                    </div>
+                                }
+                                <div class="share-actions">
+                                    <input type="text" class="form-input"
+                                        value={(*preset_name).clone()}
+                                        oninput={on_preset_name_input}
+                                        placeholder="Preset name"
+                                    />
+                                    <button
+                                        class="btn btn-secondary btn-sm"
+                                        onclick={on_save_preset}
+                                        disabled={preset_name.trim().is_empty()}
+                                        title="Save the model, temperature, max tokens, reasoning effort and extra parameters below. A preset with the same name is replaced."
+                                    >
+                                        {"Save Preset"}
+                                    </button>
+                                </div>
+                            </div>
+
                             <div class="form-group">
                                 <label class="form-label">{"Model"}</label>
                                 <input type="text" class="form-input"
@@ -216,15 +578,163 @@ pub fn settings_modal() -> Html {
                                 </div>
                             </div>
 
+                            <div class="form-group">
+                                <label class="form-label">{"History Limit"}</label>
+                                <input type="number" class="form-input"
+                                    min="1"
+                                    value={local_state.history_limit.map(|n| n.to_string()).unwrap_or_default()}
+                                    oninput={on_history_limit_input}
+                                    placeholder="All messages"
+                                />
+                            </div>
+
+                            <div class="form-group">
+                                <label class="form-label">{"Tool Result Limit"}</label>
+                                <input type="number" class="form-input"
+                                    min="1"
+                                    value={local_state.tool_result_limit.map(|n| n.to_string()).unwrap_or_default()}
+                                    oninput={on_tool_result_limit_input}
+                                    placeholder="No limit"
+                                    title="Characters of each tool result sent to the model. Chats keep the full result."
+                                />
+                            </div>
+
+                            <div class="form-group">
+                                <label class="form-label">{"Extra Parameters (JSON)"}</label>
+                                <textarea class="form-textarea" rows="3"
+                                    value={(*extra_body_text).clone()}
+                                    oninput={on_extra_body_input}
+                                    placeholder={r#"{"min_p": 0.05, "top_k": 40}"#}
+                                    title="Provider-specific fields added to every completion request"
+                                />
+                                if let Err(e) = &extra_body_check {
+                                    <div class="form-error">{e}</div>
+                                }
+                            </div>
+
                             <div class="form-group">
                                 <label class="form-label">{"Reasoning Effort"}</label>
                                 <select class="form-select" onchange={on_reasoning_change}>
+                                    <option value="off" selected={local_state.reasoning_effort == "off"} title="Don't send the parameter (for models that reject it)">{"Off"}</option>
                                     <option value="none" selected={local_state.reasoning_effort == "none"}>{"None"}</option>
                                     <option value="low" selected={local_state.reasoning_effort == "low"}>{"Low"}</option>
                                     <option value="medium" selected={local_state.reasoning_effort == "medium"}>{"Medium"}</option>
                                     <option value="high" selected={local_state.reasoning_effort == "high"}>{"High"}</option>
                                 </select>
                             </div>
+
+                            <div class="form-group form-toggle">
+                                <label class="form-label">{"Group Chat Roster"}</label>
+                                <label class="switch" title="Tell the character who else is in a group chat">
+                                    <input type="checkbox" checked={local_state.include_roster} onclick={on_roster_toggle} />
+                                    <span class="slider round"></span>
+                                </label>
+                            </div>
+
+                            <div class="form-group form-toggle">
+                                <label class="form-label">{"Example Messages"}</label>
+                                <label class="switch" title="Send the character's example messages. Turn off to save tokens once the chat has enough history.">
+                                    <input type="checkbox" checked={local_state.include_examples} onclick={on_examples_toggle} />
+                                    <span class="slider round"></span>
+                                </label>
+                            </div>
+
+                            <div class="form-group form-toggle">
+                                <label class="form-label">{"Merge Consecutive Replies"}</label>
+                                <label class="switch" title="Send back-to-back replies from the same character as one message. Some providers reject consecutive assistant messages.">
+                                    <input type="checkbox" checked={local_state.merge_consecutive_replies} onclick={on_merge_replies_toggle} />
+                                    <span class="slider round"></span>
+                                </label>
+                            </div>
+
+                            <div class="form-group form-toggle">
+                                <label class="form-label">{"Smooth Streaming"}</label>
+                                <label class="switch" title="Wait for unclosed bold, code and links before showing them">
+                                    <input type="checkbox" checked={local_state.smooth_streaming} onclick={on_smoothing_toggle} />
+                                    <span class="slider round"></span>
+                                </label>
+                            </div>
+
+                            <div class="form-group">
+                                <label class="form-label">{"Strip Reply Prefixes"}</label>
+                                <textarea class="form-textarea" rows="2"
+                                    value={local_state.strip_prefixes.join("\n")}
+                                    oninput={on_strip_prefixes_input}
+                                    placeholder={"None, e.g.\n{{char}}:"}
+                                    title="Labels removed from the start of finished replies, one per line. {{char}} stands for the character's name."
+                                />
+                            </div>
+
+                            <div class="form-group">
+                                <label class="form-label">{"Typing Speed"}</label>
+                                <input type="number" class="form-input"
+                                    min="0"
+                                    value={Some(local_state.typing_speed).filter(|n| *n > 0).map(|n| n.to_string()).unwrap_or_default()}
+                                    oninput={on_typing_speed_input}
+                                    placeholder="Off"
+                                    title="Reveal replies at most this many characters per second, for a steadier feel. Slower streams are shown as they arrive."
+                                />
+                            </div>
+
+                            <div class="form-group">
+                                <label class="form-label">{"Thinking Text"}</label>
+                                <input type="text" class="form-input"
+                                    value={local_state.thinking_text.clone()}
+                                    oninput={on_thinking_text_input}
+                                    placeholder="Off"
+                                    title="Shown beside the dots until a reply starts, such as \"{{char}} is pondering…\". {{char}} stands for the character's name."
+                                />
+                            </div>
+
+                            <div class="form-group form-toggle">
+                                <label class="form-label">{"Developer Mode"}</label>
+                                <label class="switch" title="Show streaming throughput while generating">
+                                    <input type="checkbox" checked={local_state.developer_mode} onclick={on_developer_toggle} />
+                                    <span class="slider round"></span>
+                                </label>
+                            </div>
+
+                            if local_state.developer_mode {
+                                <div class="form-group form-toggle">
+                                    <label class="form-label">{"Token Confidence"}</label>
+                                    <label class="switch" title="Request token log probabilities and shade replies by confidence. Not every provider supports this.">
+                                        <input type="checkbox" checked={local_state.show_logprobs} onclick={on_logprobs_toggle} />
+                                        <span class="slider round"></span>
+                                    </label>
+                                </div>
+                            }
+
+                            <div class="form-group">
+                                <label class="form-label">{"Share Settings"}</label>
+                                <textarea class="form-textarea" rows="4"
+                                    value={(*share_snippet).clone()}
+                                    oninput={on_snippet_input}
+                                    placeholder="Paste a settings snippet to import it"
+                                />
+                                if let Some(e) = &*import_error {
+                                    <div class="form-error">{e}</div>
+                                }
+                                <div class="share-actions">
+                                    <button class="btn btn-secondary btn-sm" onclick={on_share} title="Copy these settings without your API key or extra body parameters">{"Share Settings"}</button>
+                                    <button class="btn btn-secondary btn-sm" onclick={on_import} disabled={share_snippet.trim().is_empty()}>{"Import"}</button>
+                                </div>
+                            </div>
+                        </div>
+                    </details>
+
+                    <details class="plugins-section">
+                        <summary>{"Global Lorebook"}</summary>
+                        <div class="plugins-content">
+                            <div class="form-group">
+                                <label class="form-label">{"Lore Budget (tokens)"}</label>
+                                <input type="number" class="form-input"
+                                    min="0"
+                                    value={local_state.lore_budget.to_string()}
+                                    oninput={on_lore_budget_input}
+                                    title="Most tokens of triggered entries sent with each reply. Entries that don't fit are left out."
+                                />
+                            </div>
+                            <super::lorebook::LorebookPanel />
                         </div>
                     </details>
 
@@ -232,12 +742,37 @@ pub fn settings_modal() -> Html {
                         <summary>{"Plugins"}</summary>
                         <div class="plugins-content">
                             <div class="plugin-actions">
-                                <button class="btn btn-secondary btn-sm" onclick={on_discover}>{"Discover Plugins"}</button>
+                                <button class="btn btn-secondary btn-sm" onclick={run_discovery.reform(|_| false)}>{"Discover Plugins"}</button>
+                                <button
+                                    class="btn btn-secondary btn-sm"
+                                    onclick={run_discovery.reform(|_| true)}
+                                    title="Restart every plugin, picking up changed plugin files"
+                                >
+                                    {"Reload All"}
+                                </button>
                                 <label class="btn btn-primary btn-sm">
                                     {"Install Plugin"}
                                     <input type="file" style="display: none;" onchange={on_install} />
                                 </label>
                             </div>
+                            if let Some(e) = &*install_error {
+                                <div class="form-error">{e}</div>
+                            }
+                            if let Some(report) = &*discovery_report {
+                                <div class="discovery-report">
+                                    if report.loaded.is_empty() && report.failed.is_empty() {
+                                        <div class="discovery-empty">{"No plugins found."}</div>
+                                    }
+                                    { for report.loaded.iter().map(|name| html! {
+                                        <div class="discovery-loaded">{format!("Loaded {}", name)}</div>
+                                    })}
+                                    { for report.failed.iter().map(|f| html! {
+                                        <div class="discovery-failed" title={f.error.clone()}>
+                                            {format!("{}: {}", f.file, f.error)}
+                                        </div>
+                                    })}
+                                </div>
+                            }
                             <div class="plugin-list">
                                 {for store.plugins.iter().map(|plugin| {
                                     let name = plugin.name.clone();
@@ -266,7 +801,7 @@ pub fn settings_modal() -> Html {
 
                     <div class="form-actions">
                         <button class="btn btn-secondary" onclick={on_cancel}>{"Cancel"}</button>
-                        <button class="btn btn-primary" onclick={on_submit}>{"Save Settings"}</button>
+                        <button class="btn btn-primary" onclick={on_submit} disabled={api_base_check.is_err() || extra_body_check.is_err()}>{"Save Settings"}</button>
                     </div>
                 </div>
             </div>
@@ -1,5 +1,7 @@
 use crate::api;
 use crate::store::{Action, ModalType, StoreContext};
+use shared::models::CHAT_PREVIEW_LENGTH;
+use shared::text::to_snippet;
 use yew::prelude::*;
 
 #[function_component(CharSidebar)]
@@ -90,6 +92,69 @@ pub fn char_sidebar() -> Html {
         })
     };
 
+    // Chat whose "move to character" picker is open
+    let moving_chat = use_state(|| None::<uuid::Uuid>);
+
+    let on_move_chat = {
+        let store = store.clone();
+        let moving_chat = moving_chat.clone();
+        Callback::from(move |(chat_id, character_id): (uuid::Uuid, uuid::Uuid)| {
+            moving_chat.set(None);
+            let store = store.clone();
+            yew::platform::spawn_local(async move {
+                match api::move_chat(chat_id, character_id).await {
+                    Ok(chat) => store.dispatch(Action::MoveChat(chat)),
+                    Err(e) => tracing::error!("Failed to move chat: {:?}", e),
+                }
+            });
+        })
+    };
+
+    let on_duplicate_chat = {
+        let store = store.clone();
+        Callback::from(move |chat_id: uuid::Uuid| {
+            let store = store.clone();
+            yew::platform::spawn_local(async move {
+                match api::duplicate_chat(chat_id).await {
+                    Ok(chat) => store.dispatch(Action::AddChat(chat)),
+                    Err(e) => tracing::error!("Failed to duplicate chat: {:?}", e),
+                }
+            });
+        })
+    };
+
+    // Chat whose tag input is open, and the tag the chat list is narrowed to
+    let tagging_chat = use_state(|| None::<uuid::Uuid>);
+    let tag_filter = use_state(|| None::<String>);
+
+    let on_add_tag = {
+        let store = store.clone();
+        let tagging_chat = tagging_chat.clone();
+        Callback::from(move |(chat_id, tag): (uuid::Uuid, String)| {
+            tagging_chat.set(None);
+            let store = store.clone();
+            yew::platform::spawn_local(async move {
+                match api::add_chat_tag(chat_id, tag).await {
+                    Ok(tags) => store.dispatch(Action::SetChatTags { chat_id, tags }),
+                    Err(e) => tracing::error!("Failed to tag chat: {:?}", e),
+                }
+            });
+        })
+    };
+
+    let on_remove_tag = {
+        let store = store.clone();
+        Callback::from(move |(chat_id, tag): (uuid::Uuid, String)| {
+            let store = store.clone();
+            yew::platform::spawn_local(async move {
+                match api::remove_chat_tag(chat_id, &tag).await {
+                    Ok(tags) => store.dispatch(Action::SetChatTags { chat_id, tags }),
+                    Err(e) => tracing::error!("Failed to untag chat: {:?}", e),
+                }
+            });
+        })
+    };
+
     let open_create = {
         let store = store.clone();
         Callback::from(move |_| store.dispatch(Action::OpenModal(ModalType::CreateCharacter)))
@@ -115,7 +180,252 @@ pub fn char_sidebar() -> Html {
         })
     };
 
+    let on_favorite = {
+        let store = store.clone();
+        Callback::from(move |id: uuid::Uuid| {
+            let store = store.clone();
+            yew::platform::spawn_local(async move {
+                if let Ok(char) = api::toggle_favorite(id).await {
+                    store.dispatch(Action::UpdateCharacter(char));
+                }
+            });
+        })
+    };
+
     let active_chat_id = store.active_chat.as_ref().map(|c| c.id);
+    let (favorites, others): (Vec<_>, Vec<_>) =
+        store.characters.iter().partition(|c| c.is_favorite);
+
+    let render_character = |char: &shared::models::Character| {
+        let id = char.id;
+        let on_click = on_select.clone();
+        let on_delete_click = on_delete.clone();
+        let on_favorite_click = on_favorite.clone();
+        let is_active = Some(id) == store.active_character_id;
+        let on_select_chat = on_select_chat.clone();
+        let on_new_chat = on_new_chat.clone();
+        let on_delete_chat = on_delete_chat.clone();
+        let on_move_chat = on_move_chat.clone();
+        let on_duplicate_chat = on_duplicate_chat.clone();
+        let moving_chat = moving_chat.clone();
+        let tagging_chat = tagging_chat.clone();
+        let on_add_tag = on_add_tag.clone();
+        let on_remove_tag = on_remove_tag.clone();
+        let chats = if is_active {
+            store.chats.clone()
+        } else {
+            Vec::new()
+        };
+        let mut chat_tags = chats
+            .iter()
+            .flat_map(|c| c.tags.iter().cloned())
+            .collect::<Vec<_>>();
+        chat_tags.sort_by_key(|t| t.to_lowercase());
+        chat_tags.dedup();
+        // A filter left over from another character's chats doesn't apply here
+        let active_filter = tag_filter
+            .as_ref()
+            .filter(|tag| chat_tags.contains(tag))
+            .cloned();
+
+        html! {
+            <>
+                <div class={classes!("char-item", if is_active { "active" } else { "" })} onclick={move |_| on_click.emit(id)}>
+                    <div class="avatar bot">{char.name.chars().next().unwrap_or('?')}</div>
+                    <div class="char-info">
+                        <div class="char-name">{&char.name}</div>
+                        <div class="char-desc">{&char.description}</div>
+                    </div>
+                    <button
+                        class={classes!("list-action-btn", "favorite-btn", if char.is_favorite { "favorited" } else { "" })}
+                        onclick={move |e: MouseEvent| { e.stop_propagation(); on_favorite_click.emit(id); }}
+                        title={if char.is_favorite { "Unfavorite character" } else { "Favorite character" }}
+                    >
+                        {if char.is_favorite { "★" } else { "☆" }}
+                    </button>
+                    <button class="list-action-btn" onclick={move |e: MouseEvent| { e.stop_propagation(); on_delete_click.emit(id); }} title="Delete character">
+                        <svg viewBox="0 0 24 24"><path fill="white" d="M6 19c0 1.1.9 2 2 2h8c1.1 0 2-.9 2-2V7H6v12zM19 4h-3.5l-1-1h-5l-1 1H5v2h14V4z"></path></svg>
+                    </button>
+                </div>
+                if is_active {
+                    <div class="chat-list">
+                        if !chat_tags.is_empty() {
+                            <div class="chat-tag-filter">
+                                { for chat_tags.iter().map(|tag| {
+                                    let selected = active_filter.as_ref() == Some(tag);
+                                    let tag_filter = tag_filter.clone();
+                                    let tag = tag.clone();
+                                    html! {
+                                        <button
+                                            class={classes!("chat-tag", "chat-tag-toggle", selected.then_some("selected"))}
+                                            onclick={
+                                                let tag = tag.clone();
+                                                move |_| tag_filter.set((!selected).then(|| tag.clone()))
+                                            }
+                                            title={if selected { "Show all chats" } else { "Only show chats with this tag" }}
+                                        >
+                                            {tag}
+                                        </button>
+                                    }
+                                })}
+                            </div>
+                        }
+                        { for chats.iter().enumerate().filter(|(_, chat)| {
+                            active_filter.as_ref().is_none_or(|tag| chat.tags.contains(tag))
+                        }).map(|(idx, chat)| {
+                            let chat_id = chat.id;
+                            let on_select = on_select_chat.clone();
+                            let on_delete = on_delete_chat.clone();
+                            let on_move = on_move_chat.clone();
+                            let on_duplicate = on_duplicate_chat.clone();
+                            let toggle_move = {
+                                let moving_chat = moving_chat.clone();
+                                move |e: MouseEvent| {
+                                    e.stop_propagation();
+                                    let open = *moving_chat == Some(chat_id);
+                                    moving_chat.set((!open).then_some(chat_id));
+                                }
+                            };
+                            let is_moving = *moving_chat == Some(chat_id);
+                            let toggle_tagging = {
+                                let tagging_chat = tagging_chat.clone();
+                                move |e: MouseEvent| {
+                                    e.stop_propagation();
+                                    let open = *tagging_chat == Some(chat_id);
+                                    tagging_chat.set((!open).then_some(chat_id));
+                                }
+                            };
+                            let is_tagging = *tagging_chat == Some(chat_id);
+                            let on_add_tag = on_add_tag.clone();
+                            let on_remove_tag = on_remove_tag.clone();
+                            let is_chat_active = active_chat_id == Some(chat_id);
+                            let label = format!("Chat {}", idx + 1);
+                            // The open chat's count follows new messages as they arrive
+                            let message_count = store
+                                .active_chat
+                                .as_ref()
+                                .filter(|_| is_chat_active)
+                                .map_or(chat.message_count, |c| c.messages.len());
+                            let preview = store
+                                .active_chat
+                                .as_ref()
+                                .filter(|_| is_chat_active)
+                                .map_or(chat.preview.clone(), |c| {
+                                    c.messages.last().map(|m| {
+                                        to_snippet(m.active_content(), CHAT_PREVIEW_LENGTH)
+                                    })
+                                })
+                                .filter(|p| !p.is_empty());
+
+                            html! {
+                                <>
+                                <div
+                                    class={classes!("chat-item", if is_chat_active { "active" } else { "" })}
+                                    onclick={move |_| on_select.emit(chat_id)}
+                                >
+                                    <div class="chat-item-icon">
+                                        <svg viewBox="0 0 24 24" width="14" height="14" fill="currentColor">
+                                            <path d="M20 2H4c-1.1 0-2 .9-2 2v18l4-4h14c1.1 0 2-.9 2-2V4c0-1.1-.9-2-2-2z"/>
+                                        </svg>
+                                    </div>
+                                    <span class="chat-item-label">
+                                        {label}
+                                        if let Some(preview) = preview {
+                                            <span class="chat-item-preview">{preview}</span>
+                                        }
+                                    </span>
+                                    <span class="chat-item-count" title="Messages">{message_count}</span>
+                                    <button
+                                        class="list-action-btn"
+                                        onclick={move |e: MouseEvent| { e.stop_propagation(); on_duplicate.emit(chat_id); }}
+                                        title="Duplicate chat"
+                                    >
+                                        <svg viewBox="0 0 24 24"><path fill="white" d="M16 1H4c-1.1 0-2 .9-2 2v14h2V3h12V1zm3 4H8c-1.1 0-2 .9-2 2v14c0 1.1.9 2 2 2h11c1.1 0 2-.9 2-2V7c0-1.1-.9-2-2-2zm0 16H8V7h11v14z"></path></svg>
+                                    </button>
+                                    <button class="list-action-btn" onclick={toggle_tagging} title="Add a tag">
+                                        <svg viewBox="0 0 24 24"><path fill="white" d="M21.41 11.58l-9-9C12.05 2.22 11.55 2 11 2H4c-1.1 0-2 .9-2 2v7c0 .55.22 1.05.59 1.42l9 9c.36.36.86.58 1.41.58s1.05-.22 1.41-.59l7-7c.37-.36.59-.86.59-1.41s-.23-1.06-.59-1.42zM5.5 7C4.67 7 4 6.33 4 5.5S4.67 4 5.5 4 7 4.67 7 5.5 6.33 7 5.5 7z"></path></svg>
+                                    </button>
+                                    <button class="list-action-btn" onclick={toggle_move} title="Move to another character">
+                                        <svg viewBox="0 0 24 24"><path fill="white" d="M12 4l-1.41 1.41L16.17 11H4v2h12.17l-5.58 5.59L12 20l8-8z"></path></svg>
+                                    </button>
+                                    <button
+                                        class="list-action-btn"
+                                        onclick={move |e: MouseEvent| { e.stop_propagation(); on_delete.emit(chat_id); }}
+                                        title="Delete chat"
+                                    >
+                                        <svg viewBox="0 0 24 24"><path fill="white" d="M6 19c0 1.1.9 2 2 2h8c1.1 0 2-.9 2-2V7H6v12zM19 4h-3.5l-1-1h-5l-1 1H5v2h14V4z"></path></svg>
+                                    </button>
+                                </div>
+                                if !chat.tags.is_empty() {
+                                    <div class="chat-tags">
+                                        { for chat.tags.iter().map(|tag| {
+                                            let on_remove_tag = on_remove_tag.clone();
+                                            let remove = tag.clone();
+                                            html! {
+                                                <span class="chat-tag">
+                                                    {tag}
+                                                    <button
+                                                        class="chat-tag-remove"
+                                                        onclick={move |e: MouseEvent| { e.stop_propagation(); on_remove_tag.emit((chat_id, remove.clone())); }}
+                                                        title="Remove tag"
+                                                    >
+                                                        {"×"}
+                                                    </button>
+                                                </span>
+                                            }
+                                        })}
+                                    </div>
+                                }
+                                if is_tagging {
+                                    <input
+                                        type="text"
+                                        class="form-input chat-tag-input"
+                                        placeholder="New tag, Enter to add"
+                                        onkeydown={move |e: KeyboardEvent| {
+                                            if e.key() != "Enter" {
+                                                return;
+                                            }
+                                            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                                            let tag = input.value().trim().to_string();
+                                            if !tag.is_empty() {
+                                                on_add_tag.emit((chat_id, tag));
+                                            }
+                                        }}
+                                    />
+                                }
+                                if is_moving {
+                                    <select
+                                        class="form-select move-chat-select"
+                                        onchange={move |e: Event| {
+                                            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                                            if let Ok(character_id) = uuid::Uuid::parse_str(&select.value()) {
+                                                on_move.emit((chat_id, character_id));
+                                            }
+                                        }}
+                                    >
+                                        <option value="" selected=true disabled=true>{"Move to…"}</option>
+                                        { for store.characters.iter().filter(|c| c.id != id).map(|c| html! {
+                                            <option value={c.id.to_string()}>{&c.name}</option>
+                                        })}
+                                    </select>
+                                }
+                                </>
+                            }
+                        })}
+                        <button
+                            class="new-chat-btn"
+                            onclick={move |_| on_new_chat.emit(id)}
+                        >
+                            <svg viewBox="0 0 24 24" width="14" height="14" fill="currentColor">
+                                <path d="M19 13h-6v6h-2v-6H5v-2h6V5h2v6h6v2z"/>
+                            </svg>
+                            {"New Chat"}
+                        </button>
+                    </div>
+                }
+            </>
+        }
+    };
 
     html! {
         <div class="sidebar">
@@ -133,6 +443,21 @@ pub fn char_sidebar() -> Html {
                 </div>
             </header>
 
+            if store.server_info.ephemeral {
+                <div class="ephemeral-banner" title="The server keeps chats, characters and lore in memory, nothing is written to disk. Your settings are stored in this browser and stay.">
+                    {"Ephemeral mode: chats are lost when the server restarts"}
+                </div>
+            }
+
+            if !favorites.is_empty() {
+                <div class="section-label">
+                    {"Favorites"}
+                </div>
+                <div class="char-list favorites">
+                    { for favorites.into_iter().map(render_character) }
+                </div>
+            }
+
             <div class="section-label">
                 {"Characters"}
             </div>
@@ -143,72 +468,7 @@ pub fn char_sidebar() -> Html {
                         {"No characters found."}
                     </div>
                 }
-                { for store.characters.iter().map(|char| {
-                    let id = char.id;
-                    let on_click = on_select.clone();
-                    let on_delete_click = on_delete.clone();
-                    let is_active = Some(id) == store.active_character_id;
-                    let on_select_chat = on_select_chat.clone();
-                    let on_new_chat = on_new_chat.clone();
-                    let on_delete_chat = on_delete_chat.clone();
-                    let chats = if is_active { store.chats.clone() } else { Vec::new() };
-
-                    html! {
-                        <>
-                            <div class={classes!("char-item", if is_active { "active" } else { "" })} onclick={move |_| on_click.emit(id)}>
-                                <div class="avatar bot">{char.name.chars().next().unwrap_or('?')}</div>
-                                <div class="char-info">
-                                    <div class="char-name">{&char.name}</div>
-                                    <div class="char-desc">{&char.description}</div>
-                                </div>
-                                <button class="list-action-btn" onclick={move |e: MouseEvent| { e.stop_propagation(); on_delete_click.emit(id); }} title="Delete character">
-                                    <svg viewBox="0 0 24 24"><path fill="white" d="M6 19c0 1.1.9 2 2 2h8c1.1 0 2-.9 2-2V7H6v12zM19 4h-3.5l-1-1h-5l-1 1H5v2h14V4z"></path></svg>
-                                </button>
-                            </div>
-                            if is_active {
-                                <div class="chat-list">
-                                    { for chats.iter().enumerate().map(|(idx, chat)| {
-                                        let chat_id = chat.id;
-                                        let on_select = on_select_chat.clone();
-                                        let on_delete = on_delete_chat.clone();
-                                        let is_chat_active = active_chat_id == Some(chat_id);
-                                        let label = format!("Chat {}", idx + 1);
-
-                                        html! {
-                                            <div
-                                                class={classes!("chat-item", if is_chat_active { "active" } else { "" })}
-                                                onclick={move |_| on_select.emit(chat_id)}
-                                            >
-                                                <div class="chat-item-icon">
-                                                    <svg viewBox="0 0 24 24" width="14" height="14" fill="currentColor">
-                                                        <path d="M20 2H4c-1.1 0-2 .9-2 2v18l4-4h14c1.1 0 2-.9 2-2V4c0-1.1-.9-2-2-2z"/>
-                                                    </svg>
-                                                </div>
-                                                <span class="chat-item-label">{label}</span>
-                                                <button
-                                                    class="list-action-btn"
-                                                    onclick={move |e: MouseEvent| { e.stop_propagation(); on_delete.emit(chat_id); }}
-                                                    title="Delete chat"
-                                                >
-                                                    <svg viewBox="0 0 24 24"><path fill="white" d="M6 19c0 1.1.9 2 2 2h8c1.1 0 2-.9 2-2V7H6v12zM19 4h-3.5l-1-1h-5l-1 1H5v2h14V4z"></path></svg>
-                                                </button>
-                                            </div>
-                                        }
-                                    })}
-                                    <button
-                                        class="new-chat-btn"
-                                        onclick={move |_| on_new_chat.emit(id)}
-                                    >
-                                        <svg viewBox="0 0 24 24" width="14" height="14" fill="currentColor">
-                                            <path d="M19 13h-6v6h-2v-6H5v-2h6V5h2v6h6v2z"/>
-                                        </svg>
-                                        {"New Chat"}
-                                    </button>
-                                </div>
-                            }
-                        </>
-                    }
-                })}
+                { for others.into_iter().map(render_character) }
             </div>
 
             <div class="sidebar-footer">
@@ -0,0 +1,57 @@
+use crate::store::{Action, StoreContext};
+use shared::models::ChatMessage;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct SwipeCompareProps {
+    pub message: ChatMessage,
+    /// Shown for variants without a sender of their own
+    pub char_name: String,
+}
+
+/// Every variant of a message next to each other
+#[function_component(SwipeCompare)]
+pub fn swipe_compare(props: &SwipeCompareProps) -> Html {
+    let store = use_context::<StoreContext>().expect("Store context not found");
+
+    let on_close = {
+        let store = store.clone();
+        Callback::from(move |_| store.dispatch(Action::CloseModal))
+    };
+
+    let message = &props.message;
+    html! {
+        <div class="modal-overlay" onclick={on_close.clone()}>
+            <div class="modal-content swipe-compare" onclick={|e: MouseEvent| e.stop_propagation()}>
+                <div class="modal-header">
+                    <h2 class="modal-title">{format!("Compare {} Swipes", message.variant_count())}</h2>
+                    <button class="close-btn" onclick={on_close}>{"×"}</button>
+                </div>
+
+                <div class="modal-body swipe-compare-grid">
+                    { for (0..message.variant_count()).map(|index| {
+                        let sender = message
+                            .variant_sender(index)
+                            .and_then(|id| store.characters.iter().find(|c| c.id == id))
+                            .map(|c| c.name.clone())
+                            .unwrap_or_else(|| props.char_name.clone());
+                        let content = message.variant(index).unwrap_or_default().to_string();
+                        html! {
+                            <div class={classes!("swipe-compare-column", (index == message.active_index).then_some("active"))}>
+                                <div class="swipe-compare-header">
+                                    {format!("{} · {}", index + 1, sender)}
+                                    if index == message.active_index {
+                                        <span class="swipe-compare-shown">{"shown"}</span>
+                                    }
+                                </div>
+                                <div class="message-text">
+                                    <super::markdown::Markdown content={content} />
+                                </div>
+                            </div>
+                        }
+                    })}
+                </div>
+            </div>
+        </div>
+    }
+}
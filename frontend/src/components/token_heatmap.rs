@@ -0,0 +1,40 @@
+use shared::models::TokenLogprob;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct TokenHeatmapProps {
+    pub tokens: Vec<TokenLogprob>,
+}
+
+/// Background for a token, from red for unlikely to clear for near certain
+fn confidence_color(probability: f32) -> String {
+    let doubt = (1.0 - probability).clamp(0.0, 1.0);
+    format!("rgba(220, 38, 38, {:.2})", doubt * 0.6)
+}
+
+/// A reply's tokens shaded by how confident the model was in each
+#[function_component(TokenHeatmap)]
+pub fn token_heatmap(props: &TokenHeatmapProps) -> Html {
+    let average = props.tokens.iter().map(|t| t.probability()).sum::<f32>()
+        / props.tokens.len().max(1) as f32;
+
+    html! {
+        <details class="token-heatmap">
+            <summary>{format!("Token confidence ({} tokens, {:.0}% average)", props.tokens.len(), average * 100.0)}</summary>
+            <div class="token-heatmap-text">
+                { for props.tokens.iter().map(|t| {
+                    let probability = t.probability();
+                    html! {
+                        <span
+                            class="token"
+                            style={format!("background-color: {}", confidence_color(probability))}
+                            title={format!("{:.1}% (logprob {:.3})", probability * 100.0, t.logprob)}
+                        >
+                            {&t.token}
+                        </span>
+                    }
+                })}
+            </div>
+        </details>
+    }
+}
@@ -0,0 +1,54 @@
+//! What the user sees when the app panics.
+//!
+//! A panic in wasm leaves Yew unable to render anything again, so there is no component to
+//! fall back to. Instead the panic hook swaps the page for a plain DOM message with the
+//! panic text and a reload button.
+
+use std::panic::PanicHookInfo;
+
+/// Log panics to the browser console and show the crash screen in place of the app
+pub fn install() {
+    yew::set_custom_panic_hook(Box::new(|info: &PanicHookInfo| {
+        console_error_panic_hook::hook(info);
+        show_crash_screen(&info.to_string());
+    }));
+}
+
+fn show_crash_screen(message: &str) -> Option<()> {
+    let document = web_sys::window()?.document()?;
+    let body = document.body()?;
+
+    let screen = document.create_element("div").ok()?;
+    screen.set_class_name("crash-screen");
+
+    let title = document.create_element("h2").ok()?;
+    title.set_text_content(Some("Something went wrong"));
+    let hint = document.create_element("p").ok()?;
+    hint.set_text_content(Some(
+        "Renoma hit an error it can't recover from. Your chats are saved on the server; reloading the page should get you back.",
+    ));
+    // Text content, not HTML, since the message can echo user data
+    let details = document.create_element("pre").ok()?;
+    details.set_class_name("crash-details");
+    details.set_text_content(Some(message));
+    let reload = document.create_element("button").ok()?;
+    reload.set_class_name("btn btn-primary");
+    reload.set_text_content(Some("Reload"));
+    reload.set_attribute("onclick", "location.reload()").ok()?;
+
+    for child in [&title, &hint, &details, &reload] {
+        screen.append_child(child).ok()?;
+    }
+    body.set_text_content(None);
+    body.append_child(&screen).ok()?;
+    Some(())
+}
+
+/// In debug builds, opening the app with `#debug-panic` panics on the first render, to check
+/// the crash screen
+#[cfg(debug_assertions)]
+pub fn debug_panic_requested() -> bool {
+    web_sys::window()
+        .and_then(|w| w.location().hash().ok())
+        .is_some_and(|hash| hash == "#debug-panic")
+}
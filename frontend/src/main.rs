@@ -1,18 +1,57 @@
 mod api;
 mod components;
+mod crash;
 mod store;
 
 use components::char_modal::CharModal;
 use components::chat_stage::ChatStage;
 use components::settings_modal::SettingsModal;
 use components::sidebar::CharSidebar;
-use store::{ModalType, State, StoreContext};
+use components::swipe_compare::SwipeCompare;
+use store::{Action, ModalType, State, StoreContext};
 use yew::prelude::*;
 
 #[function_component(App)]
 fn app() -> Html {
     let store = use_reducer(State::default);
 
+    #[cfg(debug_assertions)]
+    if crash::debug_panic_requested() {
+        panic!("Deliberate panic requested with #debug-panic");
+    }
+
+    // Learn how the server runs, and take its defaults on first run instead of the compiled-in ones
+    {
+        let store = store.clone();
+        use_effect_with((), move |_| {
+            {
+                let store = store.clone();
+                yew::platform::spawn_local(async move {
+                    if let Ok(info) = api::fetch_server_info().await {
+                        store.dispatch(Action::SetServerInfo(info));
+                    }
+                });
+            }
+            // Tool schemas, to lay out tool results in the chat
+            {
+                let store = store.clone();
+                yew::platform::spawn_local(async move {
+                    if let Ok(plugins) = api::fetch_plugins().await {
+                        store.dispatch(Action::SetPlugins(plugins));
+                    }
+                });
+            }
+            if !store::has_saved_settings() {
+                yew::platform::spawn_local(async move {
+                    if let Ok(defaults) = api::fetch_config_defaults().await {
+                        store.dispatch(Action::ApplyDefaults(defaults));
+                    }
+                });
+            }
+            || {}
+        });
+    }
+
     let app_class = classes!(
         "app-container",
         store.active_chat.as_ref().map(|_| "chat-active")
@@ -31,7 +70,27 @@ fn app() -> Html {
                 {
                     match store.modal_open {
                         Some(ModalType::Settings) => html! { <SettingsModal /> },
+                        Some(ModalType::MissingApiKey) => html! { <SettingsModal missing_api_key=true /> },
                         Some(ModalType::CreateCharacter) => html! { <CharModal /> },
+                        Some(ModalType::EditCharacter(id)) => html! {
+                            <CharModal character={store.characters.iter().find(|c| c.id == id).cloned()} />
+                        },
+                        Some(ModalType::CompareSwipes(id)) => {
+                            let message = store
+                                .active_chat
+                                .as_ref()
+                                .and_then(|chat| chat.messages.iter().find(|m| m.id == id));
+                            let char_name = store
+                                .characters
+                                .iter()
+                                .find(|c| Some(c.id) == store.active_character_id)
+                                .map(|c| c.name.clone())
+                                .unwrap_or("AI".to_string());
+                            match message {
+                                Some(message) => html! { <SwipeCompare message={message.clone()} {char_name} /> },
+                                None => html! {},
+                            }
+                        }
                         None => html! {},
                     }
                 }
@@ -41,5 +100,6 @@ fn app() -> Html {
 }
 
 fn main() {
+    crash::install();
     yew::Renderer::<App>::new().render();
 }
@@ -1,5 +1,6 @@
 use gloo_storage::{LocalStorage, Storage};
 use shared::models::*;
+use std::collections::HashMap;
 use std::rc::Rc;
 use uuid::Uuid;
 use yew::prelude::*;
@@ -17,6 +18,18 @@ pub struct State {
     pub active_stream: Option<StreamingContext>,
     pub editing_message_id: Option<Uuid>,
     pub plugins: Vec<PluginManifest>,
+    /// Messages whose last generation errored out, by message id
+    pub failed_generations: HashMap<Uuid, FailedGeneration>,
+    /// Tokens per second of the running stream, once it has produced some
+    pub stream_rate: Option<f64>,
+    /// Whether the running stream has sent any reply text yet
+    pub stream_started: bool,
+    /// Progress of a running "generate swipes" batch
+    pub swipe_batch: Option<SwipeBatch>,
+    pub server_info: ServerInfo,
+    /// Token log probabilities of replies generated this session, by message id.
+    /// Only requested in developer mode and never saved.
+    pub logprobs: HashMap<Uuid, Vec<TokenLogprob>>,
 }
 
 impl Default for State {
@@ -33,20 +46,65 @@ impl Default for State {
             active_stream: None,
             editing_message_id: None,
             plugins: Vec::new(),
+            failed_generations: HashMap::new(),
+            stream_rate: None,
+            stream_started: false,
+            swipe_batch: None,
+            server_info: ServerInfo::default(),
+            logprobs: HashMap::new(),
         }
     }
 }
 
+/// Whether settings were saved before, i.e. this is not a first run
+pub fn has_saved_settings() -> bool {
+    LocalStorage::get::<serde_json::Value>(LOCAL_STORAGE_KEY).is_ok()
+}
+
+/// Persist settings to local storage, which only exists in the browser (not in native tests)
+fn save_settings(settings: &AppSettings) {
+    if cfg!(target_arch = "wasm32") {
+        let _ = LocalStorage::set(LOCAL_STORAGE_KEY, settings);
+    }
+}
+
+/// A generation that ended in an error, kept so the bubble can offer a retry
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedGeneration {
+    pub error: String,
+    /// Whether the message already existed (a regeneration) or was a new reply
+    pub regenerate: bool,
+    /// The variant holding what the server kept of the reply, which a retry writes over
+    pub slot: Option<usize>,
+}
+
+/// Several regenerations of one message, run one after another
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwipeBatch {
+    pub message_id: Uuid,
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum StreamingContext {
     Generation(Uuid),
-    Regeneration(Uuid),
+    /// A new variant of a reply, or a rewrite of the given one
+    Regeneration(Uuid, Option<usize>),
+    /// Writing the user's next message into the compose box
+    Impersonation,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ModalType {
     Settings,
+    /// Settings, opened because a request went out without an API key
+    MissingApiKey,
     CreateCharacter,
+    EditCharacter(Uuid),
+    /// All variants of a message side by side
+    CompareSwipes(Uuid),
 }
 
 pub enum Action {
@@ -58,26 +116,53 @@ pub enum Action {
     DeleteChat(Uuid),
     SetStream(Option<StreamingContext>),
     SelectCharacter(Uuid),
+    UpdateCharacter(Character),
     DeleteCharacter(Uuid),
     DeleteMessage(Uuid),
     EditMessage {
         message_id: Uuid,
         content: String,
     },
-    AppendMessage(ChatMessage),
-    AppendAlternative {
+    SetKeepInContext {
         message_id: Uuid,
-        content: String,
+        keep: bool,
+    },
+    SetHiddenFromContext {
+        message_id: Uuid,
+        hidden: bool,
+    },
+    /// A chat now belongs to another character
+    MoveChat(Chat),
+    SetChatTags {
+        chat_id: Uuid,
+        tags: Vec<String>,
     },
+    AppendMessage(ChatMessage),
     UpdateMessageContent {
         message_id: Uuid,
         content: String,
     },
+    /// Adopt the id the server saves a streamed reply under
+    ReplaceMessageId {
+        old: Uuid,
+        new: Uuid,
+    },
+    /// Swap in a whole new record for a message with the same id
+    ReplaceMessage(ChatMessage),
     UpdateMessageToolCalls {
         message_id: Uuid,
         tool_calls: Vec<ToolCall>,
     },
+    /// Streamed reasoning for the variant a message is showing
+    UpdateMessageReasoning {
+        message_id: Uuid,
+        reasoning: String,
+    },
     UpdateSettings(AppSettings),
+    /// Switch to a saved model preset, by name
+    ApplyPreset(String),
+    /// Fill in the server's recommended defaults on first run
+    ApplyDefaults(ConfigDefaults),
     OpenModal(ModalType),
     CloseModal,
     CloseChat,
@@ -86,7 +171,26 @@ pub enum Action {
         message_id: Uuid,
         direction: i32,
     },
+    /// Show the primary content again
+    ResetMessage(Uuid),
     SetPlugins(Vec<PluginManifest>),
+    MarkFailed {
+        message_id: Uuid,
+        failure: FailedGeneration,
+    },
+    /// The first reply text of the running stream arrived
+    StreamStarted,
+    /// After a round of tool calls the reply goes on in a new message, waiting for text again
+    ContinueStream(Uuid),
+    SetStreamRate(f64),
+    SetSwipeBatch(Option<SwipeBatch>),
+    SetServerInfo(ServerInfo),
+    /// Start over for a message that's being generated again
+    ClearLogprobs(Uuid),
+    AppendLogprobs {
+        message_id: Uuid,
+        tokens: Vec<TokenLogprob>,
+    },
 }
 
 impl Reducible for State {
@@ -104,6 +208,11 @@ impl Reducible for State {
                 next.chats = Vec::new();
                 next.active_chat = None;
             }
+            Action::UpdateCharacter(char) => {
+                if let Some(existing) = next.characters.iter_mut().find(|c| c.id == char.id) {
+                    *existing = char;
+                }
+            }
             Action::DeleteCharacter(id) => {
                 next.characters.retain(|c| c.id != id);
                 if next.active_character_id == Some(id) {
@@ -137,6 +246,25 @@ impl Reducible for State {
                     next.active_chat = None;
                 }
             }
+            Action::MoveChat(moved) => {
+                next.chats.retain(|c| c.id != moved.id);
+                if let Some(chat) = &mut next.active_chat
+                    && chat.id == moved.id
+                {
+                    chat.character_id = moved.character_id;
+                    chat.participants = moved.participants;
+                }
+            }
+            Action::SetChatTags { chat_id, tags } => {
+                if let Some(chat) = next.chats.iter_mut().find(|c| c.id == chat_id) {
+                    chat.tags = tags.clone();
+                }
+                if let Some(chat) = &mut next.active_chat
+                    && chat.id == chat_id
+                {
+                    chat.tags = tags;
+                }
+            }
             Action::AppendMessage(msg) => {
                 if let Some(chat) = &mut next.active_chat {
                     chat.messages.push(msg);
@@ -149,7 +277,27 @@ impl Reducible for State {
                 if let Some(chat) = &mut next.active_chat
                     && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == message_id)
                 {
-                    msg.content = content;
+                    *msg.active_content_mut() = content;
+                }
+            }
+            Action::ReplaceMessage(message) => {
+                if let Some(chat) = &mut next.active_chat
+                    && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == message.id)
+                {
+                    *msg = message;
+                }
+            }
+            Action::ReplaceMessageId { old, new } => {
+                if let Some(chat) = &mut next.active_chat
+                    && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == old)
+                {
+                    msg.id = new;
+                }
+                if next.active_stream == Some(StreamingContext::Generation(old)) {
+                    next.active_stream = Some(StreamingContext::Generation(new));
+                }
+                if let Some(failure) = next.failed_generations.remove(&old) {
+                    next.failed_generations.insert(new, failure);
                 }
             }
             Action::UpdateMessageToolCalls {
@@ -162,9 +310,32 @@ impl Reducible for State {
                     msg.tool_calls = Some(tool_calls);
                 }
             }
+            Action::UpdateMessageReasoning {
+                message_id,
+                reasoning,
+            } => {
+                if let Some(chat) = &mut next.active_chat
+                    && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == message_id)
+                {
+                    msg.set_variant_reasoning(msg.active_index, Some(reasoning));
+                }
+            }
             Action::UpdateSettings(settings) => {
-                next.settings = settings.clone();
-                let _ = LocalStorage::set(LOCAL_STORAGE_KEY, settings);
+                next.settings = settings;
+                save_settings(&next.settings);
+            }
+            Action::ApplyPreset(name) => {
+                if let Some(preset) = next.settings.presets.iter().find(|p| p.name == name) {
+                    let preset = preset.clone();
+                    next.settings.apply_preset(&preset);
+                    save_settings(&next.settings);
+                }
+            }
+            Action::ApplyDefaults(defaults) => {
+                next.settings.api_base = defaults.api_base;
+                next.settings.model = defaults.model;
+                next.settings.reasoning_effort = defaults.reasoning_effort;
+                save_settings(&next.settings);
             }
             Action::OpenModal(modal_type) => {
                 next.modal_open = Some(modal_type);
@@ -174,16 +345,34 @@ impl Reducible for State {
             }
             Action::SetStream(context) => {
                 next.active_stream = context.clone();
+                next.stream_rate = None;
+                next.stream_started = false;
 
-                if let Some(StreamingContext::Regeneration(id)) = context
+                if let Some(
+                    StreamingContext::Generation(id) | StreamingContext::Regeneration(id, _),
+                ) = &context
+                {
+                    next.failed_generations.remove(id);
+                }
+
+                if let Some(StreamingContext::Regeneration(id, slot)) = context
                     && let Some(chat) = &mut next.active_chat
                     && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == id)
-                    && msg.alternatives.is_empty()
                 {
-                    // Move current content to alternatives before it gets overwritten by stream
-                    let original = msg.content.clone();
-                    msg.alternatives.push(original);
-                    msg.content = "".to_string();
+                    // Stream into a fresh alternative so the existing variants stay intact
+                    // and the bubble shows the loading state until the first chunk arrives.
+                    // A rewrite empties its variant for the same reason.
+                    match slot.filter(|&index| index < msg.variant_count()) {
+                        Some(index) => {
+                            msg.active_index = index;
+                            msg.active_content_mut().clear();
+                            msg.set_variant_reasoning(index, None);
+                        }
+                        None => {
+                            msg.push_alternative(String::new(), None);
+                            msg.active_index = msg.alternatives.len();
+                        }
+                    }
                 }
             }
             Action::EditMessage {
@@ -206,23 +395,20 @@ impl Reducible for State {
                 if let Some(chat) = &mut next.active_chat {
                     chat.messages.retain(|m| m.id != message_id);
                 }
+                next.failed_generations.remove(&message_id);
             }
-            Action::AppendAlternative {
-                message_id,
-                content,
-            } => {
+            Action::SetKeepInContext { message_id, keep } => {
+                if let Some(chat) = &mut next.active_chat
+                    && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == message_id)
+                {
+                    msg.keep_in_context = keep;
+                }
+            }
+            Action::SetHiddenFromContext { message_id, hidden } => {
                 if let Some(chat) = &mut next.active_chat
                     && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == message_id)
                 {
-                    // If we already moved the original to alternatives in SetRegenerating,
-                    // msg.alternatives will not be empty.
-                    // We just need to ensure the final content is set, and we're on the right index.
-
-                    // Add the new content as another alternative
-                    msg.alternatives.push(content.clone());
-                    // Switch to show the new alternative
-                    msg.active_index = msg.alternatives.len();
-                    msg.content = content;
+                    msg.hidden_from_context = hidden;
                 }
             }
             Action::SwipeMessage {
@@ -244,9 +430,44 @@ impl Reducible for State {
                     }
                 }
             }
+            Action::ResetMessage(message_id) => {
+                if let Some(chat) = &mut next.active_chat
+                    && let Some(msg) = chat.messages.iter_mut().find(|m| m.id == message_id)
+                {
+                    msg.active_index = 0;
+                }
+            }
             Action::CloseChat => {
                 next.active_chat = None;
             }
+            Action::MarkFailed {
+                message_id,
+                failure,
+            } => {
+                next.failed_generations.insert(message_id, failure);
+            }
+            Action::StreamStarted => {
+                next.stream_started = true;
+            }
+            Action::ContinueStream(message_id) => {
+                next.active_stream = Some(StreamingContext::Generation(message_id));
+                next.stream_started = false;
+            }
+            Action::SetStreamRate(rate) => {
+                next.stream_rate = Some(rate);
+            }
+            Action::SetSwipeBatch(batch) => {
+                next.swipe_batch = batch;
+            }
+            Action::SetServerInfo(info) => {
+                next.server_info = info;
+            }
+            Action::ClearLogprobs(message_id) => {
+                next.logprobs.remove(&message_id);
+            }
+            Action::AppendLogprobs { message_id, tokens } => {
+                next.logprobs.entry(message_id).or_default().extend(tokens);
+            }
             Action::SetPlugins(plugins) => {
                 next.plugins = plugins;
             }
@@ -257,3 +478,135 @@ impl Reducible for State {
 }
 
 pub type StoreContext = UseReducerHandle<State>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// State showing a chat with just `message`, built by hand as `Default` reads local storage
+    fn showing(message: ChatMessage) -> Rc<State> {
+        Rc::new(State {
+            characters: Vec::new(),
+            active_character_id: None,
+            chats: Vec::new(),
+            active_chat: Some(Chat {
+                id: Uuid::now_v7(),
+                character_id: Uuid::now_v7(),
+                messages: vec![message],
+                participants: Vec::new(),
+                message_count: 1,
+                scenario_override: None,
+                tags: Vec::new(),
+                preview: None,
+            }),
+            settings: AppSettings::default(),
+            modal_open: None,
+            active_stream: None,
+            editing_message_id: None,
+            plugins: Vec::new(),
+            failed_generations: HashMap::new(),
+            stream_rate: None,
+            stream_started: false,
+            swipe_batch: None,
+            server_info: ServerInfo::default(),
+            logprobs: HashMap::new(),
+        })
+    }
+
+    fn message(state: &State) -> &ChatMessage {
+        &state.active_chat.as_ref().unwrap().messages[0]
+    }
+
+    #[test]
+    fn a_streaming_reply_takes_the_id_it_is_saved_under() {
+        let placeholder = ChatMessage::new(ROLE_ASSISTANT, "");
+        let (old, new) = (placeholder.id, Uuid::now_v7());
+        let state = showing(placeholder)
+            .reduce(Action::SetStream(Some(StreamingContext::Generation(old))))
+            .reduce(Action::ReplaceMessageId { old, new });
+
+        assert_eq!(message(&state).id, new);
+        assert_eq!(state.active_stream, Some(StreamingContext::Generation(new)));
+    }
+
+    #[test]
+    fn each_regeneration_streams_into_its_own_alternative() {
+        let reply = ChatMessage::new(ROLE_ASSISTANT, "First");
+        let id = reply.id;
+        let mut state = showing(reply);
+
+        for (take, text) in [(1, "Second"), (2, "Third")] {
+            state = state.reduce(Action::SetStream(Some(StreamingContext::Regeneration(
+                id, None,
+            ))));
+            assert_eq!(
+                state.active_stream,
+                Some(StreamingContext::Regeneration(id, None))
+            );
+            // Empty until the first chunk, so the bubble shows it's loading
+            assert_eq!(message(&state).active_index, take);
+            assert_eq!(message(&state).active_content(), "");
+
+            state = state.reduce(Action::UpdateMessageContent {
+                message_id: id,
+                content: text.to_string(),
+            });
+            state = state.reduce(Action::SetStream(None));
+        }
+
+        let message = message(&state);
+        assert_eq!(message.content, "First");
+        assert_eq!(message.alternatives, ["Second", "Third"]);
+        assert_eq!(state.active_stream, None);
+    }
+
+    #[test]
+    fn rewriting_a_variant_streams_over_it() {
+        let mut reply = ChatMessage::new(ROLE_ASSISTANT, "First");
+        reply.push_alternative("Second", None);
+        let id = reply.id;
+
+        let state = showing(reply).reduce(Action::SetStream(Some(StreamingContext::Regeneration(
+            id,
+            Some(0),
+        ))));
+        assert_eq!(message(&state).active_index, 0);
+        assert_eq!(message(&state).content, "");
+        assert_eq!(message(&state).alternatives, ["Second"]);
+    }
+
+    #[test]
+    fn applying_a_preset_sets_its_model_parameters() {
+        let mut state = showing(ChatMessage::new(ROLE_USER, "Hi"));
+        let settings = &mut Rc::make_mut(&mut state).settings;
+        settings.model = "gpt-precise".to_string();
+        settings.temperature = 0.2;
+        settings.max_tokens = 512;
+        settings.reasoning_effort = "high".to_string();
+        settings.extra_body = Some(serde_json::json!({"top_p": 0.9}));
+        settings.save_preset("Precise");
+        *settings = AppSettings {
+            presets: settings.presets.clone(),
+            ..AppSettings::default()
+        };
+
+        let unchanged = state
+            .clone()
+            .reduce(Action::ApplyPreset("Missing".to_string()));
+        assert_eq!(unchanged.settings, state.settings);
+
+        let state = state.reduce(Action::ApplyPreset("Precise".to_string()));
+        assert_eq!(state.settings.model, "gpt-precise");
+        assert_eq!(state.settings.temperature, 0.2);
+        assert_eq!(state.settings.max_tokens, 512);
+        assert_eq!(state.settings.reasoning_effort, "high");
+        assert_eq!(
+            state.settings.extra_body,
+            Some(serde_json::json!({"top_p": 0.9}))
+        );
+        assert_eq!(
+            state.settings.active_preset().map(|p| p.name.as_str()),
+            Some("Precise")
+        );
+    }
+}
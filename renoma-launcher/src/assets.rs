@@ -0,0 +1,126 @@
+use axum::Router;
+use axum::extract::Request;
+use axum::handler::HandlerWithoutStateExt;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use std::path::Path;
+use tower_http::services::{ServeDir, ServeFile};
+
+/// Serve the built frontend. Paths that look like routes rather than files get
+/// `index.html`, so deep links and refreshes on client-side routes load the app instead of
+/// a 404. Missing files and unknown `/api` paths still 404: a stale asset must not be
+/// answered (and cached) with HTML, nor a mistyped endpoint.
+pub fn router<S: Clone + Send + Sync + 'static>(dist_dir: &Path, max_age: u64) -> Router<S> {
+    let index = ServeFile::new(dist_dir.join("index.html"));
+    let spa_fallback = move |req: Request| {
+        let mut index = index.clone();
+        async move {
+            let is_file = Path::new(req.uri().path()).extension().is_some();
+            if is_file {
+                return StatusCode::NOT_FOUND.into_response();
+            }
+            match index.try_call(req).await {
+                Ok(response) => response.into_response(),
+                Err(e) => {
+                    tracing::error!("Failed to serve index.html: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }
+    };
+    let files = ServeDir::new(dist_dir).fallback(spa_fallback.into_service());
+    Router::new()
+        .route("/api/{*path}", any(|| async { StatusCode::NOT_FOUND }))
+        .fallback_service(files)
+        .layer(middleware::from_fn(move |req, next| {
+            cache_control(req, next, max_age)
+        }))
+}
+
+/// Fingerprinted assets never change under the same name, so they can be cached for
+/// `max_age` seconds. Everything else, `index.html` above all, is revalidated each time
+/// so a new build is picked up on the next load.
+async fn cache_control(req: Request, next: Next, max_age: u64) -> Response {
+    let fingerprinted = is_fingerprinted(req.uri().path());
+    let mut response = next.run(req).await;
+    if response.status().is_success() {
+        let value = if fingerprinted && max_age > 0 {
+            HeaderValue::from_str(&format!("public, max-age={}, immutable", max_age))
+                .expect("a number is a valid header value")
+        } else {
+            HeaderValue::from_static("no-cache")
+        };
+        response.headers_mut().insert(header::CACHE_CONTROL, value);
+    }
+    response
+}
+
+/// Whether a file name carries a content hash, the way trunk names its output
+/// (`frontend-1a2b3c4d5e6f7a8b.js`, `frontend-1a2b3c4d5e6f7a8b_bg.wasm`)
+fn is_fingerprinted(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let stem = name.split('.').next().unwrap_or_default();
+    let stem = stem.strip_suffix("_bg").unwrap_or(stem);
+    stem.rsplit_once('-').is_some_and(|(_, hash)| {
+        (8..=16).contains(&hash.len()) && hash.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    const INDEX: &str = "<!doctype html><title>Renoma</title>";
+    const SCRIPT: &str = "/frontend-1a2b3c4d5e6f7a8b.js";
+
+    async fn get(dist: &Path, path: &str) -> Response {
+        router::<()>(dist, 3600)
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn client_routes_get_index_html_and_missing_files_do_not() {
+        let dist = tempfile::tempdir().unwrap();
+        std::fs::write(dist.path().join("index.html"), INDEX).unwrap();
+        std::fs::write(dist.path().join(&SCRIPT[1..]), "run()").unwrap();
+
+        let response = get(dist.path(), "/chats/some-chat").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, INDEX);
+
+        let response = get(dist.path(), SCRIPT).await;
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=3600, immutable"
+        );
+
+        assert_eq!(
+            get(dist.path(), "/frontend-0000000000000000.js")
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(dist.path(), "/api/no-such-endpoint").await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn only_hashed_names_count_as_fingerprinted() {
+        assert!(is_fingerprinted(SCRIPT));
+        assert!(is_fingerprinted("/frontend-1a2b3c4d5e6f7a8b_bg.wasm"));
+        assert!(!is_fingerprinted("/index.html"));
+        assert!(!is_fingerprinted("/styles-main.css"));
+        assert!(!is_fingerprinted("/chats/some-chat"));
+    }
+}
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Args, Parser};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -7,8 +7,99 @@ pub struct Cli {
     pub port: u16,
     #[arg(long, default_value = "dist")]
     pub dist_dir: PathBuf,
+    /// Seconds browsers may cache fingerprinted frontend assets without asking again.
+    /// `index.html` and other unhashed files are always revalidated. 0 revalidates everything.
+    #[arg(long, default_value_t = 365 * 24 * 60 * 60)]
+    pub asset_max_age: u64,
     #[arg(long, default_value = "renoma.db")]
     pub local_db_path: PathBuf,
     #[arg(long)]
     pub postgres_url: Option<String>,
+    /// Keep the database in memory: nothing is written to disk and chats, characters and lore
+    /// are lost on restart. Settings live in the browser and are kept.
+    #[arg(long, conflicts_with = "postgres_url")]
+    pub ephemeral: bool,
+    /// JSON file mapping model names to prices (USD per million tokens)
+    #[arg(long)]
+    pub pricing_file: Option<PathBuf>,
+    /// Dump raw provider responses into this directory (contains chat content, off by default)
+    #[arg(long, env = "RENOMA_DEBUG_PROVIDER")]
+    pub debug_provider: Option<PathBuf>,
+    /// Reject completion requests whose API base is not https (loopback hosts are exempt)
+    #[arg(long)]
+    pub require_https: bool,
+    /// Don't create a sample character when the database has none
+    #[arg(long)]
+    pub no_seed: bool,
+    /// Only load plugins with this name (repeatable)
+    #[arg(long = "allow-plugin")]
+    pub allow_plugins: Vec<String>,
+    /// Never load plugins with this name (repeatable), even if allowed
+    #[arg(long = "deny-plugin")]
+    pub deny_plugins: Vec<String>,
+    /// Refuse plugin uploads, for deployments where the plugins directory is read-only
+    #[arg(long)]
+    pub no_plugin_install: bool,
+    /// Longest message the server accepts, in characters
+    #[arg(long, default_value_t = backend::config::DEFAULT_MAX_MESSAGE_LENGTH)]
+    pub max_message_length: usize,
+    /// Largest character bundle (a character with its chats) the server imports, in bytes
+    #[arg(long, default_value_t = backend::config::DEFAULT_MAX_IMPORT_SIZE)]
+    pub max_import_size: usize,
+    /// Import every character card (Tavern V1/V2, JSON or PNG) in this directory on startup.
+    /// Meant for a one-off migration: importing the same cards again adds numbered copies.
+    #[arg(long)]
+    pub import_cards: Option<PathBuf>,
+    /// PEM certificate chain to serve HTTPS with. Browsers only speak HTTP/2 over TLS, and
+    /// HTTP/2 carries every completion stream of a tab over one connection instead of using
+    /// up the browser's six-connections-per-host limit of HTTP/1.1.
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key for `--tls-cert`
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    #[command(flatten)]
+    pub plugin_health: PluginHealthArgs,
+}
+
+/// Detecting plugins that have hung without exiting
+#[derive(Args)]
+pub struct PluginHealthArgs {
+    /// Seconds between pings of each plugin. 0 turns pinging off.
+    #[arg(long, default_value_t = 30)]
+    pub plugin_ping_interval: u64,
+    /// Seconds a plugin has to answer a ping
+    #[arg(long, default_value_t = 10)]
+    pub plugin_ping_timeout: u64,
+    /// Unanswered pings in a row after which a plugin is killed and its tools removed.
+    /// A plugin busy with one long tool call can't answer either, so keep
+    /// interval × this above the slowest tool call.
+    #[arg(long, default_value_t = 3)]
+    pub plugin_max_missed_pings: u32,
+    /// Start a plugin killed for missing pings again
+    #[arg(long)]
+    pub restart_hung_plugins: bool,
+}
+
+/// Connection tuning. The defaults suit a handful of users with a few tabs each.
+#[derive(Args)]
+pub struct HttpArgs {
+    /// Seconds an HTTP/1.1 connection may take to send a request's headers, which is also
+    /// how long an idle keep-alive connection is kept open. Lower frees idle sockets sooner,
+    /// higher spares clients new handshakes.
+    #[arg(long, default_value_t = 30)]
+    pub header_read_timeout: u64,
+    /// Close HTTP/1.1 connections after each response. Costs a handshake per request, but
+    /// no socket is held by an idle client.
+    #[arg(long)]
+    pub no_keep_alive: bool,
+    /// Seconds between pings on HTTP/2 connections, so dead clients of a long completion
+    /// stream are noticed. 0 turns pings off.
+    #[arg(long, default_value_t = 20)]
+    pub http2_keep_alive_interval: u64,
+    /// Seconds to wait for a ping's answer before dropping the HTTP/2 connection
+    #[arg(long, default_value_t = 20)]
+    pub http2_keep_alive_timeout: u64,
 }
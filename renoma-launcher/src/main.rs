@@ -1,9 +1,12 @@
+mod assets;
 mod cli;
 
-use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
 use std::net::SocketAddr;
-use tower_http::services::ServeDir;
+use std::time::Duration;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -12,17 +15,236 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with(tracing_subscriber::fmt::layer())
         .init();
     let cli = cli::Cli::parse();
-    let router = Router::new().fallback_service(ServeDir::new(cli.dist_dir));
+    let router = assets::router(&cli.dist_dir, cli.asset_max_age);
     let addr = SocketAddr::from(([0, 0, 0, 0], cli.port));
-    tracing::info!("Listening on {}", addr);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    let config = if let Some(url) = cli.postgres_url {
+    let config = if cli.ephemeral {
+        backend::DatabaseConfig::Memory
+    } else if let Some(url) = cli.postgres_url {
         backend::DatabaseConfig::Postgres { url }
     } else {
         let db_url = format!("sqlite:{}?mode=rwc", cli.local_db_path.display());
         backend::DatabaseConfig::Local { url: db_url }
     };
-    let router = backend::init(router, config).await;
-    axum::serve(listener, router).await?;
+    let mut backend_config = backend::Config::default();
+    if let Some(path) = cli.pricing_file {
+        backend_config.pricing = backend::config::load_pricing(&path)
+            .map_err(|e| format!("Failed to load pricing file {}: {}", path.display(), e))?;
+    }
+    backend_config.debug_provider_dir = cli.debug_provider;
+    backend_config.require_https = cli.require_https;
+    backend_config.skip_seed = cli.no_seed;
+    backend_config.plugin_filter = backend::config::PluginFilter {
+        allow: (!cli.allow_plugins.is_empty()).then(|| cli.allow_plugins.into_iter().collect()),
+        deny: cli.deny_plugins.into_iter().collect(),
+    };
+    backend_config.allow_plugin_install = !cli.no_plugin_install;
+    backend_config.max_message_length = cli.max_message_length;
+    backend_config.max_import_size = cli.max_import_size;
+    backend_config.import_cards_dir = cli.import_cards;
+    backend_config.plugin_health = backend::config::PluginHealthCheck {
+        interval: Some(Duration::from_secs(cli.plugin_health.plugin_ping_interval))
+            .filter(|interval| !interval.is_zero()),
+        timeout: Duration::from_secs(cli.plugin_health.plugin_ping_timeout),
+        max_missed: cli.plugin_health.plugin_max_missed_pings,
+        restart: cli.plugin_health.restart_hung_plugins,
+    };
+    let tls =
+        match (&cli.tls_cert, &cli.tls_key) {
+            (Some(cert), Some(key)) => {
+                // Other dependencies may enable another provider, so pick one explicitly
+                let _ = rustls::crypto::ring::default_provider().install_default();
+                Some(RustlsConfig::from_pem_file(cert, key).await.map_err(|e| {
+                    format!("Failed to load TLS certificate {}: {}", cert.display(), e)
+                })?)
+            }
+            _ => None,
+        };
+    let router = backend::init(router, config, backend_config).await;
+    let app = router.into_make_service();
+
+    match tls {
+        Some(tls) => {
+            tracing::info!("Listening on https://{} (HTTP/2 and HTTP/1.1)", addr);
+            let mut server = axum_server::bind_rustls(addr, tls);
+            tune_http(server.http_builder(), &cli.http);
+            server.serve(app).await?;
+        }
+        None => {
+            tracing::info!("Listening on {}", addr);
+            let mut server = axum_server::bind(addr);
+            tune_http(server.http_builder(), &cli.http);
+            server.serve(app).await?;
+        }
+    }
     Ok(())
 }
+
+/// Apply the connection settings from the command line. HTTP/2 is offered over TLS by ALPN,
+/// and over plain connections to clients that start with it.
+fn tune_http(builder: &mut Builder<TokioExecutor>, args: &cli::HttpArgs) {
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(!args.no_keep_alive)
+        .header_read_timeout(Duration::from_secs(args.header_read_timeout));
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(
+            Some(Duration::from_secs(args.http2_keep_alive_interval))
+                .filter(|interval| !interval.is_zero()),
+        )
+        .keep_alive_timeout(Duration::from_secs(args.http2_keep_alive_timeout));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::{Body, Bytes, HttpBody};
+    use axum::http::{Method, Request, Response, Version};
+    use futures::StreamExt;
+    use http_body_util::BodyExt;
+    use hyper_util::client::legacy::Client;
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    fn chunk(content: &str) -> String {
+        let chunk = json!({
+            "id": "x",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "m",
+            "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }],
+        });
+        format!("data: {}\n\n", chunk)
+    }
+
+    async fn json<B: HttpBody<Error: std::fmt::Debug>>(response: Response<B>) -> Value {
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    /// A provider that sends "Hel", then holds the rest of the reply until `release` fires
+    async fn held_provider(release: Arc<Notify>) -> String {
+        let provider = Router::new().route(
+            "/v1/chat/completions",
+            axum::routing::post(move || async move {
+                let first = futures::stream::once(async { chunk("Hel") });
+                let rest = futures::stream::once(async move {
+                    release.notified().await;
+                    format!("{}data: [DONE]\n\n", chunk("lo"))
+                });
+                let body = Body::from_stream(
+                    first
+                        .chain(rest)
+                        .map(|event| Ok::<_, std::io::Error>(Bytes::from(event))),
+                );
+                ([("content-type", "text/event-stream")], body)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, provider).await.unwrap() });
+        format!("http://{}/v1", addr)
+    }
+
+    #[tokio::test]
+    async fn completions_stream_over_http2_with_the_default_tuning() {
+        let release = Arc::new(Notify::new());
+        let api_base = held_provider(release.clone()).await;
+        let config = backend::Config {
+            skip_seed: true,
+            ..Default::default()
+        };
+        let router = backend::init(Router::new(), backend::DatabaseConfig::Memory, config).await;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut server = axum_server::from_tcp(listener).unwrap();
+        tune_http(
+            server.http_builder(),
+            &cli::Cli::parse_from(["renoma"]).http,
+        );
+        tokio::spawn(server.serve(router.into_make_service()));
+
+        // Prior knowledge HTTP/2, as a browser would get by ALPN over TLS
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<Body>();
+        let post = |path: &str, body: Value| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!("http://{}{}", addr, path))
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let character = json(
+            client
+                .request(post(
+                    "/api/characters",
+                    json!({
+                        "name": "Tester",
+                        "description": "",
+                        "personality": "",
+                        "scenario": "",
+                        "first_message": "Hi",
+                        "example_messages": "",
+                    }),
+                ))
+                .await
+                .unwrap(),
+        )
+        .await;
+        let chat = json(
+            client
+                .request(post(
+                    "/api/chats",
+                    json!({ "character_id": character["id"] }),
+                ))
+                .await
+                .unwrap(),
+        )
+        .await;
+
+        let response = client
+            .request(post(
+                "/api/completion",
+                json!({
+                    "chat_id": chat["id"],
+                    "regenerate": false,
+                    "message_id": null,
+                    "api_key": "key",
+                    "api_base": api_base,
+                    "model": "m",
+                    "temperature": null,
+                    "max_tokens": null,
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.version(), Version::HTTP_2);
+        assert!(response.headers().get("connection").is_none());
+
+        // The first words arrive while the provider is still holding back the rest
+        let mut body = response.into_body();
+        let mut received = String::new();
+        while !received.contains(r#"data: "Hel""#) {
+            let frame = body.frame().await.unwrap().unwrap();
+            if let Ok(data) = frame.into_data() {
+                received.push_str(&String::from_utf8_lossy(&data));
+            }
+        }
+        assert!(!received.contains(r#"data: "lo""#));
+
+        release.notify_one();
+        let rest = body.collect().await.unwrap().to_bytes();
+        received.push_str(&String::from_utf8_lossy(&rest));
+        assert!(received.contains(r#"data: "lo""#));
+        assert!(received.trim_end().ends_with("data: [DONE]"));
+    }
+}
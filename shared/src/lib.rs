@@ -1 +1,3 @@
 pub mod models;
+pub mod text;
+pub mod validation;
@@ -1,3 +1,5 @@
+use super::chat::Chat;
+use super::message::{ChatMessage, MAX_ALTERNATIVES, ROLE_ASSISTANT};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -10,6 +12,186 @@ pub struct Character {
     pub scenario: String,
     pub first_message: String,
     pub example_messages: String,
+    /// Other openings a chat can start with besides `first_message`
+    #[serde(default)]
+    pub alternate_greetings: Vec<String>,
+    #[serde(default)]
+    pub greeting_mode: GreetingMode,
+    /// Pinned to the top of the sidebar
+    #[serde(default)]
+    pub is_favorite: bool,
+    /// How long replies should be, `None` leaves it to the model
+    #[serde(default)]
+    pub response_length: Option<ResponseLength>,
+    /// Reasoning effort for this character's replies, `None` uses the global setting
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    #[serde(flatten)]
+    pub metadata: CardMetadata,
+}
+
+impl Character {
+    /// `first_message` followed by the alternate greetings, skipping empty ones
+    pub fn greetings(&self) -> Vec<&str> {
+        std::iter::once(&self.first_message)
+            .chain(&self.alternate_greetings)
+            .map(|g| g.as_str())
+            .filter(|g| !g.trim().is_empty())
+            .collect()
+    }
+
+    /// The message a new chat opens with, as chosen by `greeting_mode`. `seed` picks the
+    /// greeting in random mode, so the same seed always gives the same one.
+    pub fn opening_message(&self, seed: u64) -> Option<ChatMessage> {
+        let greetings = self.greetings();
+        let first = *greetings.first()?;
+        Some(match self.greeting_mode {
+            GreetingMode::First => ChatMessage::new(ROLE_ASSISTANT, first),
+            GreetingMode::Random => ChatMessage::new(
+                ROLE_ASSISTANT,
+                greetings[(seed % greetings.len() as u64) as usize],
+            ),
+            GreetingMode::Swipes => {
+                let mut message = ChatMessage::new(ROLE_ASSISTANT, first);
+                for greeting in greetings.iter().skip(1).take(MAX_ALTERNATIVES) {
+                    message.push_alternative(*greeting, None);
+                }
+                message
+            }
+        })
+    }
+}
+
+/// A character with all of its chats, for sharing a character along with demo conversations
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CharacterBundle {
+    pub character: Character,
+    pub chats: Vec<Chat>,
+}
+
+impl CharacterBundle {
+    /// The same character and chats under new ids, so importing never clashes with what's
+    /// already there. Other characters the chats mention aren't part of the bundle, so
+    /// they're dropped from participants and senders.
+    pub fn with_fresh_ids(
+        self,
+        character_id: Uuid,
+        mut new_chat_id: impl FnMut() -> Uuid,
+        mut new_message_id: impl FnMut() -> Uuid,
+    ) -> Self {
+        let old_id = self.character.id;
+        let remap = |id: Option<Uuid>| id.filter(|id| *id == old_id).map(|_| character_id);
+        let chats = self
+            .chats
+            .iter()
+            .map(|chat| {
+                let mut copy = chat.duplicate(new_chat_id(), &mut new_message_id);
+                copy.character_id = character_id;
+                copy.participants.retain(|p| p.character_id == old_id);
+                match copy.participants.first_mut() {
+                    Some(p) => p.character_id = character_id,
+                    None => copy.add_participant(character_id),
+                }
+                for message in &mut copy.messages {
+                    message.sender_id = remap(message.sender_id);
+                    for sender in &mut message.alternative_senders {
+                        *sender = remap(*sender);
+                    }
+                }
+                copy.message_count = copy.messages.len();
+                copy
+            })
+            .collect();
+        Self {
+            character: Character {
+                id: character_id,
+                is_favorite: false,
+                ..self.character
+            },
+            chats,
+        }
+    }
+}
+
+/// How a new chat picks its opening message when the character has several greetings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GreetingMode {
+    /// Always `first_message`
+    #[default]
+    First,
+    /// One greeting at random
+    Random,
+    /// `first_message`, with the others as swipes
+    Swipes,
+}
+
+impl GreetingMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GreetingMode::First => "first",
+            GreetingMode::Random => "random",
+            GreetingMode::Swipes => "swipes",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "first" => Some(GreetingMode::First),
+            "random" => Some(GreetingMode::Random),
+            "swipes" => Some(GreetingMode::Swipes),
+            _ => None,
+        }
+    }
+}
+
+/// Card details that describe the character rather than shape its replies, as carried by
+/// Tavern V2 cards. None of it is sent to the model.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CardMetadata {
+    #[serde(default)]
+    pub creator: Option<String>,
+    /// Notes from the card's author to whoever uses it
+    #[serde(default)]
+    pub creator_notes: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub character_version: Option<String>,
+}
+
+impl CardMetadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Reply length a character is nudged towards
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseLength {
+    Short,
+    Medium,
+    Long,
+}
+
+impl ResponseLength {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseLength::Short => "short",
+            ResponseLength::Medium => "medium",
+            ResponseLength::Long => "long",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "short" => Some(ResponseLength::Short),
+            "medium" => Some(ResponseLength::Medium),
+            "long" => Some(ResponseLength::Long),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -20,4 +202,76 @@ pub struct CreateCharacterRequest {
     pub scenario: String,
     pub first_message: String,
     pub example_messages: String,
+    #[serde(default)]
+    pub alternate_greetings: Vec<String>,
+    #[serde(default)]
+    pub greeting_mode: GreetingMode,
+    #[serde(default)]
+    pub response_length: Option<ResponseLength>,
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    #[serde(flatten)]
+    pub metadata: CardMetadata,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn greeter(greeting_mode: GreetingMode) -> Character {
+        Character {
+            id: Uuid::nil(),
+            name: "Ada".to_string(),
+            description: String::new(),
+            personality: String::new(),
+            scenario: String::new(),
+            first_message: "One".to_string(),
+            example_messages: String::new(),
+            alternate_greetings: vec!["Two".to_string(), " ".to_string(), "Three".to_string()],
+            greeting_mode,
+            is_favorite: false,
+            response_length: None,
+            reasoning_effort: None,
+            metadata: CardMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn first_mode_always_opens_with_the_first_message() {
+        for seed in 0..3 {
+            let opening = greeter(GreetingMode::First).opening_message(seed).unwrap();
+            assert_eq!(opening.content, "One");
+            assert!(opening.alternatives.is_empty());
+        }
+    }
+
+    #[test]
+    fn random_mode_picks_a_greeting_by_seed() {
+        let character = greeter(GreetingMode::Random);
+        let pick = |seed| character.opening_message(seed).unwrap().content;
+        // Blank greetings are never picked
+        assert_eq!(
+            [pick(0), pick(1), pick(2), pick(3)],
+            ["One", "Two", "Three", "One"]
+        );
+        assert_eq!(pick(7), pick(7));
+    }
+
+    #[test]
+    fn swipes_mode_offers_every_greeting() {
+        let opening = greeter(GreetingMode::Swipes).opening_message(0).unwrap();
+        assert_eq!(opening.content, "One");
+        assert_eq!(opening.alternatives, ["Two", "Three"]);
+        assert_eq!(opening.active_index, 0);
+    }
+
+    #[test]
+    fn no_greetings_means_no_opening() {
+        let character = Character {
+            first_message: String::new(),
+            alternate_greetings: Vec::new(),
+            ..greeter(GreetingMode::Swipes)
+        };
+        assert!(character.opening_message(0).is_none());
+    }
 }
@@ -1,4 +1,7 @@
+use super::default_true;
+use super::lore::default_lore_budget;
 use super::message::ChatMessage;
+use super::settings::DEFAULT_REASONING_EFFORT;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -6,6 +9,9 @@ use uuid::Uuid;
 pub struct ChatParticipant {
     pub character_id: Uuid,
     pub is_active: bool, // Can take turns in group chat
+    /// Position in the speaking order, lowest first
+    #[serde(default)]
+    pub order: i32,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -15,6 +21,60 @@ pub struct Chat {
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
     pub participants: Vec<ChatParticipant>,
+    /// How many messages the chat has, also set in listings where `messages` is left empty
+    #[serde(default)]
+    pub message_count: usize,
+    /// Replaces the character's scenario in this chat only
+    #[serde(default)]
+    pub scenario_override: Option<String>,
+    /// Labels to organize chats by, e.g. "canon" or "experiment"
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Plaintext start of the latest message, filled in listings only
+    #[serde(default)]
+    pub preview: Option<String>,
+}
+
+/// Longest chat preview in listings, in characters
+pub const CHAT_PREVIEW_LENGTH: usize = 80;
+
+impl Chat {
+    /// Add a character at the end of the speaking order
+    pub fn add_participant(&mut self, character_id: Uuid) {
+        let order = self
+            .participants
+            .iter()
+            .map(|p| p.order + 1)
+            .max()
+            .unwrap_or(0);
+        self.participants.push(ChatParticipant {
+            character_id,
+            is_active: true,
+            order,
+        });
+    }
+
+    /// A deep copy under the id `id`, every message with a fresh id from `new_message_id`.
+    /// Alternatives, senders and the shown swipe carry over unchanged.
+    pub fn duplicate(&self, id: Uuid, mut new_message_id: impl FnMut() -> Uuid) -> Chat {
+        Chat {
+            id,
+            messages: self
+                .messages
+                .iter()
+                .map(|m| m.clone().with_id(new_message_id()))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Participants sorted by speaking order.
+    /// Chats saved before the order existed all have 0 and keep their stored order.
+    pub fn ordered_participants(&self) -> Vec<&ChatParticipant> {
+        let mut participants = self.participants.iter().collect::<Vec<_>>();
+        participants.sort_by_key(|p| p.order);
+        participants
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -22,6 +82,29 @@ pub struct CreateChatRequest {
     pub character_id: Uuid,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MoveChatRequest {
+    pub character_id: Uuid,
+}
+
+/// A chat's scenario override, `None` (or blank) to go back to the character's own
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SetScenarioRequest {
+    pub scenario: Option<String>,
+}
+
+/// A label to put on a chat
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChatTagRequest {
+    pub tag: String,
+}
+
+/// The full new speaking order of a chat's participants
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReorderParticipantsRequest {
+    pub character_ids: Vec<Uuid>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CompletionRequest {
     pub chat_id: Uuid,
@@ -32,5 +115,102 @@ pub struct CompletionRequest {
     pub model: String,
     pub temperature: Option<f32>,
     pub max_tokens: Option<u16>,
+    /// The global setting, used when neither the override nor the character sets one.
+    /// Older clients don't send it and get the default effort.
+    #[serde(default = "default_reasoning_effort")]
     pub reasoning_effort: String,
+    /// Effort for this request only, wins over the character's and the global one
+    #[serde(default)]
+    pub reasoning_effort_override: Option<String>,
+    /// Write the user's next message instead of the character's. Nothing is persisted.
+    #[serde(default)]
+    pub impersonate: bool,
+    /// List the other participants of a group chat in the system prompt
+    #[serde(default)]
+    pub include_roster: bool,
+    /// Put the character's example messages in the system prompt. Turning them off saves
+    /// tokens once a chat has enough history to show the character's voice.
+    #[serde(default = "default_true")]
+    pub include_examples: bool,
+    /// Send back-to-back replies from the same character as one assistant turn, for
+    /// providers that reject consecutive assistant messages
+    #[serde(default)]
+    pub merge_consecutive_replies: bool,
+    /// Most tokens of global lorebook entries to send, see [`super::lore::LoreEntry`]
+    #[serde(default = "default_lore_budget")]
+    pub lore_budget: usize,
+    /// Only send this many of the most recent messages, not counting system messages
+    #[serde(default)]
+    pub history_limit: Option<usize>,
+    /// Send this as the system prompt instead of the one built from the character and
+    /// roster. Doesn't apply to impersonation, which has a prompt of its own.
+    #[serde(default)]
+    pub system_prompt_override: Option<String>,
+    /// Cut tool results sent to the model down to this many characters.
+    /// The full result is still saved to the chat.
+    #[serde(default)]
+    pub tool_result_limit: Option<usize>,
+    /// Provider-specific fields such as `min_p` or `top_k`, merged into the request body.
+    /// Must be a JSON object.
+    #[serde(default)]
+    pub extra_body: Option<serde_json::Value>,
+    /// Ask the provider for per-token log probabilities and stream them back.
+    /// They're for analysis only and never saved.
+    #[serde(default)]
+    pub logprobs: bool,
+    /// When regenerating, overwrite this variant of the message instead of adding a new one
+    #[serde(default)]
+    pub target_alternative_index: Option<usize>,
+    /// Role labels to remove from the start of the saved reply, see
+    /// [`crate::text::strip_leaked_prefix`]
+    #[serde(default)]
+    pub strip_prefixes: Vec<String>,
+    /// A one-off instruction for this generation only, such as "make it shorter".
+    /// Sent to the model after the history and never saved.
+    #[serde(default)]
+    pub regen_instruction: Option<String>,
+}
+
+/// `error.code` of a completion refused because the request carried no API key
+pub const ERROR_MISSING_API_KEY: &str = "missing_api_key";
+
+/// How likely the model thought a token of its reply was
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+}
+
+impl TokenLogprob {
+    /// The probability in `0.0..=1.0`
+    pub fn probability(&self) -> f32 {
+        self.logprob.exp()
+    }
+}
+
+fn default_reasoning_effort() -> String {
+    DEFAULT_REASONING_EFFORT.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn requests_from_older_clients_get_the_default_effort() {
+        let request: CompletionRequest = serde_json::from_value(json!({
+            "chat_id": Uuid::nil(),
+            "regenerate": false,
+            "message_id": null,
+            "api_key": "key",
+            "api_base": null,
+            "model": "m",
+            "temperature": null,
+            "max_tokens": null,
+        }))
+        .unwrap();
+        assert_eq!(request.reasoning_effort, DEFAULT_REASONING_EFFORT);
+        assert_eq!(request.reasoning_effort_override, None);
+    }
 }
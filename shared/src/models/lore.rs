@@ -0,0 +1,98 @@
+use super::default_true;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Tokens of world info sent with a reply when the settings don't say
+pub const DEFAULT_LORE_BUDGET: usize = 1024;
+
+pub(super) fn default_lore_budget() -> usize {
+    DEFAULT_LORE_BUDGET
+}
+
+/// A world fact shared by every character, sent to the model when one of its keys comes
+/// up in the recent conversation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoreEntry {
+    pub id: Uuid,
+    pub name: String,
+    /// Words or phrases that trigger the entry, matched ignoring case
+    pub keys: Vec<String>,
+    pub content: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl LoreEntry {
+    /// Whether any key occurs in `text` as whole words, e.g. "elf" in "an elf." but not in
+    /// "itself". `text` must already be lowercase.
+    pub fn matches(&self, text: &str) -> bool {
+        self.enabled
+            && self
+                .keys
+                .iter()
+                .map(|k| k.trim().to_lowercase())
+                .any(|k| !k.is_empty() && contains_words(text, &k))
+    }
+
+    /// Rough token count of the content, at about four characters a token
+    pub fn estimated_tokens(&self) -> usize {
+        self.content.chars().count().div_ceil(4)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LoreEntryRequest {
+    pub name: String,
+    pub keys: Vec<String>,
+    pub content: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// Whether `phrase` occurs in `text` with no letter or digit right before or after it
+fn contains_words(text: &str, phrase: &str) -> bool {
+    text.match_indices(phrase).any(|(at, _)| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + phrase.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(keys: &[&str]) -> LoreEntry {
+        LoreEntry {
+            id: Uuid::nil(),
+            name: "Entry".to_string(),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            content: String::new(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn keys_match_whole_words_only() {
+        let elf = entry(&["Elf"]);
+        assert!(elf.matches("an elf."));
+        assert!(elf.matches("elf"));
+        assert!(elf.matches("(elf)"));
+        assert!(!elf.matches("itself"));
+        assert!(!elf.matches("elves and elfish things"));
+        assert!(elf.matches("itself, said the elf"));
+
+        let phrase = entry(&[" Iron Gate "]);
+        assert!(phrase.matches("past the iron gate!"));
+        assert!(!phrase.matches("the iron gates"));
+        assert!(entry(&["café"]).matches("at the café"));
+    }
+
+    #[test]
+    fn disabled_entries_and_blank_keys_never_match() {
+        let mut elf = entry(&["elf"]);
+        elf.enabled = false;
+        assert!(!elf.matches("an elf"));
+        assert!(!entry(&["  "]).matches("anything"));
+    }
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -6,6 +7,9 @@ pub const ROLE_ASSISTANT: &str = "assistant";
 pub const ROLE_SYSTEM: &str = "system";
 pub const ROLE_TOOL: &str = "tool";
 
+/// Most alternatives a message can collect on top of its primary content
+pub const MAX_ALTERNATIVES: usize = 32;
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub id: Uuid,
@@ -19,12 +23,30 @@ pub struct ChatMessage {
     /// Swipe alternatives (content variants)
     pub alternatives: Vec<String>,
     #[serde(default)]
+    /// Sender of each alternative, parallel to `alternatives` (`None` = same as `sender_id`)
+    pub alternative_senders: Vec<Option<Uuid>>,
+    #[serde(default)]
     /// Which alternative is currently shown (0 = primary content)
     pub active_index: usize,
     #[serde(default)]
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(default)]
     pub tool_call_id: Option<String>,
+    #[serde(default)]
+    /// Always sent to the model, even when it falls outside the history limit
+    pub keep_in_context: bool,
+    #[serde(default)]
+    /// Shown in the chat but never sent to the model
+    pub hidden_from_context: bool,
+    #[serde(default = "Utc::now")]
+    /// When the message was sent. Messages stored before this was recorded read as the epoch.
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The reasoning trace the model wrote before its reply, for models that share one
+    pub reasoning: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Reasoning trace of each alternative, parallel to `alternatives`
+    pub alternative_reasoning: Vec<Option<String>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -49,9 +71,15 @@ impl ChatMessage {
             content: content.into(),
             sender_id: None,
             alternatives: Vec::new(),
+            alternative_senders: Vec::new(),
             active_index: 0,
             tool_calls: None,
             tool_call_id: None,
+            keep_in_context: false,
+            hidden_from_context: false,
+            created_at: Utc::now(),
+            reasoning: None,
+            alternative_reasoning: Vec::new(),
         }
     }
 
@@ -67,12 +95,35 @@ impl ChatMessage {
             content: content.into(),
             sender_id: Some(sender_id),
             alternatives: Vec::new(),
+            alternative_senders: Vec::new(),
             active_index: 0,
             tool_calls: None,
             tool_call_id: None,
+            keep_in_context: false,
+            hidden_from_context: false,
+            created_at: Utc::now(),
+            reasoning: None,
+            alternative_reasoning: Vec::new(),
         }
     }
 
+    /// Replace the generated id, e.g. with one from a controllable clock
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
+    /// Whether the time the message was sent is known, i.e. it isn't an older message
+    /// stored before times were recorded
+    pub fn has_created_at(&self) -> bool {
+        self.created_at != DateTime::<Utc>::UNIX_EPOCH
+    }
+
     /// Get the currently active content (considering alternatives)
     pub fn active_content(&self) -> &str {
         if self.active_index == 0 || self.alternatives.is_empty() {
@@ -85,6 +136,108 @@ impl ChatMessage {
         }
     }
 
+    /// Mutable access to the currently active content
+    pub fn active_content_mut(&mut self) -> &mut String {
+        if self.active_index == 0 || self.active_index > self.alternatives.len() {
+            &mut self.content
+        } else {
+            &mut self.alternatives[self.active_index - 1]
+        }
+    }
+
+    /// Content of a variant, 0 being the primary content
+    pub fn variant(&self, index: usize) -> Option<&str> {
+        match index {
+            0 => Some(&self.content),
+            i => self.alternatives.get(i - 1).map(|s| s.as_str()),
+        }
+    }
+
+    /// Mutable access to a variant, 0 being the primary content
+    pub fn variant_mut(&mut self, index: usize) -> Option<&mut String> {
+        match index {
+            0 => Some(&mut self.content),
+            i => self.alternatives.get_mut(i - 1),
+        }
+    }
+
+    /// Sender of a variant, falling back to the message's sender like `active_sender`
+    pub fn variant_sender(&self, index: usize) -> Option<Uuid> {
+        match index {
+            0 => self.sender_id,
+            i => self
+                .alternative_senders
+                .get(i - 1)
+                .copied()
+                .flatten()
+                .or(self.sender_id),
+        }
+    }
+
+    /// Get the sender of the currently active variant
+    pub fn active_sender(&self) -> Option<Uuid> {
+        self.variant_sender(self.active_index)
+    }
+
+    /// Reasoning trace of a variant, 0 being the primary content
+    pub fn variant_reasoning(&self, index: usize) -> Option<&str> {
+        match index {
+            0 => self.reasoning.as_deref(),
+            i => self.alternative_reasoning.get(i - 1)?.as_deref(),
+        }
+    }
+
+    /// Reasoning trace of the currently active variant
+    pub fn active_reasoning(&self) -> Option<&str> {
+        self.variant_reasoning(self.active_index)
+    }
+
+    /// Set the reasoning trace of a variant, keeping `alternative_reasoning` aligned.
+    /// Does nothing if there's no variant at `index`.
+    pub fn set_variant_reasoning(&mut self, index: usize, reasoning: Option<String>) {
+        if index == 0 {
+            self.reasoning = reasoning;
+        } else if index <= self.alternatives.len() {
+            self.alternative_reasoning
+                .resize(self.alternatives.len(), None);
+            self.alternative_reasoning[index - 1] = reasoning;
+        }
+    }
+
+    /// Add an alternative, keeping `alternative_senders` and `alternative_reasoning`
+    /// aligned with `alternatives`
+    pub fn push_alternative(&mut self, content: impl Into<String>, sender_id: Option<Uuid>) {
+        self.alternative_senders
+            .resize(self.alternatives.len(), None);
+        self.alternative_reasoning
+            .resize(self.alternatives.len(), None);
+        self.alternatives.push(content.into());
+        self.alternative_senders.push(sender_id);
+        self.alternative_reasoning.push(None);
+    }
+
+    /// Overwrite a variant and its sender, keeping `alternative_senders` aligned.
+    /// Returns false if there's no variant at `index`.
+    pub fn replace_variant(
+        &mut self,
+        index: usize,
+        content: impl Into<String>,
+        sender_id: Option<Uuid>,
+    ) -> bool {
+        let Some(variant) = self.variant_mut(index) else {
+            return false;
+        };
+        *variant = content.into();
+        if index == 0 {
+            self.sender_id = sender_id;
+        } else {
+            self.alternative_senders
+                .resize(self.alternatives.len(), None);
+            self.alternative_senders[index - 1] = sender_id;
+        }
+        true
+    }
+
     /// Total number of variants (1 primary and alternatives)
     pub fn variant_count(&self) -> usize {
         1 + self.alternatives.len()
@@ -104,7 +257,81 @@ pub enum SwipeDirection {
     Right,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeepInContextRequest {
+    pub keep: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HiddenFromContextRequest {
+    pub hidden: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SwipeRequest {
     pub direction: SwipeDirection,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn messages_stored_before_per_swipe_senders_still_load() {
+        let ada = Uuid::now_v7();
+        let message: ChatMessage = serde_json::from_value(json!({
+            "id": Uuid::now_v7(),
+            "role": ROLE_ASSISTANT,
+            "content": "One",
+            "sender_id": ada,
+            "alternatives": ["Two"],
+            "active_index": 1,
+        }))
+        .unwrap();
+
+        assert!(message.alternative_senders.is_empty());
+        assert_eq!(message.active_content(), "Two");
+        assert_eq!(message.active_sender(), Some(ada));
+    }
+
+    #[test]
+    fn each_swipe_keeps_its_own_sender() {
+        let (ada, bea) = (Uuid::now_v7(), Uuid::now_v7());
+        let mut message = ChatMessage::new_from_sender(ROLE_ASSISTANT, "One", ada);
+        message.push_alternative("Two", Some(bea));
+        message.push_alternative("Three", None);
+
+        let message: ChatMessage =
+            serde_json::from_str(&serde_json::to_string(&message).unwrap()).unwrap();
+        assert_eq!(message.alternative_senders, [Some(bea), None]);
+        assert_eq!(message.variant_sender(0), Some(ada));
+        assert_eq!(message.variant_sender(1), Some(bea));
+        // No sender of its own falls back to the message's
+        assert_eq!(message.variant_sender(2), Some(ada));
+    }
+
+    #[test]
+    fn messages_carry_tool_calls_and_their_results() {
+        let mut call = ChatMessage::new(ROLE_ASSISTANT, "");
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "roll".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        let mut result = ChatMessage::new(ROLE_TOOL, "4");
+        result.tool_call_id = Some("call_1".to_string());
+
+        let json = serde_json::to_value([&call, &result]).unwrap();
+        assert_eq!(json[0]["tool_calls"][0]["function"]["name"], "roll");
+        assert_eq!(json[1]["tool_call_id"], "call_1");
+
+        let [call, result]: [ChatMessage; 2] = serde_json::from_value(json).unwrap();
+        let ChatMessage { tool_calls, .. } = call;
+        let ChatMessage { tool_call_id, .. } = result;
+        assert_eq!(tool_calls.unwrap()[0].id, tool_call_id.unwrap());
+    }
+}
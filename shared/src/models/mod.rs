@@ -1,11 +1,20 @@
 pub mod character;
 pub mod chat;
+pub mod lore;
 pub mod message;
 pub mod plugin;
 pub mod settings;
+pub mod stats;
 
 pub use character::*;
 pub use chat::*;
+pub use lore::*;
 pub use message::*;
 pub use plugin::*;
 pub use settings::*;
+pub use stats::*;
+
+/// Serde default for flags that are on unless turned off
+fn default_true() -> bool {
+    true
+}
@@ -9,9 +9,46 @@ pub struct PluginManifest {
     pub tools: Vec<Tool>,
 }
 
+/// Read-only data a plugin exposes, such as a document or a knowledge base entry
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Resource {
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    pub text: String,
+}
+
+/// Outcome of scanning the plugins directory
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryReport {
+    /// Names of the plugins that loaded
+    pub loaded: Vec<String>,
+    pub failed: Vec<DiscoveryFailure>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryFailure {
+    pub file: String,
+    pub error: String,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Tool {
     pub name: String,
     pub description: String,
     pub parameters: serde_json::Value, // JSON Schema
+    /// JSON Schema of the result, for plugins that declare one. Told to the model with the
+    /// tool's description and used to lay results out in the chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub returns: Option<serde_json::Value>,
 }
@@ -1,5 +1,36 @@
+use super::default_true;
+use super::lore::{DEFAULT_LORE_BUDGET, default_lore_budget};
 use serde::{Deserialize, Serialize};
 
+pub const DEFAULT_API_BASE: &str = "https://openrouter.ai/api/v1";
+pub const DEFAULT_MODEL: &str = "tngtech/deepseek-r1t2-chimera:free";
+pub const DEFAULT_REASONING_EFFORT: &str = "medium";
+
+/// Defaults the server recommends for a fresh install
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigDefaults {
+    pub api_base: String,
+    pub model: String,
+    pub reasoning_effort: String,
+}
+
+impl Default for ConfigDefaults {
+    fn default() -> Self {
+        Self {
+            api_base: DEFAULT_API_BASE.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            reasoning_effort: DEFAULT_REASONING_EFFORT.to_string(),
+        }
+    }
+}
+
+/// How the server is running, for the frontend to reflect
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// Chats are kept in memory only and won't survive a server restart
+    pub ephemeral: bool,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AppSettings {
     pub api_key: String,
@@ -8,17 +39,247 @@ pub struct AppSettings {
     pub temperature: f32,
     pub max_tokens: u16,
     pub reasoning_effort: String,
+    /// Tell the responding character who else is in a group chat
+    #[serde(default = "default_true")]
+    pub include_roster: bool,
+    /// Send the character's example messages with the system prompt
+    #[serde(default = "default_true")]
+    pub include_examples: bool,
+    /// Merge back-to-back replies from the same character into one turn for the model
+    #[serde(default)]
+    pub merge_consecutive_replies: bool,
+    /// Most tokens of matching global lorebook entries to send with a reply
+    #[serde(default = "default_lore_budget")]
+    pub lore_budget: usize,
+    #[serde(default)]
+    pub density: Density,
+    /// How many recent messages to send, `None` for the whole chat
+    #[serde(default)]
+    pub history_limit: Option<usize>,
+    /// Longest tool result to send to the model, `None` to send results whole
+    #[serde(default = "default_tool_result_limit")]
+    pub tool_result_limit: Option<usize>,
+    /// Extra fields merged into every completion request, for provider-specific parameters
+    #[serde(default)]
+    pub extra_body: Option<serde_json::Value>,
+    /// Hold back unclosed markdown at the end of a streaming reply to avoid flicker
+    #[serde(default = "default_true")]
+    pub smooth_streaming: bool,
+    /// Reveal streaming replies at most this many characters per second, 0 to show them as
+    /// they arrive
+    #[serde(default)]
+    pub typing_speed: u32,
+    /// Shown beside the dots while a reply hasn't started, empty for just the dots.
+    /// `{{char}}` stands for the character's name.
+    #[serde(default)]
+    pub thinking_text: String,
+    /// Show diagnostics such as streaming throughput
+    #[serde(default)]
+    pub developer_mode: bool,
+    /// Request token log probabilities and show a confidence heatmap, in developer mode
+    #[serde(default)]
+    pub show_logprobs: bool,
+    /// Role labels models sometimes echo at the start of a reply, removed once it's done.
+    /// `{{char}}` stands for the character's name. None unless the user adds some.
+    #[serde(default)]
+    pub strip_prefixes: Vec<String>,
+    /// Saved model and parameter combinations to switch between
+    #[serde(default)]
+    pub presets: Vec<ModelPreset>,
+}
+
+/// A named model and parameter combination, applied to the settings in one go
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelPreset {
+    pub name: String,
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u16,
+    pub reasoning_effort: String,
+    /// Provider-specific parameters such as `top_p`, see [`AppSettings::extra_body`]
+    #[serde(default)]
+    pub extra_body: Option<serde_json::Value>,
+}
+
+impl ModelPreset {
+    /// A preset of the model parameters currently in `settings`
+    pub fn from_settings(name: impl Into<String>, settings: &AppSettings) -> Self {
+        Self {
+            name: name.into(),
+            model: settings.model.clone(),
+            temperature: settings.temperature,
+            max_tokens: settings.max_tokens,
+            reasoning_effort: settings.reasoning_effort.clone(),
+            extra_body: settings.extra_body.clone(),
+        }
+    }
+}
+
+/// Settings keys that are never put in a shared snippet or taken from one. Extra body
+/// parameters can carry provider credentials, in the settings and in each preset.
+const PRIVATE_KEYS: &[&str] = &["api_key", "extra_body"];
+
+/// Remove the private keys from serialized settings and their presets
+fn strip_private_keys(settings: &mut serde_json::Map<String, serde_json::Value>) {
+    for key in PRIVATE_KEYS {
+        settings.remove(*key);
+    }
+    if let Some(presets) = settings.get_mut("presets").and_then(|p| p.as_array_mut()) {
+        for preset in presets.iter_mut().filter_map(|p| p.as_object_mut()) {
+            for key in PRIVATE_KEYS {
+                preset.remove(*key);
+            }
+        }
+    }
+}
+
+impl AppSettings {
+    /// Use a preset's model and parameters, leaving everything else alone
+    pub fn apply_preset(&mut self, preset: &ModelPreset) {
+        self.model = preset.model.clone();
+        self.temperature = preset.temperature;
+        self.max_tokens = preset.max_tokens;
+        self.reasoning_effort = preset.reasoning_effort.clone();
+        self.extra_body = preset.extra_body.clone();
+    }
+
+    /// Save the current model parameters as a preset, replacing one with the same name
+    pub fn save_preset(&mut self, name: &str) {
+        let preset = ModelPreset::from_settings(name, self);
+        match self.presets.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    /// The preset the current model parameters match, if any
+    pub fn active_preset(&self) -> Option<&ModelPreset> {
+        self.presets
+            .iter()
+            .find(|p| *p == &ModelPreset::from_settings(p.name.clone(), self))
+    }
+
+    /// The settings as a JSON snippet for sharing, with private keys removed
+    pub fn to_share_snippet(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("settings serialize");
+        if let Some(map) = value.as_object_mut() {
+            strip_private_keys(map);
+        }
+        serde_json::to_string_pretty(&value).expect("settings serialize")
+    }
+
+    /// These settings with a shared snippet applied on top. Keys the snippet doesn't set,
+    /// and private keys, keep their current values.
+    pub fn merge_share_snippet(&self, snippet: &str) -> Result<AppSettings, String> {
+        let mut shared: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(snippet).map_err(|e| format!("Not a settings snippet: {}", e))?;
+        strip_private_keys(&mut shared);
+
+        let mut value = serde_json::to_value(self).expect("settings serialize");
+        let map = value.as_object_mut().expect("settings are an object");
+        for (key, field) in shared {
+            map.insert(key, field);
+        }
+        serde_json::from_value(value).map_err(|e| format!("Invalid settings snippet: {}", e))
+    }
+}
+
+fn default_tool_result_limit() -> Option<usize> {
+    Some(8000)
+}
+
+/// How tightly the message list is laid out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Density {
+    #[default]
+    Comfortable,
+    Compact,
+}
+
+impl Density {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Density::Comfortable => "comfortable",
+            Density::Compact => "compact",
+        }
+    }
 }
 
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
             api_key: String::new(),
-            api_base: "https://openrouter.ai/api/v1".to_string(),
-            model: "tngtech/deepseek-r1t2-chimera:free".to_string(),
+            api_base: DEFAULT_API_BASE.to_string(),
+            model: DEFAULT_MODEL.to_string(),
             temperature: 0.7,
             max_tokens: 4096,
-            reasoning_effort: "medium".to_string(),
+            reasoning_effort: DEFAULT_REASONING_EFFORT.to_string(),
+            include_roster: true,
+            include_examples: true,
+            merge_consecutive_replies: false,
+            lore_budget: DEFAULT_LORE_BUDGET,
+            density: Density::Comfortable,
+            history_limit: None,
+            tool_result_limit: default_tool_result_limit(),
+            extra_body: None,
+            smooth_streaming: true,
+            typing_speed: 0,
+            thinking_text: String::new(),
+            developer_mode: false,
+            show_logprobs: false,
+            strip_prefixes: Vec::new(),
+            presets: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_settings_start_from_the_server_defaults() {
+        let settings = AppSettings::default();
+        let defaults = ConfigDefaults::default();
+        assert_eq!(settings.api_base, defaults.api_base);
+        assert_eq!(settings.model, defaults.model);
+        assert_eq!(settings.reasoning_effort, defaults.reasoning_effort);
+    }
+
+    #[test]
+    fn nothing_is_stripped_unless_asked() {
+        assert!(AppSettings::default().strip_prefixes.is_empty());
+        // Settings saved before the option existed
+        let mut saved = serde_json::to_value(AppSettings::default()).unwrap();
+        saved.as_object_mut().unwrap().remove("strip_prefixes");
+        let saved: AppSettings = serde_json::from_value(saved).unwrap();
+        assert!(saved.strip_prefixes.is_empty());
+    }
+
+    #[test]
+    fn shared_snippets_carry_no_credentials() {
+        let mut settings = AppSettings {
+            api_key: "sk-secret".to_string(),
+            extra_body: Some(serde_json::json!({ "api_token": "secret" })),
+            ..Default::default()
+        };
+        settings.save_preset("Mine");
+        let snippet = settings.to_share_snippet();
+        assert!(!snippet.contains("secret"));
+
+        let other = AppSettings {
+            api_key: "sk-own".to_string(),
+            extra_body: Some(serde_json::json!({ "top_p": 0.9 })),
+            ..Default::default()
+        };
+        let merged = other.merge_share_snippet(&snippet).unwrap();
+        assert_eq!(merged.api_key, "sk-own");
+        assert_eq!(merged.extra_body, other.extra_body);
+        assert_eq!(merged.presets[0].extra_body, None);
+
+        let leaked = r#"{"extra_body": {"api_token": "theirs"}}"#;
+        let merged = other.merge_share_snippet(leaked).unwrap();
+        assert_eq!(merged.extra_body, other.extra_body);
+    }
+}
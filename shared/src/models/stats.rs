@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Price of a model in USD per million tokens
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl ModelPricing {
+    pub fn estimate(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.prompt + completion_tokens as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+/// Token usage of a single model within a chat
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// `None` when no price is configured for the model
+    pub estimated_cost: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChatStats {
+    pub chat_id: Uuid,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Cost of the models that have a configured price, `None` if none of them has one.
+    /// Unpriced models are left out, their own `estimated_cost` is `None`.
+    pub estimated_cost: Option<f64>,
+    pub models: Vec<ModelUsage>,
+}
+
+impl ChatStats {
+    /// Totals of each model's usage, with costs from `pricing`
+    pub fn from_usage(
+        chat_id: Uuid,
+        mut models: Vec<ModelUsage>,
+        pricing: &HashMap<String, ModelPricing>,
+    ) -> Self {
+        for usage in &mut models {
+            usage.estimated_cost = pricing
+                .get(&usage.model)
+                .map(|price| price.estimate(usage.prompt_tokens, usage.completion_tokens));
+        }
+        let mut costs = models.iter().filter_map(|m| m.estimated_cost).peekable();
+        let estimated_cost = costs.peek().is_some().then(|| costs.sum());
+        Self {
+            chat_id,
+            prompt_tokens: models.iter().map(|m| m.prompt_tokens).sum(),
+            completion_tokens: models.iter().map(|m| m.completion_tokens).sum(),
+            estimated_cost,
+            models,
+        }
+    }
+}
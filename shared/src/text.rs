@@ -0,0 +1,83 @@
+use pulldown_cmark::{Event, Parser, TagEnd};
+
+/// Strip markdown formatting and collapse whitespace, for labels such as titles and previews
+pub fn to_plaintext(markdown: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::Rule => text.push(' '),
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item) => text.push(' '),
+            Event::End(TagEnd::CodeBlock | TagEnd::TableCell) => text.push(' '),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Cut `text` to at most `max_chars` characters, ending with an ellipsis if anything was removed
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    cut.truncate(cut.trim_end().len());
+    cut.push('…');
+    cut
+}
+
+/// Plaintext preview of a markdown message, at most `max_chars` long
+pub fn to_snippet(markdown: &str, max_chars: usize) -> String {
+    truncate(&to_plaintext(markdown), max_chars)
+}
+
+/// Remove a role label the model echoed at the start of its reply, such as `Assistant:`.
+///
+/// `{{char}}` in a pattern stands for `char_name`. Patterns match ignoring ASCII case and
+/// the whitespace around them; only the first matching one is removed.
+pub fn strip_leaked_prefix<'a>(reply: &'a str, patterns: &[String], char_name: &str) -> &'a str {
+    let trimmed = reply.trim_start();
+    for pattern in patterns {
+        if pattern.contains("{{char}}") && char_name.is_empty() {
+            continue;
+        }
+        let pattern = pattern.replace("{{char}}", char_name);
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            continue;
+        }
+        if let Some(head) = trimmed.get(..pattern.len())
+            && head.eq_ignore_ascii_case(pattern)
+        {
+            return trimmed[pattern.len()..].trim_start();
+        }
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_blocks_keep_their_text_without_fences() {
+        let markdown = "Try this:\n\n```rust\nlet x = 1;\n```\n\nand `y`.";
+        assert_eq!(to_plaintext(markdown), "Try this: let x = 1; and y.");
+    }
+
+    #[test]
+    fn links_keep_their_label() {
+        assert_eq!(
+            to_plaintext("See **[the docs](https://example.com)** now"),
+            "See the docs now"
+        );
+    }
+
+    #[test]
+    fn truncation_ends_with_a_single_ellipsis() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("hello world", 6), "hello…");
+        assert_eq!(truncate("héllo wörld", 6).chars().count(), 6);
+        assert_eq!(to_snippet("*one* two three", 8), "one two…");
+    }
+}
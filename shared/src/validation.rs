@@ -0,0 +1,98 @@
+use url::Url;
+
+/// A provider base URL that passed [`validate_api_base`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiBase {
+    pub url: Url,
+    /// Plain http to a non-loopback host, which sends the api key in the clear
+    pub insecure: bool,
+}
+
+/// Check that `base` is an absolute http(s) URL
+pub fn validate_api_base(base: &str) -> Result<ApiBase, String> {
+    let url = Url::parse(base.trim()).map_err(|e| format!("Invalid API base URL: {}", e))?;
+    let insecure = match url.scheme() {
+        "https" => false,
+        "http" => !is_loopback(&url),
+        scheme => {
+            return Err(format!(
+                "Invalid API base URL: unsupported scheme \"{}\" (expected http or https)",
+                scheme
+            ));
+        }
+    };
+    if url.host().is_none() {
+        return Err("Invalid API base URL: missing host".to_string());
+    }
+    Ok(ApiBase { url, insecure })
+}
+
+/// Parse the extra request parameters setting, which is empty or a JSON object
+pub fn parse_extra_body(text: &str) -> Result<Option<serde_json::Value>, String> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    validate_extra_body(&value)?;
+    Ok(Some(value))
+}
+
+/// Check that extra request parameters are a JSON object, so they can be merged into a request
+pub fn validate_extra_body(value: &serde_json::Value) -> Result<(), String> {
+    if value.is_object() {
+        Ok(())
+    } else {
+        Err("Extra parameters must be a JSON object".to_string())
+    }
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(domain)) => domain == "localhost",
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_bases_must_be_absolute_http_urls() {
+        assert!(validate_api_base("api.openai.com/v1").is_err());
+        assert!(validate_api_base("htps://api.openai.com/v1").is_err());
+        assert!(validate_api_base("ftp://api.openai.com/v1").is_err());
+        assert!(validate_api_base("").is_err());
+
+        let base = validate_api_base(" https://api.openai.com/v1 ").unwrap();
+        assert_eq!(base.url.as_str(), "https://api.openai.com/v1");
+        assert!(!base.insecure);
+    }
+
+    #[test]
+    fn plain_http_is_insecure_unless_it_stays_on_this_machine() {
+        assert!(
+            validate_api_base("http://api.example.com/v1")
+                .unwrap()
+                .insecure
+        );
+        assert!(
+            validate_api_base("http://192.168.1.2:8080/v1")
+                .unwrap()
+                .insecure
+        );
+        assert!(
+            !validate_api_base("http://localhost:11434/v1")
+                .unwrap()
+                .insecure
+        );
+        assert!(
+            !validate_api_base("http://127.0.0.1:5000/v1")
+                .unwrap()
+                .insecure
+        );
+        assert!(!validate_api_base("http://[::1]:5000/v1").unwrap().insecure);
+    }
+}